@@ -0,0 +1,11 @@
+//! message.attributedBody blobs come straight from chat.db.
+//!
+//! Run with `cargo fuzz run attributed_body` from `oxidized/`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = aeromessage::parse_attributed_body(data);
+});
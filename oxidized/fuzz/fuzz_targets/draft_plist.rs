@@ -0,0 +1,11 @@
+//! composition.plist files are written by Messages.app, not by us.
+//!
+//! Run with `cargo fuzz run draft_plist` from `oxidized/`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = aeromessage::parse_draft_plist(data);
+});
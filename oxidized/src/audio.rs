@@ -0,0 +1,120 @@
+//! Length of audio messages, read from the file itself.
+//!
+//! chat.db doesn't record how long a voice message is. Messages saves them
+//! as Core Audio Format (`.caf`) files, whose header says enough to work
+//! it out without decoding: the sample rate from the `desc` chunk, and the
+//! frame count from the `pakt` chunk, or for constant-size packets from
+//! the size of the `data` chunk.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Most of a file read looking for the chunks before `data`.
+const HEADER_LIMIT: u64 = 64 * 1024;
+
+/// Seconds of audio in the CAF file at `path`, rounded to the nearest, or
+/// None if it can't be read or isn't CAF.
+pub fn audio_duration_secs(path: &Path) -> Option<u32> {
+    let mut header = Vec::new();
+    File::open(path).ok()?.take(HEADER_LIMIT).read_to_end(&mut header).ok()?;
+    caf_duration(&header).map(|secs| secs.round() as u32)
+}
+
+/// Duration in seconds from the start of a CAF file.
+fn caf_duration(bytes: &[u8]) -> Option<f64> {
+    if bytes.get(..4)? != b"caff" {
+        return None;
+    }
+    let mut sample_rate = None;
+    let mut bytes_per_packet = 0;
+    let mut frames_per_packet = 0;
+    let mut frames = None;
+    let mut data_size = None;
+
+    // Version and flags, then chunks: 4-byte type, 8-byte size, contents
+    let mut at = 8;
+    while let Some(header) = bytes.get(at..at + 12) {
+        let size = i64::from_be_bytes(header[4..].try_into().ok()?);
+        let body = &bytes[at + 12..];
+        match &header[..4] {
+            b"desc" => {
+                sample_rate = Some(f64::from_be_bytes(body.get(..8)?.try_into().ok()?));
+                bytes_per_packet = u32::from_be_bytes(body.get(16..20)?.try_into().ok()?);
+                frames_per_packet = u32::from_be_bytes(body.get(20..24)?.try_into().ok()?);
+            }
+            // Packet count, then valid frames after priming and remainder
+            b"pakt" => frames = Some(i64::from_be_bytes(body.get(8..16)?.try_into().ok()?) as f64),
+            b"data" => {
+                // Size -1 means the data runs to the end of the file; the
+                // first 4 bytes are an edit count
+                data_size = (size >= 4).then(|| (size - 4) as f64);
+                break;
+            }
+            _ => {}
+        }
+        at = at.checked_add(12)?.checked_add(usize::try_from(size).ok()?)?;
+    }
+
+    let sample_rate = sample_rate.filter(|r| *r > 0.0)?;
+    let frames = match frames {
+        Some(frames) => frames,
+        None if bytes_per_packet > 0 && frames_per_packet > 0 => {
+            (data_size? / bytes_per_packet as f64).floor() * frames_per_packet as f64
+        }
+        None => return None,
+    };
+    Some(frames / sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = kind.to_vec();
+        out.extend_from_slice(&(body.len() as i64).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn desc(sample_rate: f64, bytes_per_packet: u32, frames_per_packet: u32) -> Vec<u8> {
+        let mut body = sample_rate.to_be_bytes().to_vec();
+        body.extend_from_slice(b"opus");
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&bytes_per_packet.to_be_bytes());
+        body.extend_from_slice(&frames_per_packet.to_be_bytes());
+        body.extend_from_slice(&1u32.to_be_bytes());
+        body.extend_from_slice(&0u32.to_be_bytes());
+        chunk(b"desc", &body)
+    }
+
+    fn caf(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = b"caff\0\x01\0\0".to_vec();
+        chunks.iter().for_each(|c| out.extend_from_slice(c));
+        out
+    }
+
+    #[test]
+    fn test_caf_duration() {
+        // Variable packets: frames come from the packet table
+        let mut pakt = 100i64.to_be_bytes().to_vec();
+        pakt.extend_from_slice(&(48_000i64 * 34).to_be_bytes());
+        pakt.extend_from_slice(&[0; 8]);
+        let opus = caf(&[desc(48_000.0, 0, 960), chunk(b"pakt", &pakt), chunk(b"data", &[0; 16])]);
+        assert_eq!(caf_duration(&opus), Some(34.0));
+
+        // Constant packets: frames come from the data size
+        let pcm = caf(&[desc(8_000.0, 2, 1), chunk(b"data", &[0; 4 + 16_000])]);
+        assert_eq!(caf_duration(&pcm), Some(1.0));
+
+        assert_eq!(caf_duration(&caf(&[desc(8_000.0, 0, 0), chunk(b"data", &[0; 8])])), None);
+        assert_eq!(caf_duration(b"RIFF\0\0\0\0WAVE"), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Audio Message.caf");
+        std::fs::write(&path, &opus).unwrap();
+        assert_eq!(audio_duration_secs(&path), Some(34));
+        assert_eq!(audio_duration_secs(&dir.path().join("missing.caf")), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Heuristics for spotting automated senders: verification codes, delivery
+//! updates, bank alerts.
+//!
+//! Nothing here looks at message text; the handle alone decides. Real people
+//! text from phone numbers and personal addresses, while services use short
+//! codes, no-reply addresses, or alphanumeric sender IDs like "AMAZON".
+
+use serde::Serialize;
+
+use crate::models::Conversation;
+
+/// Email local parts that belong to a mailbox nobody reads.
+const NO_REPLY_LOCAL_PARTS: &[&str] = &[
+    "noreply", "no-reply", "no_reply", "donotreply", "do-not-reply", "do_not_reply",
+    "notifications", "notification", "alerts", "alert", "mailer-daemon", "bounce",
+];
+
+/// Longest alphanumeric sender ID carriers accept.
+const MAX_SENDER_ID_CHARS: usize = 11;
+
+/// Why a handle looks automated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomatedKind {
+    /// 5–6 digit SMS short code, e.g. "72975"
+    ShortCode,
+    /// Address like no-reply@example.com
+    NoReplyEmail,
+    /// Named SMS sender, e.g. "AMAZON" or "Uber"
+    SenderId,
+}
+
+/// Classify a single handle, or None if it could be a person.
+pub fn classify_sender(handle: &str) -> Option<AutomatedKind> {
+    let handle = handle.trim();
+    if handle.is_empty() {
+        return None;
+    }
+
+    if let Some((local, _domain)) = handle.split_once('@') {
+        let local = local.to_lowercase();
+        let no_reply = local.starts_with("noreply")
+            || NO_REPLY_LOCAL_PARTS.iter().any(|p| local == *p || local.starts_with(&format!("{}+", p)));
+        return no_reply.then_some(AutomatedKind::NoReplyEmail);
+    }
+
+    let digits: String = handle.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+    if digits.chars().all(|c| c.is_ascii_digit()) {
+        return (5..=6).contains(&digits.len()).then_some(AutomatedKind::ShortCode);
+    }
+
+    // Letters with no '+' can't be a phone number
+    let is_sender_id = !handle.starts_with('+')
+        && handle.chars().count() <= MAX_SENDER_ID_CHARS
+        && handle.chars().any(|c| c.is_alphabetic())
+        && handle.chars().all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '.' | '_' | '&'));
+    is_sender_id.then_some(AutomatedKind::SenderId)
+}
+
+impl Conversation {
+    /// Why this chat looks automated. Group chats never do.
+    pub fn automated_kind(&self) -> Option<AutomatedKind> {
+        if self.is_group() {
+            return None;
+        }
+        classify_sender(&self.chat_identifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_codes() {
+        assert_eq!(classify_sender("72975"), Some(AutomatedKind::ShortCode));
+        assert_eq!(classify_sender("262-966"), Some(AutomatedKind::ShortCode));
+        assert_eq!(classify_sender("911"), None);
+        assert_eq!(classify_sender("+15551234567"), None);
+        assert_eq!(classify_sender("5551234567"), None);
+    }
+
+    #[test]
+    fn test_no_reply_emails() {
+        assert_eq!(classify_sender("no-reply@example.com"), Some(AutomatedKind::NoReplyEmail));
+        assert_eq!(classify_sender("NoReply@bank.com"), Some(AutomatedKind::NoReplyEmail));
+        assert_eq!(classify_sender("noreply-orders@shop.com"), Some(AutomatedKind::NoReplyEmail));
+        assert_eq!(classify_sender("alerts+card@bank.com"), Some(AutomatedKind::NoReplyEmail));
+        assert_eq!(classify_sender("jane.doe@icloud.com"), None);
+        assert_eq!(classify_sender("alertsmith@gmail.com"), None);
+    }
+
+    #[test]
+    fn test_sender_ids() {
+        assert_eq!(classify_sender("AMAZON"), Some(AutomatedKind::SenderId));
+        assert_eq!(classify_sender("Uber"), Some(AutomatedKind::SenderId));
+        assert_eq!(classify_sender("AT&T"), Some(AutomatedKind::SenderId));
+        assert_eq!(classify_sender("ThisNameIsTooLong"), None);
+        assert_eq!(classify_sender(""), None);
+    }
+}
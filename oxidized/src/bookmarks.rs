@@ -0,0 +1,113 @@
+//! Bookmarked messages in long threads, e.g. "the one with the door code".
+//!
+//! Bookmarks point at chat and message GUIDs rather than ROWIDs so they
+//! survive chat.db being rebuilt.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::store::Document;
+
+/// A marked message and why it was marked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub chat_guid: String,
+    pub message_guid: String,
+    pub note: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Every bookmark, saved as JSON, newest last.
+pub struct BookmarkStore {
+    doc: Document,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl BookmarkStore {
+    /// Default bookmarks file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().bookmarks()
+    }
+
+    /// Load bookmarks, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let bookmarks = doc.load().unwrap_or_default();
+        Self { doc, bookmarks }
+    }
+
+    /// Write bookmarks to disk.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.doc.save(&self.bookmarks)
+    }
+
+    /// Bookmark a message at `now`. Bookmarking it again just replaces the note.
+    pub fn add(&mut self, chat_guid: &str, message_guid: &str, note: &str, now: DateTime<Utc>) -> Bookmark {
+        self.bookmarks.retain(|b| b.message_guid != message_guid);
+        let bookmark = Bookmark {
+            chat_guid: chat_guid.to_string(),
+            message_guid: message_guid.to_string(),
+            note: note.trim().to_string(),
+            created_at: now,
+        };
+        self.bookmarks.push(bookmark.clone());
+        bookmark
+    }
+
+    /// Remove a message's bookmark; returns whether it had one.
+    pub fn remove(&mut self, message_guid: &str) -> bool {
+        let before = self.bookmarks.len();
+        self.bookmarks.retain(|b| b.message_guid != message_guid);
+        self.bookmarks.len() != before
+    }
+
+    pub fn get(&self, message_guid: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|b| b.message_guid == message_guid)
+    }
+
+    /// Bookmarks in one chat, or all of them, newest first. A query keeps
+    /// only those whose note contains it, ignoring case.
+    pub fn list(&self, chat_guid: Option<&str>, query: Option<&str>) -> Vec<Bookmark> {
+        let query = query.map(str::to_lowercase);
+        self.bookmarks
+            .iter()
+            .rev()
+            .filter(|b| chat_guid.is_none_or(|g| b.chat_guid == g))
+            .filter(|b| query.as_deref().is_none_or(|q| b.note.to_lowercase().contains(q)))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for BookmarkStore {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_list_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bookmarks.json");
+        let mut store = BookmarkStore::load(Document::file(&path));
+        store.add("chat-a", "msg-1", "door code", Utc::now());
+        store.add("chat-a", "msg-2", "wifi password", Utc::now());
+        store.add("chat-b", "msg-3", "Door code for the cabin", Utc::now());
+        store.add("chat-a", "msg-1", " front door code ", Utc::now());
+        store.save().unwrap();
+
+        let mut store = BookmarkStore::load(Document::file(&path));
+        let notes = |list: Vec<Bookmark>| list.into_iter().map(|b| b.note).collect::<Vec<_>>();
+        assert_eq!(notes(store.list(Some("chat-a"), None)), ["front door code", "wifi password"]);
+        assert_eq!(notes(store.list(None, Some("DOOR"))), ["front door code", "Door code for the cabin"]);
+
+        assert!(store.remove("msg-2"));
+        assert!(!store.remove("msg-2"));
+        assert_eq!(store.get("msg-3").unwrap().chat_guid, "chat-b");
+    }
+}
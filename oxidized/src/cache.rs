@@ -0,0 +1,143 @@
+//! Cache of HEIC attachments converted to JPEG.
+//!
+//! Conversions mirror the attachment layout: `Attachments/ab/cd/IMG.heic` is
+//! cached as `heic/ab/cd/IMG.heic.jpg`, so each one maps back to its source.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::db::Database;
+use crate::paths::AppPaths;
+
+/// Default cache directory, or the override in `AEROMESSAGE_CACHE_DIR`.
+pub fn default_cache_dir() -> PathBuf {
+    AppPaths::default().cache_dir
+}
+
+/// Attachments folder of the user's own Messages library.
+pub fn default_attachments_dir() -> PathBuf {
+    Database::default_path().with_file_name("Attachments")
+}
+
+/// Where the JPEG conversion of an attachment lives.
+/// `relative` is the attachment path under the attachments directory.
+pub fn conversion_path(cache_dir: &Path, relative: &Path) -> PathBuf {
+    let mut name = cache_dir.join("heic").join(relative).into_os_string();
+    name.push(".jpg");
+    PathBuf::from(name)
+}
+
+/// Delete conversions whose source attachment is gone, plus flat-named
+/// conversions left by older versions. Returns the number of files removed.
+///
+/// The cache directory can be one the user chose, so only files named the
+/// way conversions are named are touched.
+pub fn prune_orphaned_conversions(cache_dir: &Path, attachments_dir: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+
+    // Older versions wrote "ab_cd_IMG_heic.jpg" directly in the cache dir
+    if cache_dir.exists() {
+        for entry in fs::read_dir(cache_dir)?.flatten() {
+            let path = entry.path();
+            if path.is_file() && is_legacy_conversion(&path) {
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+    }
+
+    let heic_dir = cache_dir.join("heic");
+    if heic_dir.exists() {
+        removed += prune_dir(&heic_dir, &heic_dir, attachments_dir)?;
+    }
+
+    Ok(removed)
+}
+
+/// Whether `path` is named like an older version's flat conversion: the
+/// source path with `/` and `.` replaced by `_`, plus ".jpg".
+fn is_legacy_conversion(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+    let name = name.to_ascii_lowercase();
+    name.ends_with("_heic.jpg") || name.ends_with("_heif.jpg")
+}
+
+fn prune_dir(dir: &Path, heic_dir: &Path, attachments_dir: &Path) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            removed += prune_dir(&path, heic_dir, attachments_dir)?;
+            continue;
+        }
+        if path.extension().is_none_or(|e| e != "jpg") {
+            continue;
+        }
+
+        let source = path
+            .strip_prefix(heic_dir)
+            .ok()
+            .and_then(|rel| rel.to_str())
+            .and_then(|rel| rel.strip_suffix(".jpg"))
+            .map(|rel| attachments_dir.join(rel));
+
+        if !source.is_some_and(|s| s.exists()) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversion_path() {
+        assert_eq!(
+            conversion_path(Path::new("/cache"), Path::new("ab/cd/IMG_1.heic")),
+            PathBuf::from("/cache/heic/ab/cd/IMG_1.heic.jpg")
+        );
+    }
+
+    #[test]
+    fn test_prune_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        let attachments = dir.path().join("attachments");
+
+        // Live source and its conversion
+        fs::create_dir_all(attachments.join("ab")).unwrap();
+        fs::write(attachments.join("ab/keep.heic"), b"").unwrap();
+        let keep = conversion_path(&cache, Path::new("ab/keep.heic"));
+        fs::create_dir_all(keep.parent().unwrap()).unwrap();
+        fs::write(&keep, b"").unwrap();
+
+        // Conversion whose source was deleted
+        let orphan = conversion_path(&cache, Path::new("ab/gone.heic"));
+        fs::write(&orphan, b"").unwrap();
+
+        // Legacy flat conversion
+        fs::write(cache.join("ab_old_HEIC.jpg"), b"").unwrap();
+
+        // Someone else's files in a cache folder they picked
+        fs::write(cache.join("holiday.jpg"), b"").unwrap();
+        fs::write(cache.join("heic/notes.txt"), b"").unwrap();
+
+        assert_eq!(prune_orphaned_conversions(&cache, &attachments).unwrap(), 2);
+        assert!(keep.exists());
+        assert!(!orphan.exists());
+        assert!(cache.join("holiday.jpg").exists());
+        assert!(cache.join("heic/notes.txt").exists());
+    }
+
+    #[test]
+    fn test_prune_missing_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(prune_orphaned_conversions(&dir.path().join("none"), dir.path()).unwrap(), 0);
+    }
+}
@@ -0,0 +1,277 @@
+//! CardDAV contact sync (behind the `carddav` feature).
+//!
+//! Fetches every vCard in one address book with a single REPORT request and
+//! feeds names, phones, and emails into `ContactResolver`. The password lives
+//! in the macOS Keychain, never in settings.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use thiserror::Error;
+
+use crate::contacts::ContactResolver;
+use crate::settings::CardDavConfig;
+
+/// Keychain service name for CardDAV passwords.
+const KEYCHAIN_SERVICE: &str = "Aeromessage CardDAV";
+
+const ADDRESSBOOK_QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<card:addressbook-query xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+  <d:prop>
+    <d:getetag/>
+    <card:address-data/>
+  </d:prop>
+</card:addressbook-query>"#;
+
+#[derive(Error, Debug)]
+pub enum CardDavError {
+    #[error("Keychain error: {0}")]
+    Keychain(#[from] keyring::Error),
+    #[error("CardDAV request failed: {0}")]
+    Http(String),
+    #[error("CardDAV response unreadable: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Names and handles from one vCard.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CardContact {
+    pub name: String,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+}
+
+/// Save the CardDAV password for a user in the Keychain.
+pub fn store_password(username: &str, password: &str) -> Result<(), CardDavError> {
+    keyring::Entry::new(KEYCHAIN_SERVICE, username)?.set_password(password)?;
+    Ok(())
+}
+
+/// Download every contact in the configured address book.
+pub fn fetch_contacts(config: &CardDavConfig) -> Result<Vec<CardContact>, CardDavError> {
+    let password = keyring::Entry::new(KEYCHAIN_SERVICE, &config.username)?.get_password()?;
+    let credentials = STANDARD.encode(format!("{}:{}", config.username, password));
+
+    let body = ureq::request("REPORT", &config.url)
+        .set("Depth", "1")
+        .set("Content-Type", "application/xml; charset=utf-8")
+        .set("Authorization", &format!("Basic {}", credentials))
+        .send_string(ADDRESSBOOK_QUERY)
+        .map_err(|e| CardDavError::Http(e.to_string()))?
+        .into_string()?;
+
+    Ok(extract_address_data(&body)
+        .iter()
+        .flat_map(|data| parse_vcards(data))
+        .collect())
+}
+
+/// Fetch contacts and add them to the resolver. Returns handles added.
+pub fn sync_contacts(resolver: &mut ContactResolver, config: &CardDavConfig) -> Result<usize, CardDavError> {
+    let contacts = fetch_contacts(config)?;
+    Ok(add_contacts(resolver, &contacts))
+}
+
+/// Add fetched contacts to the resolver. Returns handles added.
+pub fn add_contacts(resolver: &mut ContactResolver, contacts: &[CardContact]) -> usize {
+    let mut count = 0;
+    for contact in contacts.iter().filter(|c| !c.name.is_empty()) {
+        for phone in &contact.phones {
+            resolver.add_phone(phone, &contact.name);
+            count += 1;
+        }
+        for email in &contact.emails {
+            resolver.add_email(email, &contact.name);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Pull the contents of every `address-data` element out of a multistatus
+/// response, whatever namespace prefix the server uses.
+fn extract_address_data(xml: &str) -> Vec<String> {
+    let mut cards = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find("address-data") {
+        let before = &rest[..start];
+        let after = &rest[start..];
+        let Some(gt) = after.find('>') else { break };
+
+        // Only opening tags like "<card:address-data ...>" hold content
+        let is_open = before
+            .rfind('<')
+            .map(|i| &before[i..])
+            .is_some_and(|tag| !tag.starts_with("</") && !tag.contains(char::is_whitespace))
+            && !after[..gt].ends_with('/');
+
+        rest = &after[gt + 1..];
+        if !is_open {
+            continue;
+        }
+
+        let content = rest.find("</").map_or(rest, |end| &rest[..end]);
+        let content = content
+            .trim()
+            .strip_prefix("<![CDATA[")
+            .and_then(|c| c.strip_suffix("]]>"))
+            .map(str::to_string)
+            .unwrap_or_else(|| unescape_xml(content));
+        cards.push(content);
+    }
+
+    cards
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#13;", "\r")
+        .replace("&#10;", "\n")
+        .replace("&amp;", "&")
+}
+
+/// Parse one or more vCards (3.0 or 4.0).
+pub fn parse_vcards(text: &str) -> Vec<CardContact> {
+    // Unfold continuation lines (RFC 6350 §3.2)
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        if let Some(cont) = line.strip_prefix([' ', '\t']) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(cont);
+                continue;
+            }
+        }
+        lines.push(line.trim_end_matches('\r').to_string());
+    }
+
+    let mut contacts = Vec::new();
+    let mut current = CardContact::default();
+    let mut structured_name = String::new();
+
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        // "item1.TEL;TYPE=cell" -> "TEL"
+        let property = key.split(';').next().unwrap_or("");
+        let property = property.rsplit('.').next().unwrap_or("").to_uppercase();
+
+        match property.as_str() {
+            "BEGIN" => {
+                current = CardContact::default();
+                structured_name.clear();
+            }
+            "END" => {
+                if current.name.is_empty() {
+                    current.name = structured_name.clone();
+                }
+                contacts.push(std::mem::take(&mut current));
+            }
+            "FN" => current.name = unescape_vcard(value).trim().to_string(),
+            "N" => {
+                // N:Family;Given;Middle;Prefix;Suffix
+                let parts: Vec<String> = value.split(';').map(unescape_vcard).collect();
+                let given = parts.get(1).map(String::as_str).unwrap_or("");
+                let family = parts.first().map(String::as_str).unwrap_or("");
+                structured_name = format!("{} {}", given, family).trim().to_string();
+            }
+            "TEL" => {
+                let phone = value.trim().trim_start_matches("tel:");
+                if !phone.is_empty() {
+                    current.phones.push(phone.to_string());
+                }
+            }
+            "EMAIL" => {
+                let email = value.trim().trim_start_matches("mailto:");
+                if !email.is_empty() {
+                    current.emails.push(email.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    contacts
+}
+
+fn unescape_vcard(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push(' '),
+                Some(other) => out.push(other),
+                None => {}
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VCARDS: &str = "BEGIN:VCARD\r\n\
+VERSION:3.0\r\n\
+FN:Jane Doe\r\n\
+N:Doe;Jane;;;\r\n\
+item1.TEL;type=CELL:+1 (555) 123-4567\r\n\
+EMAIL;TYPE=INTERNET:jane@example.com\r\n\
+END:VCARD\r\n\
+BEGIN:VCARD\r\n\
+VERSION:4.0\r\n\
+N:Smith;Bob;;;\r\n\
+TEL;VALUE=uri:tel:+15557654321\r\n\
+END:VCARD\r\n";
+
+    #[test]
+    fn test_parse_vcards() {
+        let contacts = parse_vcards(VCARDS);
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].name, "Jane Doe");
+        assert_eq!(contacts[0].phones, vec!["+1 (555) 123-4567"]);
+        assert_eq!(contacts[0].emails, vec!["jane@example.com"]);
+        assert_eq!(contacts[1].name, "Bob Smith");
+        assert_eq!(contacts[1].phones, vec!["+15557654321"]);
+    }
+
+    #[test]
+    fn test_parse_folded_and_escaped() {
+        let card = "BEGIN:VCARD\nFN:Acme\\, Inc\n  Support\nEND:VCARD\n";
+        assert_eq!(parse_vcards(card)[0].name, "Acme, Inc Support");
+    }
+
+    #[test]
+    fn test_extract_address_data() {
+        let xml = r#"<d:multistatus xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+<d:response><d:propstat><d:prop>
+<card:address-data>BEGIN:VCARD&#13;
+FN:A &amp; B&#13;
+END:VCARD</card:address-data>
+</d:prop></d:propstat></d:response>
+<d:response><d:propstat><d:prop>
+<C:address-data xmlns:C="urn:ietf:params:xml:ns:carddav"><![CDATA[BEGIN:VCARD
+FN:C
+END:VCARD]]></C:address-data>
+</d:prop></d:propstat></d:response>
+</d:multistatus>"#;
+        let cards = extract_address_data(xml);
+        assert_eq!(cards.len(), 2);
+        assert!(cards[0].contains("FN:A & B"));
+        assert_eq!(parse_vcards(&cards[1])[0].name, "C");
+    }
+
+    #[test]
+    fn test_add_contacts() {
+        let mut resolver = ContactResolver::new();
+        let added = add_contacts(&mut resolver, &parse_vcards(VCARDS));
+        assert_eq!(added, 3);
+        assert_eq!(resolver.resolve("+15551234567"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("+15557654321"), Some("Bob Smith"));
+    }
+}
@@ -0,0 +1,290 @@
+//! Aeromessage - command-line triage without the app window.
+//!
+//! Reads the same chat.db, contacts, settings and state files as the app,
+//! so chats marked read, snoozed or ignored there stay out of the list
+//! here. State files are only read: the running app holds its own copies
+//! and would write over changes made here.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+use aeromessage::{
+    addressbook_sources_dir, load_address_books, mark_as_read, mark_read_via_messages, resolve_sender_names, AppPaths, ContactResolver, Conversation,
+    Database, Document, GuardReason, IgnoreList, JsonFileStore, MuteList, ReadOverlay, ReadStrategy, SendHistory, SendRecord, Settings, SnoozeList,
+    StateStore, DUPLICATE_WINDOW_SECS, open_state_store, send_message_with_attachments,
+};
+use chrono::{Local, Utc};
+
+const USAGE: &str = "Usage: aeromessage-cli <command>
+
+Commands:
+  list [--json]                      Unread chats, oldest waiting first
+  show <chat> [--json]               Recent messages in an unread chat
+  reply <chat> <text> [--yes]        Send a reply now
+  send-all --from-file <file> [--yes]
+                                     Send replies from a JSON object of chat to text
+
+<chat> is a chat ID from `list`, a phone number, email or group ID.
+Replies the app's send guards would hold are refused unless --yes is given.";
+
+/// Messages printed by `show`.
+const SHOW_MESSAGES: usize = 20;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["list"] => list(false),
+        ["list", "--json"] => list(true),
+        ["show", chat] => show(chat, false),
+        ["show", chat, "--json"] => show(chat, true),
+        ["reply", chat, text] => reply(chat, text, false),
+        ["reply", chat, text, "--yes"] => reply(chat, text, true),
+        ["send-all", "--from-file", path] => send_all(Path::new(path), false),
+        ["send-all", "--from-file", path, "--yes"] => send_all(Path::new(path), true),
+        ["help" | "--help" | "-h"] => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("aeromessage: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Names from the address books, or none if they can't be read, with the
+/// people file's names on top.
+fn contacts(settings: &Settings) -> Result<ContactResolver, String> {
+    let contacts = Mutex::new(ContactResolver::new());
+    if let Err(e) = addressbook_sources_dir().and_then(|dir| load_address_books(&dir, &contacts, |_| {})) {
+        eprintln!("Contacts unavailable, showing handles: {}", e);
+    }
+    let mut contacts = contacts.into_inner().map_err(|e| e.to_string())?;
+    contacts.set_email_matching(settings.email_matching);
+    let people = settings.people_file.clone().unwrap_or_else(|| AppPaths::from_env().people());
+    if settings.people_file.is_some() || people.exists() {
+        if let Err(e) = contacts.load_overrides(&people) {
+            eprintln!("People file not loaded: {}", e);
+        }
+    }
+    Ok(contacts)
+}
+
+/// The state store settings pick, or the JSON files if it won't open.
+fn state_store(paths: &AppPaths, settings: &Settings) -> Arc<dyn StateStore> {
+    open_state_store(settings.state_store, paths).unwrap_or_else(|e| {
+        eprintln!("State database unavailable, using the JSON files: {}", e);
+        Arc::new(JsonFileStore::new(paths.data_dir.clone()))
+    })
+}
+
+/// chat.db, or the app's snapshot of it when it reads from one. Sends
+/// always check chat.db itself.
+fn read_path(paths: &AppPaths, settings: &Settings) -> PathBuf {
+    let snapshot = paths.chat_snapshot();
+    if settings.read_from_snapshot && snapshot.exists() { snapshot } else { Database::default_path() }
+}
+
+/// The unread queue in `path` as the app would show it.
+fn queue(paths: &AppPaths, settings: &Settings, path: &PathBuf, contacts: &ContactResolver) -> Result<Vec<Conversation>, String> {
+    let mut db = Database::open(path).map_err(|e| e.to_string())?;
+    db.set_group_name_style(settings.group_name_style);
+    db.set_include_system_events(settings.group_events);
+    let mut convs = db.unread_conversations(Some(contacts)).map_err(|e| e.to_string())?;
+
+    let store = state_store(paths, settings);
+    let doc = |path: PathBuf| Document::for_file(&store, &path);
+    let overlay = ReadOverlay::load(doc(paths.read_overlay()));
+    let snoozed = SnoozeList::load(doc(paths.snoozed()));
+    let ignored = IgnoreList::load(doc(paths.ignored()));
+    let muted = MuteList::load(doc(paths.muted()));
+    let now = Utc::now();
+    convs.retain(|c| {
+        !overlay.is_read(c) && !snoozed.is_snoozed(c, now) && !ignored.is_ignored(&c.chat_identifier) && !muted.is_hidden(c)
+    });
+    if settings.ignore_automated {
+        convs.retain(|c| c.automated_kind().is_none());
+    }
+    if settings.auto_expire_otp {
+        convs.retain(|c| !c.otp_expired(now));
+    }
+    convs.sort_by_key(|c| c.first_unread_date);
+    Ok(convs)
+}
+
+/// Find `chat` in the queue by chat ID or identifier.
+fn find<'a>(convs: &'a [Conversation], chat: &str) -> Result<&'a Conversation, String> {
+    let chat_id = chat.parse::<i64>().ok();
+    convs
+        .iter()
+        .find(|c| Some(c.chat_id) == chat_id)
+        .or_else(|| convs.iter().find(|c| c.chat_identifier == chat))
+        .ok_or_else(|| format!("{} is not in the unread queue", chat))
+}
+
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), String> {
+    println!("{}", serde_json::to_string_pretty(value).map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+fn list(json: bool) -> Result<(), String> {
+    let paths = AppPaths::from_env();
+    let settings = Settings::load(&paths.settings());
+    let convs = queue(&paths, &settings, &read_path(&paths, &settings), &contacts(&settings)?)?;
+    if json {
+        return print_json(&convs);
+    }
+    for conv in &convs {
+        let waiting = conv.first_unread_date.with_timezone(&Local).format("%b %-d %H:%M");
+        let reason = conv.needs_reply_reason.as_deref().map(|r| format!("  ({})", r)).unwrap_or_default();
+        println!("{:>6}  {:<30}  {:>3} unread since {}{}", conv.chat_id, conv.name(), conv.unread_count, waiting, reason);
+    }
+    Ok(())
+}
+
+fn show(chat: &str, json: bool) -> Result<(), String> {
+    let paths = AppPaths::from_env();
+    let settings = Settings::load(&paths.settings());
+    let contacts = contacts(&settings)?;
+    let path = read_path(&paths, &settings);
+    let convs = queue(&paths, &settings, &path, &contacts)?;
+    let conv = find(&convs, chat)?;
+    let db = Database::open(&path).map_err(|e| e.to_string())?;
+    let mut messages = db.messages_for_chat(conv.chat_id, None, SHOW_MESSAGES).map_err(|e| e.to_string())?;
+    messages.reverse();
+    resolve_sender_names(&mut messages, &conv.participants, &contacts);
+    if json {
+        return print_json(&messages);
+    }
+
+    println!("{} ({})", conv.name(), conv.chat_identifier);
+    for message in &messages {
+        let sender = if message.is_from_me {
+            "Me".to_string()
+        } else if conv.is_group() {
+            message.sender_label()
+        } else {
+            conv.name().to_string()
+        };
+        let mut text = message.display_text();
+        if message.retracted {
+            text = "[unsent]".to_string();
+        } else if message.edited {
+            text.push_str(" (edited)");
+        }
+        if let Some(audio) = message.audio_label() {
+            text = format!("{} [{}]", text, audio).trim_start().to_string();
+        } else if let (true, Some(link)) = (text.is_empty(), &message.link_preview) {
+            text = link.label();
+        } else if !message.attachments.is_empty() {
+            text = format!("{} [{} attachment(s)]", text, message.attachments.len()).trim_start().to_string();
+        }
+        println!("{}  {}: {}", message.date.with_timezone(&Local).format("%b %-d %H:%M"), sender, text);
+    }
+    Ok(())
+}
+
+fn reply(chat: &str, text: &str, confirmed: bool) -> Result<(), String> {
+    let paths = AppPaths::from_env();
+    let settings = Settings::load(&paths.settings());
+    let contacts = contacts(&settings)?;
+    let convs = queue(&paths, &settings, &Database::default_path(), &contacts)?;
+    let conv = find(&convs, chat)?;
+    send(&paths, &settings, &contacts, conv, text, confirmed)?;
+    println!("Sent to {}", conv.name());
+    Ok(())
+}
+
+/// Send each reply in a JSON object like `{"42": "on my way", "+15551234567": "yes"}`,
+/// in key order. Keeps going past failures and reports them at the end.
+fn send_all(path: &Path, confirmed: bool) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+    let replies: BTreeMap<String, String> =
+        serde_json::from_str(&contents).map_err(|e| format!("{} isn't a JSON object of chat to text: {}", path.display(), e))?;
+
+    let paths = AppPaths::from_env();
+    let settings = Settings::load(&paths.settings());
+    let contacts = contacts(&settings)?;
+    let convs = queue(&paths, &settings, &Database::default_path(), &contacts)?;
+    let mut failures = 0;
+    for (chat, text) in &replies {
+        let outcome = find(&convs, chat)
+            .and_then(|conv| send(&paths, &settings, &contacts, conv, text, confirmed).map(|()| conv.name().to_string()));
+        match outcome {
+            Ok(name) => println!("Sent to {}", name),
+            Err(e) => {
+                eprintln!("{}: {}", chat, e);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(format!("{} of {} replies failed", failures, replies.len()));
+    }
+    Ok(())
+}
+
+/// What a send guard objects to, as the app words it.
+fn guard_text(reason: &GuardReason) -> String {
+    match reason {
+        GuardReason::LargeGroup { participants, limit } => format!("group of {} (limit {})", participants, limit),
+        GuardReason::UnknownSender => "not in contacts".to_string(),
+        GuardReason::Keyword { keyword } => format!("mentions \"{}\"", keyword),
+        GuardReason::TooLong { length, limit } => format!("{} characters (limit {})", length, limit),
+        GuardReason::MmsGroup => "SMS group: goes out as MMS, without reactions or read receipts".to_string(),
+        GuardReason::Restored => "queued before the app last quit".to_string(),
+        GuardReason::Interrupted => "was sending when the app last quit, so it may already have gone out".to_string(),
+    }
+}
+
+/// Send one reply, log it to the send history, and mark the chat read the
+/// way the app's read strategy says. The same text sent moments ago is
+/// skipped, and replies the send guards would hold need `confirmed`.
+fn send(paths: &AppPaths, settings: &Settings, contacts: &ContactResolver, conv: &Conversation, text: &str, confirmed: bool) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("No text provided".to_string());
+    }
+    let store = state_store(paths, settings);
+    let history = SendHistory::open(store.clone(), &paths.send_history()).unwrap_or_else(|e| {
+        eprintln!("Send history from before this version wasn't copied: {}", e);
+        SendHistory::new(store)
+    });
+    let window = chrono::Duration::seconds(DUPLICATE_WINDOW_SECS);
+    if history.recent_duplicate(&conv.guid, text, window, Utc::now()).map_err(|e| e.to_string())?.is_some() {
+        return Err("Already sent moments ago".to_string());
+    }
+    if !confirmed {
+        let known = contacts.resolve(&conv.chat_identifier).is_some();
+        let reasons = settings.send_guards.check(conv, text, known);
+        if !reasons.is_empty() {
+            let reasons: Vec<String> = reasons.iter().map(guard_text).collect();
+            return Err(format!("needs a second look ({}); pass --yes to send anyway", reasons.join("; ")));
+        }
+    }
+
+    let outcome = send_message_with_attachments(&conv.chat_identifier, text, conv.is_group(), &[]);
+    let error = outcome.as_ref().err().map(|e| e.to_string());
+    let record = SendRecord::new(conv.chat_id, &conv.guid, &conv.chat_identifier, text, settings.log_full_text, error, Utc::now());
+    if let Err(e) = history.append(&record) {
+        eprintln!("Failed to record send: {}", e);
+    }
+    outcome.map_err(|e| e.to_string())?;
+
+    match settings.read_strategy {
+        ReadStrategy::Database => mark_as_read(&conv.chat_identifier).map(|_| ()).map_err(|e| e.to_string()),
+        ReadStrategy::MessagesApp => mark_read_via_messages(&conv.messages_url()).map_err(|e| e.to_string()),
+        // The local overlay is the app's to write, so the chat stays
+        // listed until it's marked read there
+        ReadStrategy::LocalOnly => Ok(()),
+    }
+}
@@ -0,0 +1,81 @@
+//! Where the library gets the time from.
+//!
+//! Code that ages, snoozes or schedules takes a [`Clock`] instead of calling
+//! `Utc::now()` itself, so tests can pin the time and step it forward. All
+//! times are UTC; local time only comes in when formatting or reading what
+//! the user typed, which keeps daylight saving changes out of the arithmetic.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+/// A source of the current time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Wait `duration`, e.g. between background polls.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// The real time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to. Sleeping advances it instantly.
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        *now += duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(chrono::Duration::from_std(duration).unwrap_or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_clock() {
+        let start = DateTime::parse_from_rfc3339("2024-03-10T06:30:00Z").unwrap().with_timezone(&Utc);
+        let clock = FixedClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        // Across the US spring-forward: an hour of sleep is an hour later in UTC
+        clock.sleep(Duration::from_secs(3600));
+        assert_eq!(clock.now(), start + chrono::Duration::hours(1));
+        clock.set(start);
+        clock.advance(chrono::Duration::minutes(5));
+        assert_eq!(clock.now(), start + chrono::Duration::minutes(5));
+    }
+}
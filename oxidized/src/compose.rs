@@ -0,0 +1,122 @@
+//! Mail-merge: one template, personalised for a list of recipients.
+//!
+//! Recipients with an unread 1:1 chat get an ordinary committed reply in
+//! that chat. Everyone else gets a composed message sent straight to their
+//! handle, which starts a chat if there isn't one.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::contacts::{format_display, same_handle, ContactResolver};
+use crate::models::Conversation;
+use crate::templates::{AppliedTemplate, Template, TemplateError};
+
+/// A message to a handle with no chat in the queue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposedMessage {
+    pub recipient: String,
+    /// Contact name, or the formatted handle
+    pub name: String,
+    pub text: String,
+    pub attachments: Vec<PathBuf>,
+}
+
+/// A template filled in for every recipient in a batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BatchCompose {
+    /// Replies for chats already in the queue, by chat GUID
+    pub replies: Vec<(String, AppliedTemplate)>,
+    /// Messages that go straight to a handle
+    pub composed: Vec<ComposedMessage>,
+    /// Chats left alone because something was already typed for them, by
+    /// chat GUID
+    pub skipped: Vec<String>,
+}
+
+/// Fill `template` in for each recipient handle. Every attachment slot is
+/// checked before anything is returned, so a bad template queues nothing.
+/// Blank and repeated recipients are skipped.
+pub fn compose_batch(
+    template: &Template,
+    recipients: &[String],
+    unread: &[Conversation],
+    resolver: &ContactResolver,
+    assets_dir: &Path,
+) -> Result<BatchCompose, TemplateError> {
+    let mut batch = BatchCompose::default();
+    let mut seen: Vec<&str> = Vec::new();
+
+    for recipient in recipients.iter().map(|r| r.trim()).filter(|r| !r.is_empty()) {
+        if seen.iter().any(|s| same_handle(s, recipient)) {
+            continue;
+        }
+        seen.push(recipient);
+
+        let chat = unread
+            .iter()
+            .find(|c| !c.is_group() && same_handle(&c.chat_identifier, recipient));
+        match chat {
+            Some(conv) => batch.replies.push((conv.guid.clone(), template.apply(conv, assets_dir)?)),
+            None => {
+                let name = resolver
+                    .resolve(recipient)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format_display(recipient));
+                let applied = template.apply_to(&name, false, assets_dir)?;
+                batch.composed.push(ComposedMessage {
+                    recipient: recipient.to_string(),
+                    name,
+                    text: applied.text,
+                    attachments: applied.attachments,
+                });
+            }
+        }
+    }
+
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::conversation;
+
+    fn conv(chat_id: i64, identifier: &str, name: &str) -> Conversation {
+        Conversation { chat_id, resolved_name: Some(name.into()), ..conversation(identifier) }
+    }
+
+    fn greeting() -> Template {
+        Template { name: "Holiday".into(), text: "Happy holidays, {first_name}!".into(), attachments: vec![] }
+    }
+
+    #[test]
+    fn test_splits_existing_and_new_chats() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut resolver = ContactResolver::new();
+        resolver.add("+15550000002", "Bob Jones");
+        let unread = [conv(7, "+15550000001", "Alice Smith")];
+
+        let recipients = ["+1 (555) 000-0001", "+15550000002", "5550000002", " ", "carol@example.com"]
+            .map(String::from);
+        let batch = compose_batch(&greeting(), &recipients, &unread, &resolver, dir.path()).unwrap();
+
+        assert_eq!(batch.replies.len(), 1);
+        assert_eq!(batch.replies[0].0, "iMessage;-;+15550000001");
+        assert_eq!(batch.replies[0].1.text, "Happy holidays, Alice!");
+
+        let composed: Vec<_> = batch.composed.iter().map(|c| (c.recipient.as_str(), c.text.as_str())).collect();
+        assert_eq!(composed, [
+            ("+15550000002", "Happy holidays, Bob!"),
+            ("carol@example.com", "Happy holidays, carol@example.com!"),
+        ]);
+    }
+
+    #[test]
+    fn test_missing_asset_queues_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = Template { attachments: vec!["card.jpg".into()], ..greeting() };
+        let result = compose_batch(&template, &["+15550000002".into()], &[], &ContactResolver::new(), dir.path());
+        assert!(matches!(result, Err(TemplateError::MissingAttachment(_))));
+    }
+}
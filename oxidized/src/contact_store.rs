@@ -0,0 +1,245 @@
+//! Contacts read through the Contacts framework instead of the AddressBook
+//! database.
+//!
+//! The AddressBook-v22.abcddb schema is undocumented and shifts between
+//! macOS versions, and some Exchange and CardDAV accounts never show up in
+//! it. `CNContactStore` sees every account the Contacts app does. It's
+//! reached through a JavaScript for Automation script run by `osascript`,
+//! which prints each contact as JSON, so no Objective-C bindings are
+//! needed. The first run asks for Contacts access. When the script can't
+//! run or access is denied, the AddressBook database is read as before.
+//! Contact photos still come from the database either way.
+//!
+//! The script hasn't been run on a Mac yet, so it's only built with the
+//! `contacts-framework` feature, off by default. Without it, choosing this
+//! backend reads the AddressBook database and reports why.
+
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(feature = "contacts-framework")]
+use std::process::Command;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::contacts::{load_address_book_avatars, load_address_books, ContactResolver, ContactsProgress};
+
+/// Where contacts are read from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactsBackend {
+    /// The AddressBook SQLite databases
+    #[default]
+    AddressBook,
+    /// `CNContactStore`, falling back to the AddressBook databases
+    ContactsFramework,
+}
+
+/// Prints every contact in every container as JSON, or fails if Contacts
+/// access isn't granted. Asks for access if it hasn't been asked yet; the
+/// answer comes back on a callback, so the run loop turns until it does
+/// or a minute passes.
+#[cfg(feature = "contacts-framework")]
+const CONTACTS_SCRIPT: &str = r#"
+ObjC.import('Contacts');
+const status = () => $.CNContactStore.authorizationStatusForEntityType($.CNEntityTypeContacts);
+const store = $.CNContactStore.alloc.init;
+if (status() === $.CNAuthorizationStatusNotDetermined) {
+    store.requestAccessForEntityTypeCompletionHandler($.CNEntityTypeContacts, () => {});
+    for (let i = 0; i < 600 && status() === $.CNAuthorizationStatusNotDetermined; i++) {
+        $.NSRunLoop.currentRunLoop.runUntilDate($.NSDate.dateWithTimeIntervalSinceNow(0.1));
+    }
+}
+if (status() !== $.CNAuthorizationStatusAuthorized) {
+    throw new Error('Contacts access not granted');
+}
+const keys = $([$.CNContactGivenNameKey, $.CNContactFamilyNameKey, $.CNContactOrganizationNameKey,
+    $.CNContactPhoneNumbersKey, $.CNContactEmailAddressesKey]);
+const containers = ObjC.unwrap(store.containersMatchingPredicateError(null, null)) || [];
+const contacts = [];
+for (const container of containers) {
+    const predicate = $.CNContact.predicateForContactsInContainerWithIdentifier(container.identifier);
+    const found = ObjC.unwrap(store.unifiedContactsMatchingPredicateKeysToFetchError(predicate, keys, null)) || [];
+    for (const c of found) {
+        contacts.push({
+            first: ObjC.unwrap(c.givenName),
+            last: ObjC.unwrap(c.familyName),
+            organization: ObjC.unwrap(c.organizationName),
+            phones: ObjC.unwrap(c.phoneNumbers).map(p => ObjC.unwrap(p.value.stringValue)),
+            emails: ObjC.unwrap(c.emailAddresses).map(e => ObjC.unwrap(e.value)),
+        });
+    }
+}
+JSON.stringify(contacts);
+"#;
+
+/// One contact as the script prints it.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+struct StoreContact {
+    first: String,
+    last: String,
+    organization: String,
+    phones: Vec<String>,
+    emails: Vec<String>,
+}
+
+impl StoreContact {
+    /// "First Last", or the organization for a company card.
+    fn name(&self) -> String {
+        let name = format!("{} {}", self.first.trim(), self.last.trim());
+        let name = name.trim();
+        if name.is_empty() { self.organization.trim().to_string() } else { name.to_string() }
+    }
+}
+
+/// Run the script and return its JSON.
+#[cfg(feature = "contacts-framework")]
+fn fetch_contact_store() -> Result<String, String> {
+    let output = Command::new("osascript")
+        .args(["-l", "JavaScript", "-e", CONTACTS_SCRIPT])
+        .output()
+        .map_err(|e| format!("Cannot run osascript: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(not(feature = "contacts-framework"))]
+fn fetch_contact_store() -> Result<String, String> {
+    Err("Built without the contacts-framework feature".to_string())
+}
+
+/// Names by identifier from the script's JSON. Returns handles read.
+fn parse_contact_store(json: &str) -> Result<(HashMap<String, String>, usize), String> {
+    let contacts: Vec<StoreContact> = serde_json::from_str(json.trim()).map_err(|e| format!("Unreadable contacts: {}", e))?;
+    let mut source = ContactResolver::new();
+    let mut count = 0;
+    for contact in &contacts {
+        let name = contact.name();
+        if name.is_empty() {
+            continue;
+        }
+        for phone in contact.phones.iter().filter(|p| !p.trim().is_empty()) {
+            source.add_phone(phone, &name);
+            count += 1;
+        }
+        for email in contact.emails.iter().filter(|e| !e.trim().is_empty()) {
+            source.add_email(email, &name);
+            count += 1;
+        }
+    }
+    Ok((source.into_entries(), count))
+}
+
+/// How a contacts load went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactsLoaded {
+    /// Phone numbers and emails with a name
+    pub count: usize,
+    /// Why the Contacts framework was asked for but the AddressBook read
+    /// instead
+    pub fallback: Option<String>,
+}
+
+/// Load contacts into `resolver` from `backend`. The Contacts framework
+/// is read in one go, with photos from the AddressBook databases under
+/// `sources_dir`; if that fails, those databases are read source by
+/// source as [`load_address_books`] does.
+pub fn load_contacts(
+    backend: ContactsBackend,
+    sources_dir: &Path,
+    resolver: &Mutex<ContactResolver>,
+    on_progress: impl FnMut(ContactsProgress),
+) -> Result<ContactsLoaded, String> {
+    load_contacts_with(backend, sources_dir, resolver, on_progress, fetch_contact_store)
+}
+
+fn load_contacts_with(
+    backend: ContactsBackend,
+    sources_dir: &Path,
+    resolver: &Mutex<ContactResolver>,
+    mut on_progress: impl FnMut(ContactsProgress),
+    fetch: impl FnOnce() -> Result<String, String>,
+) -> Result<ContactsLoaded, String> {
+    // The sample library only has an AddressBook database
+    if backend == ContactsBackend::ContactsFramework && !cfg!(feature = "dev-sample") {
+        match fetch().and_then(|json| parse_contact_store(&json)) {
+            Ok((entries, count)) => {
+                resolver.lock().map_err(|e| e.to_string())?.replace_address_book(entries);
+                // Names don't depend on photos, so a missing AddressBook
+                // just means none
+                let _ = load_address_book_avatars(sources_dir, resolver);
+                on_progress(ContactsProgress { sources_loaded: 1, sources_total: 1, contacts: count });
+                return Ok(ContactsLoaded { count, fallback: None });
+            }
+            Err(e) => {
+                let count = load_address_books(sources_dir, resolver, on_progress)?;
+                return Ok(ContactsLoaded { count, fallback: Some(e) });
+            }
+        }
+    }
+    let count = load_address_books(sources_dir, resolver, on_progress)?;
+    Ok(ContactsLoaded { count, fallback: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_contact_store() {
+        let json = r#"[
+            {"first": "Jane", "last": "Doe", "organization": "", "phones": ["(415) 867-5309"], "emails": ["Jane@Example.com"]},
+            {"first": "", "last": "", "organization": "Acme", "phones": ["+14155550100", " "], "emails": []},
+            {"first": "", "last": "", "organization": "", "phones": ["+14155550199"], "emails": []}
+        ]"#;
+        let (entries, count) = parse_contact_store(json).unwrap();
+        assert_eq!(count, 3);
+        let mut resolver = ContactResolver::new();
+        resolver.replace_address_book(entries);
+        assert_eq!(resolver.resolve("+14158675309"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("jane@example.com"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("+14155550100"), Some("Acme"));
+        assert_eq!(resolver.resolve("+14155550199"), None);
+        assert!(parse_contact_store("execution error: not authorized").is_err());
+    }
+
+    #[test]
+    fn test_falls_back_to_address_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = Mutex::new(ContactResolver::new());
+        let json = r#"[{"first": "Jane", "last": "Doe", "phones": ["+14158675309"]}]"#;
+
+        // A source with a photo but no names
+        let source = dir.path().join("a");
+        std::fs::create_dir_all(&source).unwrap();
+        let conn = rusqlite::Connection::open(source.join("AddressBook-v22.abcddb")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT, ZTHUMBNAILIMAGEDATA BLOB);
+             CREATE TABLE ZABCDPHONENUMBER (ZOWNER INTEGER, ZFULLNUMBER TEXT);
+             CREATE TABLE ZABCDEMAILADDRESS (ZOWNER INTEGER, ZADDRESSNORMALIZED TEXT);
+             INSERT INTO ZABCDRECORD VALUES (1, NULL, NULL, X'01FFD8');
+             INSERT INTO ZABCDPHONENUMBER VALUES (1, '+14158675309');"
+        ).unwrap();
+        drop(conn);
+
+        let loaded = load_contacts_with(ContactsBackend::ContactsFramework, dir.path(), &resolver, |_| {}, || Ok(json.into()));
+        if cfg!(feature = "dev-sample") {
+            assert_eq!(loaded, Ok(ContactsLoaded { count: 0, fallback: None }));
+        } else {
+            assert_eq!(loaded, Ok(ContactsLoaded { count: 1, fallback: None }));
+            assert_eq!(resolver.lock().unwrap().resolve("+14158675309"), Some("Jane Doe"));
+        }
+        assert_eq!(resolver.lock().unwrap().avatar("+14158675309"), Some(vec![0xFF, 0xD8]));
+        std::fs::remove_dir_all(&source).unwrap();
+
+        // A denied script reads the (here empty) AddressBook instead
+        let denied = load_contacts_with(ContactsBackend::ContactsFramework, dir.path(), &resolver, |_| {}, || Err("not authorized".into()));
+        let fallback = (!cfg!(feature = "dev-sample")).then(|| "not authorized".to_string());
+        assert_eq!(denied, Ok(ContactsLoaded { count: 0, fallback }));
+        let missing = load_contacts_with(ContactsBackend::AddressBook, &dir.path().join("missing"), &resolver, |_| {}, || unreachable!());
+        assert!(missing.is_err());
+    }
+}
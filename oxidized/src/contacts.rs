@@ -1,24 +1,128 @@
 //! Contact name resolution.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::HandleActivity;
 
 /// Resolves phone numbers and emails to contact names.
 pub struct ContactResolver {
     cache: HashMap<String, String>,
+    /// Identifiers that came from the AddressBook, replaced on reload
+    address_book_keys: HashSet<String>,
+    /// Names from the people file, by [`override_key`], which win over
+    /// every other source
+    overrides: HashMap<String, String>,
+    /// Contact photos by identifier, from the AddressBook
+    avatars: HashMap<String, Avatar>,
+    email_matching: EmailMatching,
+}
+
+/// Where a contact photo comes from.
+#[derive(Debug, Clone)]
+pub enum Avatar {
+    /// A full-size photo in the AddressBook's Images folder
+    File(PathBuf),
+    /// The thumbnail stored in the record itself
+    Thumbnail(Arc<[u8]>),
+}
+
+impl Avatar {
+    /// The image data. A photo file that can no longer be read counts as
+    /// none.
+    pub fn read(&self) -> Option<Vec<u8>> {
+        match self {
+            Avatar::File(path) => std::fs::read(path).ok(),
+            Avatar::Thumbnail(data) => Some(data.to_vec()),
+        }
+    }
+
+    /// Size of the image in bytes, without reading a photo file.
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Avatar::File(path) => std::fs::metadata(path).ok().map(|m| m.len()),
+            Avatar::Thumbnail(data) => Some(data.len() as u64),
+        }
+    }
+}
+
+/// Fallbacks for email handles that don't exactly match a saved address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmailMatching {
+    /// Match addresses regardless of case.
+    pub ignore_case: bool,
+    /// Match `user+label@domain` to `user@domain`.
+    pub strip_plus: bool,
+}
+
+impl Default for EmailMatching {
+    fn default() -> Self {
+        Self { ignore_case: true, strip_plus: true }
+    }
+}
+
+impl EmailMatching {
+    /// The form two addresses are compared in.
+    fn canonical(&self, email: &str) -> String {
+        let email = match email.split_once('@') {
+            Some((local, domain)) if self.strip_plus => {
+                let local = local.split_once('+').map_or(local, |(user, _)| user);
+                format!("{}@{}", local, domain)
+            }
+            _ => email.to_string(),
+        };
+        if self.ignore_case { email.to_lowercase() } else { email }
+    }
+}
+
+/// Directory holding one AddressBook database per account.
+#[cfg(not(feature = "dev-sample"))]
+pub fn addressbook_sources_dir() -> Result<PathBuf, String> {
+    let home = dirs::home_dir().ok_or("Cannot find home directory")?;
+    Ok(home.join("Library/Application Support/AddressBook/Sources"))
+}
+
+/// The sample library's address book, in builds for working off macOS.
+#[cfg(feature = "dev-sample")]
+pub fn addressbook_sources_dir() -> Result<PathBuf, String> {
+    Ok(crate::sample::ensure_sample_library().join("Sources"))
 }
 
 impl ContactResolver {
     pub fn new() -> Self {
-        Self { cache: HashMap::new() }
+        Self {
+            cache: HashMap::new(),
+            address_book_keys: HashSet::new(),
+            overrides: HashMap::new(),
+            avatars: HashMap::new(),
+            email_matching: EmailMatching::default(),
+        }
+    }
+
+    pub fn set_email_matching(&mut self, matching: EmailMatching) {
+        self.email_matching = matching;
     }
 
     /// Get contact name for identifier (phone/email).
     pub fn resolve(&self, identifier: &str) -> Option<&str> {
+        if let Some(name) = self.overrides.get(&override_key(identifier)) {
+            return Some(name);
+        }
+
         // Try direct lookup
         if let Some(name) = self.cache.get(identifier) {
             return Some(name);
         }
 
+        if identifier.contains('@') {
+            return self.resolve_email(identifier);
+        }
+
         // Try normalized phone
         let normalized = normalize_phone(identifier);
         if let Some(name) = self.cache.get(&normalized) {
@@ -26,8 +130,8 @@ impl ContactResolver {
         }
 
         // Try without +1 prefix
-        if normalized.starts_with("+1") {
-            if let Some(name) = self.cache.get(&normalized[2..]) {
+        if let Some(local) = normalized.strip_prefix("+1") {
+            if let Some(name) = self.cache.get(local) {
                 return Some(name);
             }
         }
@@ -35,6 +139,21 @@ impl ContactResolver {
         None
     }
 
+    /// Email fallbacks: compare canonical forms, taking the first saved
+    /// address in sort order if several match.
+    fn resolve_email(&self, email: &str) -> Option<&str> {
+        let matching = self.email_matching;
+        if !matching.ignore_case && !matching.strip_plus {
+            return None;
+        }
+        let canonical = matching.canonical(email);
+        self.cache
+            .iter()
+            .filter(|(id, _)| id.contains('@') && matching.canonical(id) == canonical)
+            .min_by_key(|(id, _)| id.as_str())
+            .map(|(_, name)| name.as_str())
+    }
+
     /// Add a mapping from identifier to name.
     pub fn add(&mut self, identifier: &str, name: &str) {
         if !identifier.is_empty() && !name.is_empty() {
@@ -42,41 +161,113 @@ impl ContactResolver {
         }
     }
 
-    /// Load contacts from macOS AddressBook database.
-    pub fn load_macos_contacts(&mut self) -> Result<usize, String> {
-        // Find AddressBook database
-        let home = dirs::home_dir().ok_or("Cannot find home directory")?;
-        let sources_dir = home.join("Library/Application Support/AddressBook/Sources");
-        
-        if !sources_dir.exists() {
-            return Err("AddressBook sources directory not found".to_string());
+    /// Add a phone number under both its written and normalized forms.
+    pub(crate) fn add_phone(&mut self, phone: &str, name: &str) {
+        self.add(phone, name);
+        let normalized = normalize_phone(phone);
+        if normalized != phone {
+            self.add(&normalized, name);
         }
-        
-        let mut count = 0;
-        
-        // Iterate through all source directories
-        let entries = std::fs::read_dir(&sources_dir)
-            .map_err(|e| format!("Cannot read sources dir: {}", e))?;
-        
-        for entry in entries.flatten() {
-            let db_path = entry.path().join("AddressBook-v22.abcddb");
-            if !db_path.exists() {
-                continue;
-            }
-            
-            count += self.load_from_addressbook_db(&db_path)?;
+    }
+
+    /// Add an email under both its written and lowercase forms.
+    pub(crate) fn add_email(&mut self, email: &str, name: &str) {
+        self.add(email, name);
+        let lower = email.to_lowercase();
+        if lower != email {
+            self.add(&lower, name);
+        }
+    }
+
+    /// Image data of an identifier's contact photo, if the AddressBook has
+    /// one. A photo file that can no longer be read counts as none.
+    pub fn avatar(&self, identifier: &str) -> Option<Vec<u8>> {
+        self.find_avatar(identifier)?.read()
+    }
+
+    /// Where an identifier's contact photo is, to read once the resolver
+    /// is no longer borrowed.
+    pub fn avatar_source(&self, identifier: &str) -> Option<Avatar> {
+        self.find_avatar(identifier).cloned()
+    }
+
+    /// Whether an identifier's contact has a photo, without reading it.
+    pub fn has_avatar(&self, identifier: &str) -> bool {
+        self.find_avatar(identifier).is_some()
+    }
+
+    fn find_avatar(&self, identifier: &str) -> Option<&Avatar> {
+        let key = if identifier.contains('@') { identifier.to_lowercase() } else { normalize_phone(identifier) };
+        self.avatars
+            .get(identifier)
+            .or_else(|| self.avatars.get(&key))
+            .or_else(|| self.avatars.get(key.strip_prefix("+1")?))
+    }
+
+    fn add_avatar(&mut self, identifier: &str, avatar: Avatar) {
+        let key = if identifier.contains('@') { identifier.to_lowercase() } else { normalize_phone(identifier) };
+        self.avatars.insert(identifier.to_string(), avatar.clone());
+        self.avatars.insert(key, avatar);
+    }
+
+    /// Identifiers saved under a contact name, matched case-insensitively.
+    pub fn identifiers_for(&self, name: &str) -> Vec<String> {
+        let name = name.trim().to_lowercase();
+        let mut identifiers: Vec<String> = self
+            .cache
+            .iter()
+            .filter(|(_, n)| n.to_lowercase() == name)
+            .map(|(id, _)| id.clone())
+            .collect();
+        identifiers.sort();
+        identifiers
+    }
+
+    /// Identifiers and names matching a search, in no particular order.
+    pub fn search(&self, query: &str) -> Vec<(&str, &str)> {
+        self.cache
+            .iter()
+            .filter(|(id, name)| match_tier(query, Some(name), id).is_some())
+            .map(|(id, name)| (id.as_str(), name.as_str()))
+            .collect()
+    }
+
+    /// Number of identifiers that resolve to a name.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Every name by identifier, to hand to another resolver.
+    pub(crate) fn into_entries(self) -> HashMap<String, String> {
+        self.cache
+    }
+
+    /// Add AddressBook entries without dropping existing ones, so names
+    /// resolve while the rest of a reload is still being read.
+    fn merge_address_book(&mut self, entries: &HashMap<String, String>) {
+        for (identifier, name) in entries {
+            self.address_book_keys.insert(identifier.clone());
+            self.cache.insert(identifier.clone(), name.clone());
+        }
+    }
+
+    /// Swap the AddressBook-sourced entries for a new set.
+    pub(crate) fn replace_address_book(&mut self, entries: HashMap<String, String>) {
+        for key in self.address_book_keys.drain() {
+            self.cache.remove(&key);
+        }
+        for (identifier, name) in entries {
+            self.address_book_keys.insert(identifier.clone());
+            self.cache.insert(identifier, name);
         }
-        
-        Ok(count)
     }
     
     fn load_from_addressbook_db(&mut self, db_path: &std::path::Path) -> Result<usize, String> {
-        use rusqlite::{Connection, OpenFlags};
-
-        let conn = Connection::open_with_flags(
-            db_path,
-            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
-        ).map_err(|e| format!("Cannot open AddressBook: {}", e))?;
+        let conn = open_address_book(db_path)?;
         
         let mut count = 0;
         
@@ -102,11 +293,7 @@ impl ContactResolver {
             let (name, phone) = row;
             let name = name.trim();
             if !name.is_empty() && name != " " {
-                self.add(&phone, name);
-                let normalized = normalize_phone(&phone);
-                if normalized != phone {
-                    self.add(&normalized, name);
-                }
+                self.add_phone(&phone, name);
                 count += 1;
             }
         }
@@ -133,31 +320,409 @@ impl ContactResolver {
             let (name, email) = row;
             let name = name.trim();
             if !name.is_empty() && name != " " {
-                self.add(&email, name);
-                // Also add lowercase version
-                let lower = email.to_lowercase();
-                if lower != email {
-                    self.add(&lower, name);
-                }
+                self.add_email(&email, name);
                 count += 1;
             }
         }
         
+        self.load_avatars(&conn, &db_path.with_file_name("Images"));
+        Ok(count)
+    }
+
+    /// Note which identifiers have a photo. Full-size photos sit beside the
+    /// database, named by the first part of the record's "UUID:ABPerson"
+    /// ID; failing that, newer AddressBooks keep a thumbnail in the record.
+    /// Older AddressBooks without either column just get no photos.
+    fn load_avatars(&mut self, conn: &rusqlite::Connection, images_dir: &Path) {
+        let columns: Vec<String> = conn
+            .prepare("SELECT name FROM pragma_table_info('ZABCDRECORD')")
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+            .unwrap_or_default();
+        let column = |name: &str| if columns.iter().any(|c| c == name) { format!("r.{}", name) } else { "NULL".to_string() };
+        let (unique_id, thumbnail) = (column("ZUNIQUEID"), column("ZTHUMBNAILIMAGEDATA"));
+
+        let Ok(mut stmt) = conn.prepare(&format!(
+            "SELECT {unique_id}, {thumbnail}, p.ZFULLNUMBER FROM ZABCDRECORD r
+             JOIN ZABCDPHONENUMBER p ON r.Z_PK = p.ZOWNER
+             WHERE ({unique_id} IS NOT NULL OR {thumbnail} IS NOT NULL) AND p.ZFULLNUMBER IS NOT NULL
+             UNION ALL
+             SELECT {unique_id}, {thumbnail}, e.ZADDRESSNORMALIZED FROM ZABCDRECORD r
+             JOIN ZABCDEMAILADDRESS e ON r.Z_PK = e.ZOWNER
+             WHERE ({unique_id} IS NOT NULL OR {thumbnail} IS NOT NULL) AND e.ZADDRESSNORMALIZED IS NOT NULL"
+        )) else {
+            return;
+        };
+        let Ok(rows) = stmt.query_map([], |row| {
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?, row.get::<_, String>(2)?))
+        }) else {
+            return;
+        };
+        for (unique_id, thumbnail, identifier) in rows.flatten() {
+            let file = unique_id
+                .map(|id| images_dir.join(id.split(':').next().unwrap_or(&id)))
+                .filter(|file| file.is_file());
+            if let Some(file) = file {
+                self.add_avatar(&identifier, Avatar::File(file));
+            } else if let Some(data) = thumbnail.as_deref().and_then(thumbnail_image) {
+                self.add_avatar(&identifier, Avatar::Thumbnail(data.into()));
+            }
+        }
+    }
+
+    /// Load names from a people file: one identifier and name per line,
+    /// tab-separated (people.tsv) or comma-separated. These win over the
+    /// AddressBook and other sources. Replaces names from a previous load;
+    /// blank lines, `#` comments and a header row are skipped.
+    pub fn load_overrides(&mut self, path: &Path) -> Result<usize, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let tsv = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tsv")) || contents.contains('\t');
+        self.overrides = parse_overrides(&contents, if tsv { b'\t' } else { b',' })?;
+        Ok(self.overrides.len())
+    }
+
+    /// Forget names from the people file.
+    pub fn clear_overrides(&mut self) {
+        self.overrides.clear();
+    }
+
+    /// Load contacts from a Google Contacts CSV export.
+    ///
+    /// Handles both the current export ("First Name", "Phone 1 - Value") and
+    /// the older one ("Given Name", "E-mail 1 - Value"). A value column may
+    /// hold several entries separated by " ::: ".
+    pub fn load_google_csv(&mut self, path: &std::path::Path) -> Result<usize, String> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Cannot open CSV: {}", e))?;
+        self.load_google_csv_from(file)
+    }
+
+    fn load_google_csv_from<R: std::io::Read>(&mut self, reader: R) -> Result<usize, String> {
+        let mut csv = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(reader);
+
+        let headers = csv.headers()
+            .map_err(|e| format!("Cannot read CSV header: {}", e))?
+            .clone();
+        let column = |name: &str| headers.iter().position(|h| h == name);
+        let first = column("First Name").or_else(|| column("Given Name"));
+        let last = column("Last Name").or_else(|| column("Family Name"));
+        let full = column("Name");
+        let org = column("Organization Name").or_else(|| column("Organization 1 - Name"));
+
+        let phone_cols: Vec<usize> = headers.iter().enumerate()
+            .filter(|(_, h)| h.starts_with("Phone ") && h.ends_with(" - Value"))
+            .map(|(i, _)| i)
+            .collect();
+        let email_cols: Vec<usize> = headers.iter().enumerate()
+            .filter(|(_, h)| h.starts_with("E-mail ") && h.ends_with(" - Value"))
+            .map(|(i, _)| i)
+            .collect();
+
+        let mut count = 0;
+        for record in csv.records().flatten() {
+            let field = |i: Option<usize>| i.and_then(|i| record.get(i)).unwrap_or("").trim();
+
+            let joined = format!("{} {}", field(first), field(last));
+            let name = [field(full), joined.trim(), field(org)]
+                .into_iter()
+                .find(|n| !n.is_empty())
+                .unwrap_or("")
+                .to_string();
+            if name.is_empty() {
+                continue;
+            }
+
+            for &i in &phone_cols {
+                for phone in split_google_values(field(Some(i))) {
+                    self.add_phone(phone, &name);
+                    count += 1;
+                }
+            }
+
+            for &i in &email_cols {
+                for email in split_google_values(field(Some(i))) {
+                    self.add_email(email, &name);
+                    count += 1;
+                }
+            }
+        }
+
         Ok(count)
     }
 }
 
+/// How far a background contact load has got.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ContactsProgress {
+    pub sources_loaded: usize,
+    pub sources_total: usize,
+    /// Contacts read so far
+    pub contacts: usize,
+}
+
+/// Open one AddressBook database read-only.
+fn open_address_book(db_path: &Path) -> Result<rusqlite::Connection, String> {
+    use rusqlite::{Connection, OpenFlags};
+
+    Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    ).map_err(|e| format!("Cannot open AddressBook: {}", e))
+}
+
+/// The AddressBook database of every source under `sources_dir`.
+fn address_book_databases(sources_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !sources_dir.exists() {
+        return Err("AddressBook sources directory not found".to_string());
+    }
+
+    Ok(std::fs::read_dir(sources_dir)
+        .map_err(|e| format!("Cannot read sources dir: {}", e))?
+        .flatten()
+        .map(|entry| entry.path().join("AddressBook-v22.abcddb"))
+        .filter(|path| path.exists())
+        .collect())
+}
+
+/// Replace the resolver's contact photos with those in every AddressBook
+/// source, for when names were read from somewhere else. Sources that
+/// can't be opened just contribute no photos.
+pub(crate) fn load_address_book_avatars(sources_dir: &Path, resolver: &Mutex<ContactResolver>) -> Result<(), String> {
+    let mut source = ContactResolver::new();
+    for db_path in address_book_databases(sources_dir)? {
+        if let Ok(conn) = open_address_book(&db_path) {
+            source.load_avatars(&conn, &db_path.with_file_name("Images"));
+        }
+    }
+    resolver.lock().map_err(|e| e.to_string())?.avatars = source.avatars;
+    Ok(())
+}
+
+/// Load every AddressBook source into a shared resolver, one at a time.
+///
+/// Each source is read without holding the lock and merged in as soon as it's
+/// done, so `resolve` keeps answering (with partial results) throughout.
+/// Stale entries from a previous load are dropped once all sources are read.
+pub fn load_address_books(
+    sources_dir: &Path,
+    resolver: &Mutex<ContactResolver>,
+    mut on_progress: impl FnMut(ContactsProgress),
+) -> Result<usize, String> {
+    let databases = address_book_databases(sources_dir)?;
+
+    let mut all = HashMap::new();
+    let mut avatars = HashMap::new();
+    let mut progress = ContactsProgress { sources_loaded: 0, sources_total: databases.len(), contacts: 0 };
+    on_progress(progress);
+
+    for db_path in &databases {
+        let mut source = ContactResolver::new();
+        progress.contacts += source.load_from_addressbook_db(db_path)?;
+        progress.sources_loaded += 1;
+
+        resolver.lock().map_err(|e| e.to_string())?.merge_address_book(&source.cache);
+        all.extend(source.cache);
+        avatars.extend(source.avatars);
+        on_progress(progress);
+    }
+
+    let mut resolver = resolver.lock().map_err(|e| e.to_string())?;
+    resolver.replace_address_book(all);
+    resolver.avatars = avatars;
+    Ok(progress.contacts)
+}
+
+/// A recipient offered while composing a new message.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientSuggestion {
+    pub name: Option<String>,
+    pub handle: String,
+    /// None for contacts never messaged from this Mac
+    pub service: Option<String>,
+    pub last_active: Option<DateTime<Utc>>,
+}
+
+/// How well a query matches a recipient: 0 for a prefix of the name, one of
+/// its words, or the handle; 1 for the query's letters appearing in order in
+/// the name. An empty query matches everything.
+fn match_tier(query: &str, name: Option<&str>, handle: &str) -> Option<u8> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Some(0);
+    }
+    let name = name.map(str::to_lowercase).unwrap_or_default();
+
+    if name.starts_with(&query) || name.split_whitespace().any(|w| w.starts_with(&query)) {
+        return Some(0);
+    }
+    if handle.to_lowercase().starts_with(&query) {
+        return Some(0);
+    }
+    // Phone-like queries match anywhere in the number, ignoring formatting
+    let digits = normalize_phone(&query);
+    let phone_like = query.chars().all(|c| c.is_ascii_digit() || " +()-.".contains(c));
+    if phone_like && digits.len() >= 3 && normalize_phone(handle).contains(digits.trim_start_matches('+')) {
+        return Some(0);
+    }
+
+    let mut chars = name.chars();
+    if !name.is_empty() && query.chars().filter(|c| !c.is_whitespace()).all(|q| chars.any(|c| c == q)) {
+        return Some(1);
+    }
+    None
+}
+
+/// Suggest recipients for `query` from chat.db handles and contacts.
+///
+/// Handles come first, most recently active first, so the person I text
+/// every day beats an address book entry with the same first name. Contacts
+/// never messaged follow. Each person's handle appears once.
+pub fn autocomplete_recipients(
+    query: &str,
+    handles: &[HandleActivity],
+    contacts: &ContactResolver,
+    limit: usize,
+) -> Vec<RecipientSuggestion> {
+    let mut scored: Vec<(u8, RecipientSuggestion)> = Vec::new();
+    let seen = |scored: &[(u8, RecipientSuggestion)], handle: &str| {
+        scored.iter().any(|(_, s)| same_handle(&s.handle, handle))
+    };
+
+    for activity in handles {
+        let name = contacts.resolve(&activity.handle);
+        let Some(tier) = match_tier(query, name, &activity.handle) else { continue };
+        if seen(&scored, &activity.handle) {
+            continue;
+        }
+        scored.push((tier, RecipientSuggestion {
+            name: name.map(str::to_string),
+            handle: activity.handle.clone(),
+            service: activity.service.clone(),
+            last_active: activity.last_active,
+        }));
+    }
+
+    let mut unmessaged: Vec<(u8, RecipientSuggestion)> = Vec::new();
+    let mut matches = contacts.search(query);
+    matches.sort();
+    for (handle, name) in matches {
+        if seen(&scored, handle) || seen(&unmessaged, handle) {
+            continue;
+        }
+        let tier = match_tier(query, Some(name), handle).unwrap_or(1);
+        unmessaged.push((tier, RecipientSuggestion {
+            name: Some(name.to_string()),
+            handle: handle.to_string(),
+            service: None,
+            last_active: None,
+        }));
+    }
+    unmessaged.sort_by(|(ta, a), (tb, b)| ta.cmp(tb).then_with(|| a.name.cmp(&b.name)));
+
+    // Stable, so recency order holds within a tier
+    scored.sort_by_key(|(tier, _)| *tier);
+    scored.extend(unmessaged);
+    scored.into_iter().take(limit).map(|(_, s)| s).collect()
+}
+
+/// The image in a ZTHUMBNAILIMAGEDATA value, which newer AddressBooks
+/// store behind a one-byte header.
+fn thumbnail_image(data: &[u8]) -> Option<&[u8]> {
+    let image = match data {
+        [0x01, rest @ ..] => rest,
+        other => other,
+    };
+    (!image.is_empty()).then_some(image)
+}
+
+/// Split a Google Contacts value cell holding several entries.
+fn split_google_values(cell: &str) -> impl Iterator<Item = &str> {
+    cell.split(":::").map(str::trim).filter(|v| !v.is_empty())
+}
+
 impl Default for ContactResolver {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Format a handle for display: "(555) 123-4567" for North American numbers,
+/// "+44 20 7946 0958" for the rest. Emails and anything that isn't a valid
+/// phone number (short codes, chat IDs) are returned unchanged.
+pub fn format_display(identifier: &str) -> String {
+    use phonenumber::{country, Mode};
+
+    if identifier.contains('@') {
+        return identifier.to_string();
+    }
+
+    match phonenumber::parse(Some(country::Id::US), identifier) {
+        Ok(number) if number.is_valid() => {
+            let mode = if number.code().value() == 1 { Mode::National } else { Mode::International };
+            number.format().mode(mode).to_string()
+        }
+        _ => identifier.to_string(),
+    }
+}
+
 /// Normalize a phone number (keep only digits and +).
 fn normalize_phone(phone: &str) -> String {
     phone.chars().filter(|c| c.is_ascii_digit() || *c == '+').collect()
 }
 
+/// How people file identifiers are matched: emails ignoring case, phone
+/// numbers ignoring formatting and a leading +1, anything else exactly.
+fn override_key(identifier: &str) -> String {
+    let identifier = identifier.trim();
+    if identifier.contains('@') {
+        return identifier.to_lowercase();
+    }
+    if identifier.chars().any(|c| c.is_alphabetic()) {
+        return identifier.to_string();
+    }
+    let phone = normalize_phone(identifier);
+    match phone.strip_prefix("+1") {
+        Some(local) if local.len() == 10 => local.to_string(),
+        _ => phone,
+    }
+}
+
+/// Names by [`override_key`] from people file `contents`.
+fn parse_overrides(contents: &str, delimiter: u8) -> Result<HashMap<String, String>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_reader(contents.as_bytes());
+    let mut overrides = HashMap::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Cannot read people file: {}", e))?;
+        let (Some(identifier), Some(name)) = (record.get(0).map(str::trim), record.get(1).map(str::trim)) else {
+            continue;
+        };
+        // A header like "identifier<TAB>name" names no handle
+        let is_header = i == 0 && !identifier.contains('@') && !identifier.chars().any(|c| c.is_ascii_digit());
+        if identifier.is_empty() || name.is_empty() || is_header {
+            continue;
+        }
+        overrides.insert(override_key(identifier), name.to_string());
+    }
+    Ok(overrides)
+}
+
+/// Whether two handles name the same person: emails ignoring case, phone
+/// numbers ignoring formatting and a leading +1.
+pub(crate) fn same_handle(a: &str, b: &str) -> bool {
+    if a.contains('@') || b.contains('@') {
+        return a.eq_ignore_ascii_case(b);
+    }
+    let (a, b) = (normalize_phone(a), normalize_phone(b));
+    let strip = |p: &str| p.strip_prefix("+1").map(str::to_string).unwrap_or_else(|| p.trim_start_matches('+').to_string());
+    !a.is_empty() && strip(&a) == strip(&b)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,6 +733,90 @@ mod tests {
         assert_eq!(normalize_phone("555.123.4567"), "5551234567");
     }
 
+    fn activity(handle: &str, secs: i64) -> HandleActivity {
+        HandleActivity {
+            handle: handle.into(),
+            service: Some("iMessage".into()),
+            last_active: DateTime::from_timestamp(secs, 0),
+        }
+    }
+
+    #[test]
+    fn test_resolve_email_fallbacks() {
+        let mut contacts = ContactResolver::new();
+        contacts.add("Jane.Doe@Example.com", "Jane Doe");
+        contacts.add("sam+work@example.com", "Sam Lee");
+
+        assert_eq!(contacts.resolve("jane.doe@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.resolve("jane.doe+shopping@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.resolve("SAM@example.com"), Some("Sam Lee"));
+        assert_eq!(contacts.resolve("jane@example.com"), None);
+
+        contacts.set_email_matching(EmailMatching { ignore_case: true, strip_plus: false });
+        assert_eq!(contacts.resolve("JANE.DOE@example.com"), Some("Jane Doe"));
+        assert_eq!(contacts.resolve("jane.doe+shopping@example.com"), None);
+
+        contacts.set_email_matching(EmailMatching { ignore_case: false, strip_plus: false });
+        assert_eq!(contacts.resolve("jane.doe@example.com"), None);
+        assert_eq!(contacts.resolve("Jane.Doe@Example.com"), Some("Jane Doe"));
+    }
+
+    #[test]
+    fn test_match_tier() {
+        assert_eq!(match_tier("jo", Some("John Doe"), "+15551234567"), Some(0));
+        assert_eq!(match_tier("doe", Some("John Doe"), "+15551234567"), Some(0));
+        assert_eq!(match_tier("jd", Some("John Doe"), "+15551234567"), Some(1));
+        assert_eq!(match_tier("555 123", None, "+15551234567"), Some(0));
+        assert_eq!(match_tier("fri", None, "friend@example.com"), Some(0));
+        assert_eq!(match_tier("xyz", Some("John Doe"), "+15551234567"), None);
+    }
+
+    #[test]
+    fn test_autocomplete_ranks_recent_handles_first() {
+        let mut contacts = ContactResolver::new();
+        contacts.add_phone("+1 (555) 111-1111", "Sam Old");
+        contacts.add_phone("+1 (555) 222-2222", "Sam New");
+        contacts.add("sam@example.com", "Samantha Never");
+        contacts.add("+15553333333", "Bob");
+        let handles = [
+            activity("+15552222222", 2_000),
+            activity("+15553333333", 1_500),
+            activity("+15551111111", 1_000),
+        ];
+
+        let names: Vec<_> = autocomplete_recipients("sam", &handles, &contacts, 10)
+            .into_iter()
+            .map(|s| (s.name.unwrap(), s.service.is_some()))
+            .collect();
+        assert_eq!(names, [
+            ("Sam New".to_string(), true),
+            ("Sam Old".to_string(), true),
+            ("Samantha Never".to_string(), false),
+        ]);
+
+        assert_eq!(autocomplete_recipients("", &handles, &contacts, 2).len(), 2);
+    }
+
+    #[test]
+    fn test_same_handle() {
+        assert!(same_handle("+1 (555) 123-4567", "5551234567"));
+        assert!(same_handle("Jane@Example.com", "jane@example.com"));
+        assert!(!same_handle("+15551234567", "+15559999999"));
+        assert!(!same_handle("", ""));
+    }
+
+    #[test]
+    fn test_identifiers_for_name() {
+        let mut resolver = ContactResolver::new();
+        resolver.add_phone("+1 555 123 4567", "John Doe");
+        resolver.add("john@example.com", "John Doe");
+        resolver.add("+15559999999", "Jane");
+        assert_eq!(
+            resolver.identifiers_for("john doe"),
+            ["+1 555 123 4567", "+15551234567", "john@example.com"]
+        );
+    }
+
     #[test]
     fn test_resolver_direct() {
         let mut resolver = ContactResolver::new();
@@ -220,6 +869,172 @@ mod tests {
     fn test_resolver_default() {
         let resolver = ContactResolver::default();
         assert_eq!(resolver.resolve("+15551234567"), None);
+        assert!(resolver.is_empty());
+    }
+
+    #[test]
+    fn test_resolver_len() {
+        let mut resolver = ContactResolver::new();
+        resolver.add("+15551234567", "John Doe");
+        resolver.add("john@example.com", "John Doe");
+        resolver.add("+15551234567", "John D");
+        assert_eq!(resolver.len(), 2);
+    }
+
+    #[test]
+    fn test_replace_address_book_keeps_other_sources() {
+        let mut resolver = ContactResolver::new();
+        resolver.add("jane@example.com", "Jane");
+
+        let mut first = HashMap::new();
+        first.insert("+15551234567".to_string(), "John".to_string());
+        first.insert("+15557654321".to_string(), "Old Name".to_string());
+        resolver.replace_address_book(first);
+        assert_eq!(resolver.resolve("+15557654321"), Some("Old Name"));
+
+        let mut second = HashMap::new();
+        second.insert("+15551234567".to_string(), "John Smith".to_string());
+        resolver.replace_address_book(second);
+
+        assert_eq!(resolver.resolve("+15551234567"), Some("John Smith"));
+        assert_eq!(resolver.resolve("+15557654321"), None);
+        assert_eq!(resolver.resolve("jane@example.com"), Some("Jane"));
+    }
+
+    /// AddressBook source directory with one contact.
+    fn address_book(sources: &Path, account: &str, first: &str, phone: &str) {
+        let dir = sources.join(account);
+        std::fs::create_dir_all(&dir).unwrap();
+        let conn = rusqlite::Connection::open(dir.join("AddressBook-v22.abcddb")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT);
+             CREATE TABLE ZABCDPHONENUMBER (ZOWNER INTEGER, ZFULLNUMBER TEXT);
+             CREATE TABLE ZABCDEMAILADDRESS (ZOWNER INTEGER, ZADDRESSNORMALIZED TEXT);"
+        ).unwrap();
+        conn.execute("INSERT INTO ZABCDRECORD VALUES (1, ?, 'Doe')", [first]).unwrap();
+        conn.execute("INSERT INTO ZABCDPHONENUMBER VALUES (1, ?)", [phone]).unwrap();
+    }
+
+    #[test]
+    fn test_load_address_books_reports_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        address_book(dir.path(), "a", "Jane", "+15551234567");
+        address_book(dir.path(), "b", "John", "+15557654321");
+
+        let resolver = Mutex::new(ContactResolver::new());
+        resolver.lock().unwrap().replace_address_book(HashMap::from([("+15550000000".to_string(), "Gone".to_string())]));
+
+        let mut updates = Vec::new();
+        let count = load_address_books(dir.path(), &resolver, |p| updates.push(p)).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates[0].sources_loaded, 0);
+        assert_eq!(updates[2].sources_loaded, 2);
+        assert!(updates.iter().all(|p| p.sources_total == 2));
+
+        let resolver = resolver.lock().unwrap();
+        assert_eq!(resolver.resolve("+15551234567"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("+15557654321"), Some("John Doe"));
+        // Entries from the previous load are dropped at the end
+        assert_eq!(resolver.resolve("+15550000000"), None);
+    }
+
+    #[test]
+    fn test_avatars_from_images_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a");
+        std::fs::create_dir_all(source.join("Images")).unwrap();
+        std::fs::write(source.join("Images/1A2B"), b"jpeg").unwrap();
+        let conn = rusqlite::Connection::open(source.join("AddressBook-v22.abcddb")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT, ZUNIQUEID TEXT);
+             CREATE TABLE ZABCDPHONENUMBER (ZOWNER INTEGER, ZFULLNUMBER TEXT);
+             CREATE TABLE ZABCDEMAILADDRESS (ZOWNER INTEGER, ZADDRESSNORMALIZED TEXT);
+             INSERT INTO ZABCDRECORD VALUES (1, 'Jane', 'Doe', '1A2B:ABPerson'), (2, 'John', 'Doe', '3C4D:ABPerson');
+             INSERT INTO ZABCDPHONENUMBER VALUES (1, '(555) 123-4567'), (2, '+15557654321');
+             INSERT INTO ZABCDEMAILADDRESS VALUES (1, 'jane@example.com');"
+        ).unwrap();
+
+        let resolver = Mutex::new(ContactResolver::new());
+        load_address_books(dir.path(), &resolver, |_| {}).unwrap();
+        let resolver = resolver.lock().unwrap();
+        assert_eq!(resolver.avatar("+15551234567"), Some(b"jpeg".to_vec()));
+        assert!(resolver.has_avatar("Jane@Example.com"));
+        assert_eq!(resolver.avatar("+15557654321"), None);
+    }
+
+    #[test]
+    fn test_avatars_from_thumbnail_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a");
+        std::fs::create_dir_all(source.join("Images")).unwrap();
+        std::fs::write(source.join("Images/1A2B"), b"full").unwrap();
+        let conn = rusqlite::Connection::open(source.join("AddressBook-v22.abcddb")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT, ZUNIQUEID TEXT,
+                 ZTHUMBNAILIMAGEDATA BLOB);
+             CREATE TABLE ZABCDPHONENUMBER (ZOWNER INTEGER, ZFULLNUMBER TEXT);
+             CREATE TABLE ZABCDEMAILADDRESS (ZOWNER INTEGER, ZADDRESSNORMALIZED TEXT);
+             INSERT INTO ZABCDRECORD VALUES (1, 'Jane', 'Doe', '1A2B:ABPerson', X'01FFD8'),
+                 (2, 'John', 'Doe', NULL, X'01FFD8FF'), (3, 'Jim', 'Doe', NULL, X'01');
+             INSERT INTO ZABCDPHONENUMBER VALUES (1, '+15551234567'), (2, '+15557654321'), (3, '+15550000000');"
+        ).unwrap();
+
+        let resolver = Mutex::new(ContactResolver::new());
+        load_address_books(dir.path(), &resolver, |_| {}).unwrap();
+        let resolver = resolver.lock().unwrap();
+        // The full-size photo wins over the thumbnail
+        assert_eq!(resolver.avatar("+15551234567"), Some(b"full".to_vec()));
+        assert_eq!(resolver.avatar("+15557654321"), Some(vec![0xFF, 0xD8, 0xFF]));
+        assert!(!resolver.has_avatar("+15550000000"));
+    }
+
+    #[test]
+    fn test_load_address_books_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = Mutex::new(ContactResolver::new());
+        assert!(load_address_books(&dir.path().join("none"), &resolver, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_google_csv_current_format() {
+        let csv = "First Name,Middle Name,Last Name,E-mail 1 - Label,E-mail 1 - Value,Phone 1 - Label,Phone 1 - Value,Phone 2 - Label,Phone 2 - Value\n\
+                   Jane,,Doe,* Home,Jane@Example.com,Mobile,+1 (555) 123-4567 ::: +1 555 765 4321,Work,\n\
+                   ,,,,,Mobile,+15550000000\n";
+        let mut resolver = ContactResolver::new();
+        let count = resolver.load_google_csv_from(csv.as_bytes()).unwrap();
+
+        assert_eq!(count, 3);
+        assert_eq!(resolver.resolve("+15551234567"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("+15557654321"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("jane@example.com"), Some("Jane Doe"));
+        assert_eq!(resolver.resolve("+15550000000"), None);
+    }
+
+    #[test]
+    fn test_google_csv_legacy_format() {
+        let csv = "Name,Given Name,Family Name,E-mail 1 - Type,E-mail 1 - Value,Phone 1 - Type,Phone 1 - Value\n\
+                   \"Smith, Bob\",Bob,Smith,* Other,bob@example.com,Mobile,555-123-4567\n";
+        let mut resolver = ContactResolver::new();
+        resolver.load_google_csv_from(csv.as_bytes()).unwrap();
+
+        assert_eq!(resolver.resolve("bob@example.com"), Some("Smith, Bob"));
+        assert_eq!(resolver.resolve("+15551234567"), Some("Smith, Bob"));
+    }
+
+    #[test]
+    fn test_split_google_values() {
+        let values: Vec<_> = split_google_values(" a ::: b :::  ").collect();
+        assert_eq!(values, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_format_display() {
+        assert_eq!(format_display("+14158675309"), "(415) 867-5309");
+        assert_eq!(format_display("+442079460958"), "+44 20 7946 0958");
+        assert_eq!(format_display("john@example.com"), "john@example.com");
+        assert_eq!(format_display("12345"), "12345");
+        assert_eq!(format_display("chat123456789"), "chat123456789");
     }
 
     #[test]
@@ -229,4 +1044,29 @@ mod tests {
         assert_eq!(normalize_phone("+++123"), "+++123");
         assert_eq!(normalize_phone("  +1  555  "), "+1555");
     }
+
+    #[test]
+    fn test_people_file_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let tsv = dir.path().join("people.tsv");
+        std::fs::write(&tsv, "identifier\tname\n# family\n(415) 867-5309\tMum\nJane@Example.com\tJaney\n\nchat123\tBook club\nbroken line\n").unwrap();
+
+        let mut resolver = ContactResolver::new();
+        resolver.add_phone("+14158675309", "Margaret Doe");
+        resolver.add_email("jane@example.com", "Jane Doe");
+        assert_eq!(resolver.load_overrides(&tsv), Ok(3));
+        assert_eq!(resolver.resolve("+14158675309"), Some("Mum"));
+        assert_eq!(resolver.resolve("jane@example.com"), Some("Janey"));
+        assert_eq!(resolver.resolve("chat123"), Some("Book club"));
+
+        let csv = dir.path().join("people.csv");
+        std::fs::write(&csv, "+1 415 867 5309,\"Doe, Margaret\"\n").unwrap();
+        assert_eq!(resolver.load_overrides(&csv), Ok(1));
+        assert_eq!(resolver.resolve("4158675309"), Some("Doe, Margaret"));
+        assert_eq!(resolver.resolve("jane@example.com"), Some("Jane Doe"));
+
+        resolver.clear_overrides();
+        assert_eq!(resolver.resolve("+14158675309"), Some("Margaret Doe"));
+        assert!(resolver.load_overrides(&dir.path().join("missing.tsv")).is_err());
+    }
 }
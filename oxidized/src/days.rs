@@ -0,0 +1,184 @@
+//! Day sections for message lists, with "Today"/"Yesterday" headers.
+//!
+//! Day boundaries are taken in the viewer's time zone, not UTC, so a message
+//! sent at 11pm lands on the day it was sent for the person reading it.
+
+use chrono::{Datelike, NaiveDate, TimeZone};
+use serde::Serialize;
+
+use crate::clock::Clock;
+use crate::models::Message;
+
+/// Header words for one language.
+struct DayNames {
+    lang: &'static str,
+    today: &'static str,
+    yesterday: &'static str,
+    /// Monday first
+    weekdays: [&'static str; 7],
+    months: [&'static str; 12],
+    /// Format a day in the current year, and in another year
+    date: fn(u32, &str) -> String,
+    date_with_year: fn(u32, &str, i32) -> String,
+}
+
+const DAY_NAMES: &[DayNames] = &[
+    DayNames {
+        lang: "en",
+        today: "Today",
+        yesterday: "Yesterday",
+        weekdays: ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"],
+        months: [
+            "January", "February", "March", "April", "May", "June",
+            "July", "August", "September", "October", "November", "December",
+        ],
+        date: |day, month| format!("{} {}", month, day),
+        date_with_year: |day, month, year| format!("{} {}, {}", month, day, year),
+    },
+    DayNames {
+        lang: "es",
+        today: "Hoy",
+        yesterday: "Ayer",
+        weekdays: ["lunes", "martes", "miércoles", "jueves", "viernes", "sábado", "domingo"],
+        months: [
+            "enero", "febrero", "marzo", "abril", "mayo", "junio",
+            "julio", "agosto", "septiembre", "octubre", "noviembre", "diciembre",
+        ],
+        date: |day, month| format!("{} de {}", day, month),
+        date_with_year: |day, month, year| format!("{} de {} de {}", day, month, year),
+    },
+    DayNames {
+        lang: "fr",
+        today: "Aujourd'hui",
+        yesterday: "Hier",
+        weekdays: ["lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche"],
+        months: [
+            "janvier", "février", "mars", "avril", "mai", "juin",
+            "juillet", "août", "septembre", "octobre", "novembre", "décembre",
+        ],
+        date: |day, month| format!("{} {}", day, month),
+        date_with_year: |day, month, year| format!("{} {} {}", day, month, year),
+    },
+    DayNames {
+        lang: "de",
+        today: "Heute",
+        yesterday: "Gestern",
+        weekdays: ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"],
+        months: [
+            "Januar", "Februar", "März", "April", "Mai", "Juni",
+            "Juli", "August", "September", "Oktober", "November", "Dezember",
+        ],
+        date: |day, month| format!("{}. {}", day, month),
+        date_with_year: |day, month, year| format!("{}. {} {}", day, month, year),
+    },
+];
+
+/// Weekday names in a language, Monday first, falling back to English.
+pub(crate) fn weekday_names(lang: &str) -> [&'static str; 7] {
+    DAY_NAMES.iter().find(|n| n.lang == lang).unwrap_or(&DAY_NAMES[0]).weekdays
+}
+
+/// Messages from one calendar day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DaySection {
+    /// The day in the viewer's time zone
+    pub date: NaiveDate,
+    /// e.g. "Today", "Yesterday", "Tuesday", "March 4", "March 4, 2023"
+    pub header: String,
+    pub messages: Vec<Message>,
+}
+
+/// Header for `date` as seen on `today`, in a language ("en", "es", ...),
+/// falling back to English for languages without a table. Days in the last
+/// week use the weekday name.
+pub fn day_header(date: NaiveDate, today: NaiveDate, lang: &str) -> String {
+    let names = DAY_NAMES.iter().find(|n| n.lang == lang).unwrap_or(&DAY_NAMES[0]);
+    let days_ago = (today - date).num_days();
+    match days_ago {
+        0 => names.today.to_string(),
+        1 => names.yesterday.to_string(),
+        2..=6 => names.weekdays[date.weekday().num_days_from_monday() as usize].to_string(),
+        _ => {
+            let month = names.months[date.month0() as usize];
+            if date.year() == today.year() {
+                (names.date)(date.day(), month)
+            } else {
+                (names.date_with_year)(date.day(), month, date.year())
+            }
+        }
+    }
+}
+
+/// Split chronologically ordered messages into one section per day in `tz`,
+/// with "today" taken from `clock`.
+pub fn group_messages_by_day<Tz: TimeZone>(messages: &[Message], tz: &Tz, lang: &str, clock: &dyn Clock) -> Vec<DaySection> {
+    let today = clock.now().with_timezone(tz).date_naive();
+    let mut sections: Vec<DaySection> = Vec::new();
+
+    for message in messages {
+        let date = message.date.with_timezone(tz).date_naive();
+        match sections.last_mut() {
+            Some(section) if section.date == date => section.messages.push(message.clone()),
+            _ => sections.push(DaySection {
+                date,
+                header: day_header(date, today, lang),
+                messages: vec![message.clone()],
+            }),
+        }
+    }
+
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, FixedOffset, Utc};
+    use crate::clock::FixedClock;
+    use crate::fixtures::message;
+
+    fn msg(rowid: i64, date: &str) -> Message {
+        Message {
+            rowid,
+            guid: format!("g{}", rowid),
+            date: DateTime::parse_from_rfc3339(date).unwrap().with_timezone(&Utc),
+            ..message("hi")
+        }
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_day_boundary_follows_time_zone() {
+        // 03:30 UTC on the 15th is still the evening of the 14th in New York
+        let messages = [msg(1, "2024-03-14T20:00:00Z"), msg(2, "2024-03-15T03:30:00Z"), msg(3, "2024-03-15T14:00:00Z")];
+        let clock = FixedClock::new(DateTime::parse_from_rfc3339("2024-03-15T16:00:00Z").unwrap().with_timezone(&Utc));
+
+        let utc = group_messages_by_day(&messages, &Utc, "en", &clock);
+        let headers: Vec<_> = utc.iter().map(|s| (s.header.as_str(), s.messages.len())).collect();
+        assert_eq!(headers, [("Yesterday", 1), ("Today", 2)]);
+
+        let new_york = FixedOffset::west_opt(4 * 3600).unwrap();
+        let local = group_messages_by_day(&messages, &new_york, "en", &clock);
+        let headers: Vec<_> = local.iter().map(|s| (s.header.as_str(), s.messages.len())).collect();
+        assert_eq!(headers, [("Yesterday", 2), ("Today", 1)]);
+        assert_eq!(local[0].date, day(2024, 3, 14));
+    }
+
+    #[test]
+    fn test_day_headers() {
+        let today = day(2024, 3, 15); // a Friday
+        assert_eq!(day_header(day(2024, 3, 12), today, "en"), "Tuesday");
+        assert_eq!(day_header(day(2024, 3, 4), today, "en"), "March 4");
+        assert_eq!(day_header(day(2023, 12, 31), today, "en"), "December 31, 2023");
+
+        assert_eq!(day_header(today, today, "es"), "Hoy");
+        assert_eq!(day_header(day(2024, 3, 14), today, "fr"), "Hier");
+        assert_eq!(day_header(day(2024, 3, 12), today, "de"), "Dienstag");
+        assert_eq!(day_header(day(2024, 3, 4), today, "de"), "4. März");
+        assert_eq!(day_header(day(2023, 3, 4), today, "es"), "4 de marzo de 2023");
+        assert_eq!(day_header(today, today, "xx"), "Today");
+    }
+}
@@ -1,13 +1,34 @@
 //! iMessage database access.
 
-use std::path::PathBuf;
-use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+use std::cell::OnceCell;
+use std::sync::Arc;
+use std::time::Duration;
+use rusqlite::{Connection, ErrorCode, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::models::{Conversation, Message, Attachment, Reaction, reaction_emoji};
-use crate::apple_to_unix;
+use crate::models::{
+    AgeBucket, ContactChat, Conversation, ConversationStats, HandleActivity, Message, MessageFilter, Attachment, MediaItem, ParticipantChange, ParticipantEvent,
+    Reaction, is_mms_group, reaction_emoji,
+};
+use crate::clock::{Clock, SystemClock};
+use crate::contacts::{ContactResolver, same_handle};
+use crate::summary::{trim_preview, SUMMARY_TEXT_CHARS};
+use edits::{parse_summary_info, revise};
+use balloons::{parse_link_preview, URL_BALLOON};
+use crate::group_name::GroupNameStyle;
+use crate::language::detect_language;
+use crate::receipts::chat_read_receipts;
+use crate::retry::BackoffPolicy;
+use crate::audio::audio_duration_secs;
+use crate::{apple_to_unix, unix_to_apple_secs};
 use chrono::{DateTime, Utc};
 
+pub mod typedstream;
+mod edits;
+mod balloons;
+
 #[derive(Error, Debug)]
 pub enum DbError {
     #[error("Database not found at {0}")]
@@ -16,23 +37,208 @@ pub enum DbError {
     PermissionDenied(PathBuf),
     #[error("SQLite error: {0}")]
     Sqlite(#[from] rusqlite::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl DbError {
+    /// chat.db was locked, usually by Messages syncing. Worth retrying.
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            DbError::Sqlite(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    }
+}
+
+/// How long SQLite itself waits on a lock before reporting busy.
+pub const BUSY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Whether chat.db could be read, for the frontend to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DatabaseStatus {
+    Ready,
+    /// Locked on the last try; `attempts` so far
+    Busy { attempts: u32 },
+}
+
+/// Chats with messages added or read since an earlier check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MessageChanges {
+    /// Distinct chat IDs, lowest first
+    pub chat_ids: Vec<i64>,
+    /// Highest message ROWID seen
+    pub newest_rowid: i64,
+    /// Latest `date_read` of a received message, so reads on another
+    /// device show up too
+    pub newest_read: i64,
+}
+
+/// One message as exported, in (date, ROWID) order across all chats.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportEvent {
+    pub chat_id: i64,
+    pub chat_guid: String,
+    pub chat_identifier: String,
+    pub rowid: i64,
+    pub guid: String,
+    pub date: DateTime<Utc>,
+    /// Raw chat.db date, kept so checkpoints are exact
+    pub apple_date: i64,
+    pub is_from_me: bool,
+    pub sender: Option<String>,
+    pub service: Option<String>,
+    pub text: String,
+    pub has_attachments: bool,
+    #[serde(default)]
+    pub attachment_count: usize,
+    /// `text` is the latest version
+    #[serde(default)]
+    pub edited: bool,
+    /// Unsent; `text` is empty
+    #[serde(default)]
+    pub retracted: bool,
+    /// "Me", or the sender's contact name; filled in by the exporters
+    #[serde(default)]
+    pub sender_name: Option<String>,
+}
+
+/// Position of the last exported message, in (date, ROWID) order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportCursor {
+    pub date: i64,
+    pub rowid: i64,
+}
+
+impl ExportCursor {
+    /// Before every message.
+    pub fn start() -> Self {
+        Self { date: i64::MIN, rowid: i64::MIN }
+    }
 }
 
+/// A message edited or unsent after it was first read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RevisedMessage {
+    pub rowid: i64,
+    pub retracted: bool,
+    /// Raw chat.db date of the latest edit or unsend
+    pub revised_at: i64,
+}
+
+/// Retries for reads that hit a locked chat.db: four tries over about
+/// three seconds, on top of [`BUSY_TIMEOUT`] each.
+pub fn busy_policy() -> BackoffPolicy {
+    BackoffPolicy {
+        max_attempts: 4,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(2),
+    }
+}
+
+/// Run `query` until it succeeds, fails for a reason other than a lock, or
+/// the policy runs out. `on_busy` hears about each locked attempt before the
+/// wait; `sleep` is injected so tests don't wait.
+pub fn retry_busy<T, Q, B, S>(policy: &BackoffPolicy, mut query: Q, mut on_busy: B, mut sleep: S) -> Result<T, DbError>
+where
+    Q: FnMut() -> Result<T, DbError>,
+    B: FnMut(u32),
+    S: FnMut(Duration),
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match query() {
+            Err(e) if e.is_busy() => {
+                on_busy(attempts);
+                if attempts >= policy.max_attempts {
+                    return Err(e);
+                }
+                sleep(policy.delay_after(attempts));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Source ID of the signed-in user's own Messages library.
+pub const LOCAL_SOURCE: &str = "local";
+
+/// Tables and columns the queries in this module read.
+pub const REQUIRED_SCHEMA: &[(&str, &[&str])] = &[
+    ("chat", &[
+        "ROWID", "guid", "chat_identifier", "display_name", "style", "service_name", "is_filtered", "properties",
+        "last_addressed_handle",
+    ]),
+    ("handle", &["ROWID", "id", "service"]),
+    ("message", &[
+        "ROWID", "guid", "text", "attributedBody", "date", "is_from_me", "is_read", "item_type",
+        "is_finished", "cache_has_attachments", "handle_id", "service", "associated_message_guid",
+        "associated_message_type", "other_handle", "group_action_type", "group_title",
+        "is_delivered", "date_delivered", "date_read",
+    ]),
+    ("chat_message_join", &["chat_id", "message_id"]),
+    ("chat_handle_join", &["chat_id", "handle_id"]),
+    ("attachment", &["ROWID", "filename", "mime_type", "transfer_name"]),
+    ("message_attachment_join", &["message_id", "attachment_id"]),
+];
+
+/// Latest messages loaded with each conversation; older ones come from
+/// [`Database::messages_for_chat`].
+pub const PREVIEW_MESSAGES: usize = 15;
+
+/// Days of history in each conversation's `activity`.
+pub const ACTIVITY_DAYS: usize = 14;
+
 /// Handle to the iMessage database.
 pub struct Database {
     conn: Connection,
+    /// Which library this is, copied onto every conversation it loads
+    source_id: String,
+    /// How unnamed groups are labelled when names are resolved
+    group_name_style: GroupNameStyle,
+    /// Fill in `Conversation::stats` when loading the queue
+    load_stats: bool,
+    /// Include group renames, joins and leaves as messages
+    system_events: bool,
+    /// "Now" for age buckets, activity and reply reasons
+    clock: Arc<dyn Clock>,
+    /// Columns of the message table, for those only newer macOS versions
+    /// have; read on first use so opening never reads the schema
+    message_columns: OnceCell<Vec<String>>,
+    /// Attachments folder of the library, for reading audio lengths
+    attachments_dir: PathBuf,
 }
 
 impl Database {
     /// Default chat.db path.
+    #[cfg(not(feature = "dev-sample"))]
     pub fn default_path() -> PathBuf {
         dirs::home_dir()
             .expect("home directory required")
             .join("Library/Messages/chat.db")
     }
 
+    /// The generated sample library, in builds for working off macOS.
+    #[cfg(feature = "dev-sample")]
+    pub fn default_path() -> PathBuf {
+        crate::sample::ensure_sample_library().join("chat.db")
+    }
+
+    /// chat.db inside another user's home directory, e.g. "/Users/kid".
+    pub fn library_path(home: &Path) -> PathBuf {
+        home.join("Library/Messages/chat.db")
+    }
+
     /// Open the database read-only.
     pub fn open(path: &PathBuf) -> Result<Self, DbError> {
+        Self::open_source(path, LOCAL_SOURCE)
+    }
+
+    /// Open a library read-only, tagging its conversations with `source_id`.
+    pub fn open_source(path: &PathBuf, source_id: &str) -> Result<Self, DbError> {
         if !path.exists() {
             return Err(DbError::NotFound(path.clone()));
         }
@@ -47,49 +253,213 @@ impl Database {
                 DbError::Sqlite(e)
             }
         })?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
 
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            source_id: source_id.to_string(),
+            group_name_style: GroupNameStyle::default(),
+            load_stats: false,
+            system_events: false,
+            clock: Arc::new(SystemClock),
+            message_columns: OnceCell::new(),
+            attachments_dir: Self::attachments_dir_for(path, source_id),
+        })
     }
 
-    /// Get all conversations with unread messages.
-    pub fn unread_conversations(&self) -> Result<Vec<Conversation>, DbError> {
-        let mut stmt = self.conn.prepare(
+    /// Attachments folder of the library `source_id`, whose chat.db is read
+    /// from `path`. The user's own library may be read from a snapshot in
+    /// the cache, so its folder is always the one beside the real chat.db;
+    /// other libraries keep theirs beside their chat.db.
+    pub fn attachments_dir_for(path: &Path, source_id: &str) -> PathBuf {
+        if source_id == LOCAL_SOURCE {
+            crate::default_attachments_dir()
+        } else {
+            path.with_file_name("Attachments")
+        }
+    }
+
+    /// Read attachment files such as audio messages from `dir`.
+    pub fn set_attachments_dir(&mut self, dir: PathBuf) {
+        self.attachments_dir = dir;
+    }
+
+    pub fn source_id(&self) -> &str {
+        &self.source_id
+    }
+
+    /// Style for group names derived in `unread_conversations`.
+    pub fn set_group_name_style(&mut self, style: GroupNameStyle) {
+        self.group_name_style = style;
+    }
+
+    /// Whether loaded conversations carry [`ConversationStats`].
+    pub fn set_load_stats(&mut self, load_stats: bool) {
+        self.load_stats = load_stats;
+    }
+
+    /// Whether loaded messages include group renames, joins and leaves,
+    /// with `system_event` set and text describing them.
+    pub fn set_include_system_events(&mut self, system_events: bool) {
+        self.system_events = system_events;
+    }
+
+    /// Clock used for anything relative to now.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// `m.<column>` if the message table has it, else `fallback`, for
+    /// columns added in later macOS versions: thread_originator_guid (11),
+    /// date_edited, date_retracted and message_summary_info (13).
+    fn message_column(&self, column: &str, fallback: &'static str) -> Result<String, DbError> {
+        if self.message_columns.get().is_none() {
+            let mut stmt = self.conn.prepare("SELECT name FROM pragma_table_info('message')")?;
+            let columns = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+            let _ = self.message_columns.set(columns);
+        }
+        let has = self.message_columns.get().is_some_and(|c| c.iter().any(|c| c == column));
+        Ok(if has { format!("m.{}", column) } else { fallback.to_string() })
+    }
+
+    /// Run a trivial query to confirm the database is actually readable.
+    pub fn ping(&self) -> Result<(), DbError> {
+        self.conn.query_row("SELECT COUNT(*) FROM message LIMIT 1", [], |row| row.get::<_, i64>(0))?;
+        Ok(())
+    }
+
+    /// Total rows in the message table.
+    pub fn message_count(&self) -> Result<i64, DbError> {
+        Ok(self.conn.query_row("SELECT COUNT(*) FROM message", [], |row| row.get(0))?)
+    }
+
+    /// Run `PRAGMA quick_check`. Returns the problems found, empty if healthy.
+    pub fn quick_check(&self) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare("PRAGMA quick_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut problems = Vec::new();
+        for row in rows {
+            let row = row?;
+            if row != "ok" {
+                problems.push(row);
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Entries of [`REQUIRED_SCHEMA`] this database lacks, as "table" or
+    /// "table.column".
+    pub fn missing_schema(&self) -> Result<Vec<String>, DbError> {
+        let mut missing = Vec::new();
+        for (table, columns) in REQUIRED_SCHEMA {
+            let mut stmt = self.conn.prepare("SELECT name FROM pragma_table_info(?)")?;
+            let present: Vec<String> = stmt
+                .query_map([table], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            if present.is_empty() {
+                missing.push(table.to_string());
+                continue;
+            }
+            for column in columns.iter() {
+                // ROWID is implicit on ordinary tables
+                if *column != "ROWID" && !present.iter().any(|p| p == column) {
+                    missing.push(format!("{}.{}", table, column));
+                }
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Write a point-in-time copy of the database to `dest`. The copy is
+    /// built beside `dest` and renamed over it, so readers of `dest` see
+    /// either the old copy or the new one.
+    pub fn snapshot_to(&self, dest: &Path) -> Result<(), DbError> {
+        let partial = dest.with_extension("partial");
+        let _ = std::fs::remove_file(&partial);
+        self.conn.execute("VACUUM INTO ?1", [partial.to_string_lossy()])?;
+        std::fs::rename(&partial, dest)?;
+        Ok(())
+    }
+
+    /// Get all conversations with unread messages, with names filled in from
+    /// `resolver` when given.
+    pub fn unread_conversations(&self, resolver: Option<&ContactResolver>) -> Result<Vec<Conversation>, DbError> {
+        self.query_unread(resolver, None)
+    }
+
+    /// One chat loaded as in [`Database::unread_conversations`], or None if
+    /// nothing in it is unread any more.
+    pub fn unread_conversation(&self, chat_id: i64, resolver: Option<&ContactResolver>) -> Result<Option<Conversation>, DbError> {
+        Ok(self.query_unread(resolver, Some(chat_id))?.pop())
+    }
+
+    fn query_unread(&self, resolver: Option<&ContactResolver>, only_chat: Option<i64>) -> Result<Vec<Conversation>, DbError> {
+        // Scan every message in the chat so my own replies count toward
+        // last_message_date but not last_incoming_date. A single chat gets
+        // its own WHERE clause so SQLite looks it up by primary key rather
+        // than joining every chat's messages first.
+        let mut stmt = self.conn.prepare(&format!(
             "SELECT 
                 c.ROWID as chat_id,
                 c.display_name,
                 c.chat_identifier,
                 c.style,
-                COUNT(*) as unread_count,
-                MAX(m.date) as last_message_date
+                SUM(m.unread) as unread_count,
+                MAX(m.date) as last_message_date,
+                c.service_name,
+                MAX(CASE WHEN m.unread THEN m.ROWID END) as last_unread_rowid,
+                MAX(CASE WHEN m.is_from_me = 0 THEN m.date END) as last_incoming_date,
+                MIN(CASE WHEN m.unread THEN m.date END) as first_unread_date,
+                c.guid,
+                c.properties
             FROM chat c
             JOIN chat_message_join cmj ON c.ROWID = cmj.chat_id
-            JOIN message m ON cmj.message_id = m.ROWID
-            WHERE m.is_read = 0 
-              AND m.is_from_me = 0
-              AND m.item_type = 0
-              AND m.is_finished = 1
-              AND c.is_filtered != 2
+            JOIN (
+                SELECT ROWID, date, is_from_me,
+                       (is_read = 0 AND is_from_me = 0 AND is_finished = 1) as unread
+                FROM message
+                WHERE item_type = 0
+            ) m ON cmj.message_id = m.ROWID
+            WHERE c.is_filtered != 2 {}
             GROUP BY c.ROWID
-            ORDER BY last_message_date DESC"
-        )?;
+            HAVING unread_count > 0
+            ORDER BY last_message_date DESC",
+            chat_filter(only_chat.as_ref().map(std::slice::from_ref))
+        ))?;
 
+        let now = self.clock.now();
+        let my_handles = self.my_handles()?;
         let mut conversations = Vec::new();
-        let rows = stmt.query_map([], |row| {
-            let apple_ts: i64 = row.get(5)?;
-            let unix_ts = apple_to_unix(apple_ts);
-            let date = DateTime::from_timestamp(unix_ts, 0)
-                .unwrap_or_else(Utc::now);
-
+        let rows = stmt.query_map(rusqlite::params_from_iter(only_chat), |row| {
+            let first_unread_date = apple_date(row.get(9)?);
+            let properties: Option<Vec<u8>> = row.get(11)?;
             Ok(Conversation {
+                source_id: self.source_id.clone(),
                 chat_id: row.get(0)?,
+                guid: row.get(10)?,
                 display_name: row.get(1)?,
                 chat_identifier: row.get(2)?,
                 style: row.get(3)?,
+                service_name: row.get(6)?,
                 unread_count: row.get(4)?,
-                last_message_date: date,
+                last_message_date: apple_date(row.get(5)?),
+                last_incoming_date: apple_date(row.get(8)?),
+                first_unread_date,
+                age_bucket: AgeBucket::since(first_unread_date, now),
+                last_unread_rowid: row.get(7)?,
                 messages: Vec::new(),
                 participants: Vec::new(),
+                my_handles: my_handles.clone(),
                 resolved_name: None,
+                messages_app_draft: None,
+                primary_language: None,
+                read_receipts: properties.as_deref().and_then(chat_read_receipts),
+                activity: Vec::new(),
+                stats: None,
+                preview_image: None,
+                needs_reply: false,
+                needs_reply_reason: None,
+                is_mms_group: false,
             })
         })?;
 
@@ -101,32 +471,655 @@ impl Database {
         for conv in &mut conversations {
             self.load_participants(conv)?;
             self.load_messages(conv)?;
+            conv.primary_language = detect_language(&conv.messages);
+            conv.activity = self.activity_histogram(conv.chat_id, ACTIVITY_DAYS)?;
+            if self.load_stats {
+                conv.stats = Some(self.conversation_stats(conv.chat_id)?);
+            }
+            if !conv.supports_read_receipts() {
+                conv.read_receipts = Some(false);
+            }
+            if let Some(resolver) = resolver {
+                conv.resolve_names(resolver, self.group_name_style);
+                let latest_image = self.latest_image(conv.chat_id)?;
+                conv.preview_image = Some(conv.preview_image_ref(latest_image.as_ref(), resolver, &self.attachments_dir));
+            }
+            conv.update_needs_reply(resolver, now);
+            conv.is_mms_group = conv.is_mms_group();
         }
 
         Ok(conversations)
     }
 
+    /// Messages per day in a chat over the last `days` days, oldest first.
+    /// Days are 24-hour windows back from now.
+    pub fn activity_histogram(&self, chat_id: i64, days: usize) -> Result<Vec<u32>, DbError> {
+        let now = self.clock.now();
+        let mut counts = vec![0; days];
+        let since = unix_to_apple_secs(now.timestamp() - days as i64 * 86_400);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT m.date FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             WHERE cmj.chat_id = ? AND m.item_type = 0 AND {} >= ?",
+            apple_seconds_sql("m.date")
+        ))?;
+        let dates = stmt.query_map(rusqlite::params![chat_id, since], |row| row.get::<_, i64>(0))?;
+        for date in dates {
+            let days_ago = (now - apple_date(date?)).num_days();
+            if (0..days as i64).contains(&days_ago) {
+                counts[days - 1 - days_ago as usize] += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Message count, my share of it, and how long I usually take to reply.
+    /// A reply gap is from an incoming message to my message right after it.
+    pub fn conversation_stats(&self, chat_id: i64) -> Result<ConversationStats, DbError> {
+        let (total, mine): (u64, u64) = self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(m.is_from_me), 0) FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             WHERE cmj.chat_id = ? AND m.item_type = 0 AND m.associated_message_type = 0",
+            [chat_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT date, prev_date FROM (
+                SELECT m.date, m.is_from_me,
+                       LAG(m.date) OVER w AS prev_date,
+                       LAG(m.is_from_me) OVER w AS prev_from_me
+                FROM message m
+                JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+                WHERE cmj.chat_id = ? AND m.item_type = 0 AND m.associated_message_type = 0
+                WINDOW w AS (ORDER BY m.date, m.ROWID)
+             )
+             WHERE is_from_me = 1 AND prev_from_me = 0",
+        )?;
+        let rows = stmt.query_map([chat_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+        let mut gaps = Vec::new();
+        for row in rows {
+            let (date, prev) = row?;
+            gaps.push(apple_to_unix(date) - apple_to_unix(prev));
+        }
+        gaps.sort_unstable();
+
+        Ok(ConversationStats {
+            total_messages: total,
+            my_share_percent: if total == 0 { 0.0 } else { mine as f32 * 100.0 / total as f32 },
+            median_reply_gap: gaps.get(gaps.len() / 2).copied(),
+        })
+    }
+
+    /// Highest message ROWID in a chat, read or not.
+    pub fn latest_message_rowid(&self, chat_identifier: &str) -> Result<Option<i64>, DbError> {
+        let rowid = self.conn.query_row(
+            "SELECT MAX(m.ROWID) FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             JOIN chat c ON cmj.chat_id = c.ROWID
+             WHERE c.chat_identifier = ?",
+            [chat_identifier],
+            |row| row.get(0),
+        )?;
+        Ok(rowid)
+    }
+
+    /// Whether the chat with `chat_guid` has nothing unread and ends with my
+    /// own message, i.e. it was answered somewhere else.
+    pub fn handled_in_messages(&self, chat_guid: &str) -> Result<bool, DbError> {
+        let (unread, last_from_me): (i64, Option<bool>) = self.conn.query_row(
+            "SELECT
+                (SELECT COUNT(*) FROM message m
+                 JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+                 JOIN chat c ON c.ROWID = cmj.chat_id
+                 WHERE c.guid = ?1 AND m.item_type = 0
+                   AND m.is_read = 0 AND m.is_from_me = 0 AND m.is_finished = 1),
+                (SELECT m.is_from_me FROM message m
+                 JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+                 JOIN chat c ON c.ROWID = cmj.chat_id
+                 WHERE c.guid = ?1 AND m.item_type = 0
+                 ORDER BY m.date DESC, m.ROWID DESC LIMIT 1)",
+            [chat_guid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(unread == 0 && last_from_me == Some(true))
+    }
+
+    /// Chats that gained messages or had messages read since `after`, the
+    /// result of the previous call. Pass None to just learn where chat.db
+    /// is up to.
+    pub fn changes_since(&self, after: Option<&MessageChanges>) -> Result<MessageChanges, DbError> {
+        let (newest_rowid, newest_read): (i64, i64) = self.conn.query_row(
+            "SELECT COALESCE(MAX(ROWID), 0),
+                    COALESCE(MAX(CASE WHEN is_from_me = 0 THEN date_read END), 0)
+             FROM message",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let Some(after) = after.filter(|after| after.newest_rowid < newest_rowid || after.newest_read < newest_read) else {
+            return Ok(MessageChanges { chat_ids: Vec::new(), newest_rowid, newest_read });
+        };
+        let mut stmt = self.conn.prepare(
+            "SELECT cmj.chat_id FROM chat_message_join cmj
+             WHERE cmj.message_id > ?1 AND cmj.message_id <= ?2
+             UNION
+             SELECT cmj.chat_id FROM message m
+             JOIN chat_message_join cmj ON cmj.message_id = m.ROWID
+             WHERE m.is_from_me = 0 AND m.date_read > ?3 AND m.date_read <= ?4
+             ORDER BY 1",
+        )?;
+        let chat_ids = stmt
+            .query_map([after.newest_rowid, newest_rowid, after.newest_read, newest_read], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(MessageChanges { chat_ids, newest_rowid, newest_read })
+    }
+
+    /// Whether the chat with this identifier is a group chat.
+    pub fn is_group_chat(&self, chat_identifier: &str) -> Result<bool, DbError> {
+        let style: Option<i32> = self.conn.query_row(
+            "SELECT style FROM chat WHERE chat_identifier = ? LIMIT 1",
+            [chat_identifier],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(style == Some(43))
+    }
+
+    /// Whether the chat with this identifier is an SMS/MMS group, as
+    /// [`Conversation::is_mms_group`] decides.
+    pub fn is_mms_group_chat(&self, chat_identifier: &str) -> Result<bool, DbError> {
+        let chat: Option<(i32, Option<String>, String)> = self.conn.query_row(
+            "SELECT style, service_name, guid FROM chat WHERE chat_identifier = ? LIMIT 1",
+            [chat_identifier],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).optional()?;
+        Ok(chat.is_some_and(|(style, service, guid)| is_mms_group(style, service.as_deref(), &guid)))
+    }
+
+    /// Identifier and whether it's a group, for the chat whose latest
+    /// message is `message_guid`. None if the message is gone or newer ones
+    /// have arrived since.
+    pub fn latest_message_chat(&self, message_guid: &str) -> Result<Option<(String, bool)>, DbError> {
+        let chat = self.conn.query_row(
+            "SELECT c.chat_identifier, c.style FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             JOIN chat c ON cmj.chat_id = c.ROWID
+             WHERE m.guid = ?1
+               AND m.ROWID = (
+                   SELECT MAX(latest.ROWID) FROM message latest
+                   JOIN chat_message_join lj ON latest.ROWID = lj.message_id
+                   WHERE lj.chat_id = cmj.chat_id AND latest.item_type = 0 AND latest.associated_message_type = 0
+               )",
+            [message_guid],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)? == 43)),
+        ).optional()?;
+        Ok(chat)
+    }
+
+    /// Service the chat with `chat_guid` runs over, if it's still there.
+    pub fn chat_service(&self, chat_guid: &str) -> Result<Option<String>, DbError> {
+        let service = self.conn.query_row(
+            "SELECT service_name FROM chat WHERE guid = ?",
+            [chat_guid],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(service.flatten())
+    }
+
+    /// Service ("iMessage", "SMS", ...) of the latest message from a handle.
+    pub fn latest_service(&self, handle: &str) -> Result<Option<String>, DbError> {
+        let service = self.conn.query_row(
+            "SELECT m.service FROM message m
+             JOIN handle h ON m.handle_id = h.ROWID
+             WHERE h.id = ? AND m.is_from_me = 0
+             ORDER BY m.date DESC
+             LIMIT 1",
+            [handle],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(service.flatten())
+    }
+
+    /// Every handle with its service, most recently active first. A handle
+    /// known on several services appears once per service.
+    pub fn handle_activity(&self) -> Result<Vec<HandleActivity>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT h.id, h.service, MAX(m.date) AS last_date
+             FROM handle h
+             LEFT JOIN message m ON m.handle_id = h.ROWID
+             GROUP BY h.ROWID
+             ORDER BY last_date IS NULL, last_date DESC"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(HandleActivity {
+                handle: row.get(0)?,
+                service: row.get(1)?,
+                last_active: row.get::<_, Option<i64>>(2)?.map(apple_date),
+            })
+        })?;
+
+        let mut handles = Vec::new();
+        for row in rows {
+            handles.push(row?);
+        }
+        Ok(handles)
+    }
+
+    /// Attachments in a chat, newest first, without loading message text.
+    ///
+    /// `kinds` are MIME type prefixes such as "image/" or "video/"; empty means
+    /// all. `before` pages backwards from a date.
+    pub fn media_for_chat(
+        &self,
+        chat_id: i64,
+        kinds: &[&str],
+        limit: usize,
+        before: Option<DateTime<Utc>>,
+    ) -> Result<Vec<MediaItem>, DbError> {
+        let kind_filter = if kinds.is_empty() {
+            String::new()
+        } else {
+            let clauses = vec!["a.mime_type LIKE ?"; kinds.len()].join(" OR ");
+            format!("AND ({})", clauses)
+        };
+
+        let query = format!(
+            "SELECT a.filename, a.mime_type, a.transfer_name,
+                    m.ROWID, m.date, m.is_from_me, h.id
+             FROM attachment a
+             JOIN message_attachment_join maj ON a.ROWID = maj.attachment_id
+             JOIN message m ON maj.message_id = m.ROWID
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             LEFT JOIN handle h ON m.handle_id = h.ROWID
+             WHERE cmj.chat_id = ?
+               AND {} < ?
+               AND a.filename IS NOT NULL
+               {}
+             ORDER BY m.date DESC
+             LIMIT ?",
+            apple_seconds_sql("m.date"),
+            kind_filter
+        );
+
+        let before = before.map(|d| unix_to_apple_secs(d.timestamp())).unwrap_or(i64::MAX);
+        let patterns: Vec<String> = kinds.iter().map(|k| format!("{}%", k)).collect();
+        let limit = limit as i64;
+
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&chat_id, &before];
+        params.extend(patterns.iter().map(|p| p as &dyn rusqlite::ToSql));
+        params.push(&limit);
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(params.as_slice(), |row| {
+            let apple_ts: i64 = row.get(4)?;
+            Ok(MediaItem {
+                attachment: Attachment::new(
+                    row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                    row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                ),
+                message_rowid: row.get(3)?,
+                date: DateTime::from_timestamp(apple_to_unix(apple_ts), 0).unwrap_or_else(Utc::now),
+                is_from_me: row.get(5)?,
+                sender: row.get(6)?,
+            })
+        })?;
+
+        let mut media = Vec::new();
+        for row in rows {
+            media.push(row?);
+        }
+        Ok(media)
+    }
+
+    /// Membership changes and renames in a group chat, oldest first.
+    ///
+    /// Rebuilt from the system rows chat.db stores alongside messages:
+    /// item_type 1 is an add (group_action_type 0) or removal (1), 2 is a
+    /// rename, and 3 with group_action_type 0 is someone leaving.
+    pub fn participant_history(&self, chat_id: i64) -> Result<Vec<ParticipantEvent>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT m.item_type, m.group_action_type, m.group_title, m.date,
+                    m.is_from_me, h.id, oh.id
+             FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             LEFT JOIN handle h ON m.handle_id = h.ROWID
+             LEFT JOIN handle oh ON m.other_handle = oh.ROWID
+             WHERE cmj.chat_id = ?
+               AND m.item_type IN (1, 2, 3)
+             ORDER BY m.date ASC"
+        )?;
+
+        let rows = stmt.query_map([chat_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<i64>>(1)?.unwrap_or(0),
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, bool>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+            ))
+        })?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            let (item_type, action, title, apple_ts, is_from_me, actor, target) = row?;
+            let Some(change) = participant_change(item_type, action, title) else {
+                continue;
+            };
+
+            events.push(ParticipantEvent {
+                date: apple_date(apple_ts),
+                change,
+                actor: if is_from_me { None } else { actor },
+                target,
+            });
+        }
+
+        Ok(events)
+    }
+
+    /// The 1:1 chat and every group someone is in, with when they last wrote
+    /// in each. Accepts a handle or a contact name known to `contacts`.
+    /// Direct chats come first, then groups by most recent activity.
+    pub fn chats_for_contact(
+        &self,
+        identifier_or_name: &str,
+        contacts: &ContactResolver,
+    ) -> Result<Vec<ContactChat>, DbError> {
+        let mut wanted = contacts.identifiers_for(identifier_or_name);
+        if wanted.is_empty() {
+            wanted.push(identifier_or_name.trim().to_string());
+        }
+
+        let mut stmt = self.conn.prepare("SELECT ROWID, id FROM handle")?;
+        let handle_ids: Vec<i64> = stmt
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .filter_map(Result::ok)
+            .filter(|(_, id)| wanted.iter().any(|w| same_handle(w, id)))
+            .map(|(rowid, _)| rowid)
+            .collect();
+        if handle_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; handle_ids.len()].join(",");
+        let query = format!(
+            "SELECT c.ROWID, c.guid, c.chat_identifier, c.display_name, c.style,
+                    (SELECT MAX(m.date) FROM message m
+                     JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+                     WHERE cmj.chat_id = c.ROWID AND m.is_from_me = 0 AND m.handle_id IN ({0}))
+             FROM chat c
+             WHERE c.ROWID IN (SELECT chat_id FROM chat_handle_join WHERE handle_id IN ({0}))",
+            placeholders
+        );
+        let params: Vec<&dyn rusqlite::ToSql> = handle_ids
+            .iter()
+            .chain(handle_ids.iter())
+            .map(|id| id as &dyn rusqlite::ToSql)
+            .collect();
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(params.as_slice(), |row| {
+            Ok(ContactChat {
+                chat_id: row.get(0)?,
+                guid: row.get(1)?,
+                chat_identifier: row.get(2)?,
+                display_name: row.get(3)?,
+                is_group: row.get::<_, i64>(4)? == 43,
+                participants: Vec::new(),
+                last_activity: row.get::<_, Option<i64>>(5)?.map(apple_date),
+            })
+        })?;
+
+        let mut chats = Vec::new();
+        for row in rows {
+            let mut chat = row?;
+            let mut stmt = self.conn.prepare(
+                "SELECT h.id FROM handle h
+                 JOIN chat_handle_join chj ON h.ROWID = chj.handle_id
+                 WHERE chj.chat_id = ?"
+            )?;
+            chat.participants = stmt
+                .query_map([chat.chat_id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            chats.push(chat);
+        }
+
+        chats.sort_by(|a, b| a.is_group.cmp(&b.is_group).then(b.last_activity.cmp(&a.last_activity)));
+        Ok(chats)
+    }
+
+    /// Up to `limit` messages after `cursor` in (date, ROWID) order, across
+    /// all chats or just `chat_ids`. Used by the CSV exporter.
+    pub fn messages_after(
+        &self,
+        cursor: &ExportCursor,
+        chat_ids: Option<&[i64]>,
+        limit: usize,
+    ) -> Result<Vec<ExportEvent>, DbError> {
+        let limit = limit as i64;
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&cursor.date, &cursor.rowid];
+        if let Some(ids) = chat_ids {
+            params.extend(ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+        }
+        params.push(&limit);
+        self.export_events(
+            &format!("AND (m.date, m.ROWID) > (?, ?) {} ORDER BY m.date, m.ROWID LIMIT ?", chat_filter(chat_ids)),
+            &params,
+        )
+    }
+
+    /// Up to `limit` messages with a ROWID above `after_rowid`, in ROWID
+    /// order. ROWIDs only grow as rows are added, so a message that arrives
+    /// late with an earlier date, delayed or synced in from iCloud, still
+    /// comes after everything read before it. For readers that keep their
+    /// place between runs.
+    pub fn messages_after_rowid(
+        &self,
+        after_rowid: i64,
+        chat_ids: Option<&[i64]>,
+        limit: usize,
+    ) -> Result<Vec<ExportEvent>, DbError> {
+        let limit = limit as i64;
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![&after_rowid];
+        if let Some(ids) = chat_ids {
+            params.extend(ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+        }
+        params.push(&limit);
+        self.export_events(
+            &format!("AND m.ROWID > ? {} ORDER BY m.ROWID LIMIT ?", chat_filter(chat_ids)),
+            &params,
+        )
+    }
+
+    /// The messages with these ROWIDs, read as [`Database::messages_after`]
+    /// reads them.
+    pub fn messages_by_rowid(&self, rowids: &[i64]) -> Result<Vec<ExportEvent>, DbError> {
+        if rowids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let params: Vec<&dyn rusqlite::ToSql> = rowids.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
+        self.export_events(
+            &format!("AND m.ROWID IN ({}) ORDER BY m.date, m.ROWID", vec!["?"; rowids.len()].join(",")),
+            &params,
+        )
+    }
+
+    /// Export rows for messages matching `conditions`, which follow the
+    /// WHERE clause and may order and limit. Edited messages read as their
+    /// latest version and unsent ones as empty, as in a chat's history.
+    fn export_events(&self, conditions: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<ExportEvent>, DbError> {
+        let query = format!(
+            "SELECT m.ROWID, m.guid, m.text, m.attributedBody, m.date, m.is_from_me,
+                    h.id, m.service, m.cache_has_attachments,
+                    c.ROWID, c.guid, c.chat_identifier,
+                    (SELECT COUNT(*) FROM message_attachment_join maj WHERE maj.message_id = m.ROWID),
+                    {}, {}, {}
+             FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             JOIN chat c ON cmj.chat_id = c.ROWID
+             LEFT JOIN handle h ON m.handle_id = h.ROWID
+             WHERE m.item_type = 0
+               AND m.associated_message_type = 0
+               {}",
+            self.message_column("date_edited", "0")?,
+            self.message_column("date_retracted", "0")?,
+            self.message_column("message_summary_info", "NULL")?,
+            conditions
+        );
+
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows = stmt.query_map(params, |row| {
+            let text: Option<String> = row.get(2)?;
+            let attributed_body: Option<Vec<u8>> = row.get(3)?;
+            let apple_ts: i64 = row.get(4)?;
+            let summary_info: Option<Vec<u8>> = row.get(15)?;
+            let mut summary = summary_info.as_deref().and_then(parse_summary_info).unwrap_or_default();
+            let revision = revise(text, attributed_body, row.get(13)?, row.get(14)?, &mut summary);
+            Ok(ExportEvent {
+                rowid: row.get(0)?,
+                guid: row.get(1)?,
+                text: revision.text,
+                apple_date: apple_ts,
+                date: apple_date(apple_ts),
+                is_from_me: row.get(5)?,
+                sender: row.get(6)?,
+                service: row.get(7)?,
+                has_attachments: row.get(8)?,
+                attachment_count: row.get(12)?,
+                chat_id: row.get(9)?,
+                chat_guid: row.get(10)?,
+                chat_identifier: row.get(11)?,
+                edited: revision.edited,
+                retracted: revision.retracted,
+                sender_name: None,
+            })
+        })?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            events.push(row?);
+        }
+        Ok(events)
+    }
+
+    /// Messages edited or unsent after `since`, a raw chat.db date. Empty
+    /// on macOS versions without edits.
+    pub fn revised_since(&self, since: i64) -> Result<Vec<RevisedMessage>, DbError> {
+        let edited = self.message_column("date_edited", "0")?;
+        let retracted = self.message_column("date_retracted", "0")?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT m.ROWID, IFNULL({retracted}, 0) > 0, MAX(IFNULL({edited}, 0), IFNULL({retracted}, 0)) AS revised
+             FROM message m
+             WHERE revised > ?1
+             ORDER BY revised",
+        ))?;
+        let rows = stmt.query_map([since], |row| {
+            Ok(RevisedMessage { rowid: row.get(0)?, retracted: row.get(1)?, revised_at: row.get(2)? })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The highest message ROWID, and how many messages have a ROWID at or
+    /// below `upto` (every message when None). ROWIDs only grow, so a
+    /// count that drops for the same `upto` means messages were deleted.
+    pub fn message_census(&self, upto: Option<i64>) -> Result<(i64, i64), DbError> {
+        Ok(self.conn.query_row(
+            "SELECT (SELECT IFNULL(MAX(ROWID), 0) FROM message),
+                    (SELECT COUNT(*) FROM message WHERE ?1 IS NULL OR ROWID <= ?1)",
+            [upto],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?)
+    }
+
+    /// Every message ROWID, oldest first.
+    pub fn message_rowids(&self) -> Result<Vec<i64>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT ROWID FROM message ORDER BY ROWID")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Distinct handles that sent messages in `chat_ids`, or in any chat.
+    pub fn sender_handles(&self, chat_ids: Option<&[i64]>) -> Result<Vec<String>, DbError> {
+        let chat_filter = match chat_ids {
+            Some(ids) => format!("WHERE cmj.chat_id IN ({})", vec!["?"; ids.len()].join(",")),
+            None => String::new(),
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT DISTINCT h.id FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             JOIN handle h ON m.handle_id = h.ROWID
+             {}
+             ORDER BY h.id",
+            chat_filter
+        ))?;
+        let ids = chat_ids.unwrap_or_default();
+        let handles = stmt.query_map(rusqlite::params_from_iter(ids), |row| row.get(0))?;
+        Ok(handles.collect::<Result<_, _>>()?)
+    }
+
+    /// Every handle I've sent from in this library: my phone number and
+    /// Apple ID emails, as chats were last addressed from them.
+    pub fn my_handles(&self) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT last_addressed_handle FROM chat
+             WHERE last_addressed_handle IS NOT NULL AND last_addressed_handle != ''"
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
     fn load_participants(&self, conv: &mut Conversation) -> Result<(), DbError> {
         if !conv.is_group() {
             return Ok(());
         }
+        conv.participants = self.chat_participants(conv.chat_id)?;
+        Ok(())
+    }
 
+    /// Handles of everyone else in the chat with this GUID.
+    pub fn chat_participants_by_guid(&self, chat_guid: &str) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT h.id FROM handle h
+             JOIN chat_handle_join chj ON h.ROWID = chj.handle_id
+             JOIN chat c ON c.ROWID = chj.chat_id
+             WHERE c.guid = ?"
+        )?;
+
+        let rows = stmt.query_map([chat_guid], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Handles of everyone else in a chat.
+    pub fn chat_participants(&self, chat_id: i64) -> Result<Vec<String>, DbError> {
         let mut stmt = self.conn.prepare(
             "SELECT h.id FROM handle h
              JOIN chat_handle_join chj ON h.ROWID = chj.handle_id
              WHERE chj.chat_id = ?"
         )?;
 
-        let rows = stmt.query_map([conv.chat_id], |row| row.get(0))?;
-        for row in rows {
-            conv.participants.push(row?);
-        }
+        let rows = stmt.query_map([chat_id], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
 
+    fn load_messages(&self, conv: &mut Conversation) -> Result<(), DbError> {
+        conv.messages = self.messages_for_chat(conv.chat_id, None, PREVIEW_MESSAGES)?;
         Ok(())
     }
 
-    fn load_messages(&self, conv: &mut Conversation) -> Result<(), DbError> {
-        let mut stmt = self.conn.prepare(
+    /// Messages in a chat, newest or oldest first per `order`, with their
+    /// attachments and reactions. `filter` is extra SQL on `m` whose
+    /// parameters start at ?2; ?1 is the chat ID.
+    fn query_messages(
+        &self,
+        chat_id: i64,
+        filter: &str,
+        order: &str,
+        limit: usize,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<Vec<Message>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
             "SELECT 
                 m.ROWID,
                 m.guid,
@@ -135,21 +1128,47 @@ impl Database {
                 m.date,
                 m.is_from_me,
                 m.cache_has_attachments,
-                h.id as sender
+                h.id as sender,
+                {},
+                m.is_delivered,
+                m.date_delivered,
+                m.date_read,
+                {},
+                {},
+                {},
+                m.item_type,
+                m.group_action_type,
+                m.group_title,
+                oh.id,
+                {},
+                {}
             FROM message m
             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
             LEFT JOIN handle h ON m.handle_id = h.ROWID
-            WHERE cmj.chat_id = ?
-              AND m.item_type = 0
+            LEFT JOIN handle oh ON m.other_handle = oh.ROWID
+            WHERE cmj.chat_id = ?1
+              AND m.item_type IN ({})
               AND m.associated_message_type = 0
-            ORDER BY m.date DESC
-            LIMIT 15"
-        )?;
+              {}
+            ORDER BY m.date {order}, m.ROWID {order}
+            LIMIT {}",
+            self.message_column("thread_originator_guid", "NULL")?,
+            self.message_column("date_edited", "0")?,
+            self.message_column("date_retracted", "0")?,
+            self.message_column("message_summary_info", "NULL")?,
+            self.message_column("balloon_bundle_id", "NULL")?,
+            self.message_column("payload_data", "NULL")?,
+            if self.system_events { "0, 1, 2, 3" } else { "0" },
+            filter,
+            limit
+        ))?;
 
         let mut messages = Vec::new();
         let mut guids = Vec::new();
 
-        let rows = stmt.query_map([conv.chat_id], |row| {
+        let mut all_params: Vec<&dyn rusqlite::ToSql> = vec![&chat_id];
+        all_params.extend_from_slice(params);
+        let rows = stmt.query_map(all_params.as_slice(), |row| {
             let rowid: i64 = row.get(0)?;
             let guid: String = row.get(1)?;
             let text: Option<String> = row.get(2)?;
@@ -158,31 +1177,77 @@ impl Database {
             let is_from_me: bool = row.get(5)?;
             let has_attachments: bool = row.get(6)?;
             let sender: Option<String> = row.get(7)?;
+            let reply_to_guid: Option<String> = row.get(8)?;
+            // Receipts only mean something for my own messages
+            let receipts = if is_from_me {
+                (row.get(9)?, receipt_date(row.get(10)?), receipt_date(row.get(11)?))
+            } else {
+                (false, None, None)
+            };
+
+            let changes: (Option<i64>, Option<i64>, Option<Vec<u8>>) = (row.get(12)?, row.get(13)?, row.get(14)?);
+            let event: (i64, Option<i64>, Option<String>, Option<String>) = (row.get(15)?, row.get(16)?, row.get(17)?, row.get(18)?);
 
-            Ok((rowid, guid, text, attributed_body, apple_ts, is_from_me, has_attachments, sender))
+            // Only URL balloons are decoded; other apps' payloads are skipped
+            let balloon: Option<String> = row.get(19)?;
+            let link_preview = if balloon.is_some_and(|b| b.ends_with(URL_BALLOON)) {
+                row.get::<_, Option<Vec<u8>>>(20)?.as_deref().and_then(parse_link_preview)
+            } else {
+                None
+            };
+
+            Ok((rowid, guid, text, attributed_body, apple_ts, is_from_me, has_attachments, sender, reply_to_guid, receipts, changes, event, link_preview))
         })?;
 
         for row in rows {
-            let (rowid, guid, text, attributed_body, apple_ts, is_from_me, has_attachments, sender) = row?;
+            let (rowid, guid, text, attributed_body, apple_ts, is_from_me, has_attachments, sender, reply_to_guid, receipts, changes, event, link_preview) = row?;
+
+            let (item_type, action, title, target) = event;
+            let system_event = if item_type == 0 {
+                None
+            } else {
+                // Group photo changes and the like aren't shown
+                let Some(change) = participant_change(item_type, action.unwrap_or(0), title) else {
+                    continue;
+                };
+                Some(ParticipantEvent {
+                    date: apple_date(apple_ts),
+                    change,
+                    actor: if is_from_me { None } else { sender.clone() },
+                    target,
+                })
+            };
+
+            let (is_delivered, date_delivered, date_read) = receipts;
+            let (date_edited, date_retracted, summary_info) = changes;
+            let mut summary = summary_info.as_deref().and_then(parse_summary_info).unwrap_or_default();
+
+            let mentions = attributed_body.as_deref().map(mentioned_handles).unwrap_or_default();
 
-            // Try text first, then parse attributedBody
-            let final_text = text
-                .filter(|t| !t.is_empty())
-                .or_else(|| attributed_body.and_then(|b| parse_attributed_body(&b)))
-                .unwrap_or_default();
+            let revision = revise(text, attributed_body, date_edited, date_retracted, &mut summary);
+            let (edited, retracted) = (revision.edited, revision.retracted);
+            let final_text = match &system_event {
+                Some(event) => event.describe(None),
+                None => revision.text,
+            };
 
             let unix_ts = apple_to_unix(apple_ts);
             let date = DateTime::from_timestamp(unix_ts, 0).unwrap_or_else(Utc::now);
 
             // Load attachments if present
-            let attachments = if has_attachments {
+            let mut attachments = if has_attachments {
                 self.load_attachments(rowid)?
             } else {
                 Vec::new()
             };
+            if let Some(audio) = attachments.iter_mut().find(|a| a.is_audio()) {
+                audio.transcription = summary.transcription;
+                audio.update_label();
+            }
 
-            // Only include if has text or attachments
-            if !final_text.trim().is_empty() || !attachments.is_empty() {
+            // Only include if has text, attachments or a link preview, or
+            // was unsent
+            if !final_text.trim().is_empty() || !attachments.is_empty() || link_preview.is_some() || retracted {
                 guids.push(guid.clone());
                 messages.push(Message {
                     rowid,
@@ -193,6 +1258,18 @@ impl Database {
                     sender,
                     attachments,
                     reactions: Vec::new(),
+                    mentions,
+                    reply_to_text: reply_to_guid.as_deref().map(|g| self.reply_preview(g)).transpose()?.flatten(),
+                    reply_to_guid,
+                    is_delivered,
+                    date_delivered,
+                    date_read,
+                    edited,
+                    retracted,
+                    edit_history: revision.earlier,
+                    sender_name: None,
+                    system_event,
+                    link_preview,
                 });
             }
         }
@@ -202,11 +1279,111 @@ impl Database {
             self.load_reactions(&mut messages, &guids)?;
         }
 
-        // Reverse to chronological order
+        Ok(messages)
+    }
+
+    /// Up to `limit` messages in a chat before the message with ROWID
+    /// `before_rowid`, or the latest ones, in chronological order. Pass the
+    /// first ROWID of one page to get the page before it.
+    pub fn messages_for_chat(&self, chat_id: i64, before_rowid: Option<i64>, limit: usize) -> Result<Vec<Message>, DbError> {
+        let mut messages = self.messages_page(chat_id, &MessageFilter::default(), before_rowid, limit)?;
         messages.reverse();
-        conv.messages = messages;
+        Ok(messages)
+    }
 
-        Ok(())
+    /// Up to `limit` messages in a chat matching `filter`, newest first.
+    /// `before_rowid` pages backwards from a message.
+    pub fn messages_page(
+        &self,
+        chat_id: i64,
+        filter: &MessageFilter,
+        before_rowid: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<Message>, DbError> {
+        // Every value is an integer, numbered after the chat ID at ?1
+        let mut values: Vec<i64> = Vec::new();
+        let mut clauses = Vec::new();
+        let bind = |values: &mut Vec<i64>, value: i64| {
+            values.push(value);
+            format!("?{}", values.len() + 1)
+        };
+
+        if let Some(from) = filter.from_date {
+            clauses.push(format!("{} >= {}", apple_seconds_sql("m.date"), bind(&mut values, unix_to_apple_secs(from.timestamp()))));
+        }
+        if let Some(to) = filter.to_date {
+            clauses.push(format!("{} < {}", apple_seconds_sql("m.date"), bind(&mut values, unix_to_apple_secs(to.timestamp()))));
+        }
+        if let Some(rowid) = before_rowid {
+            let p = bind(&mut values, rowid);
+            clauses.push(format!("(m.date, m.ROWID) < ((SELECT date FROM message WHERE ROWID = {p}), {p})"));
+        }
+        if let Some(has_attachment) = filter.has_attachment {
+            clauses.push(format!("m.cache_has_attachments = {}", bind(&mut values, has_attachment as i64)));
+        }
+        if let Some(is_from_me) = filter.is_from_me {
+            clauses.push(format!("m.is_from_me = {}", bind(&mut values, is_from_me as i64)));
+        }
+        if let Some(sender) = &filter.sender {
+            let handles = self.handle_rowids(sender)?;
+            if handles.is_empty() {
+                return Ok(Vec::new());
+            }
+            // 1:1 chats store the other person's handle on my messages too
+            let placeholders: Vec<String> = handles.into_iter().map(|h| bind(&mut values, h)).collect();
+            clauses.push(format!("m.is_from_me = 0 AND m.handle_id IN ({})", placeholders.join(", ")));
+        }
+
+        let filter_sql: String = clauses.iter().map(|c| format!("AND {} ", c)).collect();
+        let params: Vec<&dyn rusqlite::ToSql> = values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
+        self.query_messages(chat_id, &filter_sql, "DESC", limit, &params)
+    }
+
+    /// ROWIDs of every handle that is the same person as `handle`.
+    fn handle_rowids(&self, handle: &str) -> Result<Vec<i64>, DbError> {
+        let mut stmt = self.conn.prepare("SELECT ROWID, id FROM handle")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+        let mut rowids = Vec::new();
+        for row in rows {
+            let (rowid, id) = row?;
+            if same_handle(&id, handle) {
+                rowids.push(rowid);
+            }
+        }
+        Ok(rowids)
+    }
+
+    /// The message with `message_guid` and up to `around` messages either
+    /// side of it in its chat, in chronological order. Empty if the message
+    /// is gone.
+    pub fn message_context(&self, message_guid: &str, around: usize) -> Result<Vec<Message>, DbError> {
+        let target = self.conn.query_row(
+            "SELECT cmj.chat_id, m.date, m.ROWID FROM message m
+             JOIN chat_message_join cmj ON m.ROWID = cmj.message_id
+             WHERE m.guid = ?",
+            [message_guid],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)),
+        ).optional()?;
+        let Some((chat_id, date, rowid)) = target else {
+            return Ok(Vec::new());
+        };
+
+        let mut messages = self.query_messages(
+            chat_id,
+            "AND (m.date < ?2 OR (m.date = ?2 AND m.ROWID <= ?3))",
+            "DESC",
+            around + 1,
+            &[&date, &rowid],
+        )?;
+        messages.reverse();
+        messages.extend(self.query_messages(
+            chat_id,
+            "AND (m.date > ?2 OR (m.date = ?2 AND m.ROWID > ?3))",
+            "ASC",
+            around,
+            &[&date, &rowid],
+        )?);
+        Ok(messages)
     }
 
     fn load_attachments(&self, message_rowid: i64) -> Result<Vec<Attachment>, DbError> {
@@ -219,16 +1396,23 @@ impl Database {
 
         let mut attachments = Vec::new();
         let rows = stmt.query_map([message_rowid], |row| {
-            Ok(Attachment {
-                filename: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
-                mime_type: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
-                transfer_name: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
-            })
+            Ok(Attachment::new(
+                row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            ))
         })?;
 
         for row in rows {
-            let att = row?;
+            let mut att = row?;
             if !att.filename.is_empty() {
+                if att.is_audio() {
+                    att.duration_secs = att
+                        .relative_path
+                        .as_deref()
+                        .and_then(|p| audio_duration_secs(&self.attachments_dir.join(p)));
+                    att.update_label();
+                }
                 attachments.push(att);
             }
         }
@@ -236,6 +1420,36 @@ impl Database {
         Ok(attachments)
     }
 
+    /// The newest image attachment anywhere in a chat.
+    fn latest_image(&self, chat_id: i64) -> Result<Option<Attachment>, DbError> {
+        Ok(self.conn
+            .query_row(
+                "SELECT a.filename, a.mime_type, a.transfer_name
+                 FROM attachment a
+                 JOIN message_attachment_join maj ON a.ROWID = maj.attachment_id
+                 JOIN chat_message_join cmj ON cmj.message_id = maj.message_id
+                 JOIN message m ON m.ROWID = maj.message_id
+                 WHERE cmj.chat_id = ? AND a.mime_type LIKE 'image/%' AND a.filename IS NOT NULL
+                 ORDER BY m.date DESC, m.ROWID DESC
+                 LIMIT 1",
+                [chat_id],
+                |row| Ok(Attachment::new(row.get(0)?, row.get(1)?, row.get::<_, Option<String>>(2)?.unwrap_or_default())),
+            )
+            .optional()?)
+    }
+
+    /// Opening text of the message with `guid`, quoted above replies to it.
+    fn reply_preview(&self, guid: &str) -> Result<Option<String>, DbError> {
+        let row: Option<(Option<String>, Option<Vec<u8>>)> = self.conn
+            .query_row("SELECT text, attributedBody FROM message WHERE guid = ?", [guid], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        Ok(row.and_then(|(text, body)| {
+            let text = text.filter(|t| !t.is_empty()).or_else(|| body.and_then(|b| parse_attributed_body(&b)))?;
+            let text = text.replace('\u{FFFC}', "");
+            (!text.trim().is_empty()).then(|| trim_preview(&text, SUMMARY_TEXT_CHARS))
+        }))
+    }
+
     fn load_reactions(&self, messages: &mut [Message], guids: &[String]) -> Result<(), DbError> {
         // Build prefixed GUIDs for lookup
         let mut prefixed: Vec<String> = Vec::with_capacity(guids.len() * 3);
@@ -256,7 +1470,9 @@ impl Database {
              FROM message m
              LEFT JOIN handle h ON m.handle_id = h.ROWID
              WHERE m.associated_message_guid IN ({})
-               AND m.associated_message_type IN (2000, 2001, 2002, 2003, 2004, 2005, 2006)",
+               AND (m.associated_message_type BETWEEN 2000 AND 2006
+                    OR m.associated_message_type BETWEEN 3000 AND 3006)
+             ORDER BY m.date, m.ROWID",
             placeholders
         );
 
@@ -284,22 +1500,26 @@ impl Database {
             // Extract target GUID from "p:0/GUID" or "bp:GUID" format
             let target_guid = if assoc_guid.starts_with("p:") {
                 assoc_guid.split('/').nth(1).map(|s| s.to_string())
-            } else if assoc_guid.starts_with("bp:") {
-                Some(assoc_guid[3..].to_string())
+            } else if let Some(rest) = assoc_guid.strip_prefix("bp:") {
+                Some(rest.to_string())
             } else {
                 Some(assoc_guid)
             };
 
-            if let Some(target) = target_guid {
-                if let Some(&idx) = guid_map.get(&target) {
-                    if let Some(emoji) = reaction_emoji(reaction_type) {
-                        messages[idx].reactions.push(Reaction {
-                            emoji: emoji.to_string(),
-                            is_from_me,
-                            sender,
-                        });
-                    }
-                }
+            let Some(&idx) = target_guid.and_then(|t| guid_map.get(&t)) else {
+                continue;
+            };
+            // 3000-series types remove the matching 2000-series tapback
+            let removed = reaction_type >= 3000;
+            let Some(emoji) = reaction_emoji(if removed { reaction_type - 1000 } else { reaction_type }) else {
+                continue;
+            };
+            // Rows come oldest first, and each sender has one tapback per
+            // message, so a new one replaces theirs
+            let reactions = &mut messages[idx].reactions;
+            reactions.retain(|r| r.is_from_me != is_from_me || r.sender != sender || (removed && r.emoji != emoji));
+            if !removed {
+                reactions.push(Reaction { emoji: emoji.to_string(), is_from_me, sender });
             }
         }
 
@@ -315,6 +1535,7 @@ pub fn mark_as_read(chat_identifier: &str) -> Result<usize, DbError> {
         &path,
         OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
     )?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
     
     let affected = conn.execute(
         "UPDATE message SET is_read = 1
@@ -330,8 +1551,63 @@ pub fn mark_as_read(chat_identifier: &str) -> Result<usize, DbError> {
     Ok(affected)
 }
 
+/// A group event from a message's item_type and group_action_type, or
+/// None for ones that aren't about membership or the name.
+fn participant_change(item_type: i64, action: i64, title: Option<String>) -> Option<ParticipantChange> {
+    match (item_type, action) {
+        (1, 0) => Some(ParticipantChange::Added),
+        (1, 1) => Some(ParticipantChange::Removed),
+        (2, _) => Some(ParticipantChange::Renamed { name: title }),
+        (3, 0) => Some(ParticipantChange::Left),
+        _ => None,
+    }
+}
+
+/// A condition limiting an export query to `chat_ids`, one placeholder per
+/// chat, or nothing for every chat.
+fn chat_filter(chat_ids: Option<&[i64]>) -> String {
+    match chat_ids {
+        Some(ids) => format!("AND c.ROWID IN ({})", vec!["?"; ids.len()].join(",")),
+        None => String::new(),
+    }
+}
+
+/// SQL for `column`, an Apple date in seconds (before macOS 10.13) or
+/// nanoseconds, in seconds; the same detection as [`apple_to_unix`].
+fn apple_seconds_sql(column: &str) -> String {
+    format!("(CASE WHEN {0} > 1000000000000 THEN {0} / 1000000000 ELSE {0} END)", column)
+}
+
+/// Convert a chat.db date column to UTC, falling back to now if out of range.
+fn apple_date(apple_ts: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(apple_to_unix(apple_ts), 0).unwrap_or_else(Utc::now)
+}
+
+/// A delivered or read date, where 0 or NULL means it hasn't happened.
+fn receipt_date(apple_ts: Option<i64>) -> Option<DateTime<Utc>> {
+    apple_ts.filter(|&ts| ts > 0).map(apple_date)
+}
+
+/// Handles @mentioned in an attributedBody blob.
+fn mentioned_handles(blob: &[u8]) -> Vec<String> {
+    typedstream::decode_attributed_string(blob)
+        .map(|decoded| decoded.mentions().into_iter().map(|(_, handle)| handle.to_string()).collect())
+        .unwrap_or_default()
+}
+
 /// Parse text from attributedBody blob.
-fn parse_attributed_body(blob: &[u8]) -> Option<String> {
+///
+/// Decodes the typedstream when it can, otherwise falls back to scanning
+/// for the first NSString.
+pub fn parse_attributed_body(blob: &[u8]) -> Option<String> {
+    typedstream::decode_attributed_string(blob)
+        .map(|decoded| decoded.text)
+        .ok()
+        .or_else(|| scan_attributed_body(blob))
+}
+
+/// Read the string after the first NSString marker.
+fn scan_attributed_body(blob: &[u8]) -> Option<String> {
     // Find NSString marker
     let marker = b"NSString";
     let pos = blob.windows(marker.len()).position(|w| w == marker)?;
@@ -365,6 +1641,710 @@ fn parse_attributed_body(blob: &[u8]) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::FixedClock;
+    use crate::models::MessageEdit;
+    use crate::preview::PreviewImageRef;
+    use crate::sample::CHAT_DB_SCHEMA;
+    use crate::unix_to_apple_nanos;
+
+    /// Empty chat.db with the tables and columns the queries read.
+    fn fixture() -> (tempfile::TempDir, PathBuf, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(CHAT_DB_SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, style, service_name)
+                 VALUES (1, 'iMessage;-;+15551234567', '+15551234567', 45, 'iMessage');
+             INSERT INTO handle (ROWID, id, service) VALUES (1, '+15551234567', 'iMessage');"
+        ).unwrap();
+        (dir, path, conn)
+    }
+
+    /// Insert a message into chat 1 at `secs` seconds after the Apple epoch.
+    fn insert_message(conn: &Connection, rowid: i64, text: &str, secs: i64, is_from_me: bool) {
+        conn.execute(
+            "INSERT INTO message (ROWID, guid, text, date, is_from_me, handle_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![rowid, format!("guid-{}", rowid), text, secs * 1_000_000_000, is_from_me, if is_from_me { 0 } else { 1 }],
+        ).unwrap();
+        conn.execute("INSERT INTO chat_message_join VALUES (1, ?)", [rowid]).unwrap();
+    }
+
+    fn insert_attachment(conn: &Connection, message_rowid: i64, rowid: i64, mime: &str) {
+        conn.execute(
+            "INSERT INTO attachment VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![rowid, format!("~/Library/Messages/Attachments/{}", rowid), mime, format!("file{}", rowid)],
+        ).unwrap();
+        conn.execute("INSERT INTO message_attachment_join VALUES (?, ?)", [message_rowid, rowid]).unwrap();
+        conn.execute("UPDATE message SET cache_has_attachments = 1 WHERE ROWID = ?", [message_rowid]).unwrap();
+    }
+
+    #[test]
+    fn test_latest_message_chat() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "dinner?", 100, false);
+        insert_message(&conn, 2, "sure", 200, false);
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.latest_message_chat("guid-2").unwrap(), Some(("+15551234567".to_string(), false)));
+        assert_eq!(db.latest_message_chat("guid-1").unwrap(), None);
+        assert_eq!(db.latest_message_chat("gone").unwrap(), None);
+
+        // A tapback arriving later doesn't count as a newer message
+        insert_message(&conn, 3, "", 300, true);
+        conn.execute("UPDATE message SET associated_message_type = 2001 WHERE ROWID = 3", []).unwrap();
+        assert!(db.latest_message_chat("guid-2").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_my_handles() {
+        let (_dir, path, conn) = fixture();
+        conn.execute_batch(
+            "UPDATE chat SET last_addressed_handle = 'me@example.com';
+             INSERT INTO chat (ROWID, guid, chat_identifier, style, last_addressed_handle)
+                 VALUES (2, 'SMS;-;+15559876543', '+15559876543', 45, '+15550001111'),
+                        (3, 'iMessage;-;x@example.com', 'x@example.com', 45, ''),
+                        (4, 'iMessage;-;y@example.com', 'y@example.com', 45, 'me@example.com');"
+        ).unwrap();
+        let db = Database::open(&path).unwrap();
+        let mut handles = db.my_handles().unwrap();
+        handles.sort();
+        assert_eq!(handles, ["+15550001111", "me@example.com"]);
+    }
+
+    #[test]
+    fn test_is_mms_group_chat() {
+        let (_dir, path, conn) = fixture();
+        conn.execute_batch(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, style, service_name)
+                 VALUES (2, 'SMS;+;chat42', 'chat42', 43, 'SMS'), (3, 'iMessage;+;chat43', 'chat43', 43, 'iMessage');"
+        ).unwrap();
+        let db = Database::open(&path).unwrap();
+        assert!(db.is_mms_group_chat("chat42").unwrap());
+        assert!(!db.is_mms_group_chat("chat43").unwrap());
+        assert!(!db.is_mms_group_chat("+15551234567").unwrap());
+        assert!(!db.is_mms_group_chat("gone").unwrap());
+    }
+
+    #[test]
+    fn test_chat_service() {
+        let (_dir, path, _conn) = fixture();
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.chat_service("iMessage;-;+15551234567").unwrap().as_deref(), Some("iMessage"));
+        assert_eq!(db.chat_service("iMessage;-;gone").unwrap(), None);
+    }
+
+    #[test]
+    fn test_handled_in_messages() {
+        const GUID: &str = "iMessage;-;+15551234567";
+        let (_dir, path, conn) = fixture();
+        let db = Database::open(&path).unwrap();
+        assert!(!db.handled_in_messages(GUID).unwrap());
+
+        insert_message(&conn, 1, "you around?", 5_000, false);
+        assert!(!db.handled_in_messages(GUID).unwrap());
+
+        // Read in Messages.app but not answered
+        conn.execute("UPDATE message SET is_read = 1", []).unwrap();
+        assert!(!db.handled_in_messages(GUID).unwrap());
+
+        insert_message(&conn, 2, "yep", 5_060, true);
+        assert!(db.handled_in_messages(GUID).unwrap());
+
+        insert_message(&conn, 3, "great", 5_120, false);
+        assert!(!db.handled_in_messages(GUID).unwrap());
+        assert!(!db.handled_in_messages("iMessage;-;gone").unwrap());
+    }
+
+    #[test]
+    fn test_messages_page_filters() {
+        let (_dir, path, conn) = fixture();
+        conn.execute_batch(
+            "UPDATE chat SET style = 43, chat_identifier = 'chat123' WHERE ROWID = 1;
+             INSERT INTO handle (ROWID, id, service) VALUES (2, 'alice@example.com', 'iMessage');"
+        ).unwrap();
+        insert_message(&conn, 1, "old news", 5_000, false);
+        insert_message(&conn, 2, "from alice", 6_000, false);
+        insert_message(&conn, 3, "alice again", 6_100, false);
+        insert_attachment(&conn, 3, 1, "image/jpeg");
+        insert_message(&conn, 4, "mine", 6_200, true);
+        insert_message(&conn, 5, "alice later", 9_000, false);
+        conn.execute("UPDATE message SET handle_id = 2 WHERE ROWID IN (2, 3, 5)", []).unwrap();
+        let db = Database::open(&path).unwrap();
+
+        let texts = |filter: &MessageFilter, before_rowid: Option<i64>| {
+            db.messages_page(1, filter, before_rowid, 10).unwrap().into_iter().map(|m| m.text).collect::<Vec<_>>()
+        };
+        let last_week = MessageFilter {
+            from_date: DateTime::from_timestamp(apple_to_unix(6_000), 0),
+            to_date: DateTime::from_timestamp(apple_to_unix(9_000), 0),
+            ..Default::default()
+        };
+        assert_eq!(texts(&last_week, None), ["mine", "alice again", "from alice"]);
+
+        let alice = MessageFilter { sender: Some("Alice@Example.com".into()), ..last_week.clone() };
+        assert_eq!(texts(&alice, None), ["alice again", "from alice"]);
+        assert_eq!(texts(&alice, Some(3)), ["from alice"]);
+
+        let photos = MessageFilter { has_attachment: Some(true), ..alice.clone() };
+        assert_eq!(texts(&photos, None), ["alice again"]);
+        assert_eq!(texts(&MessageFilter { is_from_me: Some(true), ..Default::default() }, None), ["mine"]);
+        assert!(texts(&MessageFilter { sender: Some("bob@example.com".into()), ..Default::default() }, None).is_empty());
+
+        // Dates in seconds, as before macOS 10.13, filter the same
+        conn.execute("UPDATE message SET date = date / 1000000000", []).unwrap();
+        assert_eq!(texts(&last_week, None), ["mine", "alice again", "from alice"]);
+    }
+
+    #[test]
+    fn test_conversation_stats() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "hi", 5_000, false);
+        insert_message(&conn, 2, "hey", 5_060, true);
+        insert_message(&conn, 3, "lunch?", 6_000, false);
+        insert_message(&conn, 4, "where?", 6_100, false);
+        insert_message(&conn, 5, "sure", 9_100, true);
+        insert_message(&conn, 6, "noon", 9_200, true);
+        insert_message(&conn, 7, "ok", 9_300, false);
+        insert_message(&conn, 8, "see you", 9_900, true);
+
+        let mut db = Database::open(&path).unwrap();
+        let stats = db.conversation_stats(1).unwrap();
+        assert_eq!(stats.total_messages, 8);
+        assert_eq!(stats.my_share_percent, 50.0);
+        // Gaps of 60, 3000 and 600 seconds
+        assert_eq!(stats.median_reply_gap, Some(600));
+
+        assert!(db.unread_conversations(None).unwrap()[0].stats.is_none());
+        db.set_load_stats(true);
+        assert_eq!(db.unread_conversations(None).unwrap()[0].stats, Some(stats));
+    }
+
+    #[test]
+    fn test_messages_for_chat_pages_back() {
+        let (_dir, path, conn) = fixture();
+        for i in 1..=40 {
+            insert_message(&conn, i, &format!("m{}", i), 5_000 + i, i % 3 == 0);
+        }
+        // Imported history: same date, ROWIDs out of order with older rows
+        insert_message(&conn, 41, "tied", 5_001, false);
+        let db = Database::open(&path).unwrap();
+
+        let conv = &db.unread_conversations(None).unwrap()[0];
+        assert_eq!(conv.messages.len(), PREVIEW_MESSAGES);
+        assert_eq!(conv.messages.last().unwrap().text, "m40");
+
+        let mut before = conv.messages[0].rowid;
+        let mut seen = conv.messages.len();
+        loop {
+            let page = db.messages_for_chat(1, Some(before), 10).unwrap();
+            let Some(first) = page.first() else { break };
+            assert!(page.windows(2).all(|w| (w[0].date, w[0].rowid) < (w[1].date, w[1].rowid)));
+            seen += page.len();
+            before = first.rowid;
+        }
+        assert_eq!(seen, 41);
+        assert!(db.messages_for_chat(1, Some(999), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retry_busy() {
+        let (_dir, path, _conn) = fixture();
+        let writer = Connection::open(&path).unwrap();
+        writer.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let db = Database::open(&path).unwrap();
+        db.conn.busy_timeout(Duration::ZERO).unwrap();
+
+        let mut busy = Vec::new();
+        let mut slept = Vec::new();
+        let result = retry_busy(&busy_policy(), || db.message_count(), |n| busy.push(n), |d| slept.push(d));
+        assert!(result.unwrap_err().is_busy());
+        assert_eq!(busy, [1, 2, 3, 4]);
+        assert_eq!(slept, [Duration::from_millis(500), Duration::from_secs(1), Duration::from_secs(2)]);
+
+        // Unlocked partway through
+        let mut tries = 0;
+        let result = retry_busy(&busy_policy(), || {
+            tries += 1;
+            if tries == 2 {
+                writer.execute_batch("COMMIT").unwrap();
+            }
+            db.message_count()
+        }, |_| {}, |_| {});
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(tries, 2);
+
+        let missing = retry_busy(&busy_policy(), || Err::<(), _>(DbError::NotFound(path.clone())), |_| panic!("not busy"), |_| {});
+        assert!(matches!(missing, Err(DbError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_snapshot_is_point_in_time() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "first", 5_000, false);
+        let snapshot = dir.path().join("snapshot.db");
+        let db = Database::open(&path).unwrap();
+
+        db.snapshot_to(&snapshot).unwrap();
+        insert_message(&conn, 2, "second", 5_100, false);
+        assert_eq!(Database::open(&snapshot).unwrap().message_count().unwrap(), 1);
+
+        // Refreshing replaces the copy in place
+        db.snapshot_to(&snapshot).unwrap();
+        assert_eq!(Database::open(&snapshot).unwrap().message_count().unwrap(), 2);
+        assert!(!snapshot.with_extension("partial").exists());
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "old", 5_000, false);
+        let db = Database::open(&path).unwrap();
+        let first = db.changes_since(None).unwrap();
+        assert_eq!(first, MessageChanges { chat_ids: vec![], newest_rowid: 1, newest_read: 0 });
+
+        conn.execute_batch(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, style) VALUES (2, 'iMessage;+;chat2', 'chat2', 43);
+             INSERT INTO message (ROWID, text, date) VALUES (2, 'hi', 0), (3, 'again', 0);
+             INSERT INTO chat_message_join VALUES (2, 2), (1, 3);",
+        ).unwrap();
+        let changes = db.changes_since(Some(&first)).unwrap();
+        assert_eq!(changes, MessageChanges { chat_ids: vec![1, 2], newest_rowid: 3, newest_read: 0 });
+        assert!(db.changes_since(Some(&changes)).unwrap().chat_ids.is_empty());
+
+        // Read on another device: no new rows, just date_read set
+        conn.execute("UPDATE message SET is_read = 1, date_read = 7000 WHERE ROWID = 2", []).unwrap();
+        let read = db.changes_since(Some(&changes)).unwrap();
+        assert_eq!(read, MessageChanges { chat_ids: vec![2], newest_rowid: 3, newest_read: 7000 });
+        assert!(db.changes_since(Some(&read)).unwrap().chat_ids.is_empty());
+
+        // My own messages being "read" by the other side don't count
+        conn.execute_batch(
+            "INSERT INTO message (ROWID, text, date, is_from_me, date_read) VALUES (4, 'mine', 0, 1, 9000);
+             INSERT INTO chat_message_join VALUES (1, 4);",
+        ).unwrap();
+        let mine = db.changes_since(Some(&read)).unwrap();
+        assert_eq!(mine, MessageChanges { chat_ids: vec![1], newest_rowid: 4, newest_read: 7000 });
+    }
+
+    #[test]
+    fn test_media_for_chat() {
+        let (_dir, path, conn) = fixture();
+        // Seconds since 2001, about mid-2023
+        const T: i64 = 710_000_000;
+        insert_message(&conn, 1, "\u{FFFC}", T + 100, false);
+        insert_attachment(&conn, 1, 1, "image/jpeg");
+        insert_message(&conn, 2, "\u{FFFC}", T + 200, true);
+        insert_attachment(&conn, 2, 2, "video/quicktime");
+        insert_message(&conn, 3, "\u{FFFC}", T + 300, false);
+        insert_attachment(&conn, 3, 3, "image/heic");
+        insert_message(&conn, 4, "no media", T + 400, false);
+
+        let db = Database::open(&path).unwrap();
+
+        let all = db.media_for_chat(1, &[], 10, None).unwrap();
+        assert_eq!(all.iter().map(|m| m.message_rowid).collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert_eq!(all[0].sender.as_deref(), Some("+15551234567"));
+
+        let images = db.media_for_chat(1, &["image/"], 10, None).unwrap();
+        assert_eq!(images.len(), 2);
+        assert!(images.iter().all(|m| m.attachment.is_image()));
+
+        let limited = db.media_for_chat(1, &[], 1, None).unwrap();
+        assert_eq!(limited[0].message_rowid, 3);
+
+        let before = DateTime::from_timestamp(apple_to_unix(T + 300), 0);
+        let older = db.media_for_chat(1, &["image/", "video/"], 10, before).unwrap();
+        assert_eq!(older.iter().map(|m| m.message_rowid).collect::<Vec<_>>(), vec![2, 1]);
+
+        // Libraries from before macOS 10.13 store seconds
+        conn.execute("UPDATE message SET date = date / 1000000000", []).unwrap();
+        let older = db.media_for_chat(1, &["image/", "video/"], 10, before).unwrap();
+        assert_eq!(older.iter().map(|m| m.message_rowid).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_unread_conversation_dates() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "are you around?", 100, false);
+        insert_message(&conn, 2, "hello??", 200, false);
+        insert_message(&conn, 3, "sorry, one sec", 300, true);
+        conn.execute("UPDATE message SET is_read = 1 WHERE ROWID = 1", []).unwrap();
+
+        let db = Database::open(&path).unwrap();
+        let convs = db.unread_conversations(None).unwrap();
+        assert_eq!(convs.len(), 1);
+
+        let at = |secs: i64| apple_date(secs * 1_000_000_000);
+        let conv = &convs[0];
+        assert_eq!(conv.unread_count, 1);
+        assert_eq!(conv.last_unread_rowid, 2);
+        assert_eq!(conv.first_unread_date, at(200));
+        assert_eq!(conv.last_incoming_date, at(200));
+        assert_eq!(conv.last_message_date, at(300));
+        let single = db.unread_conversation(1, None).unwrap().unwrap();
+        assert_eq!((single.chat_id, single.unread_count, single.messages.len()), (1, 1, conv.messages.len()));
+        assert!(db.unread_conversation(2, None).unwrap().is_none());
+
+        // Nothing unread left means the chat drops out of the queue
+        conn.execute("UPDATE message SET is_read = 1", []).unwrap();
+        assert!(db.unread_conversations(None).unwrap().is_empty());
+        assert!(db.unread_conversation(1, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_message_context() {
+        let (_dir, path, conn) = fixture();
+        for rowid in 1..=6 {
+            insert_message(&conn, rowid, &format!("m{}", rowid), 5_000 + rowid * 10, rowid % 2 == 0);
+        }
+
+        let db = Database::open(&path).unwrap();
+        let texts = |guid: &str, around| -> Vec<String> {
+            db.message_context(guid, around).unwrap().into_iter().map(|m| m.text).collect()
+        };
+        assert_eq!(texts("guid-3", 1), ["m2", "m3", "m4"]);
+        assert_eq!(texts("guid-1", 2), ["m1", "m2", "m3"]);
+        assert_eq!(texts("guid-6", 1), ["m5", "m6"]);
+        assert!(texts("missing", 3).is_empty());
+    }
+
+    #[test]
+    fn test_thread_replies() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "are you free saturday?", 100, false);
+        insert_message(&conn, 2, "unrelated", 200, false);
+        insert_message(&conn, 3, "yes!", 300, true);
+
+        // Libraries from before threads load without reply info
+        let db = Database::open(&path).unwrap();
+        assert!(db.messages_for_chat(1, None, 10).unwrap().iter().all(|m| m.reply_to_guid.is_none()));
+
+        conn.execute_batch(
+            "ALTER TABLE message ADD COLUMN thread_originator_guid TEXT;
+             UPDATE message SET thread_originator_guid = 'guid-1' WHERE ROWID = 3;",
+        ).unwrap();
+        let db = Database::open(&path).unwrap();
+        let messages = db.messages_for_chat(1, None, 10).unwrap();
+        assert_eq!(messages[2].reply_to_guid.as_deref(), Some("guid-1"));
+        assert_eq!(messages[2].reply_to_text.as_deref(), Some("are you free saturday?"));
+        assert_eq!(messages[1].reply_to_guid, None);
+    }
+
+    #[test]
+    fn test_delivery_receipts() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "see you at 8", 100, true);
+        insert_message(&conn, 2, "great", 200, false);
+        insert_message(&conn, 3, "running late", 300, true);
+        conn.execute_batch(
+            "UPDATE message SET is_delivered = 1, date_delivered = 700000110000000000, date_read = 700000150000000000 WHERE ROWID = 1;
+             UPDATE message SET is_delivered = 1, date_read = 700000250000000000 WHERE ROWID = 2;",
+        ).unwrap();
+
+        let db = Database::open(&path).unwrap();
+        let messages = db.messages_for_chat(1, None, 10).unwrap();
+        assert!(messages[0].is_delivered);
+        assert_eq!(messages[0].date_delivered, Some(apple_date(700_000_110)));
+        assert_eq!(messages[0].date_read, Some(apple_date(700_000_150)));
+        // Incoming messages carry my own read time; it isn't a receipt
+        assert!(!messages[1].is_delivered && messages[1].date_read.is_none());
+        assert!(!messages[2].is_delivered && messages[2].date_delivered.is_none() && messages[2].date_read.is_none());
+    }
+
+    #[test]
+    fn test_reaction_removals() {
+        let (_dir, path, conn) = fixture();
+        conn.execute("INSERT INTO handle (ROWID, id, service) VALUES (2, 'sam@example.com', 'iMessage')", []).unwrap();
+        insert_message(&conn, 1, "dinner at 8", 100, true);
+        let react = |rowid: i64, secs: i64, kind: i32, handle: i64| {
+            insert_message(&conn, rowid, "", secs, handle == 0);
+            conn.execute(
+                "UPDATE message SET associated_message_guid = 'p:0/guid-1', associated_message_type = ?1, handle_id = ?2 WHERE ROWID = ?3",
+                rusqlite::params![kind, handle, rowid],
+            ).unwrap();
+        };
+        let emojis = |db: &Database| -> Vec<(String, Option<String>)> {
+            let messages = db.messages_for_chat(1, None, 10).unwrap();
+            messages[0].reactions.iter().map(|r| (r.emoji.clone(), r.sender.clone())).collect()
+        };
+
+        react(2, 200, 2001, 1);
+        react(3, 210, 2003, 2);
+        // Changed from 👍 to ❤️
+        react(4, 220, 2000, 1);
+        let db = Database::open(&path).unwrap();
+        let alice = Some("+15551234567".to_string());
+        let sam = Some("sam@example.com".to_string());
+        assert_eq!(emojis(&db), vec![("😂".into(), sam.clone()), ("❤️".into(), alice.clone())]);
+
+        // Removing a tapback they no longer have changes nothing
+        react(5, 230, 3001, 1);
+        assert_eq!(emojis(&db), vec![("😂".into(), sam), ("❤️".into(), alice.clone())]);
+        react(6, 240, 3003, 2);
+        assert_eq!(emojis(&db), vec![("❤️".into(), alice)]);
+    }
+
+    #[test]
+    fn test_edited_and_unsent() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "see you at 7", 100, true);
+        insert_message(&conn, 2, "oops wrong chat", 200, false);
+        insert_message(&conn, 3, "plain", 300, false);
+
+        let version = |date: i64, text: &str| {
+            let mut body = b"NSString\0\0\0\0\0".to_vec();
+            body.push(text.len() as u8);
+            body.extend_from_slice(text.as_bytes());
+            let mut dict = plist::Dictionary::new();
+            dict.insert("d".into(), plist::Value::Integer(date.into()));
+            dict.insert("t".into(), plist::Value::Data(body));
+            plist::Value::Dictionary(dict)
+        };
+        let mut parts = plist::Dictionary::new();
+        parts.insert("0".into(), plist::Value::Array(vec![version(100, "see you at 7"), version(160, "see you at 8")]));
+        let mut info = plist::Dictionary::new();
+        info.insert("ec".into(), plist::Value::Dictionary(parts));
+        let mut blob = Vec::new();
+        plist::Value::Dictionary(info).to_writer_binary(&mut blob).unwrap();
+
+        conn.execute_batch(
+            "ALTER TABLE message ADD COLUMN date_edited INTEGER DEFAULT 0;
+             ALTER TABLE message ADD COLUMN date_retracted INTEGER DEFAULT 0;
+             ALTER TABLE message ADD COLUMN message_summary_info BLOB;
+             UPDATE message SET date_edited = 700000160000000000 WHERE ROWID = 1;
+             UPDATE message SET date_retracted = 700000210000000000 WHERE ROWID = 2;",
+        ).unwrap();
+        conn.execute("UPDATE message SET message_summary_info = ? WHERE ROWID = 1", [blob]).unwrap();
+
+        let db = Database::open(&path).unwrap();
+        let messages = db.messages_for_chat(1, None, 10).unwrap();
+        assert!(messages[0].edited && !messages[0].retracted);
+        assert_eq!(messages[0].text, "see you at 8");
+        assert_eq!(messages[0].edit_history, vec![MessageEdit { date: apple_date(100), text: "see you at 7".into() }]);
+        assert!(messages[1].retracted && !messages[1].edited);
+        assert_eq!(messages[1].text, "");
+        assert!(!messages[2].edited && !messages[2].retracted && messages[2].edit_history.is_empty());
+
+        // Exports and the search index read them the same way
+        let events = db.messages_after(&ExportCursor::start(), None, 10).unwrap();
+        let read: Vec<_> = events.iter().map(|e| (e.text.as_str(), e.edited, e.retracted)).collect();
+        assert_eq!(read[..2], [("see you at 8", true, false), ("", false, true)]);
+    }
+
+    #[test]
+    fn test_audio_message() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "\u{FFFC}", 100, false);
+        insert_attachment(&conn, 1, 1, "audio/x-caf");
+
+        // 3 seconds of 8kHz 16-bit PCM
+        let mut caf = b"caff\0\x01\0\0desc".to_vec();
+        caf.extend_from_slice(&32i64.to_be_bytes());
+        caf.extend_from_slice(&8_000f64.to_be_bytes());
+        caf.extend_from_slice(b"lpcm\0\0\0\0\0\0\0\x02\0\0\0\x01\0\0\0\x01\0\0\0\x10data");
+        caf.extend_from_slice(&(4 + 48_000i64).to_be_bytes());
+        caf.resize(caf.len() + 4 + 48_000, 0);
+        std::fs::create_dir(dir.path().join("Attachments")).unwrap();
+        std::fs::write(dir.path().join("Attachments/1"), caf).unwrap();
+
+        let mut info = plist::Dictionary::new();
+        info.insert("at".into(), plist::Value::String("running late".into()));
+        let mut blob = Vec::new();
+        plist::Value::Dictionary(info).to_writer_binary(&mut blob).unwrap();
+        conn.execute_batch("ALTER TABLE message ADD COLUMN message_summary_info BLOB;").unwrap();
+        conn.execute("UPDATE message SET message_summary_info = ? WHERE ROWID = 1", [blob]).unwrap();
+
+        let mut db = Database::open(&path).unwrap();
+        db.set_attachments_dir(dir.path().join("Attachments"));
+        let messages = db.messages_for_chat(1, None, 10).unwrap();
+        let audio = &messages[0].attachments[0];
+        assert_eq!(audio.duration_secs, Some(3));
+        assert_eq!(audio.label.as_deref(), Some("Audio message (0:03): running late"));
+    }
+
+    #[test]
+    fn test_link_preview() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "", 1000, false);
+        insert_message(&conn, 2, "", 2000, false);
+        conn.execute_batch(
+            "ALTER TABLE message ADD COLUMN balloon_bundle_id TEXT;
+             ALTER TABLE message ADD COLUMN payload_data BLOB;",
+        )
+        .unwrap();
+        let blob = balloons::tests::archive("https://example.com/post", Some("A post"));
+        conn.execute(
+            "UPDATE message SET balloon_bundle_id = 'com.apple.messages.URLBalloonProvider', payload_data = ? WHERE ROWID = 1",
+            [&blob],
+        )
+        .unwrap();
+        // Another app's balloon isn't read as a link
+        conn.execute("UPDATE message SET balloon_bundle_id = 'com.example.game', payload_data = ? WHERE ROWID = 2", [&blob])
+            .unwrap();
+
+        let db = Database::open(&path).unwrap();
+        let messages = db.messages_for_chat(1, None, 10).unwrap();
+        assert_eq!(messages.len(), 1);
+        let preview = messages[0].link_preview.as_ref().unwrap();
+        assert_eq!((preview.url.as_str(), preview.title.as_deref()), ("https://example.com/post", Some("A post")));
+    }
+
+    #[test]
+    fn test_activity_histogram() {
+        let (_dir, path, conn) = fixture();
+        let now = Utc::now();
+        let apple_now = unix_to_apple_nanos(now.timestamp()) / 1_000_000_000;
+        insert_message(&conn, 1, "today", apple_now - 60, false);
+        insert_message(&conn, 2, "today too", apple_now - 120, true);
+        insert_message(&conn, 3, "two days ago", apple_now - 2 * 86_400 - 60, false);
+        insert_message(&conn, 4, "too old", apple_now - 10 * 86_400, false);
+
+        let clock = Arc::new(FixedClock::new(now));
+        let mut db = Database::open(&path).unwrap();
+        db.set_clock(clock.clone());
+        assert_eq!(db.activity_histogram(1, 3).unwrap(), [1, 0, 2]);
+        assert_eq!(db.activity_histogram(2, 3).unwrap(), [0, 0, 0]);
+        let conv = &db.unread_conversations(None).unwrap()[0];
+        assert_eq!((conv.activity.len(), conv.age_bucket), (ACTIVITY_DAYS, AgeBucket::OverAWeek));
+
+        clock.advance(chrono::Duration::days(1));
+        assert_eq!(db.activity_histogram(1, 3).unwrap(), [0, 2, 0]);
+
+        // Dates in seconds, as before macOS 10.13, count the same
+        conn.execute("UPDATE message SET date = date / 1000000000", []).unwrap();
+        assert_eq!(db.activity_histogram(1, 3).unwrap(), [0, 2, 0]);
+        clock.advance(chrono::Duration::days(20));
+        assert_eq!(db.unread_conversations(None).unwrap()[0].age_bucket, AgeBucket::Ancient);
+    }
+
+    #[test]
+    fn test_chat_read_receipt_override() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "hi", 5_000, false);
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.unread_conversations(None).unwrap()[0].read_receipts, None);
+
+        let mut dict = plist::Dictionary::new();
+        dict.insert("EnableReadReceiptForChat".into(), plist::Value::Boolean(true));
+        let mut blob = Vec::new();
+        plist::Value::Dictionary(dict).to_writer_binary(&mut blob).unwrap();
+        conn.execute("UPDATE chat SET properties = ?", [blob]).unwrap();
+        assert_eq!(db.unread_conversations(None).unwrap()[0].read_receipts, Some(true));
+
+        // SMS never sends receipts, whatever the chat says
+        conn.execute("UPDATE chat SET service_name = 'SMS'", []).unwrap();
+        assert_eq!(db.unread_conversations(None).unwrap()[0].read_receipts, Some(false));
+    }
+
+    #[test]
+    fn test_unread_conversations_resolve_names() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "hi", 5_000, false);
+
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.unread_conversations(None).unwrap()[0].resolved_name, None);
+
+        let mut resolver = ContactResolver::new();
+        resolver.add("+15551234567", "John Appleseed");
+        let conv = &db.unread_conversations(Some(&resolver)).unwrap()[0];
+        assert_eq!(conv.name(), "John Appleseed");
+    }
+
+    #[test]
+    fn test_preview_image_from_read_messages() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "", 100, false);
+        insert_attachment(&conn, 1, 1, "image/jpeg");
+        insert_message(&conn, 2, "", 150, false);
+        insert_attachment(&conn, 2, 2, "application/pdf");
+        insert_message(&conn, 3, "did you see the photo?", 200, false);
+        conn.execute("UPDATE message SET is_read = 1 WHERE ROWID < 3", []).unwrap();
+
+        // The photo is older than anything unread but still the latest image
+        let db = Database::open(&path).unwrap();
+        let conv = &db.unread_conversations(Some(&ContactResolver::new())).unwrap()[0];
+        assert_eq!(conv.preview_image, Some(PreviewImageRef::Attachment { relative_path: "1".into(), data_url: None }));
+    }
+
+    #[test]
+    fn test_source_tagging() {
+        let (_dir, path, conn) = fixture();
+        insert_message(&conn, 1, "hi", 100, false);
+
+        let local = Database::open(&path).unwrap();
+        let conv = &local.unread_conversations(None).unwrap()[0];
+        assert_eq!(conv.source_id, LOCAL_SOURCE);
+        assert_eq!(conv.guid, "iMessage;-;+15551234567");
+
+        let family = Database::open_source(&path, "kid").unwrap();
+        assert_eq!(family.source_id(), "kid");
+        assert_eq!(family.unread_conversations(None).unwrap()[0].source_id, "kid");
+
+        assert_eq!(
+            Database::library_path(Path::new("/Users/kid")),
+            PathBuf::from("/Users/kid/Library/Messages/chat.db")
+        );
+    }
+
+    #[test]
+    fn test_latest_service() {
+        let (_dir, path, conn) = fixture();
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.latest_service("+15551234567").unwrap(), None);
+
+        insert_message(&conn, 1, "hi", 100, false);
+        insert_message(&conn, 2, "new phone", 200, false);
+        insert_message(&conn, 3, "ok", 300, true);
+        conn.execute("UPDATE message SET service = 'iMessage'", []).unwrap();
+        conn.execute("UPDATE message SET service = 'SMS' WHERE ROWID = 2", []).unwrap();
+
+        assert_eq!(db.latest_service("+15551234567").unwrap().as_deref(), Some("SMS"));
+        assert_eq!(db.latest_service("+15550000000").unwrap(), None);
+    }
+
+    #[test]
+    fn test_participant_history() {
+        let (_dir, path, conn) = fixture();
+        conn.execute_batch(
+            "INSERT INTO handle (ROWID, id, service) VALUES (2, 'friend@example.com', 'iMessage');
+             INSERT INTO message (ROWID, guid, date, is_from_me, handle_id, item_type, group_action_type, other_handle)
+                 VALUES (1, 'e1', 100, 0, 1, 1, 0, 2);
+             INSERT INTO message (ROWID, guid, date, is_from_me, handle_id, item_type, group_title)
+                 VALUES (2, 'e2', 200, 1, 0, 2, 'Trip');
+             INSERT INTO message (ROWID, guid, date, is_from_me, handle_id, item_type, group_action_type)
+                 VALUES (3, 'e3', 300, 0, 2, 3, 1);
+             INSERT INTO message (ROWID, guid, date, is_from_me, handle_id, item_type, group_action_type)
+                 VALUES (4, 'e4', 400, 0, 2, 3, 0);
+             INSERT INTO chat_message_join VALUES (1, 1), (1, 2), (1, 3), (1, 4);"
+        ).unwrap();
+        insert_message(&conn, 5, "regular message", 500, false);
+
+        let db = Database::open(&path).unwrap();
+        let history = db.participant_history(1).unwrap();
+        let changes: Vec<_> = history.iter().map(|e| e.change.clone()).collect();
+        assert_eq!(changes, vec![
+            ParticipantChange::Added,
+            ParticipantChange::Renamed { name: Some("Trip".into()) },
+            ParticipantChange::Left,
+        ]);
+
+        assert_eq!(history[0].actor.as_deref(), Some("+15551234567"));
+        assert_eq!(history[0].target.as_deref(), Some("friend@example.com"));
+        assert_eq!(history[1].actor, None);
+        assert_eq!(history[2].actor.as_deref(), Some("friend@example.com"));
+
+        assert_eq!(db.messages_for_chat(1, None, 10).unwrap().len(), 1);
+        let mut db = db;
+        db.set_include_system_events(true);
+        let texts: Vec<_> = db.messages_for_chat(1, None, 10).unwrap().into_iter().map(|m| m.text).collect();
+        assert_eq!(texts, [
+            "+15551234567 added friend@example.com",
+            "You named the conversation “Trip”",
+            "friend@example.com left the conversation",
+            "regular message",
+        ]);
+    }
 
     #[test]
     fn test_parse_attributed_body_simple() {
@@ -386,6 +2366,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "dev-sample"))]
     fn test_default_path() {
         let path = Database::default_path();
         assert!(path.to_string_lossy().contains("Library/Messages/chat.db"));
@@ -422,6 +2403,69 @@ mod tests {
         assert_eq!(parse_attributed_body(&blob), None);
     }
 
+    #[test]
+    fn test_schema_checks() {
+        let (_dir, path, conn) = fixture();
+        let db = Database::open(&path).unwrap();
+        assert!(db.quick_check().unwrap().is_empty());
+        assert!(db.missing_schema().unwrap().is_empty());
+
+        conn.execute_batch(
+            "DROP TABLE message_attachment_join;
+             ALTER TABLE handle DROP COLUMN service;"
+        ).unwrap();
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.missing_schema().unwrap(), ["handle.service", "message_attachment_join"]);
+    }
+
+    #[test]
+    fn test_chats_for_contact() {
+        let (_dir, path, conn) = fixture();
+        conn.execute_batch(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, display_name, style)
+                 VALUES (2, 'iMessage;+;chat1', 'chat1', 'Climbing', 43),
+                        (3, 'iMessage;+;chat2', 'chat2', 'Work', 43);
+             INSERT INTO handle (ROWID, id, service) VALUES (2, 'friend@example.com', 'iMessage');
+             INSERT INTO chat_handle_join VALUES (1, 1), (2, 1), (2, 2), (3, 2);"
+        ).unwrap();
+        insert_message(&conn, 1, "hi", 100, false);
+        conn.execute(
+            "INSERT INTO message (ROWID, guid, text, date, handle_id) VALUES (2, 'g2', 'in group', ?, 1)",
+            [5_000 * 1_000_000_000i64],
+        ).unwrap();
+        conn.execute("INSERT INTO chat_message_join VALUES (2, 2)", []).unwrap();
+        let db = Database::open(&path).unwrap();
+
+        let mut contacts = ContactResolver::new();
+        contacts.add("5551234567", "John Doe");
+        for query in ["+1 (555) 123-4567", "john doe"] {
+            let chats = db.chats_for_contact(query, &contacts).unwrap();
+            let ids: Vec<_> = chats.iter().map(|c| c.chat_id).collect();
+            assert_eq!(ids, [1, 2]);
+            assert_eq!(chats[1].participants.len(), 2);
+            assert_eq!(chats[1].last_activity, Some(apple_date(5_000 * 1_000_000_000)));
+        }
+        assert!(db.chats_for_contact("nobody@example.com", &contacts).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_handle_activity_orders_by_recency() {
+        let (_dir, path, conn) = fixture();
+        conn.execute_batch(
+            "INSERT INTO handle (ROWID, id, service) VALUES (2, 'friend@example.com', 'iMessage'),
+                                                         (3, 'never@example.com', 'iMessage');"
+        ).unwrap();
+        insert_message(&conn, 1, "old", 1_000, false);
+        conn.execute(
+            "INSERT INTO message (ROWID, guid, text, date, handle_id) VALUES (2, 'g2', 'new', ?, 2)",
+            [2_000 * 1_000_000_000i64],
+        ).unwrap();
+        let db = Database::open(&path).unwrap();
+
+        let handles: Vec<_> = db.handle_activity().unwrap().into_iter().map(|h| h.handle).collect();
+        assert_eq!(handles, ["friend@example.com", "+15551234567", "never@example.com"]);
+    }
+
     #[test]
     fn test_db_error_display() {
         let err = DbError::NotFound(PathBuf::from("/test/path"));
@@ -430,4 +2474,114 @@ mod tests {
         let err = DbError::PermissionDenied(PathBuf::from("/secret"));
         assert!(err.to_string().contains("Permission denied"));
     }
+
+    #[test]
+    fn test_messages_after_pages_by_date() {
+        let (_dir, path, conn) = fixture();
+        // ROWID order differs from date order, as with imported history
+        insert_message(&conn, 1, "third", 300, false);
+        insert_message(&conn, 2, "first", 100, true);
+        insert_message(&conn, 3, "second", 200, false);
+        let db = Database::open(&path).unwrap();
+
+        let first = db.messages_after(&ExportCursor::start(), None, 2).unwrap();
+        let texts: Vec<_> = first.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["first", "second"]);
+        assert_eq!(first[1].sender.as_deref(), Some("+15551234567"));
+        assert_eq!(first[0].chat_guid, "iMessage;-;+15551234567");
+
+        let last = &first[1];
+        let cursor = ExportCursor { date: last.apple_date, rowid: last.rowid };
+        let rest = db.messages_after(&cursor, None, 2).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].text, "third");
+
+        assert!(db.messages_after(&ExportCursor::start(), Some(&[2]), 10).unwrap().is_empty());
+
+        // By ROWID, the earlier-dated messages inserted later still follow
+        let inserted: Vec<_> = db.messages_after_rowid(1, None, 10).unwrap().into_iter().map(|e| e.text).collect();
+        assert_eq!(inserted, ["first", "second"]);
+        assert!(db.messages_after_rowid(0, Some(&[2]), 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "too early", 5_000, false);
+        insert_message(&conn, 2, "Lunch, \"maybe\"\ntomorrow?", 6_000, false);
+        insert_attachment(&conn, 2, 1, "image/jpeg");
+        insert_message(&conn, 3, "sure", 6_100, true);
+        insert_message(&conn, 4, "too late", 7_000, false);
+        insert_message(&conn, 5, "=HYPERLINK(\"http://x\")", 6_200, false);
+        let db = Database::open(&path).unwrap();
+        assert_eq!(db.sender_handles(None).unwrap(), ["+15551234567"]);
+        assert!(db.sender_handles(Some(&[2])).unwrap().is_empty());
+        let names = std::collections::HashMap::from([("+15551234567".to_string(), "Jane Doe".to_string())]);
+
+        let out = dir.path().join("messages.csv");
+        let range = crate::export::ExportRange {
+            from: DateTime::from_timestamp(apple_to_unix(6_000), 0),
+            to: DateTime::from_timestamp(apple_to_unix(7_000), 0),
+        };
+        let rows = crate::export::export_csv(&db, &out, None, range, &names, None).unwrap();
+        assert_eq!(rows, 3);
+
+        let mut reader = csv::Reader::from_path(&out).unwrap();
+        assert_eq!(reader.headers().unwrap(), vec!["chat", "sender", "name", "date", "service", "text", "attachments"]);
+        let records: Vec<Vec<String>> = reader
+            .records()
+            .map(|r| r.unwrap().iter().map(String::from).collect())
+            .collect();
+        assert_eq!(&records[0][1..3], ["+15551234567", "Jane Doe"]);
+        assert_eq!(&records[0][5..], ["Lunch, \"maybe\"\ntomorrow?", "1"]);
+        assert_eq!(&records[1][1..3], ["me", "Me"]);
+        assert!(records[1][3].starts_with("2001-01-01T01:41:40"));
+        // Spreadsheets would run this as a formula
+        assert_eq!(records[2][5], "'=HYPERLINK(\"http://x\")");
+
+        // Dates in seconds, as before macOS 10.13, fall in the same range
+        conn.execute("UPDATE message SET date = date / 1000000000", []).unwrap();
+        assert_eq!(crate::export::export_csv(&db, &out, None, range, &names, None).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_export_jsonl_resumes_from_checkpoint() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "one", 100, false);
+        insert_message(&conn, 2, "two", 200, true);
+        let db = Database::open(&path).unwrap();
+        let out = dir.path().join("messages.jsonl");
+        let names = std::collections::HashMap::from([("+15551234567".to_string(), "John Appleseed".to_string())]);
+
+        let summary = crate::export::export_jsonl(&db, &out, None, true, &names, None).unwrap();
+        assert_eq!(summary.exported, 2);
+        assert!(!summary.resumed);
+
+        // Lines a killed run wrote past its checkpoint are dropped on resume
+        std::io::Write::write_all(&mut std::fs::OpenOptions::new().append(true).open(&out).unwrap(), b"{\"torn\":").unwrap();
+        // Synced in late, dated before what was exported, and still appended
+        insert_message(&conn, 3, "three", 50, false);
+        assert!(matches!(
+            crate::export::export_jsonl(&db, &out, Some(&[1]), true, &names, None),
+            Err(crate::export::ExportError::CheckpointMismatch)
+        ));
+        let summary = crate::export::export_jsonl(&db, &out, None, true, &names, None).unwrap();
+        assert_eq!(summary.exported, 1);
+        assert!(summary.resumed);
+
+        let lines: Vec<ExportEvent> = std::fs::read_to_string(&out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        let texts: Vec<_> = lines.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["one", "two", "three"]);
+        let senders: Vec<_> = lines.iter().map(|e| e.sender_name.as_deref()).collect();
+        assert_eq!(senders, [Some("John Appleseed"), Some("Me"), Some("John Appleseed")]);
+
+        // Without resume the file starts over
+        let summary = crate::export::export_jsonl(&db, &out, None, false, &names, None).unwrap();
+        assert_eq!(summary.exported, 3);
+        assert_eq!(std::fs::read_to_string(&out).unwrap().lines().count(), 3);
+    }
 }
@@ -0,0 +1,138 @@
+//! Link previews in `message.payload_data`.
+//!
+//! A link sent as a rich preview (`balloon_bundle_id` ending in
+//! `URLBalloonProvider`) often has no text; what it shows lives in
+//! payload_data, an NSKeyedArchiver plist of `LPLinkMetadata`. Archived
+//! objects sit in `$objects` and point at each other by UID, starting from
+//! `$top.root`, which holds the metadata under `richLinkMetadata`.
+
+use plist::{Dictionary, Value};
+
+use crate::link_title::bare_url;
+use crate::models::LinkPreview;
+
+/// Balloon bundle IDs that carry a link preview end with this.
+pub(super) const URL_BALLOON: &str = "URLBalloonProvider";
+
+/// The archive's objects, for following UIDs.
+struct Archive<'a> {
+    objects: &'a [Value],
+}
+
+impl<'a> Archive<'a> {
+    /// Follow `value` if it's a UID; archived `$null` is None.
+    fn resolve(&self, value: &'a Value) -> Option<&'a Value> {
+        let value = match value {
+            Value::Uid(uid) => self.objects.get(uid.get() as usize)?,
+            other => other,
+        };
+        match value {
+            Value::String(s) if s == "$null" => None,
+            other => Some(other),
+        }
+    }
+
+    fn field(&self, dict: &'a Dictionary, key: &str) -> Option<&'a Value> {
+        self.resolve(dict.get(key)?)
+    }
+
+    /// A string, or an NSURL's `NS.relative` string.
+    fn string(&self, value: &'a Value) -> Option<String> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            Value::Dictionary(url) => self.string(self.field(url, "NS.relative")?),
+            _ => None,
+        }
+    }
+}
+
+/// The URL and title of a link preview, or None if `blob` isn't one or
+/// doesn't point at an http(s) page. The sender controls all of it, so
+/// nothing else is trusted; the preview image is never loaded.
+pub(super) fn parse_link_preview(blob: &[u8]) -> Option<LinkPreview> {
+    let value = Value::from_reader(std::io::Cursor::new(blob)).ok()?;
+    let archive = value.as_dictionary()?;
+    let objects = archive.get("$objects")?.as_array()?;
+    let archive = Archive { objects };
+
+    let root = archive.resolve(value.as_dictionary()?.get("$top")?.as_dictionary()?.get("root")?)?.as_dictionary()?;
+    let metadata = archive
+        .field(root, "richLinkMetadata")
+        .and_then(Value::as_dictionary)
+        .unwrap_or(root);
+
+    let url = ["URL", "originalURL"]
+        .iter()
+        .find_map(|key| archive.string(archive.field(metadata, key)?))?;
+    let url = bare_url(&url)?.to_string();
+    let title = archive
+        .field(metadata, "title")
+        .and_then(|t| archive.string(t))
+        .filter(|t| !t.trim().is_empty());
+
+    Some(LinkPreview { url, title })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use plist::Uid;
+
+    /// An archived preview for `url` with an optional title, and an image
+    /// that parsing should leave alone.
+    pub(in crate::db) fn archive(url: &str, title: Option<&str>) -> Vec<u8> {
+        let mut objects = vec![Value::String("$null".into())];
+        let mut push = |value: Value| {
+            objects.push(value);
+            Value::Uid(Uid::new(objects.len() as u64 - 1))
+        };
+        let nsurl = |relative: Value| {
+            let mut dict = Dictionary::new();
+            dict.insert("NS.relative".into(), relative);
+            Value::Dictionary(dict)
+        };
+
+        let url_string = push(Value::String(url.into()));
+        let mut metadata = Dictionary::new();
+        metadata.insert("URL".into(), push(nsurl(url_string)));
+        metadata.insert("title".into(), match title {
+            Some(title) => push(Value::String(title.into())),
+            None => Value::Uid(Uid::new(0)),
+        });
+        let image_string = push(Value::String("https://tracker.example/pixel.png".into()));
+        let image_url = push(nsurl(image_string));
+        let mut image_metadata = Dictionary::new();
+        image_metadata.insert("URL".into(), image_url);
+        metadata.insert("imageMetadata".into(), push(Value::Dictionary(image_metadata)));
+        let metadata = push(Value::Dictionary(metadata));
+        let mut root = Dictionary::new();
+        root.insert("richLinkMetadata".into(), metadata);
+        let root = push(Value::Dictionary(root));
+
+        let mut top = Dictionary::new();
+        top.insert("root".into(), root);
+        let mut archive = Dictionary::new();
+        archive.insert("$archiver".into(), Value::String("NSKeyedArchiver".into()));
+        archive.insert("$top".into(), Value::Dictionary(top));
+        archive.insert("$objects".into(), Value::Array(objects));
+        let mut out = Vec::new();
+        Value::Dictionary(archive).to_writer_binary(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_link_preview() {
+        let preview = parse_link_preview(&archive("https://example.com/post", Some("A post"))).unwrap();
+        assert_eq!(preview, LinkPreview { url: "https://example.com/post".into(), title: Some("A post".into()) });
+
+        let bare = parse_link_preview(&archive("https://example.com", None)).unwrap();
+        assert_eq!(bare.title, None);
+
+        // Only web pages; anything else could open a local file or app
+        for url in ["file:///Applications/Calculator.app", "javascript:alert(1)", "https://x/ y"] {
+            assert_eq!(parse_link_preview(&archive(url, Some("Open me"))), None, "{}", url);
+        }
+
+        assert_eq!(parse_link_preview(b"not a plist"), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Edit, unsend and transcription records in `message.message_summary_info`.
+//!
+//! Since macOS 13 an edited or unsent message keeps a binary plist here.
+//! `ec` maps each message part, by index, to its versions in order, each a
+//! date (`d`) and a typedstream attributedBody (`t`). `rp` lists the parts
+//! that were unsent.
+//!
+//! Audio transcriptions are read from a plain string under `at`. That key
+//! hasn't been checked against a real library yet; if it's wrong, audio
+//! messages just show without a transcription.
+
+use plist::Value;
+
+use super::{apple_date, parse_attributed_body};
+use crate::models::MessageEdit;
+
+/// What a message's summary info says about its edits.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct SummaryInfo {
+    /// Every version of the first edited part, oldest first; the last is
+    /// the current text
+    pub versions: Vec<MessageEdit>,
+    /// Parts that were unsent
+    pub retracted_parts: usize,
+    /// Transcription of an audio message
+    pub transcription: Option<String>,
+}
+
+/// A message as it reads now, after any edits or unsend.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct Revision {
+    /// The latest version; empty if unsent
+    pub text: String,
+    pub edited: bool,
+    pub retracted: bool,
+    /// Versions before the latest, oldest first, if edited
+    pub earlier: Vec<MessageEdit>,
+}
+
+/// Work out a message's current text from its `text` and `attributedBody`
+/// columns, its edit and unsend dates, and its summary info, whose versions
+/// are taken.
+pub(super) fn revise(
+    text: Option<String>,
+    attributed_body: Option<Vec<u8>>,
+    date_edited: Option<i64>,
+    date_retracted: Option<i64>,
+    summary: &mut SummaryInfo,
+) -> Revision {
+    let retracted = date_retracted.unwrap_or(0) > 0 || (summary.retracted_parts > 0 && text.as_deref().is_none_or(str::is_empty));
+    let edited = !retracted && date_edited.unwrap_or(0) > 0;
+    let mut versions = std::mem::take(&mut summary.versions);
+    let text = if retracted {
+        String::new()
+    } else if edited {
+        // `text` can keep the original; the latest version is in the edit
+        // record, or failing that attributedBody
+        versions
+            .pop()
+            .map(|latest| latest.text)
+            .or_else(|| attributed_body.and_then(|b| parse_attributed_body(&b)))
+            .or(text)
+            .unwrap_or_default()
+    } else {
+        // Try text first, then parse attributedBody
+        text.filter(|t| !t.is_empty())
+            .or_else(|| attributed_body.and_then(|b| parse_attributed_body(&b)))
+            .unwrap_or_default()
+    };
+    if !edited {
+        versions.clear();
+    }
+    Revision { text, edited, retracted, earlier: versions }
+}
+
+pub(super) fn parse_summary_info(blob: &[u8]) -> Option<SummaryInfo> {
+    let value = Value::from_reader(std::io::Cursor::new(blob)).ok()?;
+    let dict = value.as_dictionary()?;
+
+    let versions = dict
+        .get("ec")
+        .and_then(Value::as_dictionary)
+        .and_then(|parts| {
+            // Keys are part indexes; the lowest is the text
+            parts
+                .iter()
+                .filter_map(|(part, versions)| Some((part.parse::<u32>().ok()?, versions.as_array()?)))
+                .min_by_key(|(part, _)| *part)
+        })
+        .map(|(_, versions)| versions.iter().filter_map(version).collect())
+        .unwrap_or_default();
+    let retracted_parts = dict.get("rp").and_then(Value::as_array).map_or(0, Vec::len);
+    let transcription = dict
+        .get("at")
+        .and_then(Value::as_string)
+        .filter(|t| !t.trim().is_empty())
+        .map(str::to_string);
+
+    Some(SummaryInfo { versions, retracted_parts, transcription })
+}
+
+fn version(value: &Value) -> Option<MessageEdit> {
+    let dict = value.as_dictionary()?;
+    let date = match dict.get("d")? {
+        Value::Integer(n) => n.as_signed()?,
+        Value::Real(secs) => *secs as i64,
+        _ => return None,
+    };
+    let text = parse_attributed_body(dict.get("t")?.as_data()?)?;
+    Some(MessageEdit { date: apple_date(date), text })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+
+    /// Minimal attributedBody holding `text`: marker, 5 bytes, length, text.
+    fn body(text: &str) -> Vec<u8> {
+        let mut blob = b"NSString\0\0\0\0\0".to_vec();
+        blob.push(text.len() as u8);
+        blob.extend_from_slice(text.as_bytes());
+        blob
+    }
+
+    fn entry(date: i64, text: &str) -> Value {
+        let mut dict = Dictionary::new();
+        dict.insert("d".into(), Value::Integer(date.into()));
+        dict.insert("t".into(), Value::Data(body(text)));
+        Value::Dictionary(dict)
+    }
+
+    fn blob(info: Dictionary) -> Vec<u8> {
+        let mut out = Vec::new();
+        Value::Dictionary(info).to_writer_binary(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_parse_summary_info() {
+        let mut parts = Dictionary::new();
+        parts.insert("0".into(), Value::Array(vec![entry(700_000_000, "see you at 7"), entry(700_000_060, "see you at 8")]));
+        let mut info = Dictionary::new();
+        info.insert("ec".into(), Value::Dictionary(parts));
+        let parsed = parse_summary_info(&blob(info)).unwrap();
+        assert_eq!(parsed.retracted_parts, 0);
+        let texts: Vec<_> = parsed.versions.iter().map(|v| v.text.as_str()).collect();
+        assert_eq!(texts, ["see you at 7", "see you at 8"]);
+        assert_eq!(parsed.versions[1].date, apple_date(700_000_060));
+
+        let mut info = Dictionary::new();
+        info.insert("rp".into(), Value::Array(vec![Value::Integer(0.into())]));
+        assert_eq!(parse_summary_info(&blob(info)), Some(SummaryInfo { versions: vec![], retracted_parts: 1, transcription: None }));
+
+        let mut info = Dictionary::new();
+        info.insert("at".into(), Value::String("running late".into()));
+        assert_eq!(parse_summary_info(&blob(info)).unwrap().transcription.as_deref(), Some("running late"));
+        assert_eq!(parse_summary_info(b"not a plist"), None);
+    }
+}
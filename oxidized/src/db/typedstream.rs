@@ -0,0 +1,502 @@
+//! Decoder for the typedstream archives in `message.attributedBody`.
+//!
+//! typedstream is the pre-keyed `NSArchiver` format. A stream is a header
+//! followed by groups, each a type encoding such as `@` or `iI` and one value
+//! per type character. Objects carry their class chain and then their own
+//! groups up to an end marker. Strings, classes and objects can be written
+//! once and referred back to by index afterwards.
+//!
+//! An archived `NSAttributedString` holds an `NSString` with the text, then
+//! runs: a UTF-16 length and an `NSDictionary` of attributes for each. The
+//! attributes mark mentions, links, and which message part a run belongs to.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use serde::Serialize;
+use thiserror::Error;
+
+const TAG_INT16: u8 = 0x81;
+const TAG_INT32: u8 = 0x82;
+const TAG_FLOAT: u8 = 0x83;
+const TAG_NEW: u8 = 0x84;
+const TAG_NIL: u8 = 0x85;
+const TAG_END: u8 = 0x86;
+/// References count up from here: 0x92 is the first string or object.
+const REFERENCE_BASE: u8 = 0x92;
+
+/// Deeper nesting than any real message; stops hostile blobs recursing forever.
+const MAX_DEPTH: usize = 32;
+
+/// Attribute on a confirmed @mention; the value is the handle.
+pub const MENTION_ATTRIBUTE: &str = "__kIMMentionConfirmedMention";
+/// Attribute on a detected link; the value is the URL.
+pub const LINK_ATTRIBUTE: &str = "__kIMLinkAttributeName";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum TypedStreamError {
+    #[error("Not a typedstream")]
+    BadHeader,
+    #[error("Typedstream ended early")]
+    Truncated,
+    #[error("Unexpected tag {0:#04x} in typedstream")]
+    UnexpectedTag(u8),
+    #[error("Typedstream reference {0} points at nothing")]
+    BadReference(usize),
+    #[error("Unsupported typedstream type {0:?}")]
+    UnsupportedType(char),
+    #[error("Typedstream nested too deeply")]
+    TooDeep,
+    #[error("Archived object is a {0}, not an attributed string")]
+    NotAttributedString(String),
+}
+
+/// Plain text with the attributes on each run of it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttributedText {
+    pub text: String,
+    pub runs: Vec<AttributeRun>,
+}
+
+/// A stretch of the text and its attributes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttributeRun {
+    /// Byte range in `AttributedText::text`
+    pub range: Range<usize>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+/// An attribute value, reduced to what the app can use.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum AttributeValue {
+    Text(String),
+    Number(f64),
+    Url(String),
+    /// Any other object, by class name
+    Other(String),
+}
+
+impl AttributedText {
+    /// Handles @mentioned in the text, with the range each mention covers.
+    pub fn mentions(&self) -> Vec<(Range<usize>, &str)> {
+        self.runs_with(MENTION_ATTRIBUTE)
+    }
+
+    /// Links detected in the text, with the range each covers.
+    pub fn links(&self) -> Vec<(Range<usize>, &str)> {
+        self.runs_with(LINK_ATTRIBUTE)
+    }
+
+    fn runs_with(&self, key: &str) -> Vec<(Range<usize>, &str)> {
+        self.runs
+            .iter()
+            .filter_map(|run| match run.attributes.get(key)? {
+                AttributeValue::Text(s) | AttributeValue::Url(s) => Some((run.range.clone(), s.as_str())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Decode an attributedBody blob.
+pub fn decode_attributed_string(blob: &[u8]) -> Result<AttributedText, TypedStreamError> {
+    let mut stream = Stream::new(blob);
+    let root = stream.read_root()?;
+    stream.attributed_text(root)
+}
+
+/// A decoded group value.
+#[derive(Debug, Clone)]
+enum Value {
+    Nil,
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    /// Index into the object table
+    Object(usize),
+}
+
+#[derive(Debug)]
+enum Entry {
+    Class { name: String },
+    Object { class: Option<usize>, values: Vec<Value> },
+}
+
+struct Stream<'a> {
+    data: &'a [u8],
+    pos: usize,
+    strings: Vec<String>,
+    objects: Vec<Entry>,
+}
+
+impl<'a> Stream<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0, strings: Vec::new(), objects: Vec::new() }
+    }
+
+    fn byte(&mut self) -> Result<u8, TypedStreamError> {
+        let b = *self.data.get(self.pos).ok_or(TypedStreamError::Truncated)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn peek(&self) -> Result<u8, TypedStreamError> {
+        self.data.get(self.pos).copied().ok_or(TypedStreamError::Truncated)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], TypedStreamError> {
+        let end = self.pos.checked_add(len).ok_or(TypedStreamError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(TypedStreamError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// A signed byte, or a tag and a 2- or 4-byte little-endian integer.
+    fn int_after(&mut self, tag: u8) -> Result<i64, TypedStreamError> {
+        Ok(match tag {
+            TAG_INT16 => i16::from_le_bytes(self.take(2)?.try_into().unwrap()) as i64,
+            TAG_INT32 => i32::from_le_bytes(self.take(4)?.try_into().unwrap()) as i64,
+            TAG_FLOAT..=0x91 => return Err(TypedStreamError::UnexpectedTag(tag)),
+            b => b as i8 as i64,
+        })
+    }
+
+    fn int(&mut self) -> Result<i64, TypedStreamError> {
+        let tag = self.byte()?;
+        self.int_after(tag)
+    }
+
+    fn len(&mut self) -> Result<usize, TypedStreamError> {
+        usize::try_from(self.int()?).map_err(|_| TypedStreamError::Truncated)
+    }
+
+    /// Version, "streamtyped", then the system version.
+    fn header(&mut self) -> Result<(), TypedStreamError> {
+        self.int().map_err(|_| TypedStreamError::BadHeader)?;
+        let len = self.len().map_err(|_| TypedStreamError::BadHeader)?;
+        if self.take(len).map_err(|_| TypedStreamError::BadHeader)? != b"streamtyped" {
+            return Err(TypedStreamError::BadHeader);
+        }
+        self.int().map_err(|_| TypedStreamError::BadHeader)?;
+        Ok(())
+    }
+
+    /// A string from the shared table, new or by reference.
+    fn shared_string(&mut self) -> Result<Option<String>, TypedStreamError> {
+        match self.byte()? {
+            TAG_NIL => Ok(None),
+            TAG_NEW => {
+                let len = self.len()?;
+                let s = String::from_utf8_lossy(self.take(len)?).into_owned();
+                self.strings.push(s.clone());
+                Ok(Some(s))
+            }
+            tag => {
+                let index = self.reference(tag)?;
+                self.strings.get(index).cloned().map(Some).ok_or(TypedStreamError::BadReference(index))
+            }
+        }
+    }
+
+    fn reference(&mut self, tag: u8) -> Result<usize, TypedStreamError> {
+        let index = self.int_after(tag)? - REFERENCE_BASE as i8 as i64;
+        usize::try_from(index).map_err(|_| TypedStreamError::UnexpectedTag(tag))
+    }
+
+    /// A class and its superclasses, returning the class's table index.
+    fn class(&mut self, depth: usize) -> Result<Option<usize>, TypedStreamError> {
+        if depth > MAX_DEPTH {
+            return Err(TypedStreamError::TooDeep);
+        }
+        match self.byte()? {
+            TAG_NIL => Ok(None),
+            TAG_NEW => {
+                let name = self.shared_string()?.ok_or(TypedStreamError::UnexpectedTag(TAG_NIL))?;
+                self.int()?; // version
+                let index = self.objects.len();
+                self.objects.push(Entry::Class { name });
+                self.class(depth + 1)?;
+                Ok(Some(index))
+            }
+            tag => {
+                let index = self.reference(tag)?;
+                match self.objects.get(index) {
+                    Some(Entry::Class { .. }) => Ok(Some(index)),
+                    _ => Err(TypedStreamError::BadReference(index)),
+                }
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, TypedStreamError> {
+        if depth > MAX_DEPTH {
+            return Err(TypedStreamError::TooDeep);
+        }
+        match self.byte()? {
+            TAG_NIL => Ok(Value::Nil),
+            TAG_NEW => {
+                // Registered before its contents so they can refer back to it
+                let index = self.objects.len();
+                self.objects.push(Entry::Object { class: None, values: Vec::new() });
+                let class = self.class(0)?;
+                let mut values = Vec::new();
+                while self.peek()? != TAG_END {
+                    values.extend(self.group(depth + 1)?);
+                }
+                self.pos += 1;
+                self.objects[index] = Entry::Object { class, values };
+                Ok(Value::Object(index))
+            }
+            tag => {
+                let index = self.reference(tag)?;
+                match self.objects.get(index) {
+                    Some(Entry::Object { .. }) => Ok(Value::Object(index)),
+                    _ => Err(TypedStreamError::BadReference(index)),
+                }
+            }
+        }
+    }
+
+    /// A type encoding and a value for each of its characters.
+    fn group(&mut self, depth: usize) -> Result<Vec<Value>, TypedStreamError> {
+        let types = self.shared_string()?.ok_or(TypedStreamError::UnexpectedTag(TAG_NIL))?;
+        let mut values = Vec::with_capacity(types.len());
+        for t in types.chars() {
+            values.push(self.value(t, depth)?);
+        }
+        Ok(values)
+    }
+
+    fn value(&mut self, type_char: char, depth: usize) -> Result<Value, TypedStreamError> {
+        Ok(match type_char {
+            '@' => self.object(depth)?,
+            '+' => {
+                let len = self.len()?;
+                Value::Bytes(self.take(len)?.to_vec())
+            }
+            // C strings only appear as NSValue type codes, which aren't needed
+            '*' => match self.byte()? {
+                TAG_NIL => Value::Nil,
+                TAG_NEW => {
+                    self.shared_string()?;
+                    Value::Nil
+                }
+                tag => return Err(TypedStreamError::UnexpectedTag(tag)),
+            },
+            'c' | 'C' | 's' | 'S' | 'i' | 'I' | 'l' | 'L' | 'q' | 'Q' => Value::Int(self.int()?),
+            'f' | 'd' => match self.byte()? {
+                TAG_FLOAT if type_char == 'f' => Value::Float(f32::from_le_bytes(self.take(4)?.try_into().unwrap()) as f64),
+                TAG_FLOAT => Value::Float(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+                tag => Value::Float(self.int_after(tag)? as f64),
+            },
+            other => return Err(TypedStreamError::UnsupportedType(other)),
+        })
+    }
+
+    /// The first top-level object.
+    fn read_root(&mut self) -> Result<usize, TypedStreamError> {
+        self.header()?;
+        match self.group(0)?.first() {
+            Some(Value::Object(index)) => Ok(*index),
+            _ => Err(TypedStreamError::NotAttributedString("nothing".into())),
+        }
+    }
+
+    fn class_name(&self, object: usize) -> &str {
+        match &self.objects[object] {
+            Entry::Object { class: Some(class), .. } => match &self.objects[*class] {
+                Entry::Class { name } => name,
+                Entry::Object { .. } => "",
+            },
+            _ => "",
+        }
+    }
+
+    fn values(&self, object: usize) -> &[Value] {
+        match &self.objects[object] {
+            Entry::Object { values, .. } => values,
+            Entry::Class { .. } => &[],
+        }
+    }
+
+    /// Text of an NSString, or of the first string inside other objects
+    /// such as NSURL.
+    fn string_in(&self, object: usize, depth: usize) -> Option<String> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.values(object).iter().find_map(|v| match v {
+            Value::Bytes(b) => Some(String::from_utf8_lossy(b).into_owned()),
+            Value::Object(o) => self.string_in(*o, depth + 1),
+            _ => None,
+        })
+    }
+
+    fn attribute_value(&self, value: &Value) -> AttributeValue {
+        let Value::Object(object) = value else {
+            return AttributeValue::Other(String::new());
+        };
+        let class = self.class_name(*object);
+        match class {
+            "NSString" | "NSMutableString" => AttributeValue::Text(self.string_in(*object, 0).unwrap_or_default()),
+            "NSURL" => AttributeValue::Url(self.string_in(*object, 0).unwrap_or_default()),
+            "NSNumber" => self
+                .values(*object)
+                .iter()
+                .find_map(|v| match v {
+                    Value::Int(n) => Some(AttributeValue::Number(*n as f64)),
+                    Value::Float(f) => Some(AttributeValue::Number(*f)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| AttributeValue::Other(class.to_string())),
+            _ => AttributeValue::Other(class.to_string()),
+        }
+    }
+
+    fn dictionary(&self, value: &Value) -> BTreeMap<String, AttributeValue> {
+        let mut attributes = BTreeMap::new();
+        let Value::Object(object) = value else { return attributes };
+        // A count, then alternating keys and values
+        let values = self.values(*object);
+        for pair in values.get(1..).unwrap_or_default().chunks_exact(2) {
+            if let Value::Object(key) = &pair[0] {
+                if let Some(key) = self.string_in(*key, 0) {
+                    attributes.insert(key, self.attribute_value(&pair[1]));
+                }
+            }
+        }
+        attributes
+    }
+
+    fn attributed_text(&self, root: usize) -> Result<AttributedText, TypedStreamError> {
+        let class = self.class_name(root);
+        if !matches!(class, "NSAttributedString" | "NSMutableAttributedString") {
+            return Err(TypedStreamError::NotAttributedString(class.to_string()));
+        }
+        let values = self.values(root);
+        let text = match values.first() {
+            Some(Value::Object(s)) => self.string_in(*s, 0).unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        // Byte offset of every UTF-16 boundary, since run lengths count UTF-16 units
+        let mut offsets = vec![0];
+        for (i, c) in text.char_indices() {
+            for _ in 1..c.len_utf16() {
+                offsets.push(i);
+            }
+            offsets.push(i + c.len_utf8());
+        }
+
+        let mut runs = Vec::new();
+        let mut start = 0usize;
+        for run in values.get(1..).unwrap_or_default().chunks_exact(3) {
+            let (Value::Int(_), Value::Int(len)) = (&run[0], &run[1]) else { break };
+            let end = start.saturating_add(usize::try_from(*len).unwrap_or(0)).min(offsets.len() - 1);
+            runs.push(AttributeRun { range: offsets[start]..offsets[end], attributes: self.dictionary(&run[2]) });
+            start = end;
+        }
+
+        Ok(AttributedText { text, runs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// "Hello" as stored by Messages on macOS 14, one run tagged as part 0.
+    const HELLO: &str = "
+        040b73747265616d747970656481e803 840140 848484124e5341747472696275746564537472696e6700
+        8484084e534f626a6563740085 92 848484084e53537472696e670194 84012b0548656c6c6f86
+        84026949 0105 92 8484840c4e5344696374696f6e6172790094 84016901
+        92 8496961d5f5f6b494d4d657373616765506172744174747269627574654e616d6586
+        92 848484084e534e756d626572008484074e5356616c75650094 84012a8499 9900 86 86 86";
+
+    #[test]
+    fn test_decodes_single_run() {
+        let decoded = decode_attributed_string(&hex(HELLO)).unwrap();
+        assert_eq!(decoded.text, "Hello");
+        assert_eq!(decoded.runs.len(), 1);
+        assert_eq!(decoded.runs[0].range, 0..5);
+        assert_eq!(
+            decoded.runs[0].attributes.get("__kIMMessagePartAttributeName"),
+            Some(&AttributeValue::Number(0.0))
+        );
+    }
+
+    /// Build an attributed string the way Messages archives one, with runs
+    /// of (UTF-16 length, attributes as key and NSString value pairs).
+    fn archive(text: &str, runs: &[(usize, &[(&str, &str)])]) -> Vec<u8> {
+        fn string(out: &mut Vec<u8>, s: &str) {
+            if s.len() < 0x80 {
+                out.push(s.len() as u8);
+            } else {
+                out.push(TAG_INT16);
+                out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+            }
+            out.extend_from_slice(s.as_bytes());
+        }
+        let mut out = hex("040b73747265616d747970656481e803 840140 848484124e5341747472696275746564537472696e6700 8484084e534f626a6563740085");
+        // Strings: 0 "@", 1 NSAttributedString, 2 NSObject, 3 NSString, 4 "+", 5 "iI", 6 NSDictionary, 7 "i"
+        // Objects: 0 root, 1 its class, 2 NSObject, 3 text, 4 NSString class, then dictionaries
+        out.extend(hex("92 848484084e53537472696e670194 84012b"));
+        string(&mut out, text);
+        out.push(TAG_END);
+        for (i, (len, attributes)) in runs.iter().enumerate() {
+            out.extend(if i == 0 { hex("84026949") } else { vec![0x97] });
+            out.push(i as u8 + 1);
+            out.push(*len as u8);
+            out.extend(if i == 0 { hex("92 8484840c4e5344696374696f6e6172790094 840169") } else { hex("92 84 98 99") });
+            out.push(attributes.len() as u8);
+            for (key, value) in attributes.iter() {
+                for s in [key, value] {
+                    out.extend(hex("92 84 96 96"));
+                    string(&mut out, s);
+                    out.push(TAG_END);
+                }
+            }
+            out.push(TAG_END);
+        }
+        out.push(TAG_END);
+        out
+    }
+
+    #[test]
+    fn test_mentions_and_links_across_runs() {
+        let text = "Hey @Jane 👋 see https://example.com/a";
+        let blob = archive(text, &[
+            (4, &[("__kIMMessagePartAttributeName", "0")]),
+            (5, &[(MENTION_ATTRIBUTE, "jane@example.com")]),
+            (8, &[]),
+            (21, &[(LINK_ATTRIBUTE, "https://example.com/a")]),
+        ]);
+        let decoded = decode_attributed_string(&blob).unwrap();
+        assert_eq!(decoded.text, text);
+
+        let mentions = decoded.mentions();
+        assert_eq!(mentions, [(4..9, "jane@example.com")]);
+        assert_eq!(&decoded.text[mentions[0].0.clone()], "@Jane");
+
+        let links = decoded.links();
+        assert_eq!(&decoded.text[links[0].0.clone()], "https://example.com/a");
+        // The emoji is two UTF-16 units but four bytes
+        assert_eq!(decoded.runs[2].range, 9..19);
+    }
+
+    #[test]
+    fn test_rejects_other_input() {
+        assert_eq!(decode_attributed_string(b""), Err(TypedStreamError::BadHeader));
+        assert_eq!(decode_attributed_string(b"NSString\x01\x94\x84\x01+\x02hi"), Err(TypedStreamError::BadHeader));
+        let blob = hex(HELLO);
+        for cut in [1, 10, 40] {
+            assert!(decode_attributed_string(&blob[..blob.len() - cut]).is_err());
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Health checks that repair what they safely can.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::cache::prune_orphaned_conversions;
+use crate::db::Database;
+use crate::persist::backup_path;
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl DiagnosticCheck {
+    fn new(name: &str, ok: bool, detail: impl Into<String>) -> Self {
+        Self { name: name.to_string(), ok, detail: detail.into() }
+    }
+}
+
+/// Locations the diagnostics inspect.
+pub struct DiagnosticPaths {
+    pub cache_dir: PathBuf,
+    pub attachments_dir: PathBuf,
+    /// Persisted JSON state files
+    pub state_files: Vec<PathBuf>,
+}
+
+/// Run every check and return the results in order.
+pub fn run_diagnostics(paths: &DiagnosticPaths) -> Vec<DiagnosticCheck> {
+    let mut checks = vec![check_writable(&paths.cache_dir)];
+
+    checks.push(match prune_orphaned_conversions(&paths.cache_dir, &paths.attachments_dir) {
+        Ok(n) => DiagnosticCheck::new("orphaned conversions", true, format!("Removed {} file(s)", n)),
+        Err(e) => DiagnosticCheck::new("orphaned conversions", false, e.to_string()),
+    });
+
+    for path in &paths.state_files {
+        checks.push(validate_state_file(path));
+    }
+
+    checks
+}
+
+/// A WAL this large means Messages hasn't checkpointed in a long while, so
+/// recent messages may be slow to read or missing from a copied database.
+const WAL_WARN_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Pre-flight checks on chat.db, so an empty list comes with a reason.
+pub fn verify_database(path: &Path) -> Vec<DiagnosticCheck> {
+    let db = match Database::open(&path.to_path_buf()) {
+        Ok(db) => db,
+        Err(e) => return vec![DiagnosticCheck::new("database access", false, e.to_string())],
+    };
+    let mut checks = vec![DiagnosticCheck::new("database access", true, format!("Opened {}", path.display()))];
+
+    checks.push(match db.quick_check() {
+        Ok(problems) if problems.is_empty() => DiagnosticCheck::new("database integrity", true, "quick_check passed"),
+        Ok(problems) => DiagnosticCheck::new("database integrity", false, problems.join("; ")),
+        Err(e) => DiagnosticCheck::new("database integrity", false, e.to_string()),
+    });
+
+    checks.push(match db.missing_schema() {
+        Ok(missing) if missing.is_empty() => DiagnosticCheck::new("database schema", true, "All required tables and columns present"),
+        Ok(missing) => DiagnosticCheck::new("database schema", false, format!("Missing {}", missing.join(", "))),
+        Err(e) => DiagnosticCheck::new("database schema", false, e.to_string()),
+    });
+
+    checks.push(match db.message_count() {
+        Ok(0) => DiagnosticCheck::new("messages", false, "The message table is empty"),
+        Ok(n) => DiagnosticCheck::new("messages", true, format!("{} messages", n)),
+        Err(e) => DiagnosticCheck::new("messages", false, e.to_string()),
+    });
+
+    checks.push(check_wal(path));
+    checks
+}
+
+/// Flag an oversized write-ahead log next to the database.
+fn check_wal(path: &Path) -> DiagnosticCheck {
+    const NAME: &str = "database WAL";
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+
+    match fs::metadata(PathBuf::from(wal)) {
+        Err(_) => DiagnosticCheck::new(NAME, true, "No WAL file"),
+        Ok(meta) if meta.len() > WAL_WARN_BYTES => DiagnosticCheck::new(
+            NAME,
+            false,
+            format!("WAL is {} MB; quit and reopen Messages to checkpoint it", meta.len() / (1024 * 1024)),
+        ),
+        Ok(meta) => DiagnosticCheck::new(NAME, true, format!("WAL is {} KB", meta.len() / 1024)),
+    }
+}
+
+/// Confirm a directory exists (creating it if needed) and accepts writes.
+pub fn check_writable(dir: &Path) -> DiagnosticCheck {
+    const NAME: &str = "cache directory";
+    let probe = dir.join(".write-test");
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b"ok"))
+        .and_then(|_| fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => DiagnosticCheck::new(NAME, true, format!("{} is writable", dir.display())),
+        Err(e) => DiagnosticCheck::new(NAME, false, format!("{}: {}", dir.display(), e)),
+    }
+}
+
+/// Check a persisted JSON file parses. A corrupt file is restored from its
+/// `.bak`, or moved aside to `.corrupt` so the app can start fresh.
+pub fn validate_state_file(path: &Path) -> DiagnosticCheck {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+
+    let Ok(contents) = fs::read_to_string(path) else {
+        return DiagnosticCheck::new(&name, true, "Not created yet");
+    };
+    if serde_json::from_str::<serde_json::Value>(&contents).is_ok() {
+        return DiagnosticCheck::new(&name, true, "Valid");
+    }
+
+    let backup = backup_path(path);
+    let backup_valid = fs::read_to_string(&backup)
+        .ok()
+        .is_some_and(|b| serde_json::from_str::<serde_json::Value>(&b).is_ok());
+
+    if backup_valid {
+        return match fs::copy(&backup, path) {
+            Ok(_) => DiagnosticCheck::new(&name, true, "Corrupt; restored from backup"),
+            Err(e) => DiagnosticCheck::new(&name, false, format!("Corrupt; restore failed: {}", e)),
+        };
+    }
+
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(".corrupt");
+    match fs::rename(path, PathBuf::from(aside)) {
+        Ok(()) => DiagnosticCheck::new(&name, false, "Corrupt with no backup; moved aside and reset"),
+        Err(e) => DiagnosticCheck::new(&name, false, format!("Corrupt with no backup: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_writable() {
+        let dir = tempfile::tempdir().unwrap();
+        let check = check_writable(&dir.path().join("new/cache"));
+        assert!(check.ok);
+        assert!(dir.path().join("new/cache").exists());
+    }
+
+    #[test]
+    fn test_validate_missing_and_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("failed.json");
+        assert!(validate_state_file(&path).ok);
+
+        fs::write(&path, "[]").unwrap();
+        let check = validate_state_file(&path);
+        assert!(check.ok);
+        assert_eq!(check.name, "failed.json");
+    }
+
+    #[test]
+    fn test_validate_restores_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("failed.json");
+        fs::write(&path, "{corrupt").unwrap();
+        fs::write(backup_path(&path), "[1]").unwrap();
+
+        let check = validate_state_file(&path);
+        assert!(check.ok);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[1]");
+    }
+
+    #[test]
+    fn test_validate_moves_corrupt_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("failed.json");
+        fs::write(&path, "{corrupt").unwrap();
+
+        let check = validate_state_file(&path);
+        assert!(!check.ok);
+        assert!(!path.exists());
+        assert!(dir.path().join("failed.json.corrupt").exists());
+    }
+
+    #[test]
+    fn test_verify_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.db");
+        let checks = verify_database(&path);
+        assert_eq!(checks.len(), 1);
+        assert!(!checks[0].ok);
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE message (ROWID INTEGER PRIMARY KEY, text TEXT);").unwrap();
+        let checks = verify_database(&path);
+        let failed: Vec<_> = checks.iter().filter(|c| !c.ok).map(|c| c.name.as_str()).collect();
+        assert_eq!(failed, ["database schema", "messages"]);
+        assert!(checks[2].detail.contains("message.guid"));
+        assert!(checks[2].detail.contains("chat_message_join"));
+    }
+
+    #[test]
+    fn test_run_diagnostics() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = DiagnosticPaths {
+            cache_dir: dir.path().join("cache"),
+            attachments_dir: dir.path().join("attachments"),
+            state_files: vec![dir.path().join("a.json"), dir.path().join("b.json")],
+        };
+        let checks = run_diagnostics(&paths);
+        assert_eq!(checks.len(), 4);
+        assert!(checks.iter().all(|c| c.ok));
+    }
+}
@@ -0,0 +1,107 @@
+//! Read drafts that Messages.app keeps for each chat.
+//!
+//! Messages.app stores a half-typed reply in
+//! `~/Library/Messages/Drafts/<chat_identifier>/composition.plist`, a keyed
+//! archive whose text is an NSAttributedString in typedstream form.
+
+use std::path::{Path, PathBuf};
+use plist::Value;
+
+use crate::db::parse_attributed_body;
+
+/// Default Messages.app drafts directory.
+pub fn default_drafts_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("home directory required")
+        .join("Library/Messages/Drafts")
+}
+
+/// Read the Messages.app draft for a chat, if there is one.
+pub fn messages_app_draft(drafts_dir: &Path, chat_identifier: &str) -> Option<String> {
+    // Identifiers are phone numbers, emails, or chat GUIDs; never paths
+    if chat_identifier.is_empty() || chat_identifier.contains('/') || chat_identifier.contains("..") {
+        return None;
+    }
+
+    let path = drafts_dir.join(chat_identifier).join("composition.plist");
+    parse_draft_plist(&std::fs::read(path).ok()?)
+}
+
+/// Parse the bytes of a composition.plist (binary or XML) into draft text.
+pub fn parse_draft_plist(data: &[u8]) -> Option<String> {
+    let value = Value::from_reader(std::io::Cursor::new(data)).ok()?;
+    extract_draft_text(&value)
+}
+
+/// Pull the composed text out of a composition.plist keyed archive.
+fn extract_draft_text(value: &Value) -> Option<String> {
+    let objects = value.as_dictionary()?.get("$objects")?.as_array()?;
+
+    // Current versions archive the text as a typedstream attributed string
+    let archived = objects
+        .iter()
+        .filter_map(|o| o.as_data())
+        .filter_map(parse_attributed_body)
+        .map(|t| t.trim().to_string())
+        .find(|t| !t.is_empty());
+    if archived.is_some() {
+        return archived;
+    }
+
+    // Older versions store the plain string next to attribute keys
+    objects
+        .iter()
+        .filter_map(|o| o.as_string())
+        .filter(|s| *s != "$null" && !s.starts_with("__k"))
+        .map(|s| s.trim().to_string())
+        .find(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+
+    fn archive(objects: Vec<Value>) -> Value {
+        let mut dict = Dictionary::new();
+        dict.insert("$objects".into(), Value::Array(objects));
+        Value::Dictionary(dict)
+    }
+
+    #[test]
+    fn test_extract_typedstream_text() {
+        let mut blob = Vec::new();
+        blob.extend_from_slice(b"NSString");
+        blob.extend_from_slice(&[0, 0, 0, 0, 0]);
+        blob.push(9);
+        blob.extend_from_slice(b"On my way");
+
+        let value = archive(vec![Value::String("$null".into()), Value::Data(blob)]);
+        assert_eq!(extract_draft_text(&value), Some("On my way".to_string()));
+    }
+
+    #[test]
+    fn test_extract_plain_string() {
+        let value = archive(vec![
+            Value::String("$null".into()),
+            Value::String("__kIMMessagePartAttributeName".into()),
+            Value::String("See you soon".into()),
+        ]);
+        assert_eq!(extract_draft_text(&value), Some("See you soon".to_string()));
+    }
+
+    #[test]
+    fn test_extract_empty_draft() {
+        let value = archive(vec![Value::String("$null".into()), Value::String("  ".into())]);
+        assert_eq!(extract_draft_text(&value), None);
+        assert_eq!(extract_draft_text(&Value::Boolean(true)), None);
+    }
+
+    #[test]
+    fn test_missing_or_unsafe_identifier() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(messages_app_draft(dir.path(), "+15551234567"), None);
+        assert_eq!(messages_app_draft(dir.path(), "../etc"), None);
+        assert_eq!(messages_app_draft(dir.path(), ""), None);
+    }
+}
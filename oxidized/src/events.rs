@@ -0,0 +1,144 @@
+//! In-process event bus between watchers and whatever reacts to them.
+//!
+//! Watchers publish what they saw once; each subscriber (the frontend
+//! bridge, the search indexer, ...) gets its own bounded queue and thread,
+//! so adding an integration doesn't add another poll of chat.db. A
+//! subscriber that falls behind misses events rather than slowing the
+//! watcher down, and is told how many it missed so it can catch up.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+
+use crate::db::MessageChanges;
+
+/// Queue length per subscriber unless it asks for another.
+pub const DEFAULT_EVENT_CAPACITY: usize = 64;
+
+/// Something that happened that other parts of the app may care about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppEvent {
+    /// chat.db gained messages in these chats; none means any may have
+    MessagesChanged(MessageChanges),
+    /// Contacts were reloaded, with this many names
+    ContactsChanged(usize),
+    /// Something failed in the background that the user should hear about,
+    /// e.g. a send that went out but couldn't be logged
+    Problem(String),
+}
+
+struct Subscriber {
+    sender: SyncSender<AppEvent>,
+    missed: Arc<AtomicUsize>,
+}
+
+/// Fans each published event out to every subscriber.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start receiving events published from now on, queueing at most
+    /// `capacity` of them.
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let missed = Arc::new(AtomicUsize::new(0));
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(Subscriber { sender, missed: missed.clone() });
+        }
+        Subscription { receiver, missed }
+    }
+
+    /// Hand `event` to every subscriber without blocking, and return how
+    /// many took it. Full queues drop it and count the miss; subscribers
+    /// that have gone away are forgotten.
+    pub fn publish(&self, event: AppEvent) -> usize {
+        let Ok(mut subscribers) = self.subscribers.lock() else {
+            return 0;
+        };
+        let mut delivered = 0;
+        subscribers.retain(|s| match s.sender.try_send(event.clone()) {
+            Ok(()) => {
+                delivered += 1;
+                true
+            }
+            Err(TrySendError::Full(_)) => {
+                s.missed.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+        delivered
+    }
+
+    /// How many subscribers are listening.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().map(|s| s.len()).unwrap_or(0)
+    }
+}
+
+/// One subscriber's end of the bus. Dropping it unsubscribes.
+pub struct Subscription {
+    receiver: Receiver<AppEvent>,
+    missed: Arc<AtomicUsize>,
+}
+
+impl Subscription {
+    /// Wait for the next event, or None once the bus is gone.
+    pub fn recv(&self) -> Option<AppEvent> {
+        self.receiver.recv().ok()
+    }
+
+    /// Events dropped since the last call because the queue was full.
+    pub fn take_missed(&self) -> usize {
+        self.missed.swap(0, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(chat_id: i64) -> AppEvent {
+        AppEvent::MessagesChanged(MessageChanges { chat_ids: vec![chat_id], newest_rowid: chat_id, newest_read: 0 })
+    }
+
+    #[test]
+    fn test_fan_out_and_backpressure() {
+        let bus = EventBus::new();
+        let fast = bus.subscribe(DEFAULT_EVENT_CAPACITY);
+        let slow = bus.subscribe(1);
+
+        assert_eq!(bus.publish(changed(1)), 2);
+        assert_eq!(bus.publish(changed(2)), 1);
+        assert_eq!(bus.publish(AppEvent::ContactsChanged(3)), 1);
+
+        assert_eq!(fast.recv(), Some(changed(1)));
+        assert_eq!(fast.recv(), Some(changed(2)));
+        assert_eq!(fast.recv(), Some(AppEvent::ContactsChanged(3)));
+        assert_eq!(fast.take_missed(), 0);
+
+        assert_eq!(slow.recv(), Some(changed(1)));
+        assert_eq!(slow.take_missed(), 2);
+        assert_eq!(slow.take_missed(), 0);
+
+        drop(slow);
+        assert_eq!(bus.publish(changed(4)), 1);
+        assert_eq!(bus.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_recv_ends_with_bus() {
+        let bus = EventBus::new();
+        let subscription = bus.subscribe(DEFAULT_EVENT_CAPACITY);
+        bus.publish(AppEvent::ContactsChanged(1));
+        drop(bus);
+        assert_eq!(subscription.recv(), Some(AppEvent::ContactsChanged(1)));
+        assert_eq!(subscription.recv(), None);
+    }
+}
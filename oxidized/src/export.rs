@@ -0,0 +1,251 @@
+//! Streaming export of messages to JSON lines or CSV.
+//!
+//! One object per message, in the order chat.db stored them (by ROWID), for
+//! jq or DuckDB's `read_json`. That's oldest first except for messages that
+//! arrived late, delayed or synced in from iCloud, which come where they were
+//! added. Progress is checkpointed after every batch next to the output file,
+//! so an interrupted export of a huge chat.db picks up where it stopped, and a
+//! later resumed run appends every message added since, whatever its date.
+//! The checkpoint records how many bytes it covers, and a resume cuts the file
+//! back to that length first, so lines written after the last checkpoint
+//! aren't duplicated.
+//!
+//! CSV exports are one row per message for spreadsheets. They cover a date
+//! range and are always written in full.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::db::{Database, DbError, ExportCursor, ExportEvent};
+use crate::persist::{atomic_write, load_json};
+use crate::redact::Redactor;
+use crate::{unix_to_apple_nanos, unix_to_apple_secs};
+
+/// Messages fetched and written per batch.
+const BATCH_SIZE: usize = 1000;
+
+/// chat.db dates above this are nanoseconds, as in [`crate::apple_to_unix`].
+const NANOSECOND_DATES: i64 = 1_000_000_000_000;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error("Export I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Export encoding failed: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("CSV export failed: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("The checkpoint is for a different set of chats; export without resuming")]
+    CheckpointMismatch,
+}
+
+/// Saved after each batch of a JSONL export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Checkpoint {
+    /// The highest ROWID written
+    rowid: i64,
+    /// Length of the output file when `rowid` was saved
+    bytes: u64,
+    /// The chats exported, sorted, or None for all of them
+    chat_ids: Option<Vec<i64>>,
+}
+
+/// What an export run did.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportSummary {
+    /// Messages written by this run
+    pub exported: usize,
+    pub resumed: bool,
+    /// The highest ROWID written so far
+    pub last_rowid: i64,
+}
+
+/// Dates a CSV export covers, `from` inclusive and `to` exclusive. Either
+/// end may be left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+const CSV_HEADER: [&str; 7] = ["chat", "sender", "name", "date", "service", "text", "attachments"];
+
+/// Checkpoint file for an export ("messages.jsonl" -> "messages.jsonl.checkpoint").
+pub fn checkpoint_path(out: &Path) -> PathBuf {
+    let mut name = out.as_os_str().to_owned();
+    name.push(".checkpoint");
+    PathBuf::from(name)
+}
+
+/// Export messages to `out` as JSON lines.
+///
+/// With `resume`, continues from the checkpoint and appends; otherwise the
+/// file is rewritten from the first message. `chat_ids` limits the export to
+/// those chats. Senders are named from `names` as in [`export_csv`], and
+/// `redactor` masks text, handles and names before they're written.
+pub fn export_jsonl(
+    db: &Database,
+    out: &Path,
+    chat_ids: Option<&[i64]>,
+    resume: bool,
+    names: &HashMap<String, String>,
+    redactor: Option<&Redactor>,
+) -> Result<ExportSummary, ExportError> {
+    let checkpoint = checkpoint_path(out);
+    let chat_ids = chat_ids.map(|ids| {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    });
+    let saved = if resume { load_json::<Checkpoint>(&checkpoint) } else { None };
+    if saved.as_ref().is_some_and(|saved| saved.chat_ids != chat_ids) {
+        return Err(ExportError::CheckpointMismatch);
+    }
+
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(out)?;
+    // A file shorter than its checkpoint was replaced or cut; start over
+    let len = file.metadata()?.len();
+    let saved = saved.filter(|saved| saved.bytes <= len);
+    let resumed = saved.is_some();
+    let (mut last_rowid, bytes) = saved.map_or((0, 0), |saved| (saved.rowid, saved.bytes));
+    // Drop anything written after the checkpoint by a run that was cut short
+    file.set_len(bytes)?;
+    file.seek(SeekFrom::End(0))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut exported = 0;
+    loop {
+        let batch = db.messages_after_rowid(last_rowid, chat_ids.as_deref(), BATCH_SIZE)?;
+        let Some(last) = batch.last() else { break };
+        let next = last.rowid;
+
+        for mut event in batch.iter().cloned() {
+            event.sender_name = sender_name(&event, names);
+            if let Some(redactor) = redactor {
+                redactor.redact_event(&mut event);
+            }
+            serde_json::to_writer(&mut writer, &event)?;
+            writer.write_all(b"\n")?;
+        }
+        // Lines must be on disk before the checkpoint claims them
+        writer.flush()?;
+        let file = writer.get_mut();
+        file.sync_data()?;
+        let bytes = file.stream_position()?;
+        // Beside the export, so no backup cluttering the user's folder
+        let progress = Checkpoint { rowid: next, bytes, chat_ids: chat_ids.clone() };
+        atomic_write(&checkpoint, &serde_json::to_vec(&progress)?)?;
+
+        exported += batch.len();
+        last_rowid = next;
+        if batch.len() < BATCH_SIZE {
+            break;
+        }
+    }
+
+    Ok(ExportSummary { exported, resumed, last_rowid })
+}
+
+/// Export messages in `range` to `out` as CSV, returning how many rows were
+/// written. Senders are named from `names`, handle to contact name, which
+/// the caller resolves beforehand (see [`Database::sender_handles`]) so no
+/// contacts lock is held while writing; my own messages have sender "me".
+/// `redactor` masks text, handles and names.
+pub fn export_csv(
+    db: &Database,
+    out: &Path,
+    chat_ids: Option<&[i64]>,
+    range: ExportRange,
+    names: &HashMap<String, String>,
+    redactor: Option<&Redactor>,
+) -> Result<usize, ExportError> {
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = csv::Writer::from_path(out)?;
+    writer.write_record(CSV_HEADER)?;
+
+    // Dates in seconds (before macOS 10.13) sort below nanosecond ones. Start
+    // at the cutoff in seconds and jump to it in nanoseconds once the dates
+    // switch units; anything earlier still read is skipped below.
+    let from_nanos = range.from.map(|from| unix_to_apple_nanos(from.timestamp()));
+    let mut cursor = match range.from {
+        Some(from) => ExportCursor { date: unix_to_apple_secs(from.timestamp()), rowid: i64::MIN },
+        None => ExportCursor::start(),
+    };
+    let mut exported = 0;
+    'batches: loop {
+        let batch = db.messages_after(&cursor, chat_ids, BATCH_SIZE)?;
+        let Some(last) = batch.last() else { break };
+        cursor = ExportCursor { date: last.apple_date, rowid: last.rowid };
+        if let Some(from_nanos) = from_nanos {
+            if cursor.date > NANOSECOND_DATES && cursor.date < from_nanos {
+                cursor = ExportCursor { date: from_nanos, rowid: i64::MIN };
+            }
+        }
+
+        for event in &batch {
+            if range.from.is_some_and(|from| event.date < from) {
+                continue;
+            }
+            if range.to.is_some_and(|to| event.date >= to) {
+                break 'batches;
+            }
+            let mut event = event.clone();
+            event.sender_name = sender_name(&event, names);
+            if let Some(redactor) = redactor {
+                redactor.redact_event(&mut event);
+            }
+            let name = event.sender_name.as_deref().unwrap_or_default();
+            let sender = if event.is_from_me { "me" } else { event.sender.as_deref().unwrap_or_default() };
+            writer.write_record([
+                event.chat_identifier.as_str(),
+                sender,
+                &neutralise_formula(name),
+                &event.date.to_rfc3339(),
+                event.service.as_deref().unwrap_or_default(),
+                &neutralise_formula(&event.text),
+                &event.attachment_count.to_string(),
+            ])?;
+            exported += 1;
+        }
+        if batch.len() < BATCH_SIZE {
+            break;
+        }
+    }
+
+    writer.flush()?;
+    Ok(exported)
+}
+
+/// "Me" for my own messages, otherwise the sender's name in `names`.
+fn sender_name(event: &ExportEvent, names: &HashMap<String, String>) -> Option<String> {
+    if event.is_from_me {
+        Some("Me".to_string())
+    } else {
+        event.sender.as_ref().and_then(|h| names.get(h)).cloned()
+    }
+}
+
+/// `cell` with a leading `'` if a spreadsheet would otherwise read it as a
+/// formula, which could fetch URLs or run commands when the file is opened.
+fn neutralise_formula(cell: &str) -> Cow<'_, str> {
+    if cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{}", cell))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
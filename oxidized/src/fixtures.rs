@@ -0,0 +1,66 @@
+//! Conversations and messages for tests, with every field filled in. A
+//! test spells out only the fields it cares about and takes the rest with
+//! `..conversation("chat1")`, so a new field is added here and nowhere else.
+
+use chrono::Utc;
+
+use crate::models::{AgeBucket, Conversation, Message};
+
+/// An incoming text from nobody in particular, sent just now.
+pub(crate) fn message(text: &str) -> Message {
+    Message {
+        rowid: 1,
+        guid: "m".into(),
+        text: text.into(),
+        date: Utc::now(),
+        is_from_me: false,
+        sender: None,
+        sender_name: None,
+        system_event: None,
+        link_preview: None,
+        attachments: vec![],
+        reactions: vec![],
+        mentions: vec![],
+        reply_to_guid: None,
+        reply_to_text: None,
+        is_delivered: false,
+        date_delivered: None,
+        date_read: None,
+        edited: false,
+        retracted: false,
+        edit_history: vec![],
+    }
+}
+
+/// A one-to-one iMessage chat with one unread message and nothing loaded.
+pub(crate) fn conversation(chat_identifier: &str) -> Conversation {
+    let now = Utc::now();
+    Conversation {
+        source_id: "local".into(),
+        chat_id: 1,
+        guid: format!("iMessage;-;{}", chat_identifier),
+        display_name: None,
+        chat_identifier: chat_identifier.into(),
+        style: 45,
+        service_name: None,
+        unread_count: 1,
+        last_message_date: now,
+        last_incoming_date: now,
+        first_unread_date: now,
+        age_bucket: AgeBucket::Today,
+        last_unread_rowid: 0,
+        messages: vec![],
+        participants: vec![],
+        my_handles: vec![],
+        resolved_name: None,
+        messages_app_draft: None,
+        primary_language: None,
+        read_receipts: None,
+        activity: Vec::new(),
+        stats: None,
+        preview_image: None,
+        needs_reply: false,
+        needs_reply_reason: None,
+        is_mms_group: false,
+    }
+}
@@ -0,0 +1,172 @@
+//! macOS Focus detection, so the app can stay quiet while Sleep or Do Not
+//! Disturb is on.
+//!
+//! Focus state lives in two JSON files under ~/Library/DoNotDisturb/DB:
+//! Assertions.json names the mode that was switched on, and
+//! ModeConfigurations.json maps mode identifiers to their display names. A
+//! Focus started by a schedule rather than by hand leaves no assertion, so it
+//! isn't seen.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::contacts::same_handle;
+use crate::models::Conversation;
+
+/// How Focus modes affect the app.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FocusSettings {
+    /// Hold the app's alerts and defer sends while a Focus is on.
+    pub respect_focus: bool,
+    /// Focus names that count, e.g. "Sleep"; empty means any Focus.
+    pub modes: Vec<String>,
+    /// Handles whose replies still go out during a Focus.
+    pub vip_handles: Vec<String>,
+}
+
+impl FocusSettings {
+    /// Whether a reply to this chat goes out regardless of Focus. A group
+    /// counts when any VIP is in it.
+    pub fn is_vip(&self, conv: &Conversation) -> bool {
+        std::iter::once(&conv.chat_identifier)
+            .chain(&conv.participants)
+            .any(|h| self.vip_handles.iter().any(|vip| same_handle(vip, h)))
+    }
+}
+
+/// The active Focus and what the app does about it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FocusState {
+    pub active: bool,
+    /// e.g. "com.apple.sleep.sleep-mode"
+    pub mode_identifier: Option<String>,
+    /// e.g. "Sleep"
+    pub name: Option<String>,
+    /// Hold background-failure alerts until the Focus ends
+    pub suppress_notifications: bool,
+    /// Keep non-VIP replies queued until the Focus ends
+    pub defer_sends: bool,
+}
+
+/// ~/Library/DoNotDisturb/DB
+pub fn focus_db_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("home directory required")
+        .join("Library/DoNotDisturb/DB")
+}
+
+impl FocusState {
+    /// Read the Focus state from the DoNotDisturb database files. Missing or
+    /// unreadable files mean no Focus.
+    pub fn current(settings: &FocusSettings) -> Self {
+        Self::read(&focus_db_dir(), settings)
+    }
+
+    pub fn read(dir: &Path, settings: &FocusSettings) -> Self {
+        let assertions = fs::read_to_string(dir.join("Assertions.json")).unwrap_or_default();
+        let modes = fs::read_to_string(dir.join("ModeConfigurations.json")).unwrap_or_default();
+        Self::from_json(&assertions, &modes, settings)
+    }
+
+    /// Build the state from the contents of Assertions.json and
+    /// ModeConfigurations.json.
+    pub fn from_json(assertions: &str, modes: &str, settings: &FocusSettings) -> Self {
+        let mode_identifier = serde_json::from_str::<Value>(assertions).ok().and_then(|v| {
+            v["data"].as_array()?.iter().find_map(|entry| {
+                entry["storeAssertionRecords"].as_array()?.iter().find_map(|record| {
+                    record["assertionDetails"]["assertionDetailsModeIdentifier"].as_str().map(str::to_string)
+                })
+            })
+        });
+
+        let name = mode_identifier.as_deref().and_then(|id| {
+            let configs: Value = serde_json::from_str(modes).ok()?;
+            configs["data"].as_array()?.iter().find_map(|entry| {
+                entry["modeConfigurations"][id]["mode"]["name"].as_str().map(str::to_string)
+            })
+        });
+
+        let active = mode_identifier.is_some();
+        let counts = active
+            && settings.respect_focus
+            && (settings.modes.is_empty()
+                || name.as_deref().is_some_and(|n| settings.modes.iter().any(|m| m.eq_ignore_ascii_case(n))));
+
+        Self { active, mode_identifier, name, suppress_notifications: counts, defer_sends: counts }
+    }
+
+    /// Whether a reply to `conv` should wait until the Focus ends.
+    pub fn defers(&self, conv: &Conversation, settings: &FocusSettings) -> bool {
+        self.defer_sends && !settings.is_vip(conv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLEEP: &str = r#"{"data":[{"storeAssertionRecords":[{"assertionUUID":"A1",
+        "assertionDetails":{"assertionDetailsIdentifier":"x","assertionDetailsModeIdentifier":"com.apple.sleep.sleep-mode",
+        "assertionDetailsReason":"user-action"}}]}]}"#;
+    const NONE: &str = r#"{"data":[{"storeAssertionRecords":[]}]}"#;
+    const MODES: &str = r#"{"data":[{"modeConfigurations":{
+        "com.apple.sleep.sleep-mode":{"mode":{"name":"Sleep","modeIdentifier":"com.apple.sleep.sleep-mode"}},
+        "com.apple.focus.work":{"mode":{"name":"Work","modeIdentifier":"com.apple.focus.work"}}}}]}"#;
+
+    fn settings(modes: &[&str]) -> FocusSettings {
+        FocusSettings {
+            respect_focus: true,
+            modes: modes.iter().map(|m| m.to_string()).collect(),
+            vip_handles: vec!["+1 (555) 123-4567".into()],
+        }
+    }
+
+    #[test]
+    fn test_reads_active_focus() {
+        let state = FocusState::from_json(SLEEP, MODES, &settings(&[]));
+        assert!(state.active);
+        assert_eq!(state.name.as_deref(), Some("Sleep"));
+        assert!(state.suppress_notifications);
+        assert!(state.defer_sends);
+
+        let off = FocusState::from_json(NONE, MODES, &settings(&[]));
+        assert!(!off.active);
+        assert!(!off.suppress_notifications);
+        assert!(!off.defer_sends);
+
+        assert!(!FocusState::from_json("", "", &settings(&[])).active);
+    }
+
+    #[test]
+    fn test_only_configured_modes_count() {
+        assert!(FocusState::from_json(SLEEP, MODES, &settings(&["sleep"])).defer_sends);
+        let work_only = FocusState::from_json(SLEEP, MODES, &settings(&["Work"]));
+        assert!(work_only.active);
+        assert!(!work_only.defer_sends);
+
+        let ignored = FocusState::from_json(SLEEP, MODES, &FocusSettings::default());
+        assert!(ignored.active);
+        assert!(!ignored.suppress_notifications);
+        assert!(!ignored.defer_sends);
+    }
+
+    #[test]
+    fn test_vips_are_not_deferred() {
+        use crate::fixtures::conversation;
+
+        let conv = |identifier: &str, participants: &[&str]| Conversation {
+            participants: participants.iter().map(|p| p.to_string()).collect(),
+            ..conversation(identifier)
+        };
+        let settings = settings(&[]);
+        let state = FocusState::from_json(SLEEP, MODES, &settings);
+
+        assert!(!state.defers(&conv("+15551234567", &[]), &settings));
+        assert!(state.defers(&conv("+15559999999", &[]), &settings));
+        assert!(!state.defers(&conv("chat123", &["+15559999999", "5551234567"]), &settings));
+    }
+}
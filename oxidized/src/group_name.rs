@@ -0,0 +1,241 @@
+//! Display names for conversations without one set in Messages: the contact
+//! name for 1:1 chats, and a name built from the participants for groups.
+
+use serde::{Deserialize, Serialize};
+
+use crate::contacts::{format_display, same_handle, ContactResolver};
+use crate::models::{Conversation, Message};
+
+/// Most names listed before the rest are counted as "+N".
+pub const GROUP_NAME_MAX_NAMES: usize = 3;
+
+/// Longest name, in characters, before the rest are counted as "+N". The
+/// first name is always listed however long it is.
+pub const GROUP_NAME_MAX_CHARS: usize = 40;
+
+/// How an unnamed group is labelled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupNameStyle {
+    /// "Alice, Bob +2"
+    #[default]
+    FirstNames,
+    /// "Alice Smith, Bob Jones +2"
+    FullNames,
+    /// First names, whoever wrote last first
+    LastSpeakerFirst,
+}
+
+impl Conversation {
+    /// A name for a group built from its participants, or None if there are
+    /// none. Unknown handles are shown formatted.
+    pub fn derive_group_name(&self, resolver: &ContactResolver, style: GroupNameStyle) -> Option<String> {
+        let mut handles: Vec<&String> = self.participants.iter().collect();
+        if style == GroupNameStyle::LastSpeakerFirst {
+            let mut speakers: Vec<&str> = Vec::new();
+            for sender in self.messages.iter().rev().filter_map(|m| m.sender.as_deref()) {
+                if !speakers.iter().any(|s| same_handle(s, sender)) {
+                    speakers.push(sender);
+                }
+            }
+            // Stable, so quiet participants keep their order
+            handles.sort_by_key(|h| speakers.iter().position(|s| same_handle(s, h)).unwrap_or(usize::MAX));
+        }
+
+        let names: Vec<String> = handles
+            .iter()
+            .map(|h| match resolver.resolve(h) {
+                Some(name) if style == GroupNameStyle::FullNames => name.to_string(),
+                Some(name) => name.split_whitespace().next().unwrap_or(name).to_string(),
+                None => format_display(h),
+            })
+            .collect();
+
+        join_names(&names)
+    }
+
+    /// Fill in each message's `sender_name`, and `resolved_name` unless the
+    /// chat already has a display name: the contact name or formatted handle
+    /// for 1:1 chats, a derived name for groups.
+    pub fn resolve_names(&mut self, resolver: &ContactResolver, style: GroupNameStyle) {
+        resolve_sender_names(&mut self.messages, &self.participants, resolver);
+        if self.display_name.as_deref().is_some_and(|n| !n.is_empty()) {
+            return;
+        }
+        if self.is_group() {
+            if let Some(name) = self.derive_group_name(resolver, style) {
+                self.resolved_name = Some(name);
+            }
+        } else {
+            self.resolved_name = Some(match resolver.resolve(&self.chat_identifier) {
+                Some(name) => name.to_string(),
+                None => format_display(&self.chat_identifier),
+            });
+        }
+    }
+}
+
+/// Fill in `sender_name` on messages from others: the contact's first name,
+/// or the full name when another of `participants` shares the first name,
+/// else the formatted handle. Group events are described with names too.
+pub fn resolve_sender_names(messages: &mut [Message], participants: &[String], resolver: &ContactResolver) {
+    let first_name = |name: &str| name.split_whitespace().next().unwrap_or(name).to_string();
+    let first_names: Vec<String> = participants.iter().filter_map(|p| resolver.resolve(p)).map(first_name).collect();
+    for message in messages.iter_mut() {
+        if let Some(event) = &message.system_event {
+            message.text = event.describe(Some(resolver));
+        }
+        if message.is_from_me {
+            continue;
+        }
+        let Some(handle) = message.sender.as_deref() else {
+            continue;
+        };
+        message.sender_name = Some(match resolver.resolve(handle) {
+            Some(name) if first_names.iter().filter(|f| **f == first_name(name)).count() > 1 => name.to_string(),
+            Some(name) => first_name(name),
+            None => format_display(handle),
+        });
+    }
+}
+
+/// Join names up to the count and length limits, summarising the rest.
+fn join_names(names: &[String]) -> Option<String> {
+    let (first, rest) = names.split_first()?;
+    let mut joined = first.clone();
+    let mut shown = 1;
+    for name in rest {
+        if shown == GROUP_NAME_MAX_NAMES || joined.chars().count() + 2 + name.chars().count() > GROUP_NAME_MAX_CHARS {
+            break;
+        }
+        joined.push_str(", ");
+        joined.push_str(name);
+        shown += 1;
+    }
+    if shown < names.len() {
+        joined.push_str(&format!(" +{}", names.len() - shown));
+    }
+    Some(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use crate::fixtures::{conversation, message};
+    use crate::models::Message;
+
+    fn resolver() -> ContactResolver {
+        let mut resolver = ContactResolver::new();
+        resolver.add("+15550000001", "Alice Smith");
+        resolver.add("+15550000002", "Bob Jones");
+        resolver.add("+15550000003", "Carol White");
+        resolver.add("+15550000004", "Dan Brown");
+        resolver.add("+15550000005", "Bartholomew Fitzgerald-Montgomery");
+        resolver
+    }
+
+    fn said(sender: &str, secs: i64) -> Message {
+        Message {
+            rowid: secs,
+            guid: format!("g{}", secs),
+            date: DateTime::from_timestamp(secs, 0).unwrap(),
+            sender: Some(sender.into()),
+            ..message("hi")
+        }
+    }
+
+    fn group(participants: &[&str], messages: Vec<Message>) -> Conversation {
+        Conversation {
+            guid: "iMessage;+;chat1".into(),
+            style: 43,
+            messages,
+            participants: participants.iter().map(|p| p.to_string()).collect(),
+            ..conversation("chat1")
+        }
+    }
+
+    #[test]
+    fn test_first_names() {
+        let resolver = resolver();
+        let conv = group(&["+15550000001", "+15550000002"], vec![]);
+        assert_eq!(conv.derive_group_name(&resolver, GroupNameStyle::FirstNames).as_deref(), Some("Alice, Bob"));
+
+        let conv = group(&["+15550000001", "+15550000002", "+15550000003", "+15550000004", "+15559999999"], vec![]);
+        assert_eq!(
+            conv.derive_group_name(&resolver, GroupNameStyle::FirstNames).as_deref(),
+            Some("Alice, Bob, Carol +2")
+        );
+
+        assert_eq!(group(&[], vec![]).derive_group_name(&resolver, GroupNameStyle::FirstNames), None);
+    }
+
+    #[test]
+    fn test_full_names_truncate_by_length() {
+        let resolver = resolver();
+        let conv = group(&["+15550000001", "+15550000002", "+15550000003"], vec![]);
+        assert_eq!(
+            conv.derive_group_name(&resolver, GroupNameStyle::FullNames).as_deref(),
+            Some("Alice Smith, Bob Jones, Carol White")
+        );
+
+        let conv = group(&["+15550000001", "+15550000005", "+15550000002"], vec![]);
+        assert_eq!(
+            conv.derive_group_name(&resolver, GroupNameStyle::FullNames).as_deref(),
+            Some("Alice Smith +2")
+        );
+    }
+
+    #[test]
+    fn test_last_speaker_first() {
+        let resolver = resolver();
+        let conv = group(
+            &["+15550000001", "+15550000002", "+15550000003"],
+            vec![said("+15550000002", 1), said("+1 (555) 000-0003", 2)],
+        );
+        assert_eq!(
+            conv.derive_group_name(&resolver, GroupNameStyle::LastSpeakerFirst).as_deref(),
+            Some("Carol, Bob, Alice")
+        );
+    }
+
+    #[test]
+    fn test_resolve_names() {
+        let resolver = resolver();
+        let mut conv = group(&["+15550000001", "+15550000002"], vec![]);
+        conv.resolve_names(&resolver, GroupNameStyle::FirstNames);
+        assert_eq!(conv.resolved_name.as_deref(), Some("Alice, Bob"));
+
+        let mut named = group(&["+15550000001"], vec![]);
+        named.display_name = Some("Book Club".into());
+        named.resolve_names(&resolver, GroupNameStyle::FirstNames);
+        assert_eq!(named.resolved_name, None);
+
+        let mut direct = group(&[], vec![]);
+        direct.style = 45;
+        direct.chat_identifier = "+15550000003".into();
+        direct.resolve_names(&resolver, GroupNameStyle::FirstNames);
+        assert_eq!(direct.resolved_name.as_deref(), Some("Carol White"));
+    }
+
+    #[test]
+    fn test_resolve_sender_names() {
+        let mut resolver = resolver();
+        resolver.add("+15550000006", "Alice Jones");
+        let mut conv = group(
+            &["+15550000001", "+15550000002", "+15550000006"],
+            vec![said("+1 (555) 000-0002", 1), said("+15550000001", 2), said("+14158675309", 3)],
+        );
+        conv.display_name = Some("Book Club".into());
+        conv.resolve_names(&resolver, GroupNameStyle::FirstNames);
+        let names: Vec<_> = conv.messages.iter().map(|m| m.sender_label()).collect();
+        assert_eq!(names, ["Bob", "Alice Smith", "(415) 867-5309"]);
+    }
+
+    #[test]
+    fn test_join_names() {
+        let long = "x".repeat(60);
+        assert_eq!(join_names(&[long.clone(), "Bob".into()]), Some(format!("{} +1", long)));
+        assert_eq!(join_names(&["Al".into()]).as_deref(), Some("Al"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Confirm-before-send policies.
+//!
+//! Guards never block a send outright. A committed reply that trips one is
+//! held back until the user acknowledges the reasons and sends again.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::Conversation;
+
+/// Which replies need a second look before going out. Every guard but
+/// `mms_groups` is off by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SendGuards {
+    /// Confirm sends to groups with more than this many other people.
+    pub max_group_size: Option<usize>,
+    /// Confirm replies to handles not in contacts.
+    pub unknown_sender: bool,
+    /// Confirm replies containing any of these, ignoring case.
+    pub keywords: Vec<String>,
+    /// Confirm replies longer than this many characters.
+    pub max_length: Option<usize>,
+    /// Confirm sends to SMS/MMS groups, where delivery and reactions work
+    /// differently from iMessage.
+    pub mms_groups: bool,
+}
+
+impl Default for SendGuards {
+    fn default() -> Self {
+        Self { max_group_size: None, unknown_sender: false, keywords: Vec::new(), max_length: None, mms_groups: true }
+    }
+}
+
+/// Why a reply is waiting for confirmation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GuardReason {
+    LargeGroup { participants: usize, limit: usize },
+    UnknownSender,
+    Keyword { keyword: String },
+    TooLong { length: usize, limit: usize },
+    MmsGroup,
+    /// Committed before the app last quit and not confirmed since
+    Restored,
+    /// Was being sent when the app last quit, so it may already have gone out
+    Interrupted,
+}
+
+impl SendGuards {
+    /// Reasons `text` to `conv` needs confirming; empty if it can go.
+    /// `known_contact` is whether the chat's handle resolves to a contact.
+    pub fn check(&self, conv: &Conversation, text: &str, known_contact: bool) -> Vec<GuardReason> {
+        let mut reasons = Vec::new();
+
+        if let Some(limit) = self.max_group_size {
+            if conv.is_group() && conv.participants.len() > limit {
+                reasons.push(GuardReason::LargeGroup { participants: conv.participants.len(), limit });
+            }
+        }
+        if self.unknown_sender && !conv.is_group() && !known_contact {
+            reasons.push(GuardReason::UnknownSender);
+        }
+        if self.mms_groups && conv.is_mms_group() {
+            reasons.push(GuardReason::MmsGroup);
+        }
+        self.check_text(text, &mut reasons);
+        reasons
+    }
+
+    /// Reasons `text` to a recipient outside the queue needs confirming.
+    /// `mms_group` is whether the recipient names an SMS/MMS group chat.
+    pub fn check_recipient(&self, text: &str, known_contact: bool, mms_group: bool) -> Vec<GuardReason> {
+        let mut reasons = Vec::new();
+        if self.unknown_sender && !known_contact {
+            reasons.push(GuardReason::UnknownSender);
+        }
+        if self.mms_groups && mms_group {
+            reasons.push(GuardReason::MmsGroup);
+        }
+        self.check_text(text, &mut reasons);
+        reasons
+    }
+
+    fn check_text(&self, text: &str, reasons: &mut Vec<GuardReason>) {
+        let lower = text.to_lowercase();
+        for keyword in &self.keywords {
+            if !keyword.trim().is_empty() && lower.contains(&keyword.trim().to_lowercase()) {
+                reasons.push(GuardReason::Keyword { keyword: keyword.clone() });
+            }
+        }
+
+        if let Some(limit) = self.max_length {
+            let length = text.chars().count();
+            if length > limit {
+                reasons.push(GuardReason::TooLong { length, limit });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::conversation;
+
+    fn conv(style: i32, participants: usize) -> Conversation {
+        Conversation {
+            style,
+            participants: (0..participants).map(|i| format!("+1555000000{}", i)).collect(),
+            ..conversation("+15551234567")
+        }
+    }
+
+    #[test]
+    fn test_default_guards_allow_everything() {
+        assert!(SendGuards::default().check(&conv(43, 50), &"x".repeat(5000), false).is_empty());
+    }
+
+    #[test]
+    fn test_mms_group_guard() {
+        let mut group = conv(43, 3);
+        group.service_name = Some("SMS".into());
+        assert!(group.is_mms_group());
+        assert_eq!(SendGuards::default().check(&group, "ok", true), [GuardReason::MmsGroup]);
+        let off = SendGuards { mms_groups: false, ..Default::default() };
+        assert!(off.check(&group, "ok", true).is_empty());
+
+        group.service_name = None;
+        group.guid = "RCS;+;chat42".into();
+        assert!(group.is_mms_group());
+        group.guid = "iMessage;+;chat42".into();
+        assert!(!group.is_mms_group());
+
+        let mut direct = conv(45, 1);
+        direct.service_name = Some("SMS".into());
+        assert!(!direct.is_mms_group());
+    }
+
+    #[test]
+    fn test_guards() {
+        let guards = SendGuards {
+            max_group_size: Some(5),
+            unknown_sender: true,
+            keywords: vec!["Password".into()],
+            max_length: Some(10),
+            mms_groups: true,
+        };
+
+        assert_eq!(
+            guards.check(&conv(43, 6), "ok", false),
+            [GuardReason::LargeGroup { participants: 6, limit: 5 }]
+        );
+        assert!(guards.check(&conv(43, 5), "ok", false).is_empty());
+
+        assert_eq!(guards.check(&conv(45, 1), "ok", false), [GuardReason::UnknownSender]);
+        assert!(guards.check(&conv(45, 1), "ok", true).is_empty());
+
+        assert_eq!(
+            guards.check(&conv(45, 1), "the PASSWORD is hunter2", true),
+            [
+                GuardReason::Keyword { keyword: "Password".into() },
+                GuardReason::TooLong { length: 23, limit: 10 },
+            ]
+        );
+
+        assert_eq!(guards.check_recipient("ok", false, false), [GuardReason::UnknownSender]);
+        assert_eq!(guards.check_recipient("a long hello", true, false), [GuardReason::TooLong { length: 12, limit: 10 }]);
+        assert_eq!(guards.check_recipient("ok", true, true), [GuardReason::MmsGroup]);
+    }
+}
@@ -0,0 +1,291 @@
+//! Append-only log of send attempts.
+//!
+//! Attempts are a log in the [`StateStore`]: one JSON line each with the
+//! JSON store, so a crash mid-batch never loses earlier records, or rows
+//! in the SQLite store, looked up by chat and time without reading the
+//! rest. History from before the store, `send_history.jsonl` with one bare
+//! record per line, is copied in the first time.
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::store::{JsonFileStore, LogEntry, LogQuery, StateStore, StoreError};
+
+/// Name of the send history log in a [`StateStore`].
+const HISTORY_LOG: &str = "send_history";
+
+/// How long a successful send blocks the same text to the same chat.
+pub const DUPLICATE_WINDOW_SECS: i64 = 10 * 60;
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("History I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("History record invalid: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("History store failed: {0}")]
+    Store(#[from] StoreError),
+}
+
+/// One send attempt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendRecord {
+    pub chat_id: i64,
+    /// chat.guid, which survives chat.db rebuilds that renumber chat_id.
+    /// A message that started a new chat has the recipient's handle instead.
+    #[serde(default)]
+    pub chat_guid: String,
+    pub chat_identifier: String,
+    /// SHA-256 of the sent text, always recorded
+    pub text_hash: String,
+    /// Full text, only when configured to keep it
+    pub text: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl SendRecord {
+    /// Build a record for an attempt that finished at `at`.
+    pub fn new(
+        chat_id: i64,
+        chat_guid: &str,
+        chat_identifier: &str,
+        text: &str,
+        keep_text: bool,
+        error: Option<String>,
+        at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            chat_id,
+            chat_guid: chat_guid.to_string(),
+            chat_identifier: chat_identifier.to_string(),
+            text_hash: hash_text(text),
+            text: keep_text.then(|| text.to_string()),
+            timestamp: at,
+            success: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// Send history kept in a state store. Records are looked up by chat GUID.
+pub struct SendHistory {
+    store: Arc<dyn StateStore>,
+}
+
+impl SendHistory {
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self { store }
+    }
+
+    /// History kept in `store`. Records in the old history file at
+    /// `legacy` are copied in the first time, in one go, so upgrading or
+    /// switching stores keeps them and a crash mid-copy leaves the log
+    /// empty to try again.
+    pub fn open(store: Arc<dyn StateStore>, legacy: &Path) -> Result<Self, HistoryError> {
+        if store.entries(HISTORY_LOG, &LogQuery { limit: Some(1), ..Default::default() })?.is_empty() {
+            let entries = read_legacy(legacy)?.iter().map(log_entry).collect::<Result<Vec<_>, _>>()?;
+            store.append_all(HISTORY_LOG, &entries)?;
+        }
+        Ok(Self { store })
+    }
+
+    /// Records matching `query`, oldest first.
+    fn query(&self, query: LogQuery) -> Result<Vec<SendRecord>, HistoryError> {
+        self.store
+            .entries(HISTORY_LOG, &query)?
+            .iter()
+            .map(|entry| Ok(serde_json::from_str(&entry.json)?))
+            .collect()
+    }
+
+    /// Append a record to the log. It's on disk when this returns, so a
+    /// crash can't forget a reply that went out and send it again.
+    pub fn append(&self, record: &SendRecord) -> Result<(), HistoryError> {
+        Ok(self.store.append(HISTORY_LOG, &log_entry(record)?)?)
+    }
+
+    /// All records, oldest first.
+    pub fn read_all(&self) -> Result<Vec<SendRecord>, HistoryError> {
+        self.query(LogQuery::default())
+    }
+
+    /// Records for one chat, newest first.
+    pub fn for_chat(&self, chat_guid: &str) -> Result<Vec<SendRecord>, HistoryError> {
+        let mut records = self.query(LogQuery { key: Some(chat_guid.to_string()), ..Default::default() })?;
+        records.reverse();
+        Ok(records)
+    }
+
+    /// Most recent records across all chats, newest first.
+    pub fn recent(&self, limit: usize) -> Result<Vec<SendRecord>, HistoryError> {
+        let mut records = self.query(LogQuery { limit: Some(limit), ..Default::default() })?;
+        records.reverse();
+        Ok(records)
+    }
+
+    /// The latest successful send of this exact text to this chat within
+    /// `window` before `now`, if any. Checked before sending so a double-triggered batch
+    /// or a retry after a crash doesn't deliver the same message twice.
+    pub fn recent_duplicate(
+        &self,
+        chat_guid: &str,
+        text: &str,
+        window: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<Option<SendRecord>, HistoryError> {
+        let hash = hash_text(text);
+        let since = Some(now - window);
+        Ok(self.query(LogQuery { key: Some(chat_guid.to_string()), since, limit: None })?
+            .into_iter()
+            .rev()
+            .find(|r| r.success && r.text_hash == hash))
+    }
+}
+
+/// Records in an old history file, oldest first. Unreadable lines are
+/// skipped.
+fn read_legacy(path: &Path) -> Result<Vec<SendRecord>, HistoryError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let file = fs::File::open(path)?;
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if let Ok(record) = serde_json::from_str(&line) {
+            records.push(record);
+        }
+    }
+
+    Ok(records)
+}
+
+/// A record as a store log entry, keyed by chat GUID.
+fn log_entry(record: &SendRecord) -> Result<LogEntry, HistoryError> {
+    Ok(LogEntry { key: record.chat_guid.clone(), at: record.timestamp, json: serde_json::to_string(record)? })
+}
+
+impl Default for SendHistory {
+    fn default() -> Self {
+        Self::new(Arc::new(JsonFileStore::new(crate::AppPaths::default().data_dir)))
+    }
+}
+
+/// Hex SHA-256 of message text.
+fn hash_text(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_history() -> (tempfile::TempDir, SendHistory) {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SendHistory::new(Arc::new(JsonFileStore::new(dir.path().join("nested"))));
+        (dir, history)
+    }
+
+    #[test]
+    fn test_hash_text() {
+        assert_eq!(
+            hash_text("hello"),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn test_record_keeps_text_only_when_asked() {
+        let hashed = SendRecord::new(1, "guid-+15551234567", "+15551234567", "hi", false, None, Utc::now());
+        assert_eq!(hashed.text, None);
+        assert!(hashed.success);
+
+        let full = SendRecord::new(1, "guid-+15551234567", "+15551234567", "hi", true, Some("boom".into()), Utc::now());
+        assert_eq!(full.text.as_deref(), Some("hi"));
+        assert!(!full.success);
+        assert_eq!(full.text_hash, hashed.text_hash);
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let (_dir, history) = temp_history();
+        assert!(history.read_all().unwrap().is_empty());
+
+        history.append(&SendRecord::new(1, "guid-a", "a", "first", false, None, Utc::now())).unwrap();
+        history.append(&SendRecord::new(2, "guid-b", "b", "second", false, None, Utc::now())).unwrap();
+        history.append(&SendRecord::new(1, "guid-a", "a", "third", true, None, Utc::now())).unwrap();
+
+        let chat1 = history.for_chat("guid-a").unwrap();
+        assert_eq!(chat1.len(), 2);
+        assert_eq!(chat1[0].text.as_deref(), Some("third"));
+
+        let recent = history.recent(2).unwrap();
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].chat_id, 1);
+        assert_eq!(recent[1].chat_id, 2);
+    }
+
+    #[test]
+    fn test_recent_duplicate() {
+        let (_dir, history) = temp_history();
+        let window = Duration::seconds(DUPLICATE_WINDOW_SECS);
+        history.append(&SendRecord::new(1, "guid-a", "a", "on my way", false, None, Utc::now())).unwrap();
+        history.append(&SendRecord::new(2, "guid-b", "b", "failed", false, Some("boom".into()), Utc::now())).unwrap();
+
+        assert!(history.recent_duplicate("guid-a", "on my way", window, Utc::now()).unwrap().is_some());
+        // Judged from the time given, not the wall clock
+        assert!(history.recent_duplicate("guid-a", "on my way", window, Utc::now() + Duration::days(1)).unwrap().is_none());
+        assert!(history.recent_duplicate("guid-a", "something else", window, Utc::now()).unwrap().is_none());
+        assert!(history.recent_duplicate("guid-z", "on my way", window, Utc::now()).unwrap().is_none());
+        // Failed attempts don't count as sent
+        assert!(history.recent_duplicate("guid-b", "failed", window, Utc::now()).unwrap().is_none());
+
+        let mut old = SendRecord::new(4, "guid-c", "c", "yesterday", false, None, Utc::now());
+        old.timestamp = Utc::now() - Duration::days(1);
+        let (_dir, history) = temp_history();
+        history.append(&old).unwrap();
+        assert!(history.recent_duplicate("guid-c", "yesterday", window, Utc::now()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_history_copies_legacy_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = dir.path().join("send_history.jsonl");
+        let old = SendRecord::new(1, "guid-a", "a", "from the file", false, None, Utc::now());
+        let lines = format!("{}\n{{not json\n{{\"chat_id\": 2, \"tex", serde_json::to_string(&old).unwrap());
+        std::fs::write(&legacy, lines).unwrap();
+
+        let sqlite: Arc<dyn StateStore> = Arc::new(crate::store::SqliteStore::open(&dir.path().join("state.sqlite")).unwrap());
+        let json: Arc<dyn StateStore> = Arc::new(JsonFileStore::new(dir.path().to_path_buf()));
+        for store in [sqlite, json] {
+            let history = SendHistory::open(store.clone(), &legacy).unwrap();
+            history.append(&SendRecord::new(2, "guid-b", "b", "second", false, None, Utc::now())).unwrap();
+            history.append(&SendRecord::new(1, "guid-a", "a", "third", true, None, Utc::now())).unwrap();
+
+            assert_eq!(history.for_chat("guid-a").unwrap().len(), 2);
+            assert_eq!(history.recent(1).unwrap()[0].text.as_deref(), Some("third"));
+            let window = Duration::seconds(DUPLICATE_WINDOW_SECS);
+            assert!(history.recent_duplicate("guid-a", "from the file", window, Utc::now()).unwrap().is_some());
+            assert!(history.recent_duplicate("guid-b", "from the file", window, Utc::now()).unwrap().is_none());
+
+            // The file is only copied into an empty log
+            let reopened = SendHistory::open(store, &legacy).unwrap();
+            assert_eq!(reopened.read_all().unwrap().len(), 3);
+        }
+        // New sends don't go to the old file
+        assert_eq!(read_legacy(&legacy).unwrap().len(), 1);
+    }
+}
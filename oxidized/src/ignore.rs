@@ -0,0 +1,174 @@
+//! Chats hidden from the queue by rule.
+//!
+//! A rule names one chat, every email address at a domain, or every short
+//! code starting with a prefix (short codes from one sender often share
+//! their leading digits). Rules are saved as JSON.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::automated::{classify_sender, AutomatedKind};
+use crate::store::Document;
+
+/// Shortest prefix `prefix_rule` will suggest; anything shorter hides too much.
+pub const MIN_IGNORE_PREFIX: usize = 3;
+
+/// What an ignore rule matches, by chat identifier.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum IgnoreRule {
+    /// One chat identifier exactly
+    Chat(String),
+    /// Email addresses at this domain, e.g. "example.com"
+    Domain(String),
+    /// Short codes starting with this, e.g. "2233". Phone numbers and
+    /// addresses never match, however short the prefix.
+    Prefix(String),
+}
+
+impl IgnoreRule {
+    pub fn matches(&self, chat_identifier: &str) -> bool {
+        match self {
+            IgnoreRule::Chat(id) => id == chat_identifier,
+            IgnoreRule::Domain(domain) => email_domain(chat_identifier)
+                .is_some_and(|d| d.eq_ignore_ascii_case(domain)),
+            IgnoreRule::Prefix(prefix) => {
+                is_short_code(chat_identifier) && chat_identifier.starts_with(prefix.as_str())
+            }
+        }
+    }
+}
+
+fn email_domain(identifier: &str) -> Option<&str> {
+    identifier.rsplit_once('@').map(|(_, domain)| domain).filter(|d| !d.is_empty())
+}
+
+fn is_short_code(identifier: &str) -> bool {
+    classify_sender(identifier) == Some(AutomatedKind::ShortCode)
+}
+
+/// One domain rule per email domain in a selection.
+pub fn domain_rules<S: AsRef<str>>(chat_identifiers: &[S]) -> Vec<IgnoreRule> {
+    let domains: BTreeSet<String> = chat_identifiers
+        .iter()
+        .filter_map(|id| email_domain(id.as_ref()))
+        .map(str::to_lowercase)
+        .collect();
+    domains.into_iter().map(IgnoreRule::Domain).collect()
+}
+
+/// A prefix rule covering the whole selection, if it's all short codes
+/// sharing at least [`MIN_IGNORE_PREFIX`] leading characters.
+pub fn prefix_rule<S: AsRef<str>>(chat_identifiers: &[S]) -> Option<IgnoreRule> {
+    if !chat_identifiers.iter().all(|id| is_short_code(id.as_ref())) {
+        return None;
+    }
+    let (first, rest) = chat_identifiers.split_first()?;
+    let mut prefix = first.as_ref();
+    for id in rest {
+        let shared = prefix
+            .char_indices()
+            .zip(id.as_ref().chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(id.as_ref().len()), |((i, _), _)| i);
+        prefix = &prefix[..shared];
+    }
+    (prefix.chars().count() >= MIN_IGNORE_PREFIX).then(|| IgnoreRule::Prefix(prefix.to_string()))
+}
+
+/// Ignore rules, saved as JSON.
+pub struct IgnoreList {
+    doc: Document,
+    rules: BTreeSet<IgnoreRule>,
+}
+
+impl IgnoreList {
+    /// Default ignore file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().ignored()
+    }
+
+    /// Load the list, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let rules = doc.load().unwrap_or_default();
+        Self { doc, rules }
+    }
+
+    /// Write the list to disk.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.doc.save(&self.rules)
+    }
+
+    /// Add or remove `rules` in one go. Returns how many actually changed.
+    pub fn set(&mut self, rules: Vec<IgnoreRule>, ignored: bool) -> usize {
+        rules
+            .into_iter()
+            .filter(|rule| if ignored { self.rules.insert(rule.clone()) } else { self.rules.remove(rule) })
+            .count()
+    }
+
+    /// Whether any rule hides `chat_identifier`.
+    pub fn is_ignored(&self, chat_identifier: &str) -> bool {
+        self.rules.iter().any(|r| r.matches(chat_identifier))
+    }
+
+    /// Rules hiding `chat_identifier`, to remove when un-ignoring it.
+    pub fn matching(&self, chat_identifier: &str) -> Vec<IgnoreRule> {
+        self.rules.iter().filter(|r| r.matches(chat_identifier)).cloned().collect()
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &IgnoreRule> {
+        self.rules.iter()
+    }
+}
+
+impl Default for IgnoreList {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_helpers() {
+        let selection = ["22395", "22397", "22310"];
+        assert_eq!(prefix_rule(&selection), Some(IgnoreRule::Prefix("223".into())));
+        assert_eq!(prefix_rule(&["22395", "5551234"]), None);
+        assert_eq!(prefix_rule(&["+15551234567", "+15559876543"]), None);
+        assert_eq!(prefix_rule::<&str>(&[]), None);
+        assert!(IgnoreRule::Prefix("223".into()).matches("22395"));
+        assert!(!IgnoreRule::Prefix("+15".into()).matches("+15551234567"));
+
+        let emails = ["news@Shop.example", "deals@shop.example", "+15551234567", "alerts@bank.example"];
+        assert_eq!(domain_rules(&emails), [
+            IgnoreRule::Domain("bank.example".into()),
+            IgnoreRule::Domain("shop.example".into()),
+        ]);
+        assert!(domain_rules(&emails)[1].matches("news@Shop.example"));
+        assert!(!IgnoreRule::Domain("shop.example".into()).matches("shop.example"));
+    }
+
+    #[test]
+    fn test_bulk_set_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ignored.json");
+        let mut list = IgnoreList::load(Document::file(&path));
+
+        let codes: Vec<_> = (0..12).map(|i| IgnoreRule::Chat(format!("2233{}", i))).collect();
+        assert_eq!(list.set(codes.clone(), true), 12);
+        assert_eq!(list.set(vec![codes[0].clone(), IgnoreRule::Prefix("555".into())], true), 1);
+        list.save().unwrap();
+
+        let mut list = IgnoreList::load(Document::file(&path));
+        assert!(list.is_ignored("22335") && list.is_ignored("55500"));
+        assert!(!list.is_ignored("5550000"));
+        assert_eq!(list.matching("55500"), [IgnoreRule::Prefix("555".into())]);
+        assert_eq!(list.set(codes, false), 12);
+        assert_eq!(list.rules().count(), 1);
+    }
+}
@@ -0,0 +1,147 @@
+//! What sort of message something is, judged from its text and attachments.
+//!
+//! A pile of unread "ok", "👍" and "haha" is far less pressing than one real
+//! question. Classifying each message lets ranking and digests discount
+//! chats that are only acknowledgments.
+
+use serde::Serialize;
+
+use crate::models::{Conversation, Message};
+
+/// Replies that acknowledge rather than ask or tell, compared lowercase
+/// with trailing punctuation removed.
+const ACKNOWLEDGMENTS: &[&str] = &[
+    "ok", "okay", "k", "kk", "yes", "yep", "yeah", "ya", "no", "nope", "sure", "cool", "nice",
+    "great", "perfect", "thanks", "thank you", "thx", "ty", "np", "lol", "lmao", "haha", "hahaha",
+    "got it", "sounds good", "will do", "done",
+];
+
+/// How a message reads at a glance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// Anything worth reading
+    Text,
+    /// Only emoji, e.g. "👍" or "😂😂"
+    EmojiOnly,
+    /// A known acknowledgment such as "ok", "thanks!" or "got it 👍"
+    Short,
+    /// Nothing but one or more links
+    LinkOnly,
+    /// Files or images with no text
+    AttachmentOnly,
+    /// A group rename, join or leave, when those are loaded
+    SystemEvent,
+}
+
+impl MessageKind {
+    /// Whether the message just acknowledges something.
+    pub fn is_acknowledgment(self) -> bool {
+        matches!(self, MessageKind::EmojiOnly | MessageKind::Short)
+    }
+}
+
+impl Message {
+    pub fn kind(&self) -> MessageKind {
+        if self.system_event.is_some() {
+            return MessageKind::SystemEvent;
+        }
+        let text = self.display_text();
+        if text.is_empty() {
+            return if self.link_preview.is_some() {
+                MessageKind::LinkOnly
+            } else if self.attachments.is_empty() {
+                MessageKind::Text
+            } else {
+                MessageKind::AttachmentOnly
+            };
+        }
+        if text.split_whitespace().all(|w| w.starts_with("https://") || w.starts_with("http://")) {
+            return MessageKind::LinkOnly;
+        }
+        if is_emoji_only(&text) {
+            return MessageKind::EmojiOnly;
+        }
+        // Brevity alone isn't enough: "help", "911" and "now" need an answer
+        if !text.contains('?') {
+            let words: String = text.chars().filter(|&c| !is_emoji_part(c)).collect();
+            let words = words.trim().trim_end_matches(|c: char| c.is_ascii_punctuation()).trim_end().to_lowercase();
+            if ACKNOWLEDGMENTS.contains(&words.as_str()) {
+                return MessageKind::Short;
+            }
+        }
+        MessageKind::Text
+    }
+}
+
+impl Conversation {
+    /// Whether the loaded unread messages are all acknowledgments.
+    pub fn only_acknowledgments(&self) -> bool {
+        let mut unread = self
+            .messages
+            .iter()
+            .filter(|m| !m.is_from_me && m.date >= self.first_unread_date)
+            .peekable();
+        unread.peek().is_some() && unread.all(|m| m.kind().is_acknowledgment())
+    }
+}
+
+/// Pictographs plus the joiners, variation selectors and skin tones that
+/// build up composite emoji. At least one pictograph is required, so
+/// whitespace or joiners alone don't count.
+fn is_emoji_only(text: &str) -> bool {
+    let mut pictographs = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if is_pictograph(c) {
+            pictographs += 1;
+        } else if !is_emoji_part(c) {
+            return false;
+        }
+    }
+    pictographs > 0
+}
+
+fn is_pictograph(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2300..=0x23FF | 0x2B00..=0x2BFF | 0x2190..=0x21FF)
+}
+
+/// A pictograph, or the ZWJ, variation selectors, keycap and tag
+/// characters that combine with them.
+fn is_emoji_part(c: char) -> bool {
+    is_pictograph(c) || matches!(c as u32, 0x200D | 0xFE0E | 0xFE0F | 0x20E3 | 0xE0020..=0xE007F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use crate::fixtures::message;
+    use crate::models::{Attachment, ParticipantChange, ParticipantEvent, LinkPreview};
+
+    #[test]
+    fn test_message_kind() {
+        for text in ["👍", "😂😂 😂", "👩‍👩‍👧", "🇯🇵", "👍🏽", "❤️"] {
+            assert_eq!(message(text).kind(), MessageKind::EmojiOnly, "{}", text);
+        }
+        for text in ["ok", "Thanks!", "got it.", "k", "lol", "thanks 🙏", "👍 sounds good!"] {
+            assert_eq!(message(text).kind(), MessageKind::Short, "{}", text);
+        }
+        for text in ["why?", "ok?", "see you at 5", "ok 👍 but call me", "help", "sos", "911", "call", "now"] {
+            assert_eq!(message(text).kind(), MessageKind::Text, "{}", text);
+        }
+        assert_eq!(message("https://a.example/x  http://b.example").kind(), MessageKind::LinkOnly);
+
+        let mut preview = message("");
+        preview.link_preview = Some(LinkPreview { url: "https://a.example/x".into(), title: Some("X".into()) });
+        assert_eq!(preview.kind(), MessageKind::LinkOnly);
+
+        let mut photo = message("\u{FFFC}");
+        photo.attachments.push(Attachment::new("~/Library/Messages/Attachments/a.jpg".into(), "image/jpeg".into(), "a.jpg".into()));
+        assert_eq!(photo.kind(), MessageKind::AttachmentOnly);
+        assert!(!photo.kind().is_acknowledgment());
+
+        let mut renamed = message("You named the conversation “Trip”");
+        renamed.system_event = Some(ParticipantEvent { date: Utc::now(), change: ParticipantChange::Renamed { name: Some("Trip".into()) }, actor: None, target: None });
+        assert_eq!(renamed.kind(), MessageKind::SystemEvent);
+    }
+}
@@ -0,0 +1,55 @@
+//! Language detection for incoming messages.
+
+use whatlang::Detector;
+
+use crate::models::Message;
+
+/// Detect the language the other side writes in, from their recent messages.
+/// Returns an ISO 639-3 code ("eng", "spa", ...) or None if unsure.
+pub fn detect_language(messages: &[Message]) -> Option<String> {
+    let text: Vec<String> = messages
+        .iter()
+        .filter(|m| !m.is_from_me)
+        .map(|m| m.display_text())
+        .filter(|t| !t.is_empty())
+        .collect();
+    let text = text.join("\n");
+
+    // Short snippets ("ok", "lol") detect as anything
+    if text.chars().filter(|c| c.is_alphabetic()).count() < 12 {
+        return None;
+    }
+
+    let info = Detector::new().detect(&text)?;
+    info.is_reliable().then(|| info.lang().code().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::message;
+
+    fn msg(text: &str, is_from_me: bool) -> Message {
+        Message { guid: "test".into(), is_from_me, ..message(text) }
+    }
+
+    #[test]
+    fn test_detects_incoming_language() {
+        let messages = vec![
+            msg("¿Vienes a la cena mañana por la noche?", false),
+            msg("Sure, what time should I be there?", true),
+            msg("A las ocho, en la casa de mi hermana. Trae algo de postre.", false),
+        ];
+        assert_eq!(detect_language(&messages).as_deref(), Some("spa"));
+    }
+
+    #[test]
+    fn test_ignores_my_messages_and_short_text() {
+        let messages = vec![
+            msg("This is a long message written entirely by me in English", true),
+            msg("ok", false),
+        ];
+        assert_eq!(detect_language(&messages), None);
+        assert_eq!(detect_language(&[]), None);
+    }
+}
@@ -6,11 +6,134 @@ mod db;
 mod models;
 mod contacts;
 mod send;
+mod drafts;
+mod settings;
+mod history;
+mod retry;
+mod staging;
+mod templates;
+mod compose;
+mod overlay;
+mod receipts;
+mod snooze;
+mod ignore;
+mod stale;
+mod bookmarks;
+mod session;
+mod onboarding;
+mod persist;
+mod cache;
+mod paths;
+mod diagnostics;
+mod watch;
+mod events;
+mod shutdown;
+mod throttle;
+mod clock;
+mod power;
+mod focus;
+mod snapshot;
+mod export;
+mod guard;
+mod outbox;
+mod sms;
+mod language;
+mod prompt;
+mod summary;
+mod group_name;
+mod preview;
+mod otp;
+mod automated;
+mod kind;
+mod reply;
+mod queue_state;
+mod days;
+mod when;
+mod redact;
+mod search;
+mod audio;
+mod mute;
+mod link_title;
+mod contact_store;
+mod store;
+#[cfg(any(test, feature = "dev-sample"))]
+mod sample;
+#[cfg(test)]
+mod fixtures;
+#[cfg(feature = "carddav")]
+pub mod carddav;
+
+pub use db::{Database, DbError, DatabaseStatus, MessageChanges, ExportEvent, ExportCursor, BUSY_TIMEOUT, busy_policy, retry_busy, LOCAL_SOURCE, REQUIRED_SCHEMA, ACTIVITY_DAYS, PREVIEW_MESSAGES, mark_as_read, parse_attributed_body};
+pub use db::typedstream::{AttributedText, AttributeRun, AttributeValue, TypedStreamError, decode_attributed_string};
+pub use models::{
+    Conversation, ConversationStats, ContactChat, HandleActivity, Message, MessageEdit, MessageFilter, Attachment, Reaction, ReactionCount, MediaItem, AgeBucket, ParticipantChange, ParticipantEvent, LinkPreview,
+    messages_url, reaction_label, sort_conversations, attachment_relative_path,
+};
+pub use contacts::{
+    Avatar, ContactResolver, ContactsProgress, EmailMatching, RecipientSuggestion, autocomplete_recipients, format_display, addressbook_sources_dir, load_address_books,
+};
+pub use send::{
+    send_message, send_message_with, send_message_with_attachments, check_attachment, MAX_ATTACHMENT_BYTES, mark_read_via_messages, SendPlan, SendError, ErrorCategory,
+    ScriptRunner, Osascript, Tapback, send_reaction, send_reaction_with,
+};
+pub use drafts::{default_drafts_dir, messages_app_draft, parse_draft_plist};
+pub use settings::{Settings, ReadStrategy, SortOrder, CardDavConfig, LibrarySource};
+pub use history::{SendHistory, SendRecord, HistoryError, DUPLICATE_WINDOW_SECS};
+pub use staging::{AttachmentStaging, StagedFile, MAX_IMAGE_BYTES, read_clipboard_image};
+pub use templates::{Template, TemplateStore, AppliedTemplate, TemplateError};
+pub use compose::{ComposedMessage, BatchCompose, compose_batch};
+pub use retry::{FailedQueue, FailedSend, BackoffPolicy, RetryOutcome, send_with_backoff};
+pub use overlay::ReadOverlay;
+pub use receipts::{global_read_receipts, chat_read_receipts, messages_prefs_path};
+pub use bookmarks::{Bookmark, BookmarkStore};
+pub use session::{TriageSession, SessionReport, SessionLog};
+pub use stale::{StaleDraft, StaleDraftPolicy, find_stale_drafts};
+pub use queue_state::QueueState;
+pub use clock::{Clock, SystemClock, FixedClock};
+pub use when::{parse_when, DateLocale, WhenError, DEFAULT_HOUR};
+pub use ignore::{IgnoreRule, IgnoreList, MIN_IGNORE_PREFIX, domain_rules, prefix_rule};
+pub use snooze::{Snooze, SnoozeList, DEFAULT_HANDOFF_SNOOZE_MINUTES};
+pub use onboarding::{OnboardingStatus, onboarding_status};
+pub use paths::{AppPaths, DATA_DIR_ENV, CACHE_DIR_ENV};
+pub use cache::{default_cache_dir, default_attachments_dir, conversion_path, prune_orphaned_conversions};
+pub use diagnostics::{DiagnosticCheck, DiagnosticPaths, run_diagnostics, verify_database};
+pub use watch::ChangeWatcher;
+pub use events::{AppEvent, EventBus, Subscription, DEFAULT_EVENT_CAPACITY};
+pub use shutdown::{Shutdown, SendSlot, SHUTDOWN_GRACE};
+pub use throttle::Throttle;
+pub use power::{PowerMonitor, PowerState, PowerSource, PowerSaving, POWER_CHECK_INTERVAL};
+pub use focus::{FocusState, FocusSettings, focus_db_dir};
+pub use language::detect_language;
+pub use prompt::{PromptContext, PromptMessage};
+pub use kind::MessageKind;
+pub use automated::{AutomatedKind, classify_sender};
+pub use otp::OTP_EXPIRY_MINUTES;
+pub use preview::PreviewImageRef;
+pub use group_name::{resolve_sender_names, GroupNameStyle, GROUP_NAME_MAX_NAMES, GROUP_NAME_MAX_CHARS};
+pub use summary::{SummaryCard, SummaryLine, SUMMARY_TEXT_CHARS};
+pub use days::{DaySection, day_header, group_messages_by_day};
+pub use search::{SearchHit, SearchIndex};
+pub use audio::audio_duration_secs;
+pub use mute::MuteList;
+pub use link_title::{LinkTitles, PageFetcher, Curl, MAX_TITLE_CHARS, bare_url, page_title, with_link_title};
+pub use contact_store::{ContactsBackend, ContactsLoaded, load_contacts};
+pub use store::{StateStore, StateStoreKind, JsonFileStore, SqliteStore, Document, LogEntry, LogQuery, StoreError, open_state_store};
+#[cfg(feature = "dev-sample")]
+pub use sample::{SAMPLE_CONTACTS, ensure_sample_library, sample_dir, write_sample_library};
+pub use redact::{Redactor, RedactionConfig};
+pub use export::{ExportRange, ExportSummary, ExportError, export_jsonl, export_csv, checkpoint_path};
+pub use guard::{SendGuards, GuardReason};
+pub use outbox::{Outbox, OutboxItem, OutboxStatus};
+pub use sms::{DraftAnalysis, SmsEncoding, WARN_SEGMENTS, analyze_draft};
+pub use snapshot::{
+    UnreadSnapshot, SnapshotConversation, DraftConflict, DraftImportReport, merge_drafts, read_draft_map,
+};
 
-pub use db::{Database, mark_as_read};
-pub use models::{Conversation, Message, Attachment, Reaction};
-pub use contacts::ContactResolver;
-pub use send::send_message;
+/// Directory for files the app writes (history, persisted state), or the
+/// override in `AEROMESSAGE_DATA_DIR`.
+pub fn app_data_dir() -> std::path::PathBuf {
+    AppPaths::default().data_dir
+}
 
 /// Apple epoch: January 1, 2001 00:00:00 UTC
 pub const APPLE_EPOCH_OFFSET: i64 = 978307200;
@@ -26,6 +149,18 @@ pub fn apple_to_unix(apple_ts: i64) -> i64 {
     ts + APPLE_EPOCH_OFFSET
 }
 
+/// Convert Unix timestamp to an Apple timestamp in nanoseconds,
+/// the unit chat.db uses since macOS 10.13.
+pub fn unix_to_apple_nanos(unix_ts: i64) -> i64 {
+    (unix_ts - APPLE_EPOCH_OFFSET) * 1_000_000_000
+}
+
+/// Convert Unix timestamp to an Apple timestamp in seconds, for comparing
+/// with dates read in either unit the way [`apple_to_unix`] reads them.
+pub fn unix_to_apple_secs(unix_ts: i64) -> i64 {
+    unix_ts - APPLE_EPOCH_OFFSET
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -42,8 +177,14 @@ mod tests {
     #[test]
     fn test_apple_to_unix_nanoseconds() {
         // Same time but in nanoseconds
-        let apple_ts = 725846400_000_000_000_i64;
+        let apple_ts = 725_846_400_000_000_000_i64;
         let unix_ts = apple_to_unix(apple_ts);
         assert_eq!(unix_ts, 1704153600);
     }
+
+    #[test]
+    fn test_unix_to_apple_nanos_roundtrip() {
+        let unix_ts = 1704153600_i64;
+        assert_eq!(apple_to_unix(unix_to_apple_nanos(unix_ts)), unix_ts);
+    }
 }
@@ -0,0 +1,195 @@
+//! Page titles for replies that are just a link.
+//!
+//! iMessage shows a rich preview for a bare URL; SMS shows the URL and
+//! nothing else. When enabled, committing a reply that's only a link
+//! fetches the page from this Mac and appends its title, so the recipient
+//! has some idea what they're being sent.
+
+use std::process::Command;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Longest title appended, in characters.
+pub const MAX_TITLE_CHARS: usize = 80;
+
+/// Seconds to wait for a page before committing the link as typed.
+const FETCH_TIMEOUT_SECS: u32 = 5;
+
+/// When a link-only reply gets its page title appended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkTitles {
+    #[default]
+    Off,
+    /// Only in SMS chats, which have no rich previews
+    SmsOnly,
+    Always,
+}
+
+impl LinkTitles {
+    /// Whether titles are added in a chat on `service`.
+    pub fn applies_to(self, service: Option<&str>) -> bool {
+        match self {
+            LinkTitles::Off => false,
+            LinkTitles::SmsOnly => service.is_some_and(|s| s.eq_ignore_ascii_case("SMS")),
+            LinkTitles::Always => true,
+        }
+    }
+}
+
+/// Something that can download a page's HTML.
+///
+/// Lets tests supply pages without the network.
+pub trait PageFetcher {
+    fn fetch(&self, url: &str) -> Option<String>;
+}
+
+/// Fetches pages with `curl`, which ships with macOS.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Curl;
+
+impl PageFetcher for Curl {
+    fn fetch(&self, url: &str) -> Option<String> {
+        let output = Command::new("curl")
+            .args(["--silent", "--fail", "--location", "--max-filesize", "2000000"])
+            .args(["--max-time", &FETCH_TIMEOUT_SECS.to_string()])
+            .arg("--")
+            .arg(url)
+            .output()
+            .ok()?;
+        output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// The URL, if `text` is nothing but one http(s) link.
+pub fn bare_url(text: &str) -> Option<&str> {
+    let text = text.trim();
+    let is_url = (text.starts_with("https://") || text.starts_with("http://")) && !text.contains(char::is_whitespace);
+    is_url.then_some(text)
+}
+
+/// A page's `<title>`, or failing that its `og:title`, tidied up and
+/// shortened to [`MAX_TITLE_CHARS`].
+pub fn page_title(html: &str) -> Option<String> {
+    static TITLE: OnceLock<Regex> = OnceLock::new();
+    static OG_TITLE: OnceLock<Regex> = OnceLock::new();
+    let title = TITLE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+    let og_title = OG_TITLE.get_or_init(|| {
+        Regex::new(r#"(?is)<meta\s[^>]*property\s*=\s*["']og:title["'][^>]*content\s*=\s*["']([^"']*)["']"#).unwrap()
+    });
+
+    let raw = title
+        .captures(html)
+        .map(|c| c[1].to_string())
+        .filter(|t| !t.trim().is_empty())
+        .or_else(|| og_title.captures(html).map(|c| c[1].to_string()))?;
+    let title = decode_entities(&raw).split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() {
+        return None;
+    }
+    if title.chars().count() > MAX_TITLE_CHARS {
+        let cut: String = title.chars().take(MAX_TITLE_CHARS - 1).collect();
+        return Some(format!("{}…", cut.trim_end()));
+    }
+    Some(title)
+}
+
+/// Decode the character references that turn up in titles.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let name = &rest[1..end];
+            let c = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => name.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `text` with the linked page's title appended when it's a bare link in a
+/// chat `mode` applies to. Anything that fails leaves `text` unchanged.
+pub fn with_link_title(text: &str, mode: LinkTitles, service: Option<&str>, fetcher: &dyn PageFetcher) -> String {
+    if !mode.applies_to(service) {
+        return text.to_string();
+    }
+    let Some(url) = bare_url(text) else {
+        return text.to_string();
+    };
+    match fetcher.fetch(url).as_deref().and_then(page_title) {
+        Some(title) => format!("{} — '{}'", url, title),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Page(Option<&'static str>);
+
+    impl PageFetcher for Page {
+        fn fetch(&self, _url: &str) -> Option<String> {
+            self.0.map(str::to_string)
+        }
+    }
+
+    #[test]
+    fn test_page_title() {
+        assert_eq!(page_title("<html><head><TITLE>\n  Rust &amp; You &#8212; a\tguide </TITLE>").as_deref(), Some("Rust & You — a guide"));
+        assert_eq!(
+            page_title(r#"<title></title><meta property="og:title" content="Fallback &quot;title&quot;">"#).as_deref(),
+            Some("Fallback \"title\"")
+        );
+        assert_eq!(page_title("<p>no title</p>"), None);
+        assert_eq!(decode_entities("AT&T &bogus; &#x41;"), "AT&T &bogus; A");
+
+        let long = format!("<title>{}</title>", "word ".repeat(40));
+        let title = page_title(&long).unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS);
+        assert!(title.ends_with("word…"));
+    }
+
+    #[test]
+    fn test_with_link_title() {
+        let page = Page(Some("<title>An article</title>"));
+        let url = "https://example.com/a";
+        assert_eq!(with_link_title(url, LinkTitles::Always, None, &page), "https://example.com/a — 'An article'");
+        assert_eq!(with_link_title(" https://example.com/a\n", LinkTitles::SmsOnly, Some("SMS"), &page), "https://example.com/a — 'An article'");
+        assert_eq!(with_link_title(url, LinkTitles::SmsOnly, Some("iMessage"), &page), url);
+        assert_eq!(with_link_title(url, LinkTitles::Off, Some("SMS"), &page), url);
+        assert_eq!(with_link_title("look https://example.com/a", LinkTitles::Always, None, &page), "look https://example.com/a");
+        assert_eq!(with_link_title(url, LinkTitles::Always, None, &Page(None)), url);
+        assert_eq!(with_link_title(url, LinkTitles::Always, None, &Page(Some("<p>untitled</p>"))), url);
+    }
+}
@@ -2,177 +2,1648 @@
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use aeromessage::{Database, Conversation, ContactResolver, send_message, mark_as_read};
+use aeromessage::{
+    Database, DatabaseStatus, MessageChanges, SearchHit, SearchIndex, busy_policy, retry_busy, Conversation, Message, MessageFilter, Bookmark, BookmarkStore, TriageSession, SessionReport, SessionLog, ContactChat, SummaryCard, DaySection, group_messages_by_day, MediaItem, ParticipantEvent, UnreadSnapshot, DraftImportReport, merge_drafts,
+    read_draft_map, ContactResolver, RecipientSuggestion, Settings, GuardReason, ReadStrategy, ReadOverlay, global_read_receipts, messages_prefs_path, SnoozeList, DEFAULT_HANDOFF_SNOOZE_MINUTES, DateLocale, parse_when, QueueState, IgnoreList, IgnoreRule, domain_rules, prefix_rule, StaleDraft, StaleDraftPolicy, find_stale_drafts, Redactor, SendPlan, SendHistory, SendRecord,
+    FailedQueue, FailedSend, AttachmentStaging, StagedFile, read_clipboard_image, Template, TemplateStore, ComposedMessage, BatchCompose, Outbox, OutboxItem, BackoffPolicy, ErrorCategory, send_message_with_attachments, send_with_backoff, mark_as_read,
+    mark_read_via_messages, messages_url, Tapback, send_reaction, OnboardingStatus, onboarding_status,
+    DiagnosticCheck, DiagnosticPaths, DraftAnalysis, AppPaths, Clock, SystemClock, ExportSummary, ExportRange, export_jsonl, export_csv,
+    default_attachments_dir, conversion_path, LOCAL_SOURCE, ChangeWatcher, AppEvent, EventBus, DEFAULT_EVENT_CAPACITY, Shutdown, SHUTDOWN_GRACE, Throttle, PowerState, PowerMonitor, FocusState, addressbook_sources_dir, load_contacts as load_contacts_from,
+    default_drafts_dir, messages_app_draft, DUPLICATE_WINDOW_SECS, sort_conversations, resolve_sender_names, MuteList,
+    Curl, LinkTitles, bare_url, with_link_title, Document, JsonFileStore, StateStore, open_state_store,
+};
+use chrono::{DateTime, Local, Utc};
 use std::collections::{HashMap, HashSet};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::process::Command;
 use tauri::State;
 
+/// Youngest a chat.db snapshot can be before the watcher copies it again.
+const SNAPSHOT_MIN_AGE_SECS: i64 = 30;
+
 /// Application state shared across commands.
 struct AppState {
-    drafts: Mutex<HashMap<i64, String>>,
-    committed: Mutex<HashMap<i64, String>>,
-    later: Mutex<HashSet<i64>>,
-    ignored: Mutex<HashSet<String>>,
+    /// Where state, logs, staging and the cache live
+    paths: AppPaths,
+    /// Reply state, keyed by chat GUID
+    drafts: Mutex<HashMap<String, String>>,
+    committed: Mutex<HashMap<String, String>>,
+    /// Committed replies as loaded from disk; these need confirming again
+    /// while the text is unchanged
+    restored: Mutex<HashMap<String, String>>,
+    later: Mutex<HashSet<String>>,
+    ignored: Mutex<IgnoreList>,
+    /// Groups hidden until someone mentions me or asks something
+    muted: Mutex<MuteList>,
     contacts: Mutex<ContactResolver>,
+    settings: Mutex<Settings>,
+    history: SendHistory,
+    failed: Mutex<FailedQueue>,
+    read_overlay: Mutex<ReadOverlay>,
+    snoozed: Mutex<SnoozeList>,
+    outbox: Mutex<Outbox>,
+    staging: Mutex<AttachmentStaging>,
+    templates: Mutex<TemplateStore>,
+    /// Batch messages to handles without a chat in the queue
+    composed: Mutex<Vec<ComposedMessage>>,
+    bookmarks: Mutex<BookmarkStore>,
+    session: Mutex<Option<TriageSession>>,
+    sessions: Mutex<SessionLog>,
+    /// Whether the last chat.db read got through
+    db_status: Mutex<DatabaseStatus>,
+    /// When this run last copied chat.db to its snapshot. Held while
+    /// copying, so two copies never race.
+    snapshot_taken: Mutex<Option<DateTime<Utc>>>,
+    /// Time source for everything relative to now
+    clock: Arc<dyn Clock>,
+    /// What the watchers saw, for the frontend bridge
+    events: EventBus,
+    /// Quitting, and the sends it has to wait for
+    shutdown: Shutdown,
+    /// AC or battery, checked at most once a minute
+    power: PowerMonitor,
+    /// Background failures the window hasn't taken yet
+    problems: Mutex<Vec<String>>,
+    /// Whether quitting was already held up once because state didn't save
+    exit_warned: AtomicBool,
+    /// Where the state saved whole is kept
+    store: Arc<dyn StateStore>,
+}
+
+/// A resolver holding just the people file's names: the one in settings,
+/// or people.tsv in the data folder if it exists.
+fn people_overrides(paths: &AppPaths, settings: &Settings, problems: &mut Vec<String>) -> ContactResolver {
+    let mut contacts = ContactResolver::new();
+    let path = settings.people_file.clone().unwrap_or_else(|| paths.people());
+    // A chosen file that's gone is worth a word; a missing default isn't
+    if settings.people_file.is_some() || path.exists() {
+        if let Err(e) = contacts.load_overrides(&path) {
+            problems.push(format!("People file not loaded: {}", e));
+        }
+    }
+    contacts
 }
 
 impl Default for AppState {
     fn default() -> Self {
+        let paths = AppPaths::from_env();
+        let settings = Settings::load(&paths.settings());
+        let mut problems = Vec::new();
+        let store = open_state_store(settings.state_store, &paths).unwrap_or_else(|e| {
+            problems.push(format!("State database unavailable, using the JSON files: {}", e));
+            Arc::new(JsonFileStore::new(paths.data_dir.clone()))
+        });
+        let doc = |path: std::path::PathBuf| Document::for_file(&store, &path);
+        let queue = QueueState::load(&doc(paths.queue_state()));
+        let mut contacts = people_overrides(&paths, &settings, &mut problems);
+        contacts.set_email_matching(settings.email_matching);
+        let history = SendHistory::open(store.clone(), &paths.send_history()).unwrap_or_else(|e| {
+            problems.push(format!("Send history from before this version wasn't copied: {}", e));
+            SendHistory::new(store.clone())
+        });
         Self {
-            drafts: Mutex::new(HashMap::new()),
-            committed: Mutex::new(HashMap::new()),
-            later: Mutex::new(HashSet::new()),
-            ignored: Mutex::new(HashSet::new()),
-            contacts: Mutex::new(ContactResolver::new()),
+            drafts: Mutex::new(queue.drafts),
+            restored: Mutex::new(queue.committed.clone()),
+            committed: Mutex::new(queue.committed),
+            later: Mutex::new(queue.later),
+            ignored: Mutex::new(IgnoreList::load(doc(paths.ignored()))),
+            muted: Mutex::new(MuteList::load(doc(paths.muted()))),
+            contacts: Mutex::new(contacts),
+            settings: Mutex::new(settings),
+            history,
+            failed: Mutex::new(FailedQueue::load(doc(paths.failed_sends()))),
+            read_overlay: Mutex::new(ReadOverlay::load(doc(paths.read_overlay()))),
+            snoozed: Mutex::new(SnoozeList::load(doc(paths.snoozed()))),
+            outbox: Mutex::new(Outbox::load(doc(paths.outbox()))),
+            staging: Mutex::new(AttachmentStaging::load(paths.staging())),
+            templates: Mutex::new(TemplateStore::load(doc(paths.templates()))),
+            composed: Mutex::new(queue.composed),
+            bookmarks: Mutex::new(BookmarkStore::load(doc(paths.bookmarks()))),
+            session: Mutex::new(None),
+            sessions: Mutex::new(SessionLog::load(doc(paths.sessions()))),
+            db_status: Mutex::new(DatabaseStatus::Ready),
+            snapshot_taken: Mutex::new(None),
+            clock: Arc::new(SystemClock),
+            events: EventBus::new(),
+            shutdown: Shutdown::new(),
+            power: PowerMonitor::default(),
+            problems: Mutex::new(problems),
+            exit_warned: AtomicBool::new(false),
+            store,
+            paths,
         }
     }
 }
 
+/// Why the queue couldn't be loaded.
+#[derive(serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum LoadError {
+    /// chat.db stayed locked, usually while Messages syncs; try again shortly
+    DatabaseBusy { attempts: u32 },
+    Failed { message: String },
+}
+
+#[tauri::command(async)]
+fn get_conversations(state: State<AppState>) -> Result<Vec<Conversation>, LoadError> {
+    load_conversations(&state).map_err(|message| match state.db_status.lock().map(|s| *s) {
+        Ok(DatabaseStatus::Busy { attempts }) => LoadError::DatabaseBusy { attempts },
+        _ => LoadError::Failed { message },
+    })
+}
+
+/// Refetch one conversation, e.g. after a send or a new message in it,
+/// without reloading the whole queue. None once it has left the queue.
+#[tauri::command(async)]
+fn reload_conversation(chat_id: i64, state: State<AppState>) -> Result<Option<Conversation>, String> {
+    Ok(load_queue(&state, Some(chat_id))?.pop())
+}
+
+/// Whether chat.db was readable last time, for a "Messages is syncing" banner.
 #[tauri::command]
-fn get_conversations(state: State<AppState>) -> Result<Vec<Conversation>, String> {
-    let path = Database::default_path();
-    let db = Database::open(&path).map_err(|e| e.to_string())?;
-    let mut convs = db.unread_conversations().map_err(|e| e.to_string())?;
+fn get_database_status(state: State<AppState>) -> Result<DatabaseStatus, String> {
+    Ok(*state.db_status.lock().map_err(|e| e.to_string())?)
+}
+
+/// Load the triage queue with names resolved and local read state applied.
+fn load_conversations(state: &AppState) -> Result<Vec<Conversation>, String> {
+    load_queue(state, None)
+}
+
+/// The triage queue, or just `only_chat` if it's still in it.
+fn load_queue(state: &AppState, only_chat: Option<i64>) -> Result<Vec<Conversation>, String> {
+    let mut convs = unread_from_db(state, only_chat)?;
+    
+    // Hide chats already handled locally
+    let overlay = state.read_overlay.lock().map_err(|e| e.to_string())?;
+    convs.retain(|c| !overlay.is_read(c));
+    drop(overlay);
+    
+    let mut snoozed = state.snoozed.lock().map_err(|e| e.to_string())?;
+    let now = state.clock.now();
+    if snoozed.prune(now) {
+        snoozed.save().map_err(|e| e.to_string())?;
+    }
+    convs.retain(|c| !snoozed.is_snoozed(c, now));
+    drop(snoozed);
+    
+    let muted = state.muted.lock().map_err(|e| e.to_string())?;
+    convs.retain(|c| !muted.is_hidden(c));
+    drop(muted);
+    
+    let (ignore_automated, auto_expire_otp) = {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?;
+        (settings.ignore_automated, settings.auto_expire_otp)
+    };
+    if ignore_automated {
+        convs.retain(|c| c.automated_kind().is_none());
+    }
+    if auto_expire_otp {
+        // Codes nobody used in time are done with. Loading only hides them;
+        // mark_expired_codes_read is what marks them read
+        convs.retain(|c| !c.otp_expired(now));
+    }
+    
+    // Staleness is judged against the whole queue
+    let stale_policy = state.settings.lock().map_err(|e| e.to_string())?.stale_drafts;
+    if stale_policy == StaleDraftPolicy::Clear && only_chat.is_none() {
+        if let Err(e) = sweep_stale_drafts(state, &convs) {
+            report_problem(state, format!("Failed to clear stale drafts: {}", e));
+        }
+    }
+    
+    // Surface replies half-typed in Messages.app
+    let settings = state.settings.lock().map_err(|e| e.to_string())?;
+    if settings.read_messages_app_drafts {
+        let drafts_dir = default_drafts_dir();
+        for conv in &mut convs {
+            conv.messages_app_draft = messages_app_draft(&drafts_dir, &conv.chat_identifier);
+        }
+    }
+    sort_conversations(&mut convs, settings.sort_order);
+    
+    Ok(convs)
+}
+
+/// Drafts and committed replies for chats since answered in Messages.app.
+/// Under the clear policy these are the ones just deleted.
+#[tauri::command]
+fn get_stale_drafts(state: State<AppState>) -> Result<Vec<StaleDraft>, String> {
+    let convs = unread_conversations(&state)?;
+    sweep_stale_drafts(&state, &convs)
+}
+
+/// Find stale drafts, deleting them if the policy says to.
+fn sweep_stale_drafts(state: &AppState, queue: &[Conversation]) -> Result<Vec<StaleDraft>, String> {
+    let policy = state.settings.lock().map_err(|e| e.to_string())?.stale_drafts;
+    let db = Database::open(&chat_db_path(state)?).map_err(|e| e.to_string())?;
+    let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
     
-    // Resolve contact names
+    let stale = find_stale_drafts(&db, queue, &drafts, &committed).map_err(|e| e.to_string())?;
+    if policy != StaleDraftPolicy::Clear || stale.is_empty() {
+        return Ok(stale);
+    }
+    for draft in &stale {
+        if draft.committed {
+            committed.remove(&draft.chat_guid);
+        } else {
+            drafts.remove(&draft.chat_guid);
+        }
+    }
+    drop((drafts, committed));
+    save_queue_state(state)?;
+    Ok(stale)
+}
+
+/// Write drafts, committed replies and the later list to disk. Takes their
+/// locks, so callers must have released them, and holds all three until
+/// written so the file is one consistent state and saves can't land out
+/// of order.
+fn save_queue_state(state: &AppState) -> Result<(), String> {
+    save_queue_with(state, &[])
+}
+
+/// Save the queue with `pending`, composed messages taken out for sending
+/// that haven't gone yet, still in it.
+///
+/// Lock order: anything holding more than one of these queue locks takes
+/// them as drafts, committed, later, composed, the order here, so it can't
+/// deadlock against a save running on another thread.
+fn save_queue_with(state: &AppState, pending: &[ComposedMessage]) -> Result<(), String> {
+    let drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+    let committed = state.committed.lock().map_err(|e| e.to_string())?;
+    let later = state.later.lock().map_err(|e| e.to_string())?;
+    let composed = state.composed.lock().map_err(|e| e.to_string())?;
+    let queue = QueueState {
+        drafts: drafts.clone(),
+        committed: committed.clone(),
+        later: later.clone(),
+        composed: composed.iter().chain(pending).cloned().collect(),
+    };
+    queue.save(&Document::for_file(&state.store, &state.paths.queue_state())).map_err(|e| e.to_string())
+}
+
+/// Mark a reply in flight, saved before it's handed to Messages.
+fn start_send(state: &AppState, chat_guid: &str, name: &str, text: &str) -> Result<(), String> {
+    let mut outbox = state.outbox.lock().map_err(|e| e.to_string())?;
+    outbox.start(chat_guid, name, text, state.clock.now());
+    outbox.save().map_err(|e| e.to_string())
+}
+
+/// Mark a send done once its outcome is saved.
+fn finish_send(state: &AppState, chat_guid: &str, success: bool) -> Result<(), String> {
+    let mut outbox = state.outbox.lock().map_err(|e| e.to_string())?;
+    outbox.finish(chat_guid, success, state.clock.now());
+    outbox.save().map_err(|e| e.to_string())
+}
+
+/// Unread conversations from every extra library in settings, tagged by
+/// source. These can't be replied to from this account.
+#[tauri::command]
+fn get_library_conversations(state: State<AppState>) -> Result<Vec<Conversation>, String> {
+    let (libraries, sort_order, style) = {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?;
+        (settings.libraries.clone(), settings.sort_order, settings.group_name_style)
+    };
     let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
-    for conv in &mut convs {
-        if conv.display_name.is_none() || conv.display_name.as_ref().map(|s| s.is_empty()).unwrap_or(false) {
-            if conv.is_group() {
-                // For groups, resolve participant names
-                let names: Vec<String> = conv.participants.iter()
-                    .filter_map(|p| contacts.resolve(p).map(|n| {
-                        // Use first name only for groups
-                        n.split_whitespace().next().unwrap_or(n).to_string()
-                    }))
-                    .collect();
-                
-                if !names.is_empty() {
-                    conv.resolved_name = Some(names.join(", "));
-                }
-            } else {
-                // For 1:1 chats, resolve the identifier
-                if let Some(name) = contacts.resolve(&conv.chat_identifier) {
-                    conv.resolved_name = Some(name.to_string());
+    
+    let mut convs = Vec::new();
+    for library in &libraries {
+        // One unreadable library shouldn't hide the others
+        let loaded = Database::open_source(&library.path, &library.id).and_then(|mut db| {
+            db.set_group_name_style(style);
+            db.unread_conversations(Some(&contacts))
+        });
+        match loaded {
+            Ok(found) => convs.extend(found),
+            Err(e) => report_problem(&state, format!("Skipping library {}: {}", library.id, e)),
+        }
+    }
+    
+    sort_conversations(&mut convs, sort_order);
+    Ok(convs)
+}
+
+/// chat.db, or its snapshot once one exists if snapshot reads are on.
+fn chat_db_path(state: &AppState) -> Result<std::path::PathBuf, String> {
+    let use_snapshot = state.settings.lock().map_err(|e| e.to_string())?.read_from_snapshot;
+    let snapshot = state.paths.chat_snapshot();
+    Ok(if use_snapshot && snapshot.exists() { snapshot } else { Database::default_path() })
+}
+
+/// Retake the chat.db snapshot now, however recent the last one is. The
+/// new copy replaces the old in one rename, the search index catches up
+/// to it, and then one `conversations-updated` for every chat moves all
+/// views to the same moment. Returns when it was taken, or None when
+/// snapshot reads are off.
+#[tauri::command(async)]
+fn refresh_snapshot(state: State<AppState>) -> Result<Option<DateTime<Utc>>, String> {
+    let Some(taken) = take_snapshot(&state, None)? else {
+        return Ok(None);
+    };
+    // An index never searched isn't built here; its first search does that
+    let index_path = state.paths.search_index();
+    if index_path.exists() {
+        let caught_up = Database::open(&state.paths.chat_snapshot())
+            .and_then(|db| SearchIndex::open(&index_path)?.update(&db));
+        if let Err(e) = caught_up {
+            report_problem(&state, format!("Couldn't update the search index: {}", e));
+        }
+    }
+    state.events.publish(AppEvent::MessagesChanged(MessageChanges { chat_ids: Vec::new(), newest_rowid: 0, newest_read: 0 }));
+    Ok(Some(taken))
+}
+
+/// Copy chat.db to its snapshot for the watcher, unless this run copied
+/// it under `SNAPSHOT_MIN_AGE_SECS` ago, since each copy is the whole
+/// database. Returns when the snapshot in use was taken.
+fn retake_snapshot(state: &AppState) -> Result<Option<DateTime<Utc>>, String> {
+    take_snapshot(state, Some(SNAPSHOT_MIN_AGE_SECS))
+}
+
+/// Copy chat.db to its snapshot, or keep the current one if it's under
+/// `min_age_secs` old. None when snapshot reads are off.
+fn take_snapshot(state: &AppState, min_age_secs: Option<i64>) -> Result<Option<DateTime<Utc>>, String> {
+    if !state.settings.lock().map_err(|e| e.to_string())?.read_from_snapshot {
+        return Ok(None);
+    }
+    let mut taken = state.snapshot_taken.lock().map_err(|e| e.to_string())?;
+    let snapshot = state.paths.chat_snapshot();
+    let now = state.clock.now();
+    if let (Some(last), Some(min_age)) = (*taken, min_age_secs) {
+        if (now - last).num_seconds() < min_age && snapshot.exists() {
+            return Ok(Some(last));
+        }
+    }
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&state.paths.cache_dir).map_err(|e| e.to_string())?;
+    retry_busy(&busy_policy(), || db.snapshot_to(&snapshot), |_| {}, std::thread::sleep)
+        .map_err(|e| e.to_string())?;
+    *taken = Some(now);
+    Ok(Some(now))
+}
+
+/// Unread conversations from the user's chat.db with names resolved.
+fn unread_conversations(state: &AppState) -> Result<Vec<Conversation>, String> {
+    unread_from_db(state, None)
+}
+
+/// Unread conversations from chat.db itself, never the snapshot, for
+/// checks made just before sending.
+fn live_unread_conversations(state: &AppState) -> Result<Vec<Conversation>, String> {
+    unread_from(state, &Database::default_path(), None)
+}
+
+/// Unread chats from chat.db, or just `only_chat` if it has unread messages.
+fn unread_from_db(state: &AppState, only_chat: Option<i64>) -> Result<Vec<Conversation>, String> {
+    unread_from(state, &chat_db_path(state)?, only_chat)
+}
+
+fn unread_from(state: &AppState, path: &std::path::PathBuf, only_chat: Option<i64>) -> Result<Vec<Conversation>, String> {
+    let (style, stats, group_events) = {
+        let settings = state.settings.lock().map_err(|e| e.to_string())?;
+        (settings.group_name_style, settings.conversation_stats, settings.group_events)
+    };
+    let set_status = |status| {
+        if let Ok(mut current) = state.db_status.lock() {
+            *current = status;
+        }
+    };
+    let loaded = Database::open(path).and_then(|mut db| {
+        db.set_group_name_style(style);
+        db.set_load_stats(stats);
+        db.set_include_system_events(group_events);
+        db.set_clock(state.clock.clone());
+        retry_busy(
+            &busy_policy(),
+            || {
+                // Taken per attempt so the backoff doesn't hold up contact loading
+                let contacts = state.contacts.lock().unwrap_or_else(|e| e.into_inner());
+                match only_chat {
+                    Some(chat_id) => db.unread_conversation(chat_id, Some(&contacts)).map(|c| c.into_iter().collect()),
+                    None => db.unread_conversations(Some(&contacts)),
                 }
+            },
+            |attempts| set_status(DatabaseStatus::Busy { attempts }),
+            std::thread::sleep,
+        )
+    });
+    // Still busy only if the last try was locked out
+    if !matches!(&loaded, Err(e) if e.is_busy()) {
+        set_status(DatabaseStatus::Ready);
+    }
+    let mut convs = loaded.map_err(|e| e.to_string())?;
+    
+    let receipts = global_read_receipts(&messages_prefs_path());
+    for conv in &mut convs {
+        conv.apply_global_read_receipts(receipts);
+    }
+    Ok(convs)
+}
+
+#[tauri::command]
+fn export_unread_snapshot(path: String, state: State<AppState>) -> Result<usize, String> {
+    let mut convs = load_conversations(&state)?;
+    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+    if !settings.export_unredacted {
+        let redactor = Redactor::new(settings.redaction);
+        for conv in &mut convs {
+            redactor.redact_conversation(conv);
+        }
+    }
+    let drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+    let committed = state.committed.lock().map_err(|e| e.to_string())?;
+    let later = state.later.lock().map_err(|e| e.to_string())?;
+    
+    let snapshot = UnreadSnapshot::new(&convs, &drafts, &committed, &later, state.clock.now());
+    let path = export_path(&state, &path)?;
+    snapshot.write(&path).map_err(|e| e.to_string())?;
+    
+    Ok(snapshot.conversations.len())
+}
+
+/// Where an export goes: `path` as given if absolute, otherwise under the
+/// exports folder, which is created if needed.
+fn export_path(state: &AppState, path: &str) -> Result<std::path::PathBuf, String> {
+    let path = state.paths.exports().join(path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    Ok(path)
+}
+
+/// Stream messages to a JSONL file. With `resume`, continue from the last
+/// checkpoint instead of starting over.
+#[tauri::command(async)]
+fn export_messages_jsonl(
+    path: String,
+    chat_ids: Option<Vec<i64>>,
+    resume: bool,
+    state: State<AppState>,
+) -> Result<ExportSummary, String> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+    let redactor = (!settings.export_unredacted).then(|| Redactor::new(settings.redaction));
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    let names = sender_names(&state, &db, chat_ids.as_deref())?;
+    
+    export_jsonl(&db, &export_path(&state, &path)?, chat_ids.as_deref(), resume, &names, redactor.as_ref())
+        .map_err(|e| e.to_string())
+}
+
+/// Contact names for everyone who sent a message in `chat_ids`, or in any
+/// chat, resolved up front so no contacts lock is held while exporting.
+fn sender_names(state: &AppState, db: &Database, chat_ids: Option<&[i64]>) -> Result<HashMap<String, String>, String> {
+    let handles = db.sender_handles(chat_ids).map_err(|e| e.to_string())?;
+    let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
+    Ok(handles.into_iter()
+        .filter_map(|handle| Some((handle.clone(), contacts.resolve(&handle)?.to_string())))
+        .collect())
+}
+
+/// Write messages in `range` to `path` as CSV, one row per message.
+#[tauri::command(async)]
+fn export_messages_csv(
+    chat_ids: Option<Vec<i64>>,
+    range: ExportRange,
+    path: String,
+    state: State<AppState>,
+) -> Result<usize, String> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+    let redactor = (!settings.export_unredacted).then(|| Redactor::new(settings.redaction));
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    let names = sender_names(&state, &db, chat_ids.as_deref())?;
+    
+    export_csv(&db, &export_path(&state, &path)?, chat_ids.as_deref(), range, &names, redactor.as_ref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_drafts(path: String, state: State<AppState>) -> Result<DraftImportReport, String> {
+    let incoming = read_draft_map(std::path::Path::new(&path))?;
+    let convs = load_conversations(&state)?;
+    let report = {
+        let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+        let committed = state.committed.lock().map_err(|e| e.to_string())?;
+        merge_drafts(incoming, &convs, &mut drafts, &committed)
+    };
+    save_queue_state(&state)?;
+    Ok(report)
+}
+
+/// Compact cards for the unread queue, for menus and notifications.
+#[tauri::command]
+fn get_summary_cards(state: State<AppState>) -> Result<Vec<SummaryCard>, String> {
+    let convs = load_conversations(&state)?;
+    Ok(convs.iter().map(Conversation::summary_card).collect())
+}
+
+/// A chat's loaded messages split into days in the local time zone.
+#[tauri::command]
+fn get_message_days(chat_id: i64, lang: String, state: State<AppState>) -> Result<Vec<DaySection>, String> {
+    let convs = load_conversations(&state)?;
+    let conv = convs.iter().find(|c| c.chat_id == chat_id).ok_or("Chat not found")?;
+    Ok(group_messages_by_day(&conv.messages, &Local, &lang, &*state.clock))
+}
+
+/// Mark read the chats whose unread messages are all expired verification
+/// codes. Returns how many chats were marked.
+#[tauri::command(async)]
+fn mark_expired_codes_read(state: State<AppState>) -> Result<usize, String> {
+    let now = state.clock.now();
+    let expired: Vec<_> = live_unread_conversations(&state)?.into_iter().filter(|c| c.otp_expired(now)).collect();
+    for conv in &expired {
+        mark_chat_read(&state, &conv.chat_identifier)?;
+    }
+    Ok(expired.len())
+}
+
+/// Copy the newest unexpired verification code to the clipboard.
+#[tauri::command]
+fn copy_latest_otp(state: State<AppState>) -> Result<Option<String>, String> {
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    let convs = db.unread_conversations(None).map_err(|e| e.to_string())?;
+    let now = state.clock.now();
+    let Some((_, code)) = convs.iter().filter_map(|c| c.latest_otp(now)).max() else {
+        return Ok(None);
+    };
+    // No pbcopy off macOS; the code is still returned to show
+    if cfg!(feature = "dev-sample") {
+        return Ok(Some(code));
+    }
+    
+    let mut child = Command::new("pbcopy")
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        use std::io::Write;
+        stdin.write_all(code.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    child.wait().map_err(|e| e.to_string())?;
+    Ok(Some(code))
+}
+
+/// Who in a chat has written or reacted since my last message.
+#[tauri::command]
+fn get_active_since_my_last_message(chat_id: i64, state: State<AppState>) -> Result<Option<Vec<String>>, String> {
+    let convs = load_conversations(&state)?;
+    let conv = convs.iter().find(|c| c.chat_id == chat_id).ok_or("Chat not found")?;
+    Ok(conv.active_since_my_last_message())
+}
+
+#[tauri::command]
+fn add_bookmark(chat_guid: String, message_guid: String, note: String, state: State<AppState>) -> Result<Bookmark, String> {
+    let mut bookmarks = state.bookmarks.lock().map_err(|e| e.to_string())?;
+    let bookmark = bookmarks.add(&chat_guid, &message_guid, &note, state.clock.now());
+    bookmarks.save().map_err(|e| e.to_string())?;
+    Ok(bookmark)
+}
+
+#[tauri::command]
+fn remove_bookmark(message_guid: String, state: State<AppState>) -> Result<bool, String> {
+    let mut bookmarks = state.bookmarks.lock().map_err(|e| e.to_string())?;
+    let removed = bookmarks.remove(&message_guid);
+    bookmarks.save().map_err(|e| e.to_string())?;
+    Ok(removed)
+}
+
+#[tauri::command]
+fn list_bookmarks(chat_guid: Option<String>, query: Option<String>, state: State<AppState>) -> Result<Vec<Bookmark>, String> {
+    let bookmarks = state.bookmarks.lock().map_err(|e| e.to_string())?;
+    Ok(bookmarks.list(chat_guid.as_deref(), query.as_deref()))
+}
+
+/// The bookmarked message with `around` messages either side of it.
+#[tauri::command]
+fn jump_to_bookmark(message_guid: String, around: usize, state: State<AppState>) -> Result<Vec<Message>, String> {
+    let chat_guid = state.bookmarks.lock().map_err(|e| e.to_string())?
+        .get(&message_guid)
+        .map(|b| b.chat_guid.clone())
+        .ok_or("Bookmark not found")?;
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    let mut messages = db.message_context(&message_guid, around).map_err(|e| e.to_string())?;
+    if messages.is_empty() {
+        return Err("The bookmarked message no longer exists".to_string());
+    }
+    // Everyone in the chat, so shared first names are told apart
+    let participants = db.chat_participants_by_guid(&chat_guid).map_err(|e| e.to_string())?;
+    let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
+    resolve_sender_names(&mut messages, &participants, &contacts);
+    Ok(messages)
+}
+
+/// Start a time-boxed triage session over the current queue, replacing
+/// any session already running. Only chats that need a reply are queued
+/// unless `all` is set.
+#[tauri::command]
+fn start_triage_session(minutes: i64, all: Option<bool>, state: State<AppState>) -> Result<TriageSession, String> {
+    if minutes <= 0 {
+        return Err("A session needs at least one minute".to_string());
+    }
+    let mut convs = load_conversations(&state)?;
+    if !all.unwrap_or(false) {
+        convs.retain(|c| c.needs_reply);
+    }
+    let session = TriageSession::start(&convs, minutes, state.clock.now()).ok_or("That session is too long")?;
+    *state.session.lock().map_err(|e| e.to_string())? = Some(session.clone());
+    Ok(session)
+}
+
+/// End the running session and add its report to the session log.
+#[tauri::command]
+fn end_session(state: State<AppState>) -> Result<SessionReport, String> {
+    let session = state.session.lock().map_err(|e| e.to_string())?.take().ok_or("No session running")?;
+    let report = session.end(state.clock.now());
+    state.sessions.lock().map_err(|e| e.to_string())?.record(report.clone()).map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Past session reports, oldest first.
+#[tauri::command]
+fn get_session_reports(state: State<AppState>) -> Result<Vec<SessionReport>, String> {
+    Ok(state.sessions.lock().map_err(|e| e.to_string())?.reports().to_vec())
+}
+
+/// Count a successful send towards the running session, if any.
+fn record_session_send(state: &AppState, chat_identifier: &str) {
+    if let Ok(mut session) = state.session.lock() {
+        if let Some(session) = session.as_mut() {
+            session.record_sent(chat_identifier);
+        }
+    }
+}
+
+#[tauri::command]
+fn get_media(
+    chat_id: i64,
+    kinds: Vec<String>,
+    limit: usize,
+    before: Option<DateTime<Utc>>,
+) -> Result<Vec<MediaItem>, String> {
+    let path = Database::default_path();
+    let db = Database::open(&path).map_err(|e| e.to_string())?;
+    let kinds: Vec<&str> = kinds.iter().map(String::as_str).collect();
+    db.media_for_chat(chat_id, &kinds, limit, before).map_err(|e| e.to_string())
+}
+
+/// Find messages in any conversation containing every word of `query`,
+/// best matches first.
+#[tauri::command(async)]
+fn search_messages(
+    query: String,
+    filter: Option<MessageFilter>,
+    limit: Option<usize>,
+    state: State<AppState>,
+) -> Result<Vec<SearchHit>, String> {
+    let db = Database::open(&chat_db_path(&state)?).map_err(|e| e.to_string())?;
+    let mut index = SearchIndex::open(&state.paths.search_index()).map_err(|e| e.to_string())?;
+    index.search(&db, &query, &filter.unwrap_or_default(), limit.unwrap_or(50))
+        .map_err(|e| e.to_string())
+}
+
+/// A page of a chat's history in chronological order, for scrolling back
+/// past the messages loaded with the conversation. `before_rowid` is the
+/// first message already shown; `filter` narrows what's loaded.
+#[tauri::command]
+fn get_messages(
+    chat_id: i64,
+    before_rowid: Option<i64>,
+    limit: usize,
+    filter: Option<MessageFilter>,
+    state: State<AppState>,
+) -> Result<Vec<Message>, String> {
+    let group_events = state.settings.lock().map_err(|e| e.to_string())?.group_events;
+    let mut db = Database::open(&chat_db_path(&state)?).map_err(|e| e.to_string())?;
+    db.set_include_system_events(group_events);
+    let mut messages = db.messages_page(chat_id, &filter.unwrap_or_default(), before_rowid, limit)
+        .map_err(|e| e.to_string())?;
+    messages.reverse();
+    let participants = db.chat_participants(chat_id).map_err(|e| e.to_string())?;
+    let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
+    resolve_sender_names(&mut messages, &participants, &contacts);
+    Ok(messages)
+}
+
+/// Every chat a person is in, for the person view.
+#[tauri::command]
+fn get_chats_for_contact(query: String, state: State<AppState>) -> Result<Vec<ContactChat>, String> {
+    let path = Database::default_path();
+    let db = Database::open(&path).map_err(|e| e.to_string())?;
+    let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
+    db.chats_for_contact(&query, &contacts).map_err(|e| e.to_string())
+}
+
+/// Recipients for the new-message composer, up to `limit` (default 10).
+#[tauri::command]
+fn autocomplete_recipients(
+    prefix: String,
+    limit: Option<usize>,
+    state: State<AppState>,
+) -> Result<Vec<RecipientSuggestion>, String> {
+    let path = Database::default_path();
+    let db = Database::open(&path).map_err(|e| e.to_string())?;
+    let handles = db.handle_activity().map_err(|e| e.to_string())?;
+    let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
+    Ok(aeromessage::autocomplete_recipients(&prefix, &handles, &contacts, limit.unwrap_or(10)))
+}
+
+/// Character and SMS segment counts, called on each keystroke.
+#[tauri::command]
+fn analyze_draft(text: String, service: String) -> DraftAnalysis {
+    aeromessage::analyze_draft(&text, &service)
+}
+
+#[tauri::command]
+fn get_participant_history(chat_id: i64) -> Result<Vec<ParticipantEvent>, String> {
+    let path = Database::default_path();
+    let db = Database::open(&path).map_err(|e| e.to_string())?;
+    db.participant_history(chat_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command(async)]
+fn save_draft(chat_guid: String, text: String, state: State<AppState>) -> Result<String, String> {
+    let result = {
+        let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+        let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+        
+        // Remove from committed if editing
+        committed.remove(&chat_guid);
+        state.restored.lock().map_err(|e| e.to_string())?.remove(&chat_guid);
+        
+        if text.trim().is_empty() {
+            drafts.remove(&chat_guid);
+            "empty"
+        } else {
+            drafts.insert(chat_guid, text);
+            "draft"
+        }
+    };
+    save_queue_state(&state)?;
+    
+    Ok(result.to_string())
+}
+
+/// Queue a reply, returning the text committed: a bare link may have had
+/// its page title appended, per the `link_titles` setting.
+#[tauri::command(async)]
+fn commit_message(chat_guid: String, text: String, state: State<AppState>) -> Result<String, String> {
+    if text.trim().is_empty() {
+        return Err("No text provided".to_string());
+    }
+    
+    {
+        let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+        let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+        drafts.remove(&chat_guid);
+        state.outbox.lock().map_err(|e| e.to_string())?.queue(&chat_guid, &text, state.clock.now());
+        state.restored.lock().map_err(|e| e.to_string())?.remove(&chat_guid);
+        committed.insert(chat_guid.clone(), text.clone());
+    }
+    save_queue_state(&state)?;
+
+    let link_titles = state.settings.lock().map_err(|e| e.to_string())?.link_titles;
+    if bare_url(&text).is_none() || link_titles == LinkTitles::Off {
+        return Ok(text);
+    }
+    // The fetch can take seconds, so the reply is queued as typed first
+    let db = Database::open(&chat_db_path(&state)?).map_err(|e| e.to_string())?;
+    let service = db.chat_service(&chat_guid).map_err(|e| e.to_string())?;
+    let titled = with_link_title(&text, link_titles, service.as_deref(), &Curl);
+    {
+        // Unless it was sent, edited or committed again meanwhile
+        let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+        if titled == text || committed.get(&chat_guid) != Some(&text) {
+            return Ok(text);
+        }
+        state.outbox.lock().map_err(|e| e.to_string())?.queue(&chat_guid, &titled, state.clock.now());
+        committed.insert(chat_guid, titled.clone());
+    }
+    save_queue_state(&state)?;
+
+    Ok(titled)
+}
+
+/// Copy a file into staging to go out with the chat's reply.
+#[tauri::command]
+fn attach_file_to_draft(chat_guid: String, path: String, state: State<AppState>) -> Result<StagedFile, String> {
+    let mut staging = state.staging.lock().map_err(|e| e.to_string())?;
+    let staged = staging.stage(&chat_guid, std::path::Path::new(&path), state.clock.now()).map_err(|e| e.to_string())?;
+    staging.save().map_err(|e| e.to_string())?;
+    Ok(staged)
+}
+
+/// Stage the image on the clipboard, e.g. a screenshot pasted into a draft.
+#[tauri::command(async)]
+fn stage_clipboard_image(chat_guid: String, state: State<AppState>) -> Result<StagedFile, String> {
+    let bytes = read_clipboard_image(&state.paths.staging()).map_err(|e| e.to_string())?;
+    let mut staging = state.staging.lock().map_err(|e| e.to_string())?;
+    let staged = staging.stage_image(&chat_guid, &bytes, state.clock.now()).map_err(|e| e.to_string())?;
+    staging.save().map_err(|e| e.to_string())?;
+    Ok(staged)
+}
+
+/// Stage image bytes dropped or pasted in the frontend.
+#[tauri::command]
+fn stage_image_bytes(chat_guid: String, bytes: Vec<u8>, state: State<AppState>) -> Result<StagedFile, String> {
+    let mut staging = state.staging.lock().map_err(|e| e.to_string())?;
+    let staged = staging.stage_image(&chat_guid, &bytes, state.clock.now()).map_err(|e| e.to_string())?;
+    staging.save().map_err(|e| e.to_string())?;
+    Ok(staged)
+}
+
+#[tauri::command]
+fn remove_draft_attachment(chat_guid: String, path: String, state: State<AppState>) -> Result<(), String> {
+    let mut staging = state.staging.lock().map_err(|e| e.to_string())?;
+    staging.remove(&chat_guid, std::path::Path::new(&path)).map_err(|e| e.to_string())?;
+    staging.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_templates(state: State<AppState>) -> Result<Vec<Template>, String> {
+    Ok(state.templates.lock().map_err(|e| e.to_string())?.all().to_vec())
+}
+
+#[tauri::command]
+fn save_template(template: Template, state: State<AppState>) -> Result<(), String> {
+    let mut templates = state.templates.lock().map_err(|e| e.to_string())?;
+    templates.upsert(template);
+    templates.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_template(name: String, state: State<AppState>) -> Result<bool, String> {
+    let mut templates = state.templates.lock().map_err(|e| e.to_string())?;
+    let removed = templates.remove(&name);
+    templates.save().map_err(|e| e.to_string())?;
+    Ok(removed)
+}
+
+/// Fill a template into the draft of each chat and stage its attachments.
+/// Every chat is checked and every file staged before any draft changes,
+/// so a missing asset or a failed copy leaves everything as it was.
+#[tauri::command]
+fn apply_template(name: String, chat_guids: Vec<String>, state: State<AppState>) -> Result<HashMap<String, String>, String> {
+    let template = state.templates.lock().map_err(|e| e.to_string())?
+        .get(&name).map_err(|e| e.to_string())?
+        .clone();
+    let assets_dir = state.settings.lock().map_err(|e| e.to_string())?
+        .template_assets_dir.clone()
+        .unwrap_or_else(|| state.paths.template_assets());
+    
+    let convs = unread_conversations(&state)?;
+    let mut applied = Vec::new();
+    for chat_guid in chat_guids {
+        let conv = convs.iter().find(|c| c.guid == chat_guid).ok_or("Chat not found")?;
+        applied.push((chat_guid, template.apply(conv, &assets_dir).map_err(|e| e.to_string())?));
+    }
+    
+    let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+    let mut staging = state.staging.lock().map_err(|e| e.to_string())?;
+    // Stage every file before touching drafts
+    let files: Vec<_> = applied.iter()
+        .flat_map(|(chat_guid, filled)| filled.attachments.iter().map(move |f| (chat_guid.as_str(), f.as_path())))
+        .collect();
+    staging.stage_all(&files, state.clock.now()).map_err(|e| e.to_string())?;
+    staging.save().map_err(|e| e.to_string())?;
+    
+    let mut texts = HashMap::new();
+    for (chat_guid, filled) in applied {
+        committed.remove(&chat_guid);
+        drafts.insert(chat_guid.clone(), filled.text.clone());
+        texts.insert(chat_guid, filled.text);
+    }
+    drop((drafts, committed, staging));
+    save_queue_state(&state)?;
+    Ok(texts)
+}
+
+/// Personalise a template for each recipient and queue the results: as
+/// committed replies for chats in the queue, otherwise as composed messages
+/// for `send_composed`.
+#[tauri::command]
+fn compose_batch(recipients: Vec<String>, template_id: String, state: State<AppState>) -> Result<BatchCompose, String> {
+    let template = state.templates.lock().map_err(|e| e.to_string())?
+        .get(&template_id).map_err(|e| e.to_string())?
+        .clone();
+    let assets_dir = state.settings.lock().map_err(|e| e.to_string())?
+        .template_assets_dir.clone()
+        .unwrap_or_else(|| state.paths.template_assets());
+    
+    let convs = unread_conversations(&state)?;
+    let mut batch = {
+        let contacts = state.contacts.lock().map_err(|e| e.to_string())?;
+        aeromessage::compose_batch(&template, &recipients, &convs, &contacts, &assets_dir)
+            .map_err(|e| e.to_string())?
+    };
+    
+    let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+    let mut staging = state.staging.lock().map_err(|e| e.to_string())?;
+    let mut outbox = state.outbox.lock().map_err(|e| e.to_string())?;
+    // A draft or reply already typed for a chat wins over the template
+    let (replies, skipped): (Vec<_>, Vec<_>) = batch.replies.into_iter().partition(|(chat_guid, _)| {
+        !committed.contains_key(chat_guid) && drafts.get(chat_guid).is_none_or(|d| d.trim().is_empty())
+    });
+    batch.replies = replies;
+    batch.skipped = skipped.into_iter().map(|(chat_guid, _)| chat_guid).collect();
+    
+    let files: Vec<_> = batch.replies.iter()
+        .flat_map(|(chat_guid, applied)| applied.attachments.iter().map(move |f| (chat_guid.as_str(), f.as_path())))
+        .collect();
+    staging.stage_all(&files, state.clock.now()).map_err(|e| e.to_string())?;
+    staging.save().map_err(|e| e.to_string())?;
+    for (chat_guid, applied) in &batch.replies {
+        drafts.remove(chat_guid);
+        committed.insert(chat_guid.clone(), applied.text.clone());
+        outbox.queue(chat_guid, &applied.text, state.clock.now());
+    }
+    drop((drafts, committed, staging, outbox));
+    state.composed.lock().map_err(|e| e.to_string())?.extend(batch.composed.iter().cloned());
+    save_queue_state(&state)?;
+    
+    Ok(batch)
+}
+
+/// What happened to one composed message in `send_composed`.
+#[derive(serde::Serialize)]
+struct ComposedResult {
+    recipient: String,
+    name: String,
+    success: bool,
+    /// Skipped because the same text was just sent to this recipient
+    duplicate: bool,
+    /// Held back by send guards; still queued until confirmed
+    needs_confirmation: Vec<GuardReason>,
+    error: Option<String>,
+}
+
+/// Send every composed batch message, starting chats as needed. Messages
+/// go through the same guards and duplicate check as replies, keyed by
+/// recipient; `confirmed` lists recipients whose guard reasons were
+/// acknowledged. Held and failed messages stay queued for another try, and
+/// each message stays saved in the queue until it's been sent.
+#[tauri::command(async)]
+fn send_composed(confirmed: Option<Vec<String>>, state: State<AppState>) -> Result<Vec<ComposedResult>, String> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+    let db = if settings.send_guards.mms_groups {
+        Some(Database::open(&chat_db_path(&state)?).map_err(|e| e.to_string())?)
+    } else {
+        None
+    };
+    let queued = std::mem::take(&mut *state.composed.lock().map_err(|e| e.to_string())?);
+    let confirmed: HashSet<String> = confirmed.unwrap_or_default().into_iter().collect();
+    
+    let mut results = Vec::new();
+    let mut keep = Vec::new();
+    let mut queued = queued.into_iter();
+    while let Some(message) = queued.next() {
+        let result = |success: bool, duplicate: bool, needs_confirmation: Vec<GuardReason>, error: Option<String>| ComposedResult {
+            recipient: message.recipient.clone(),
+            name: message.name.clone(),
+            success,
+            duplicate,
+            needs_confirmation,
+            error,
+        };
+        if already_sent(&state, &message.recipient, &message.text) {
+            results.push(result(true, true, Vec::new(), None));
+            continue;
+        }
+        if !confirmed.contains(&message.recipient) {
+            let known = state.contacts.lock().map_err(|e| e.to_string())?.resolve(&message.recipient).is_some();
+            let mms_group = match &db {
+                Some(db) => db.is_mms_group_chat(&message.recipient).map_err(|e| e.to_string())?,
+                None => false,
+            };
+            let mut reasons = settings.send_guards.check_recipient(&message.text, known, mms_group);
+            if state.outbox.lock().map_err(|e| e.to_string())?.was_interrupted(&message.recipient, &message.text) {
+                reasons.push(GuardReason::Interrupted);
+            }
+            if !reasons.is_empty() {
+                results.push(result(false, false, reasons, None));
+                keep.push(message);
+                continue;
             }
         }
+        
+        // Quitting: the rest stay queued
+        let Some(_sending) = state.shutdown.begin_send() else {
+            keep.push(message);
+            keep.extend(queued);
+            break;
+        };
+        if let Err(e) = start_send(&state, &message.recipient, &message.name, &message.text) {
+            keep.push(message);
+            keep.extend(queued);
+            state.composed.lock().map_err(|e| e.to_string())?.extend(keep);
+            return Err(e);
+        }
+        let error = SendPlan::to_recipient(&message.recipient, &message.text)
+            .with_attachments(&message.attachments)
+            .execute()
+            .err()
+            .map(|e| e.to_string());
+        // New chats have no GUID yet, so they're logged under the recipient
+        let record = SendRecord::new(0, &message.recipient, &message.recipient, &message.text, settings.log_full_text, error.clone(), state.clock.now());
+        if let Err(e) = state.history.append(&record) {
+            report_problem(&state, format!("Failed to record send: {}", e));
+        }
+        results.push(result(error.is_none(), false, Vec::new(), error.clone()));
+        let success = error.is_none();
+        if !success {
+            keep.push(message.clone());
+        }
+        let pending: Vec<_> = keep.iter().chain(queued.as_slice()).cloned().collect();
+        save_queue_with(&state, &pending)?;
+        finish_send(&state, &message.recipient, success)?;
     }
     
-    Ok(convs)
+    state.composed.lock().map_err(|e| e.to_string())?.extend(keep);
+    save_queue_state(&state)?;
+    Ok(results)
 }
 
 #[tauri::command]
-fn save_draft(chat_id: i64, text: String, state: State<AppState>) -> Result<String, String> {
-    let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
-    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
-    
-    // Remove from committed if editing
-    committed.remove(&chat_id);
-    
-    let result = if text.trim().is_empty() {
-        drafts.remove(&chat_id);
-        "empty"
-    } else {
-        drafts.insert(chat_id, text);
-        "draft"
+fn toggle_later(chat_guid: String, state: State<AppState>) -> Result<bool, String> {
+    let is_later = {
+        let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
+        let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+        let mut later = state.later.lock().map_err(|e| e.to_string())?;
+        
+        if later.remove(&chat_guid) {
+            false
+        } else {
+            drafts.remove(&chat_guid);
+            committed.remove(&chat_guid);
+            later.insert(chat_guid);
+            true
+        }
     };
+    save_queue_state(&state)?;
     
-    Ok(result.to_string())
+    Ok(is_later)
 }
 
+/// Open a chat in Messages.app for a real back-and-forth and snooze it here.
+/// The snooze is undone if Messages can't be opened.
 #[tauri::command]
-fn commit_message(chat_id: i64, text: String, state: State<AppState>) -> Result<String, String> {
-    if text.trim().is_empty() {
-        return Err("No text provided".to_string());
-    }
+fn handoff(chat_guid: String, state: State<AppState>) -> Result<DateTime<Utc>, String> {
+    let convs = unread_conversations(&state)?;
+    let conv = convs.iter().find(|c| c.guid == chat_guid).ok_or("Chat not found")?;
     
-    let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
-    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
+    let minutes = state
+        .settings
+        .lock()
+        .map_err(|e| e.to_string())?
+        .handoff_snooze_minutes
+        .unwrap_or(DEFAULT_HANDOFF_SNOOZE_MINUTES);
+    let until = state.clock.now() + chrono::Duration::minutes(minutes as i64);
     
-    drafts.remove(&chat_id);
-    committed.insert(chat_id, text);
-    
-    Ok("committed".to_string())
+    let mut snoozed = state.snoozed.lock().map_err(|e| e.to_string())?;
+    snoozed.snooze(conv, until);
+    if let Err(e) = open_url(conv.messages_url()) {
+        snoozed.wake(&chat_guid);
+        return Err(e);
+    }
+    snoozed.save().map_err(|e| e.to_string())?;
+    Ok(until)
 }
 
+/// Snooze a chat until a typed time like "tomorrow 9am" or "in 2h", read
+/// in the local time zone. `locale` is a tag like "en-GB" for numeric dates.
 #[tauri::command]
-fn toggle_later(chat_id: i64, state: State<AppState>) -> Result<bool, String> {
-    let mut later = state.later.lock().map_err(|e| e.to_string())?;
-    let mut drafts = state.drafts.lock().map_err(|e| e.to_string())?;
-    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
-    
-    let is_later = if later.contains(&chat_id) {
-        later.remove(&chat_id);
-        false
-    } else {
-        later.insert(chat_id);
-        drafts.remove(&chat_id);
-        committed.remove(&chat_id);
-        true
-    };
+fn snooze_chat(chat_guid: String, when: String, locale: Option<String>, state: State<AppState>) -> Result<DateTime<Utc>, String> {
+    let locale = locale.map(|tag| DateLocale::from_tag(&tag)).unwrap_or_default();
+    let until = parse_when(&when, state.clock.now().with_timezone(&Local), &locale).map_err(|e| e.to_string())?;
+    let convs = unread_conversations(&state)?;
+    let conv = convs.iter().find(|c| c.guid == chat_guid).ok_or("Chat not found")?;
     
-    Ok(is_later)
+    let mut snoozed = state.snoozed.lock().map_err(|e| e.to_string())?;
+    snoozed.snooze(conv, until);
+    snoozed.save().map_err(|e| e.to_string())?;
+    Ok(until)
 }
 
+/// Ignore one chat, or un-ignore it. Un-ignoring removes only its own
+/// rule; domain and prefix rules that also hide it are left for
+/// `set_ignored`, so it may stay ignored. Returns whether it's ignored now.
 #[tauri::command]
 fn toggle_ignore(chat_identifier: String, state: State<AppState>) -> Result<bool, String> {
     let mut ignored = state.ignored.lock().map_err(|e| e.to_string())?;
+    let own_rule = vec![IgnoreRule::Chat(chat_identifier.clone())];
     
-    let is_ignored = if ignored.contains(&chat_identifier) {
-        ignored.remove(&chat_identifier);
-        false
+    if ignored.is_ignored(&chat_identifier) {
+        ignored.set(own_rule, false);
     } else {
-        ignored.insert(chat_identifier);
-        true
-    };
+        ignored.set(own_rule, true);
+    }
     
-    Ok(is_ignored)
+    ignored.save().map_err(|e| e.to_string())?;
+    Ok(ignored.is_ignored(&chat_identifier))
 }
 
+/// Which of `chat_identifiers` an ignore rule hides.
 #[tauri::command]
-fn send_all(state: State<AppState>) -> Result<Vec<SendResult>, String> {
-    let path = Database::default_path();
-    let db = Database::open(&path).map_err(|e| e.to_string())?;
-    let convs = db.unread_conversations().map_err(|e| e.to_string())?;
+fn get_ignored_chats(chat_identifiers: Vec<String>, state: State<AppState>) -> Result<Vec<String>, String> {
+    let ignored = state.ignored.lock().map_err(|e| e.to_string())?;
+    Ok(chat_identifiers.into_iter().filter(|id| ignored.is_ignored(id)).collect())
+}
+
+/// Mute a group until a message mentions me or asks a question, or unmute
+/// it. Returns whether it's now muted.
+#[tauri::command]
+fn set_muted(chat_identifier: String, muted: bool, state: State<AppState>) -> Result<bool, String> {
+    let mut list = state.muted.lock().map_err(|e| e.to_string())?;
+    if list.set(&chat_identifier, muted) {
+        list.save().map_err(|e| e.to_string())?;
+    }
+    Ok(list.is_muted(&chat_identifier))
+}
+
+/// Add or remove several ignore rules at once. Returns how many changed.
+#[tauri::command]
+fn set_ignored(rules: Vec<IgnoreRule>, ignored: bool, state: State<AppState>) -> Result<usize, String> {
+    let mut list = state.ignored.lock().map_err(|e| e.to_string())?;
+    let changed = list.set(rules, ignored);
+    if changed > 0 {
+        list.save().map_err(|e| e.to_string())?;
+    }
+    Ok(changed)
+}
+
+/// Rules that would cover a selection of chats: one per chat, one per
+/// email domain, and a shared prefix if they are all short codes.
+#[tauri::command]
+fn suggest_ignore_rules(chat_identifiers: Vec<String>) -> Vec<IgnoreRule> {
+    let mut rules: Vec<_> = chat_identifiers.iter().cloned().map(IgnoreRule::Chat).collect();
+    rules.extend(domain_rules(&chat_identifiers));
+    rules.extend(prefix_rule(&chat_identifiers));
+    rules
+}
+
+/// Send every committed reply. Replies tripping a send guard, or restored
+/// from disk and not committed again since, stay committed and come back
+/// with `needs_confirmation` unless their chat is in `confirmed`.
+/// During a Focus that defers sends, non-VIP replies stay committed as `deferred`.
+///
+/// Locks are taken per reply so `get_send_queue` can follow along.
+#[tauri::command(async)]
+fn send_all(confirmed: Option<Vec<String>>, state: State<AppState>) -> Result<Vec<SendResult>, String> {
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    let convs = live_unread_conversations(&state)?;
     
-    let conv_map: HashMap<i64, &Conversation> = convs.iter()
-        .map(|c| (c.chat_id, c))
+    let conv_map: HashMap<&str, &Conversation> = convs.iter()
+        .map(|c| (c.guid.as_str(), c))
         .collect();
     
-    let mut committed = state.committed.lock().map_err(|e| e.to_string())?;
-    let to_send: Vec<_> = committed.drain().collect();
+    let to_send: Vec<String> = state.committed.lock().map_err(|e| e.to_string())?
+        .keys()
+        .cloned()
+        .collect();
+    let settings = state.settings.lock().map_err(|e| e.to_string())?.clone();
+    let log_full_text = settings.log_full_text;
+    let confirmed: HashSet<String> = confirmed.unwrap_or_default().into_iter().collect();
+    let focus = FocusState::current(&settings.focus);
+    
+    // Put back a claimed reply that isn't being sent, unless it was
+    // committed again meanwhile
+    let unclaim = |chat_guid: &str, text: String| -> Result<(), String> {
+        state.committed.lock().map_err(|e| e.to_string())?.entry(chat_guid.to_string()).or_insert(text);
+        Ok(())
+    };
     
     let mut results = Vec::new();
-    for (chat_id, text) in to_send {
-        if let Some(conv) = conv_map.get(&chat_id) {
-            let success = send_message(&conv.chat_identifier, &text, conv.is_group()).is_ok();
-            if success {
-                // Mark conversation as read after successful send
-                let _ = mark_as_read(&conv.chat_identifier);
+    for chat_guid in to_send {
+        if let Some(conv) = conv_map.get(chat_guid.as_str()) {
+            // Claim the reply so a second send_all running at once skips it
+            let Some(text) = state.committed.lock().map_err(|e| e.to_string())?.remove(&chat_guid) else {
+                continue;
+            };
+            if already_sent(&state, &chat_guid, &text) {
+                results.push(SendResult {
+                    chat_guid: chat_guid.clone(),
+                    success: true,
+                    duplicate: true,
+                    name: conv.name().to_string(),
+                    needs_confirmation: Vec::new(),
+                    deferred: false,
+                });
+                continue;
+            }
+            
+            if focus.defers(conv, &settings.focus) {
+                unclaim(&chat_guid, text)?;
+                results.push(SendResult {
+                    chat_guid: chat_guid.clone(),
+                    success: false,
+                    duplicate: false,
+                    name: conv.name().to_string(),
+                    needs_confirmation: Vec::new(),
+                    deferred: true,
+                });
+                continue;
+            }
+            
+            if confirmed.contains(&chat_guid) {
+                state.restored.lock().map_err(|e| e.to_string())?.remove(&chat_guid);
+            } else {
+                let known = state.contacts.lock().map_err(|e| e.to_string())?
+                    .resolve(&conv.chat_identifier)
+                    .is_some();
+                let mut reasons = settings.send_guards.check(conv, &text, known);
+                if state.restored.lock().map_err(|e| e.to_string())?.get(&chat_guid) == Some(&text) {
+                    reasons.push(GuardReason::Restored);
+                }
+                if state.outbox.lock().map_err(|e| e.to_string())?.was_interrupted(&chat_guid, &text) {
+                    reasons.push(GuardReason::Interrupted);
+                }
+                if !reasons.is_empty() {
+                    state.outbox.lock().map_err(|e| e.to_string())?.hold(&chat_guid, conv.name(), &text, state.clock.now());
+                    unclaim(&chat_guid, text)?;
+                    results.push(SendResult {
+                        chat_guid: chat_guid.clone(),
+                        success: false,
+                        duplicate: false,
+                        name: conv.name().to_string(),
+                        needs_confirmation: reasons,
+                        deferred: false,
+                    });
+                    continue;
+                }
+            }
+            
+            // Quitting: the rest stay committed for next time
+            let Some(_sending) = state.shutdown.begin_send() else {
+                unclaim(&chat_guid, text)?;
+                break;
+            };
+            if let Err(e) = start_send(&state, &chat_guid, conv.name(), &text) {
+                unclaim(&chat_guid, text)?;
+                return Err(e);
+            }
+            let attachments = state.staging.lock().map_err(|e| e.to_string())?.paths(&chat_guid);
+            let outcome = send_message_with_attachments(&conv.chat_identifier, &text, conv.is_group(), &attachments)
+                .map_err(|e| match e.category() {
+                    // A 1:1 chat that vanished may mean they dropped iMessage
+                    ErrorCategory::NotFound if !conv.is_group() => {
+                        let service = db.latest_service(&conv.chat_identifier).ok().flatten();
+                        e.with_recipient_service(&conv.chat_identifier, service.as_deref())
+                    }
+                    _ => e,
+                });
+            let error = outcome.as_ref().err().map(|e| e.to_string());
+            let success = error.is_none();
+            
+            // Record the attempt; a logging failure must not abort the batch
+            let record = SendRecord::new(conv.chat_id, &conv.guid, &conv.chat_identifier, &text, log_full_text, error, state.clock.now());
+            if let Err(e) = state.history.append(&record) {
+                report_problem(&state, format!("Failed to record send: {}", e));
             }
+            
+            match outcome {
+                Ok(()) => {
+                    // Mark conversation as read after successful send
+                    let _ = mark_chat_read(&state, &conv.chat_identifier);
+                    record_session_send(&state, &conv.chat_identifier);
+                    clear_staged(&state, &chat_guid);
+                }
+                Err(e) => state.failed.lock().map_err(|e| e.to_string())?.push(FailedSend {
+                    chat_id: conv.chat_id,
+                    chat_guid: conv.guid.clone(),
+                    chat_identifier: conv.chat_identifier.clone(),
+                    is_group: conv.is_group(),
+                    name: conv.name().to_string(),
+                    text,
+                    attempts: 1,
+                    category: e.category(),
+                    last_error: e.to_string(),
+                    failed_at: Some(state.clock.now()),
+                }),
+            }
+            // Until this is saved the reply is still committed on disk and
+            // in flight in the outbox, so a crash mid-send asks before
+            // sending it again rather than losing it
+            state.failed.lock().map_err(|e| e.to_string())?.save().map_err(|e| e.to_string())?;
+            save_queue_state(&state)?;
+            finish_send(&state, &chat_guid, success)?;
             results.push(SendResult {
-                chat_id,
+                chat_guid: chat_guid.clone(),
                 success,
+                duplicate: false,
                 name: conv.name().to_string(),
+                needs_confirmation: Vec::new(),
+                deferred: false,
+            });
+        } else {
+            state.committed.lock().map_err(|e| e.to_string())?.remove(&chat_guid);
+        }
+    }
+    
+    state.failed.lock().map_err(|e| e.to_string())?.save().map_err(|e| e.to_string())?;
+    save_queue_state(&state)?;
+    
+    Ok(results)
+}
+
+/// Try failed sends again, each up to `max_attempts` times with backoff.
+/// One that was in flight when the app last quit is held until its chat
+/// GUID is in `confirmed`.
+#[tauri::command(async)]
+fn retry_failed(max_attempts: u32, confirmed: Option<Vec<String>>, state: State<AppState>) -> Result<Vec<SendResult>, String> {
+    let entries = state.failed.lock().map_err(|e| e.to_string())?.take();
+    let log_full_text = state.settings.lock().map_err(|e| e.to_string())?.log_full_text;
+    let policy = BackoffPolicy::new(max_attempts.max(1));
+    let confirmed: HashSet<String> = confirmed.unwrap_or_default().into_iter().collect();
+    
+    let mut results = Vec::new();
+    let mut entries = entries.into_iter();
+    while let Some(mut entry) = entries.next() {
+        // Permission and missing-chat errors won't fix themselves
+        if !entry.category.is_retryable() {
+            results.push(SendResult {
+                chat_guid: entry.chat_guid.clone(),
+                success: false,
+                duplicate: false,
+                name: entry.name.clone(),
+                needs_confirmation: Vec::new(),
+                deferred: false,
+            });
+            state.failed.lock().map_err(|e| e.to_string())?.push(entry);
+            continue;
+        }
+        
+        // A send that "failed" may still have gone out, or been re-sent since
+        if already_sent(&state, &entry.chat_guid, &entry.text) {
+            results.push(SendResult {
+                chat_guid: entry.chat_guid.clone(),
+                success: true,
+                duplicate: true,
+                name: entry.name.clone(),
+                needs_confirmation: Vec::new(),
+                deferred: false,
+            });
+            continue;
+        }
+        
+        if !confirmed.contains(&entry.chat_guid)
+            && state.outbox.lock().map_err(|e| e.to_string())?.was_interrupted(&entry.chat_guid, &entry.text)
+        {
+            results.push(SendResult {
+                chat_guid: entry.chat_guid.clone(),
+                success: false,
+                duplicate: false,
+                name: entry.name.clone(),
+                needs_confirmation: vec![GuardReason::Interrupted],
+                deferred: false,
             });
+            state.failed.lock().map_err(|e| e.to_string())?.push(entry);
+            continue;
+        }
+        
+        // Quitting: this and the rest stay queued for next time
+        let Some(_sending) = state.shutdown.begin_send() else {
+            let mut failed = state.failed.lock().map_err(|e| e.to_string())?;
+            failed.push(entry);
+            entries.for_each(|e| failed.push(e));
+            break;
+        };
+        if let Err(e) = start_send(&state, &entry.chat_guid, &entry.name, &entry.text) {
+            let mut failed = state.failed.lock().map_err(|e| e.to_string())?;
+            failed.push(entry);
+            entries.for_each(|e| failed.push(e));
+            return Err(e);
+        }
+        let attachments = state.staging.lock().map_err(|e| e.to_string())?.paths(&entry.chat_guid);
+        let outcome = send_with_backoff(
+            &policy,
+            || send_message_with_attachments(&entry.chat_identifier, &entry.text, entry.is_group, &attachments),
+            |delay| {
+                let retry_at = state.clock.now() + chrono::Duration::from_std(delay).unwrap_or_default();
+                if let Ok(mut outbox) = state.outbox.lock() {
+                    outbox.delay(&entry.chat_guid, retry_at, None, state.clock.now());
+                }
+                // Nothing is locked while waiting, so other sends and the
+                // queue view aren't held up by a long backoff
+                state.clock.sleep(delay);
+                if let Ok(mut outbox) = state.outbox.lock() {
+                    outbox.start(&entry.chat_guid, &entry.name, &entry.text, state.clock.now());
+                }
+            },
+        );
+        entry.attempts += outcome.attempts;
+        let success = outcome.result.is_ok();
+        let chat_guid = entry.chat_guid.clone();
+        
+        let error = outcome.result.as_ref().err().map(|e| e.to_string());
+        let record = SendRecord::new(
+            entry.chat_id,
+            &entry.chat_guid,
+            &entry.chat_identifier,
+            &entry.text,
+            log_full_text,
+            error,
+            state.clock.now(),
+        );
+        if let Err(e) = state.history.append(&record) {
+            report_problem(&state, format!("Failed to record send: {}", e));
+        }
+        
+        results.push(SendResult {
+            chat_guid: entry.chat_guid.clone(),
+            success: outcome.result.is_ok(),
+            duplicate: false,
+            name: entry.name.clone(),
+            needs_confirmation: Vec::new(),
+            deferred: false,
+        });
+        
+        match outcome.result {
+            Ok(()) => {
+                let _ = mark_chat_read(&state, &entry.chat_identifier);
+                record_session_send(&state, &entry.chat_identifier);
+                clear_staged(&state, &entry.chat_guid);
+            }
+            Err(e) => {
+                entry.category = e.category();
+                entry.last_error = e.to_string();
+                entry.failed_at = Some(state.clock.now());
+                state.failed.lock().map_err(|e| e.to_string())?.push(entry);
+            }
         }
+        // The rest of the batch stays saved until it's been tried
+        state.failed.lock().map_err(|e| e.to_string())?.save_with(entries.as_slice()).map_err(|e| e.to_string())?;
+        finish_send(&state, &chat_guid, success)?;
     }
     
+    state.failed.lock().map_err(|e| e.to_string())?.save().map_err(|e| e.to_string())?;
+    
     Ok(results)
 }
 
+/// Drop a chat's staged files once they've been sent.
+fn clear_staged(state: &AppState, chat_guid: &str) {
+    let Ok(mut staging) = state.staging.lock() else { return };
+    if let Err(e) = staging.clear(chat_guid).and_then(|_| staging.save()) {
+        drop(staging);
+        report_problem(state, format!("Failed to clear staged attachments: {}", e));
+    }
+}
+
+/// Whether this text already went to this chat recently, per the send history.
+fn already_sent(state: &AppState, chat_guid: &str, text: &str) -> bool {
+    let window = chrono::Duration::seconds(DUPLICATE_WINDOW_SECS);
+    match state.history.recent_duplicate(chat_guid, text, window, state.clock.now()) {
+        Ok(found) => found.is_some(),
+        Err(e) => {
+            // Fail open: an unreadable log shouldn't block sending
+            report_problem(state, format!("Failed to check send history: {}", e));
+            false
+        }
+    }
+}
+
+/// Everything queued, held, sending, retrying, failed, or sent this session.
+#[tauri::command]
+fn get_send_queue(state: State<AppState>) -> Result<Vec<OutboxItem>, String> {
+    let committed = state.committed.lock().map_err(|e| e.to_string())?.clone();
+    let failed = state.failed.lock().map_err(|e| e.to_string())?.entries().to_vec();
+    let outbox = state.outbox.lock().map_err(|e| e.to_string())?;
+    Ok(outbox.items(&committed, &failed))
+}
+
+#[tauri::command]
+fn get_failed(state: State<AppState>) -> Result<Vec<FailedSend>, String> {
+    let failed = state.failed.lock().map_err(|e| e.to_string())?;
+    Ok(failed.entries().to_vec())
+}
+
+#[tauri::command]
+fn get_send_history(chat_guid: String, state: State<AppState>) -> Result<Vec<SendRecord>, String> {
+    state.history.for_chat(&chat_guid).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_recent_sends(state: State<AppState>) -> Result<Vec<SendRecord>, String> {
+    state.history.recent(50).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn preview_send_plan(state: State<AppState>) -> Result<Vec<SendPreview>, String> {
+    let convs = live_unread_conversations(&state)?;
+    
+    let conv_map: HashMap<&str, &Conversation> = convs.iter()
+        .map(|c| (c.guid.as_str(), c))
+        .collect();
+    
+    let committed = state.committed.lock().map_err(|e| e.to_string())?;
+    let staging = state.staging.lock().map_err(|e| e.to_string())?;
+    
+    let mut previews = Vec::new();
+    for (chat_guid, text) in committed.iter() {
+        if let Some(conv) = conv_map.get(chat_guid.as_str()) {
+            let plan = SendPlan::new(&conv.chat_identifier, text, conv.is_group())
+                .with_attachments(&staging.paths(chat_guid));
+            previews.push(SendPreview {
+                chat_guid: chat_guid.clone(),
+                name: conv.name().to_string(),
+                service: conv.service_name.clone().unwrap_or_else(|| "any".to_string()),
+                full_chat_id: plan.full_chat_id,
+                text: plan.text,
+                script: plan.script,
+            });
+        }
+    }
+    
+    Ok(previews)
+}
+
+#[derive(serde::Serialize)]
+struct SendPreview {
+    chat_guid: String,
+    name: String,
+    service: String,
+    full_chat_id: String,
+    text: String,
+    script: String,
+}
+
 #[tauri::command]
-fn mark_read(chat_identifier: String) -> Result<usize, String> {
-    mark_as_read(&chat_identifier).map_err(|e| e.to_string())
+fn mark_read(chat_identifier: String, state: State<AppState>) -> Result<usize, String> {
+    mark_chat_read(&state, &chat_identifier)
+}
+
+/// Mark a chat read using the configured strategy.
+fn mark_chat_read(state: &AppState, chat_identifier: &str) -> Result<usize, String> {
+    let marked = mark_chat_read_with_strategy(state, chat_identifier)?;
+    if let Some(session) = state.session.lock().map_err(|e| e.to_string())?.as_mut() {
+        session.record_handled(chat_identifier);
+    }
+    Ok(marked)
+}
+
+fn mark_chat_read_with_strategy(state: &AppState, chat_identifier: &str) -> Result<usize, String> {
+    let strategy = state.settings.lock().map_err(|e| e.to_string())?.read_strategy;
+    match strategy {
+        ReadStrategy::Database => mark_as_read(chat_identifier).map_err(|e| e.to_string()),
+        ReadStrategy::LocalOnly => {
+            let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+            let latest = db.latest_message_rowid(chat_identifier).map_err(|e| e.to_string())?;
+            let mut overlay = state.read_overlay.lock().map_err(|e| e.to_string())?;
+            if let Some(rowid) = latest {
+                overlay.mark(chat_identifier, rowid);
+                overlay.save().map_err(|e| e.to_string())?;
+            }
+            // Nothing in chat.db changes
+            Ok(0)
+        }
+        ReadStrategy::MessagesApp => {
+            let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+            let is_group = db.is_group_chat(chat_identifier).map_err(|e| e.to_string())?;
+            mark_read_via_messages(&messages_url(chat_identifier, is_group))
+                .map_err(|e| e.to_string())?;
+            Ok(0)
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ReactionResult {
+    message_guid: String,
+    /// Why the tapback wasn't sent, if it wasn't
+    error: Option<String>,
+}
+
+/// Give each message the same tapback, e.g. 👍 to acknowledge a batch of
+/// chats without writing replies. Keeps going past failures.
+#[tauri::command(async)]
+fn send_reactions(message_guids: Vec<String>, reaction: Tapback) -> Result<Vec<ReactionResult>, String> {
+    // The live chat.db, like sends: a snapshot could miss a newer message
+    // that ⌘T would react to instead
+    let db = Database::open(&Database::default_path()).map_err(|e| e.to_string())?;
+    Ok(message_guids
+        .into_iter()
+        .map(|message_guid| {
+            let error = send_reaction(&db, &message_guid, reaction).err().map(|e| e.to_string());
+            ReactionResult { message_guid, error }
+        })
+        .collect())
 }
 
 #[derive(serde::Serialize)]
 struct SendResult {
-    chat_id: i64,
+    chat_guid: String,
     success: bool,
+    /// Skipped because the same text was just sent to this chat
+    duplicate: bool,
     name: String,
+    /// Held back by send guards; still committed until confirmed
+    needs_confirmation: Vec<GuardReason>,
+    /// Held back until the current Focus ends; still committed
+    deferred: bool,
 }
 
 #[tauri::command]
@@ -180,26 +1651,92 @@ fn get_state(state: State<AppState>) -> Result<StateSnapshot, String> {
     let drafts = state.drafts.lock().map_err(|e| e.to_string())?;
     let committed = state.committed.lock().map_err(|e| e.to_string())?;
     let later = state.later.lock().map_err(|e| e.to_string())?;
+    let restored = state.restored.lock().map_err(|e| e.to_string())?;
     let ignored = state.ignored.lock().map_err(|e| e.to_string())?;
+    let muted = state.muted.lock().map_err(|e| e.to_string())?;
+    let staging = state.staging.lock().map_err(|e| e.to_string())?;
+    let snoozed = state.snoozed.lock().map_err(|e| e.to_string())?;
+    let composed = state.composed.lock().map_err(|e| e.to_string())?;
+    let session = state.session.lock().map_err(|e| e.to_string())?;
     
     Ok(StateSnapshot {
         drafts: drafts.clone(),
         committed: committed.clone(),
         later: later.iter().cloned().collect(),
-        ignored: ignored.iter().cloned().collect(),
+        restored: restored.iter()
+            .filter(|(guid, text)| committed.get(*guid) == Some(*text))
+            .map(|(guid, _)| guid.clone())
+            .collect(),
+        ignored: ignored.rules().cloned().collect(),
+        muted: muted.all().cloned().collect(),
+        attachments: staging.all().clone(),
+        snoozed: snoozed.all().iter().map(|(guid, s)| (guid.clone(), s.until)).collect(),
+        composed: composed.clone(),
+        session: session.clone(),
     })
 }
 
 #[derive(serde::Serialize)]
 struct StateSnapshot {
-    drafts: HashMap<i64, String>,
-    committed: HashMap<i64, String>,
-    later: Vec<i64>,
-    ignored: Vec<String>,
+    /// Reply state by chat GUID
+    drafts: HashMap<String, String>,
+    committed: HashMap<String, String>,
+    later: Vec<String>,
+    /// Committed replies restored from disk, awaiting a fresh confirmation
+    restored: Vec<String>,
+    ignored: Vec<IgnoreRule>,
+    /// Chat identifiers of muted groups
+    muted: Vec<String>,
+    /// Files staged to go out with each chat's reply
+    attachments: HashMap<String, Vec<StagedFile>>,
+    /// When each snoozed chat comes back
+    snoozed: HashMap<String, DateTime<Utc>>,
+    /// Batch messages waiting for `send_composed`
+    composed: Vec<ComposedMessage>,
+    /// The triage session in progress
+    session: Option<TriageSession>,
+}
+
+#[tauri::command]
+fn get_settings(state: State<AppState>) -> Result<Settings, String> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?;
+    Ok(settings.clone())
+}
+
+#[tauri::command]
+fn update_settings(settings: Settings, state: State<AppState>) -> Result<(), String> {
+    state.contacts.lock().map_err(|e| e.to_string())?.set_email_matching(settings.email_matching);
+    let mut current = state.settings.lock().map_err(|e| e.to_string())?;
+    settings.save(&state.paths.settings()).map_err(|e| e.to_string())?;
+    *current = settings;
+    Ok(())
+}
+
+#[tauri::command(async)]
+fn get_onboarding_status(state: State<AppState>) -> Result<OnboardingStatus, String> {
+    let contacts_loaded = state.contacts.lock().map_err(|e| e.to_string())?.len();
+    Ok(onboarding_status(&Database::default_path(), contacts_loaded))
+}
+
+/// The active macOS Focus and whether sends are held.
+#[tauri::command]
+fn get_focus_state(state: State<AppState>) -> Result<FocusState, String> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?;
+    Ok(FocusState::current(&settings.focus))
+}
+
+#[tauri::command(async)]
+fn get_power_state(state: State<AppState>) -> Result<PowerState, String> {
+    let saving = state.settings.lock().map_err(|e| e.to_string())?.power_saving;
+    Ok(state.power.state(saving, &*state.clock))
 }
 
 #[tauri::command]
 fn open_full_disk_access() -> Result<(), String> {
+    // The sample library needs no permissions
+    if cfg!(feature = "dev-sample") {
+        return Ok(());
+    }
     Command::new("open")
         .arg("x-apple.systempreferences:com.apple.preference.security?Privacy_AllFiles")
         .spawn()
@@ -207,10 +1744,18 @@ fn open_full_disk_access() -> Result<(), String> {
     Ok(())
 }
 
+/// Open a web link in the browser. Links come from message text, so
+/// anything but http(s) is refused: `open` would launch a file: URL.
 #[tauri::command]
 fn open_url(url: String) -> Result<(), String> {
+    let url = bare_url(&url).ok_or_else(|| format!("Not a web link: {}", url))?;
+    // No `open` off macOS
+    if cfg!(feature = "dev-sample") {
+        return Ok(());
+    }
     Command::new("open")
-        .arg(&url)
+        .arg("--")
+        .arg(url)
         .spawn()
         .map_err(|e| e.to_string())?;
     Ok(())
@@ -221,25 +1766,305 @@ fn get_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
 }
 
+/// Tell the user about a background failure. It waits in `problems` until
+/// the window takes it, so ones from before the window loaded aren't lost.
+fn report_problem(state: &AppState, problem: String) {
+    if let Ok(mut problems) = state.problems.lock() {
+        problems.push(problem.clone());
+    }
+    state.events.publish(AppEvent::Problem(problem));
+}
+
+/// Background failures not shown yet, oldest first.
+#[tauri::command]
+fn take_problems(state: State<AppState>) -> Result<Vec<String>, String> {
+    Ok(std::mem::take(&mut *state.problems.lock().map_err(|e| e.to_string())?))
+}
+
+#[tauri::command(async)]
+fn load_contacts(handle: tauri::AppHandle) -> Result<usize, String> {
+    reload_contacts(&handle)
+}
+
+/// Read contacts into the resolver from the configured backend, emitting
+/// `contacts-progress` as it goes and `contacts-updated` when done.
+fn reload_contacts(handle: &tauri::AppHandle) -> Result<usize, String> {
+    use tauri::{Emitter, Manager};
+    
+    let state = handle.state::<AppState>();
+    let backend = state.settings.lock().map_err(|e| e.to_string())?.contacts_backend;
+    let loaded = load_contacts_from(backend, &addressbook_sources_dir()?, &state.contacts, |progress| {
+        let _ = handle.emit("contacts-progress", progress);
+    })?;
+    if let Some(e) = loaded.fallback {
+        report_problem(&state, format!("Contacts framework unavailable, read the AddressBook instead: {}", e));
+    }
+    state.events.publish(AppEvent::ContactsChanged(loaded.count));
+    Ok(loaded.count)
+}
+
+/// Load contacts in the background at startup so the first conversations
+/// show up right away, named as soon as each source is read.
+fn spawn_contacts_load(handle: tauri::AppHandle) {
+    use tauri::Manager;
+    
+    std::thread::spawn(move || {
+        if let Err(e) = reload_contacts(&handle) {
+            report_problem(&handle.state::<AppState>(), format!("Contacts load failed: {}", e));
+        }
+    });
+}
+
+/// Load the people file, saving `path` in settings as the one to use from
+/// now on if given and it loads. Returns how many names it overrides.
+#[tauri::command]
+fn load_people_file(path: Option<String>, state: State<AppState>) -> Result<usize, String> {
+    let chosen = path.map(std::path::PathBuf::from);
+    let path = match &chosen {
+        Some(path) => path.clone(),
+        None => state.settings.lock().map_err(|e| e.to_string())?
+            .people_file.clone()
+            .unwrap_or_else(|| state.paths.people()),
+    };
+    let count = state.contacts.lock().map_err(|e| e.to_string())?.load_overrides(&path)?;
+    if chosen.is_some() {
+        let mut settings = state.settings.lock().map_err(|e| e.to_string())?;
+        settings.people_file = chosen;
+        settings.save(&state.paths.settings()).map_err(|e| e.to_string())?;
+    }
+    state.events.publish(AppEvent::ContactsChanged(count));
+    Ok(count)
+}
+
 #[tauri::command]
-fn load_contacts(state: State<AppState>) -> Result<usize, String> {
+fn import_google_contacts(path: String, state: State<AppState>) -> Result<usize, String> {
     let mut contacts = state.contacts.lock().map_err(|e| e.to_string())?;
-    contacts.load_macos_contacts()
+    contacts.load_google_csv(std::path::Path::new(&path))
+}
+
+#[tauri::command]
+fn set_carddav_password(password: String, state: State<AppState>) -> Result<(), String> {
+    let settings = state.settings.lock().map_err(|e| e.to_string())?;
+    let config = settings.carddav.as_ref().ok_or("CardDAV is not configured")?;
+    
+    #[cfg(feature = "carddav")]
+    return aeromessage::carddav::store_password(&config.username, &password)
+        .map_err(|e| e.to_string());
+    
+    #[cfg(not(feature = "carddav"))]
+    {
+        let _ = (config, password);
+        Err("CardDAV support is not built in".to_string())
+    }
+}
+
+#[tauri::command(async)]
+fn sync_carddav_now(state: State<AppState>) -> Result<usize, String> {
+    let config = state.settings.lock().map_err(|e| e.to_string())?
+        .carddav
+        .clone()
+        .ok_or("CardDAV is not configured")?;
+    
+    #[cfg(feature = "carddav")]
+    {
+        // Fetch before locking so a slow server doesn't block name resolution
+        let contacts = aeromessage::carddav::fetch_contacts(&config).map_err(|e| e.to_string())?;
+        let mut resolver = state.contacts.lock().map_err(|e| e.to_string())?;
+        Ok(aeromessage::carddav::add_contacts(&mut resolver, &contacts))
+    }
+    
+    #[cfg(not(feature = "carddav"))]
+    {
+        let _ = config;
+        Err("CardDAV support is not built in".to_string())
+    }
+}
+
+/// Reload contacts whenever the AddressBook changes on disk.
+fn spawn_contacts_watcher(handle: tauri::AppHandle) {
+    use tauri::Manager;
+    
+    // Without a home directory there's no address book to watch
+    let Ok(sources_dir) = addressbook_sources_dir() else {
+        return;
+    };
+    std::thread::spawn(move || {
+        // Sources/<account>/AddressBook-v22.abcddb(-wal)
+        let mut watcher = ChangeWatcher::new(vec![sources_dir], 2);
+        let mut throttle = Throttle::new(Duration::from_secs(10), Duration::from_secs(5 * 60));
+        loop {
+            // Poll a quarter as often on battery
+            let state = handle.state::<AppState>();
+            let saving = state.settings.lock().map(|s| s.power_saving).unwrap_or_default();
+            throttle.set_slowdown(if state.power.state(saving, &*state.clock).low_power { 4 } else { 1 });
+            
+            throttle.wait(&*state.clock);
+            if state.shutdown.is_stopping() {
+                return;
+            }
+            let changed = watcher.poll();
+            throttle.record(changed);
+            if !changed {
+                continue;
+            }
+            
+            if let Err(e) = reload_contacts(&handle) {
+                report_problem(&state, format!("Contacts reload failed: {}", e));
+            }
+        }
+    });
+}
+
+/// Watch chat.db and its WAL, publishing the chats that gained messages
+/// or had them read, here or on another device.
+fn spawn_database_watcher(handle: tauri::AppHandle) {
+    use tauri::Manager;
+    
+    std::thread::spawn(move || {
+        let path = Database::default_path();
+        let mut wal = path.clone().into_os_string();
+        wal.push("-wal");
+        let mut watcher = ChangeWatcher::new(vec![path.clone(), wal.into()], 0);
+        let mut throttle = Throttle::new(Duration::from_secs(2), Duration::from_secs(60));
+        let mut seen: Option<MessageChanges> = None;
+        let mut first = true;
+        let mut snapshot_behind = false;
+        loop {
+            let state = handle.state::<AppState>();
+            let saving = state.settings.lock().map(|s| s.power_saving).unwrap_or_default();
+            throttle.set_slowdown(if state.power.state(saving, &*state.clock).low_power { 4 } else { 1 });
+            
+            // The first pass only records where chat.db is up to
+            if !std::mem::take(&mut first) {
+                throttle.wait(&*state.clock);
+                if state.shutdown.is_stopping() {
+                    return;
+                }
+                let changed = watcher.poll();
+                throttle.record(changed);
+                if !changed && !snapshot_behind {
+                    continue;
+                }
+            }
+            
+            // With snapshot reads on, new messages show once they're copied;
+            // a copy skipped as too soon is tried again next pass
+            let now = state.clock.now();
+            snapshot_behind = matches!(retake_snapshot(&state), Ok(Some(taken)) if taken < now);
+            
+            // Locked mid-sync; the next write brings us back here
+            let Ok(read_path) = chat_db_path(&state) else {
+                continue;
+            };
+            let Ok(changes) = Database::open(&read_path).and_then(|db| db.changes_since(seen.as_ref())) else {
+                continue;
+            };
+            if !changes.chat_ids.is_empty() {
+                state.events.publish(AppEvent::MessagesChanged(changes.clone()));
+            }
+            seen = Some(changes);
+        }
+    });
+}
+
+/// Pass events on to the frontend: `conversations-updated` with the chats
+/// that changed, so it can refresh just those, `contacts-updated`, and
+/// `problem` when there are problems to take.
+/// An empty `chat_ids` means events were missed and everything may have.
+fn spawn_frontend_bridge(handle: tauri::AppHandle) {
+    use tauri::{Emitter, Manager};
+    
+    let events = handle.state::<AppState>().events.subscribe(DEFAULT_EVENT_CAPACITY);
+    std::thread::spawn(move || {
+        while let Some(event) = events.recv() {
+            if events.take_missed() > 0 {
+                let _ = handle.emit("conversations-updated", MessageChanges { chat_ids: Vec::new(), newest_rowid: 0, newest_read: 0 });
+            }
+            let _ = match event {
+                AppEvent::MessagesChanged(changes) => handle.emit("conversations-updated", &changes),
+                AppEvent::ContactsChanged(count) => handle.emit("contacts-updated", count),
+                AppEvent::Problem(problem) => handle.emit("problem", problem),
+            };
+        }
+    });
+}
+
+/// Re-sync CardDAV contacts in the background on the configured interval.
+#[cfg(feature = "carddav")]
+fn spawn_carddav_sync(handle: tauri::AppHandle) {
+    use tauri::Manager;
+    
+    std::thread::spawn(move || loop {
+        let state = handle.state::<AppState>();
+        if state.shutdown.is_stopping() {
+            return;
+        }
+        let config = state.settings.lock().ok().and_then(|s| s.carddav.clone());
+        let saving = state.settings.lock().map(|s| s.power_saving).unwrap_or_default();
+        
+        let interval = match config {
+            Some(config) => {
+                match aeromessage::carddav::fetch_contacts(&config) {
+                    Ok(contacts) => {
+                        if let Ok(mut resolver) = state.contacts.lock() {
+                            aeromessage::carddav::add_contacts(&mut resolver, &contacts);
+                        }
+                    }
+                    Err(e) => report_problem(&state, format!("CardDAV sync failed: {}", e)),
+                }
+                config.sync_interval_minutes.max(5)
+            }
+            // Check again later in case it gets configured
+            None => 5,
+        };
+        
+        // Sync a quarter as often on battery
+        let slowdown = if state.power.state(saving, &*state.clock).low_power { 4 } else { 1 };
+        if state.shutdown.sleep(Duration::from_secs(interval * 60 * slowdown)) {
+            return;
+        }
+    });
 }
 
+/// Contact photo for a conversation whose preview is an avatar too big to
+/// have come inline, as raw bytes rather than a JSON array of numbers.
+#[tauri::command(async)]
+fn get_contact_avatar(handle: String, state: State<AppState>) -> Result<tauri::ipc::Response, String> {
+    // Read the photo file without holding up contact loading
+    let avatar = state.contacts.lock().map_err(|e| e.to_string())?.avatar_source(&handle);
+    let bytes = avatar.and_then(|a| a.read()).ok_or_else(|| "No photo for this contact".to_string())?;
+    Ok(tauri::ipc::Response::new(bytes))
+}
+
+/// The attachments folder of the library `source_id`, or of the user's own
+/// when None, with the cache folder its photo conversions go in.
+fn library_attachments(state: &AppState, source_id: Option<&str>) -> Result<(std::path::PathBuf, std::path::PathBuf), String> {
+    let Some(id) = source_id.filter(|id| *id != LOCAL_SOURCE) else {
+        return Ok((default_attachments_dir(), state.paths.cache_dir.clone()));
+    };
+    let settings = state.settings.lock().map_err(|e| e.to_string())?;
+    let library = settings.libraries.iter().find(|l| l.id == id).ok_or("Unknown library")?;
+    // The ID names the library's cache folder
+    if !matches!(std::path::Path::new(id).components().collect::<Vec<_>>()[..], [std::path::Component::Normal(_)]) {
+        return Err("Invalid library ID".to_string());
+    }
+    Ok((Database::attachments_dir_for(&library.path, id), state.paths.cache_dir.join("libraries").join(id)))
+}
+
+/// An attachment's bytes, by its path under the attachments folder of the
+/// library it came from; HEIC photos come back as JPEG where possible.
 #[tauri::command]
-fn get_attachment(path: String) -> Result<Vec<u8>, String> {
-    let home = dirs::home_dir().ok_or("Cannot find home directory")?;
-    let attachments_dir = home.join("Library/Messages/Attachments");
+fn get_attachment(path: String, source_id: Option<String>, state: State<AppState>) -> Result<Vec<u8>, String> {
+    let (attachments_dir, cache_dir) = library_attachments(&state, source_id.as_deref())?;
     let full_path = attachments_dir.join(&path);
     
     // Resolve to prevent path traversal
     let canonical = full_path.canonicalize().map_err(|e| e.to_string())?;
     let canonical_base = attachments_dir.canonicalize().map_err(|e| e.to_string())?;
     
-    if !canonical.starts_with(&canonical_base) {
+    let Ok(relative) = canonical.strip_prefix(&canonical_base) else {
         return Err("Access denied".to_string());
-    }
+    };
     
     // Read the file
     let data = std::fs::read(&canonical).map_err(|e| e.to_string())?;
@@ -251,11 +2076,19 @@ fn get_attachment(path: String) -> Result<Vec<u8>, String> {
         .to_lowercase();
     
     if extension == "heic" || extension == "heif" {
-        let cache_dir = home.join("Library/Caches/Aeromessage");
-        std::fs::create_dir_all(&cache_dir).map_err(|e| e.to_string())?;
+        let cached_path = conversion_path(&cache_dir, relative);
+        if let Some(parent) = cached_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
         
-        let cache_key = path.replace('/', "_").replace('.', "_") + ".jpg";
-        let cached_path = cache_dir.join(&cache_key);
+        // Converting is the costly part of showing a photo, so on battery an
+        // unconverted HEIC is sent as is. WebKit can draw it, just more slowly
+        // than a JPEG, and it's converted once the Mac is back on power.
+        let saving = state.settings.lock().map_err(|e| e.to_string())?.power_saving;
+        // No sips off macOS either
+        if !cached_path.exists() && (state.power.state(saving, &*state.clock).low_power || cfg!(feature = "dev-sample")) {
+            return Ok(data);
+        }
         
         if !cached_path.exists() {
             Command::new("sips")
@@ -275,25 +2108,200 @@ fn get_attachment(path: String) -> Result<Vec<u8>, String> {
     Ok(data)
 }
 
+#[tauri::command]
+fn run_diagnostics(state: State<AppState>) -> Vec<DiagnosticCheck> {
+    aeromessage::run_diagnostics(&DiagnosticPaths {
+        cache_dir: state.paths.cache_dir.clone(),
+        attachments_dir: default_attachments_dir(),
+        state_files: state.paths.state_files(),
+    })
+}
+
+/// Where the app keeps its files.
+#[tauri::command]
+fn get_app_paths(state: State<AppState>) -> AppPaths {
+    state.paths.clone()
+}
+
+/// Open the data folder in Finder.
+#[tauri::command]
+fn reveal_data_folder(state: State<AppState>) -> Result<(), String> {
+    std::fs::create_dir_all(&state.paths.data_dir).map_err(|e| e.to_string())?;
+    // No Finder off macOS
+    if cfg!(feature = "dev-sample") {
+        return Ok(());
+    }
+    Command::new("open")
+        .arg(&state.paths.data_dir)
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Check chat.db is intact and has the schema we read. quick_check scans the
+/// whole file, so this runs off the main thread.
+#[tauri::command(async)]
+fn verify_database() -> Vec<DiagnosticCheck> {
+    aeromessage::verify_database(&Database::default_path())
+}
+
+/// Write out every state file, e.g. on the way out. The error names each
+/// one that failed.
+fn flush_state(state: &AppState) -> Result<(), String> {
+    let saves: [(&str, std::io::Result<()>); 8] = [
+        ("queue", save_queue_state(state).map_err(std::io::Error::other)),
+        ("outbox", state.outbox.lock().map_or(Ok(()), |o| o.save())),
+        ("failed sends", state.failed.lock().map_or(Ok(()), |f| f.save())),
+        ("read overlay", state.read_overlay.lock().map_or(Ok(()), |o| o.save())),
+        ("snoozes", state.snoozed.lock().map_or(Ok(()), |s| s.save())),
+        ("ignore list", state.ignored.lock().map_or(Ok(()), |i| i.save())),
+        ("muted groups", state.muted.lock().map_or(Ok(()), |m| m.save())),
+        ("staged attachments", state.staging.lock().map_or(Ok(()), |s| s.save())),
+    ];
+    let failed: Vec<String> = saves
+        .into_iter()
+        .filter_map(|(what, result)| result.err().map(|e| format!("{}: {}", what, e)))
+        .collect();
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Failed to save {}", failed.join("; ")))
+    }
+}
+
+/// Before quitting, save everything; if some of it won't save, stay open
+/// once so the user hears about it. Quitting again goes ahead regardless.
+fn confirm_exit(handle: &tauri::AppHandle, api: &tauri::ExitRequestApi) {
+    use tauri::Manager;
+    
+    let state = handle.state::<AppState>();
+    if let Err(e) = flush_state(&state) {
+        if !state.exit_warned.swap(true, Ordering::SeqCst) {
+            api.prevent_exit();
+            report_problem(&state, format!("{}. Quit again to quit anyway", e));
+        }
+    }
+}
+
+/// Quit cleanly: stop background threads and new sends, let sends in
+/// flight finish and record their outcome, then save everything.
+fn shut_down(handle: &tauri::AppHandle) {
+    use tauri::Manager;
+    
+    let state = handle.state::<AppState>();
+    // A send still out after the grace period stays committed, to be
+    // confirmed again next time. With the window gone there's no one to
+    // tell about a failed save; confirm_exit already did
+    state.shutdown.stop(SHUTDOWN_GRACE);
+    let _ = flush_state(&state);
+}
+
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .manage(AppState::default())
+        .setup(|app| {
+            use tauri::Manager;
+            
+            // The search index used to sit in the cache folder; don't leave
+            // that copy of every message behind
+            let _ = std::fs::remove_file(app.state::<AppState>().paths.cache_dir.join("search.db"));
+            
+            // Subscribers first, so they see the watchers' first events
+            spawn_frontend_bridge(app.handle().clone());
+            spawn_contacts_load(app.handle().clone());
+            spawn_contacts_watcher(app.handle().clone());
+            spawn_database_watcher(app.handle().clone());
+            #[cfg(feature = "carddav")]
+            spawn_carddav_sync(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             get_conversations,
+            reload_conversation,
+            get_database_status,
+            refresh_snapshot,
+            get_stale_drafts,
+            get_library_conversations,
+            get_media,
+            get_messages,
+            search_messages,
+            get_summary_cards,
+            get_message_days,
+            add_bookmark,
+            remove_bookmark,
+            list_bookmarks,
+            jump_to_bookmark,
+            start_triage_session,
+            end_session,
+            get_session_reports,
+            get_active_since_my_last_message,
+            copy_latest_otp,
+            mark_expired_codes_read,
+            get_participant_history,
+            get_chats_for_contact,
+            autocomplete_recipients,
+            export_unread_snapshot,
+            export_messages_jsonl,
+            export_messages_csv,
+            import_drafts,
             save_draft,
+            attach_file_to_draft,
+            stage_clipboard_image,
+            stage_image_bytes,
+            remove_draft_attachment,
+            analyze_draft,
             commit_message,
             toggle_later,
+            get_templates,
+            save_template,
+            delete_template,
+            apply_template,
+            compose_batch,
+            send_composed,
             toggle_ignore,
+            set_muted,
+            set_ignored,
+            get_ignored_chats,
+            suggest_ignore_rules,
+            handoff,
+            snooze_chat,
             send_all,
+            retry_failed,
+            get_failed,
+            get_send_queue,
+            preview_send_plan,
+            get_send_history,
+            get_recent_sends,
             mark_read,
+            send_reactions,
             get_state,
+            get_settings,
+            update_settings,
             get_version,
+            take_problems,
+            get_onboarding_status,
+            get_power_state,
+            get_focus_state,
             open_full_disk_access,
             open_url,
             load_contacts,
+            load_people_file,
+            import_google_contacts,
+            set_carddav_password,
+            sync_carddav_now,
             get_attachment,
+            get_contact_avatar,
+            run_diagnostics,
+            get_app_paths,
+            reveal_data_folder,
+            verify_database,
         ])
-        .run(tauri::generate_context!())
-        .expect("error running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error building tauri application")
+        .run(|handle, event| match event {
+            tauri::RunEvent::ExitRequested { api, .. } => confirm_exit(handle, &api),
+            tauri::RunEvent::Exit => shut_down(handle),
+            _ => {}
+        });
 }
@@ -1,8 +1,14 @@
 //! Data models for iMessage conversations.
 
+use std::path::{Component, Path};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::contacts::{format_display, same_handle, ContactResolver};
+use crate::preview::PreviewImageRef;
+use crate::settings::SortOrder;
+
 /// Reaction emoji mappings by associated_message_type.
 pub const REACTION_EMOJI: &[(i32, &str)] = &[
     (2000, "❤️"),  // Loved
@@ -22,32 +28,225 @@ pub fn reaction_emoji(code: i32) -> Option<&'static str> {
         .map(|(_, e)| *e)
 }
 
+/// Reaction names per language, in REACTION_EMOJI order.
+const REACTION_LABELS: &[(&str, [&str; 7])] = &[
+    ("en", ["Loved", "Liked", "Disliked", "Laughed at", "Emphasized", "Questioned", "Heart hands"]),
+    ("es", ["Le encantó", "Le gustó", "No le gustó", "Le hizo gracia", "Destacó", "Preguntó", "Manos en corazón"]),
+    ("fr", ["A adoré", "A aimé", "N'a pas aimé", "A ri de", "A souligné", "A questionné", "Mains en cœur"]),
+    ("de", ["Geliebt", "Gefällt", "Gefällt nicht", "Gelacht über", "Hervorgehoben", "Infrage gestellt", "Herzhände"]),
+];
+
+/// Name of a reaction emoji in a language ("en", "es", ...), falling back
+/// to English for languages without a table.
+pub fn reaction_label(emoji: &str, lang: &str) -> Option<&'static str> {
+    let index = REACTION_EMOJI.iter().position(|(_, e)| *e == emoji)?;
+    let labels = REACTION_LABELS
+        .iter()
+        .find(|(l, _)| *l == lang)
+        .unwrap_or(&REACTION_LABELS[0]);
+    Some(labels.1[index])
+}
+
+/// URL that opens a chat in Messages.app.
+pub fn messages_url(chat_identifier: &str, is_group: bool) -> String {
+    if is_group {
+        format!("imessage://?groupID={}", chat_identifier)
+    } else {
+        format!("imessage://{}", chat_identifier)
+    }
+}
+
+/// How long someone has been waiting on a reply, for triage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgeBucket {
+    Today,
+    ThisWeek,
+    OverAWeek,
+    /// Unread for more than a month
+    Ancient,
+}
+
+impl AgeBucket {
+    /// Bucket for an unread message received at `since`.
+    pub fn since(since: DateTime<Utc>, now: DateTime<Utc>) -> Self {
+        let days = (now - since).num_days();
+        if days < 1 {
+            AgeBucket::Today
+        } else if days < 7 {
+            AgeBucket::ThisWeek
+        } else if days < 30 {
+            AgeBucket::OverAWeek
+        } else {
+            AgeBucket::Ancient
+        }
+    }
+}
+
+/// Folders chat.db has stored attachment paths under, relative to a home
+/// directory. `Library/SMS` rows come from libraries restored from a phone.
+const ATTACHMENT_ROOTS: [&str; 2] = ["Library/Messages/Attachments/", "Library/SMS/Attachments/"];
+
 /// A message attachment (image, file, etc).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attachment {
     pub filename: String,
     pub mime_type: String,
     pub transfer_name: String,
+    /// Path under the attachments folder of the library the message is
+    /// from, if it's in there
+    #[serde(default)]
+    pub relative_path: Option<String>,
+    /// Length of an audio message, when its file could be read
+    #[serde(default)]
+    pub duration_secs: Option<u32>,
+    /// What Messages transcribed an audio message as
+    #[serde(default)]
+    pub transcription: Option<String>,
+    /// [`Attachment::audio_label`], for the frontend; set by
+    /// [`Attachment::update_label`] once the length and transcription are in
+    #[serde(default)]
+    pub label: Option<String>,
 }
 
 impl Attachment {
+    pub fn new(filename: String, mime_type: String, transfer_name: String) -> Self {
+        let relative_path = attachment_relative_path(&filename);
+        Self { filename, mime_type, transfer_name, relative_path, duration_secs: None, transcription: None, label: None }
+    }
+
     /// Check if this attachment is an image.
     pub fn is_image(&self) -> bool {
         self.mime_type.starts_with("image/")
     }
 
+    /// Check if this attachment is audio, such as a voice message. Older
+    /// rows can leave the MIME type empty for `.caf` recordings.
+    pub fn is_audio(&self) -> bool {
+        self.mime_type.starts_with("audio/") || self.filename.to_lowercase().ends_with(".caf")
+    }
+
+    /// "Audio message (0:34): transcript", with the parts that are known.
+    /// None for anything but audio.
+    pub fn audio_label(&self) -> Option<String> {
+        if !self.is_audio() {
+            return None;
+        }
+        let mut label = "Audio message".to_string();
+        if let Some(secs) = self.duration_secs {
+            label.push_str(&format!(" ({}:{:02})", secs / 60, secs % 60));
+        }
+        if let Some(transcription) = self.transcription.as_deref().filter(|t| !t.trim().is_empty()) {
+            label.push_str(&format!(": {}", transcription.trim()));
+        }
+        Some(label)
+    }
+
+    /// Refresh `label` after the length or transcription changed.
+    pub fn update_label(&mut self) {
+        self.label = self.audio_label();
+    }
+
     /// Get the URL path for serving this attachment.
-    /// Returns None if filename doesn't have expected prefix.
+    /// Returns None if it isn't in the attachments folder.
     pub fn url_path(&self) -> Option<String> {
-        const PREFIX: &str = "~/Library/Messages/Attachments/";
-        if self.filename.starts_with(PREFIX) {
-            Some(format!("/attachment/{}", &self.filename[PREFIX.len()..]))
-        } else {
-            None
+        attachment_relative_path(&self.filename).map(|p| format!("/attachment/{}", p))
+    }
+}
+
+/// Where a chat.db attachment filename points inside the attachments folder.
+///
+/// Rows use `~/Library/Messages/Attachments/...`, absolute paths such as
+/// `/Users/jane/Library/Messages/Attachments/...`, or the same layout under
+/// `Library/SMS/Attachments`, which maps onto the Messages folder. Anything
+/// else, such as a temporary file, or a path that climbs out with `..`, is None.
+/// The home in a row is that library owner's, so the result is relative to
+/// the Attachments folder next to the chat.db it came from, not necessarily
+/// the current user's.
+pub fn attachment_relative_path(filename: &str) -> Option<String> {
+    let (prefix, rest) = ATTACHMENT_ROOTS
+        .iter()
+        .find_map(|root| filename.find(root).map(|i| (&filename[..i], &filename[i + root.len()..])))?;
+    if prefix != "~/" && !(prefix.starts_with('/') && prefix.ends_with('/')) {
+        return None;
+    }
+    let normal = Path::new(rest).components().all(|c| matches!(c, Component::Normal(_)));
+    (normal && !rest.is_empty()).then(|| rest.trim_end_matches('/').to_string())
+}
+
+/// An attachment with the message it arrived in, for media grids.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaItem {
+    pub attachment: Attachment,
+    pub message_rowid: i64,
+    pub date: DateTime<Utc>,
+    pub is_from_me: bool,
+    pub sender: Option<String>,
+}
+
+/// What happened in a group membership event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParticipantChange {
+    Added,
+    Removed,
+    Left,
+    Renamed { name: Option<String> },
+}
+
+/// A join, leave, or rename in a group chat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParticipantEvent {
+    pub date: DateTime<Utc>,
+    pub change: ParticipantChange,
+    /// Who did it; None when it was me
+    pub actor: Option<String>,
+    /// Who was added or removed
+    pub target: Option<String>,
+}
+
+impl ParticipantEvent {
+    /// What happened as a sentence, e.g. "Alice added Bob", with handles
+    /// named by `resolver` where it knows them.
+    pub fn describe(&self, resolver: Option<&ContactResolver>) -> String {
+        let name = |handle: Option<&str>, fallback: &str| match handle {
+            Some(h) => resolver.and_then(|r| r.resolve(h)).map(str::to_string).unwrap_or_else(|| format_display(h)),
+            None => fallback.to_string(),
+        };
+        let actor = name(self.actor.as_deref(), "You");
+        let target = name(self.target.as_deref(), "someone");
+        match &self.change {
+            ParticipantChange::Added => format!("{} added {}", actor, target),
+            ParticipantChange::Removed => format!("{} removed {}", actor, target),
+            ParticipantChange::Left => format!("{} left the conversation", actor),
+            ParticipantChange::Renamed { name: Some(title) } => format!("{} named the conversation “{}”", actor, title),
+            ParticipantChange::Renamed { name: None } => format!("{} removed the conversation name", actor),
         }
     }
 }
 
+/// A handle seen in chat.db and when it was last used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandleActivity {
+    pub handle: String,
+    pub service: Option<String>,
+    /// Newest message to or from the handle
+    pub last_active: Option<DateTime<Utc>>,
+}
+
+/// A chat someone takes part in, for the person view.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactChat {
+    pub chat_id: i64,
+    pub guid: String,
+    pub chat_identifier: String,
+    pub display_name: Option<String>,
+    pub is_group: bool,
+    pub participants: Vec<String>,
+    /// Their newest message in this chat, if they've sent any
+    pub last_activity: Option<DateTime<Utc>>,
+}
+
 /// A reaction on a message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reaction {
@@ -56,6 +255,41 @@ pub struct Reaction {
     pub sender: Option<String>,
 }
 
+impl Reaction {
+    /// English name of the reaction, e.g. "Laughed at".
+    pub fn label(&self) -> &'static str {
+        self.label_in("en")
+    }
+
+    /// Name of the reaction in a language ("en", "es", ...).
+    pub fn label_in(&self, lang: &str) -> &'static str {
+        reaction_label(&self.emoji, lang).unwrap_or("Reacted")
+    }
+}
+
+/// One reaction pill: an emoji, how many used it, and whether I did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: usize,
+    /// Tapping the pill should remove my reaction rather than add one
+    pub reacted_by_me: bool,
+}
+
+/// Which messages to load. Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageFilter {
+    /// Sent at or after this
+    pub from_date: Option<DateTime<Utc>>,
+    /// Sent before this
+    pub to_date: Option<DateTime<Utc>>,
+    /// Sent by this handle, matched ignoring formatting
+    pub sender: Option<String>,
+    pub has_attachment: Option<bool>,
+    pub is_from_me: Option<bool>,
+}
+
 /// A single message in a conversation.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -65,8 +299,70 @@ pub struct Message {
     pub date: DateTime<Utc>,
     pub is_from_me: bool,
     pub sender: Option<String>,
+    /// Who `sender` is, by name where contacts know it, once resolved
+    #[serde(default)]
+    pub sender_name: Option<String>,
+    /// The group event this stands for, when group events are loaded;
+    /// `text` then describes it
+    #[serde(default)]
+    pub system_event: Option<ParticipantEvent>,
+    /// The link, when sent as a preview balloon
+    #[serde(default)]
+    pub link_preview: Option<LinkPreview>,
     pub attachments: Vec<Attachment>,
     pub reactions: Vec<Reaction>,
+    /// Handles @mentioned in the text
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// GUID of the message this replies to in a thread. Only read: the
+    /// app's own replies can't be sent into a thread
+    #[serde(default)]
+    pub reply_to_guid: Option<String>,
+    /// Start of that message's text, for a quote above the reply
+    #[serde(default)]
+    pub reply_to_text: Option<String>,
+    /// My message reached them; always false for theirs
+    #[serde(default)]
+    pub is_delivered: bool,
+    /// When my message reached them
+    #[serde(default)]
+    pub date_delivered: Option<DateTime<Utc>>,
+    /// When they read my message, if they send read receipts
+    #[serde(default)]
+    pub date_read: Option<DateTime<Utc>>,
+    /// Changed after sending; `text` is the latest version
+    #[serde(default)]
+    pub edited: bool,
+    /// Unsent; `text` is empty
+    #[serde(default)]
+    pub retracted: bool,
+    /// Earlier versions of an edited message, oldest first
+    #[serde(default)]
+    pub edit_history: Vec<MessageEdit>,
+}
+
+/// A link sent as a rich preview.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+impl LinkPreview {
+    /// The title followed by the URL, or just the URL.
+    pub fn label(&self) -> String {
+        match &self.title {
+            Some(title) => format!("{} ({})", title, self.url),
+            None => self.url.clone(),
+        }
+    }
+}
+
+/// One earlier version of an edited message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageEdit {
+    pub date: DateTime<Utc>,
+    pub text: String,
 }
 
 impl Message {
@@ -81,6 +377,20 @@ impl Message {
         has_image && self.display_text().is_empty()
     }
 
+    /// Who sent a message from someone else: the resolved name, else the
+    /// formatted handle.
+    pub fn sender_label(&self) -> String {
+        self.sender_name
+            .clone()
+            .or_else(|| self.sender.as_deref().map(format_display))
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+
+    /// Label for the message's audio attachment, if it has one.
+    pub fn audio_label(&self) -> Option<String> {
+        self.attachments.iter().find_map(Attachment::audio_label)
+    }
+
     /// Get unique reaction emojis as a combined string.
     pub fn reaction_summary(&self) -> String {
         let mut seen = Vec::new();
@@ -91,21 +401,120 @@ impl Message {
         }
         seen.join("")
     }
+
+    /// Each distinct reaction with how many people used it and whether I'm
+    /// one of them, in first-seen order.
+    pub fn reaction_counts(&self) -> Vec<ReactionCount> {
+        let mut counts: Vec<ReactionCount> = Vec::new();
+        for r in &self.reactions {
+            match counts.iter_mut().find(|c| c.emoji == r.emoji) {
+                Some(c) => {
+                    c.count += 1;
+                    c.reacted_by_me |= r.is_from_me;
+                }
+                None => counts.push(ReactionCount {
+                    emoji: r.emoji.clone(),
+                    count: 1,
+                    reacted_by_me: r.is_from_me,
+                }),
+            }
+        }
+        counts
+    }
+
+    /// Reactions with counts, e.g. "❤️ 2 · 😂 1".
+    pub fn reaction_count_summary(&self) -> String {
+        self.reaction_counts()
+            .iter()
+            .map(|c| format!("{} {}", c.emoji, c.count))
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
+
+    /// Reactions spelled out for exports and screen readers,
+    /// e.g. "Loved 2 · Laughed at 1".
+    pub fn reaction_label_summary(&self, lang: &str) -> String {
+        self.reaction_counts()
+            .iter()
+            .map(|c| format!("{} {}", reaction_label(&c.emoji, lang).unwrap_or("Reacted"), c.count))
+            .collect::<Vec<_>>()
+            .join(" · ")
+    }
 }
 
 /// A conversation with messages.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Conversation {
+    /// Library the chat came from; `LOCAL_SOURCE` for the user's own
+    pub source_id: String,
     pub chat_id: i64,
+    /// chat.guid, stable across chat.db rebuilds unlike the ROWID
+    pub guid: String,
     pub display_name: Option<String>,
     pub chat_identifier: String,
     pub style: i32, // 43 = group, 45 = 1:1
+    /// Service the chat runs over ("iMessage", "SMS", ...)
+    pub service_name: Option<String>,
     pub unread_count: i64,
+    /// Latest message in the chat, including my own
     pub last_message_date: DateTime<Utc>,
+    /// Latest message from the other side
+    pub last_incoming_date: DateTime<Utc>,
+    /// Oldest unread message, i.e. how long they've been waiting on me
+    pub first_unread_date: DateTime<Utc>,
+    /// How old `first_unread_date` is
+    pub age_bucket: AgeBucket,
+    /// Highest ROWID among the unread messages
+    pub last_unread_rowid: i64,
     pub messages: Vec<Message>,
     pub participants: Vec<String>,
-    /// Resolved name (from contacts or people.tsv)
+    /// Handles I use in this library, so an @mention of me can be told
+    /// from a mention of someone who has left the chat
+    #[serde(default)]
+    pub my_handles: Vec<String>,
+    /// Resolved name (from contacts or people.tsv), or the formatted handle
     pub resolved_name: Option<String>,
+    /// Reply half-typed in Messages.app, if drafts are being read
+    pub messages_app_draft: Option<String>,
+    /// ISO 639-3 code of the language their recent messages are in
+    pub primary_language: Option<String>,
+    /// Whether marking the chat read shows them a read receipt; None if unknown
+    pub read_receipts: Option<bool>,
+    /// Messages per day over the last `ACTIVITY_DAYS` days, oldest first
+    #[serde(default)]
+    pub activity: Vec<u32>,
+    /// Only loaded when the database is asked for stats
+    #[serde(default)]
+    pub stats: Option<ConversationStats>,
+    /// Thumbnail for the list; set when loaded with contacts
+    #[serde(default)]
+    pub preview_image: Option<PreviewImageRef>,
+    /// Someone is waiting on an answer from me; see `reply_reason`
+    #[serde(default)]
+    pub needs_reply: bool,
+    /// Why, e.g. "Alice asked a question 2 days ago"
+    #[serde(default)]
+    pub needs_reply_reason: Option<String>,
+    /// [`Conversation::is_mms_group`], filled in when loaded
+    #[serde(default)]
+    pub is_mms_group: bool,
+}
+
+/// Long-run numbers about a chat, e.g. for "you usually reply within 2 hours".
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConversationStats {
+    pub total_messages: u64,
+    /// Share of messages I sent, 0 to 100
+    pub my_share_percent: f32,
+    /// Median seconds between their message and my reply; None if I've
+    /// never replied
+    pub median_reply_gap: Option<i64>,
+}
+
+/// [`Conversation::is_mms_group`] for a chat row's style, service and GUID.
+pub(crate) fn is_mms_group(style: i32, service_name: Option<&str>, guid: &str) -> bool {
+    let carrier = |service: &str| service.eq_ignore_ascii_case("SMS") || service.eq_ignore_ascii_case("RCS");
+    style == 43 && (service_name.is_some_and(carrier) || guid.split(';').next().is_some_and(carrier))
 }
 
 impl Conversation {
@@ -114,6 +523,14 @@ impl Conversation {
         self.style == 43
     }
 
+    /// Whether this is a group sent over SMS/MMS (or RCS) rather than
+    /// iMessage, as when anyone in it isn't on iMessage. Such groups get no
+    /// tapbacks, edits or read receipts, and big ones are often split up
+    /// or dropped by carriers.
+    pub fn is_mms_group(&self) -> bool {
+        is_mms_group(self.style, self.service_name.as_deref(), &self.guid)
+    }
+
     /// Get the best display name for this conversation.
     pub fn name(&self) -> &str {
         if let Some(ref name) = self.display_name {
@@ -129,17 +546,49 @@ impl Conversation {
 
     /// Get URL to open this conversation in Messages.app.
     pub fn messages_url(&self) -> String {
-        if self.is_group() {
-            format!("imessage://?groupID={}", self.chat_identifier)
-        } else {
-            format!("imessage://{}", self.chat_identifier)
+        messages_url(&self.chat_identifier, self.is_group())
+    }
+
+    /// Participants who have written or reacted since my last message, as a
+    /// hint that they've seen it. None if no message of mine is loaded.
+    pub fn active_since_my_last_message(&self) -> Option<Vec<String>> {
+        let mine = self.messages.iter().rposition(|m| m.is_from_me)?;
+
+        let mut active: Vec<String> = Vec::new();
+        let mut note = |handle: &str| {
+            if !active.iter().any(|a| same_handle(a, handle)) {
+                active.push(handle.to_string());
+            }
+        };
+        for (i, message) in self.messages.iter().enumerate().skip(mine) {
+            // My own message counts only for the reactions on it
+            if i > mine && !message.is_from_me {
+                if let Some(sender) = &message.sender {
+                    note(sender);
+                }
+            }
+            for reaction in message.reactions.iter().filter(|r| !r.is_from_me) {
+                if let Some(sender) = &reaction.sender {
+                    note(sender);
+                }
+            }
         }
+        Some(active)
+    }
+}
+
+/// Sort the triage queue.
+pub fn sort_conversations(convs: &mut [Conversation], order: SortOrder) {
+    match order {
+        SortOrder::Recent => convs.sort_by_key(|c| std::cmp::Reverse(c.last_message_date)),
+        SortOrder::OldestUnread => convs.sort_by_key(|c| c.first_unread_date),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fixtures::{conversation, message};
 
     #[test]
     fn test_reaction_emoji_lookup() {
@@ -150,66 +599,72 @@ mod tests {
 
     #[test]
     fn test_attachment_is_image() {
-        let img = Attachment {
-            filename: "test.jpg".into(),
-            mime_type: "image/jpeg".into(),
-            transfer_name: "test.jpg".into(),
-        };
+        let img = Attachment::new("test.jpg".into(), "image/jpeg".into(), "test.jpg".into());
         assert!(img.is_image());
 
-        let pdf = Attachment {
-            filename: "doc.pdf".into(),
-            mime_type: "application/pdf".into(),
-            transfer_name: "doc.pdf".into(),
-        };
+        let pdf = Attachment::new("doc.pdf".into(), "application/pdf".into(), "doc.pdf".into());
         assert!(!pdf.is_image());
     }
 
+    #[test]
+    fn test_attachment_audio_label() {
+        let mut voice = Attachment::new("~/Library/Messages/Attachments/a/Audio Message.caf".into(), String::new(), "Audio Message.caf".into());
+        assert!(voice.is_audio());
+        assert_eq!(voice.audio_label().as_deref(), Some("Audio message"));
+        voice.duration_secs = Some(34);
+        voice.transcription = Some("running late ".into());
+        assert_eq!(voice.audio_label().as_deref(), Some("Audio message (0:34): running late"));
+
+        let img = Attachment::new("test.jpg".into(), "image/jpeg".into(), "test.jpg".into());
+        assert_eq!(img.audio_label(), None);
+    }
+
     #[test]
     fn test_attachment_url_path() {
-        let att = Attachment {
-            filename: "~/Library/Messages/Attachments/ab/cd/file.jpg".into(),
-            mime_type: "image/jpeg".into(),
-            transfer_name: "file.jpg".into(),
-        };
+        let att = Attachment::new("~/Library/Messages/Attachments/ab/cd/file.jpg".into(), "image/jpeg".into(), "file.jpg".into());
         assert_eq!(att.url_path(), Some("/attachment/ab/cd/file.jpg".into()));
 
-        let other = Attachment {
-            filename: "/some/other/path.jpg".into(),
-            mime_type: "image/jpeg".into(),
-            transfer_name: "path.jpg".into(),
-        };
+        let other = Attachment::new("/some/other/path.jpg".into(), "image/jpeg".into(), "path.jpg".into());
         assert_eq!(other.url_path(), None);
     }
 
+    #[test]
+    fn test_attachment_relative_path_variants() {
+        let cases = [
+            ("~/Library/Messages/Attachments/3f/15/7A1C2D4E-0B9F-4C3A-9E55-2A6B8F1D0C33/IMG_4021.heic", Some("3f/15/7A1C2D4E-0B9F-4C3A-9E55-2A6B8F1D0C33/IMG_4021.heic")),
+            ("/Users/jane/Library/Messages/Attachments/0a/10/at_0_5E8A/IMG_0001.jpeg", Some("0a/10/at_0_5E8A/IMG_0001.jpeg")),
+            ("~/Library/SMS/Attachments/6c/12/4B1E9A2C-77D1-4F0E-8F4B-0E3C2F6A9B10/IMG_1187.jpg", Some("6c/12/4B1E9A2C-77D1-4F0E-8F4B-0E3C2F6A9B10/IMG_1187.jpg")),
+            ("/var/mobile/Library/SMS/Attachments/c2/02/IMG_2210.MOV", Some("c2/02/IMG_2210.MOV")),
+            ("/private/var/folders/xy/T/com.apple.MobileSMS/sticker.heic", None),
+            ("~/Library/Messages/Attachments/../chat.db", None),
+            ("~/Library/Messages/Attachments/", None),
+            ("notes~/Library/Messages/Attachments/ab/file.jpg", None),
+            ("", None),
+        ];
+        for (filename, expected) in cases {
+            assert_eq!(attachment_relative_path(filename).as_deref(), expected, "{}", filename);
+        }
+    }
+
+    #[test]
+    fn test_age_bucket() {
+        let now = Utc::now();
+        let ago = |days| now - chrono::Duration::days(days);
+        assert_eq!(AgeBucket::since(now, now), AgeBucket::Today);
+        assert_eq!(AgeBucket::since(ago(3), now), AgeBucket::ThisWeek);
+        assert_eq!(AgeBucket::since(ago(7), now), AgeBucket::OverAWeek);
+        assert_eq!(AgeBucket::since(ago(400), now), AgeBucket::Ancient);
+    }
+
     #[test]
     fn test_message_display_text() {
-        let msg = Message {
-            rowid: 1,
-            guid: "test".into(),
-            text: "Hello \u{FFFC} world".into(),
-            date: Utc::now(),
-            is_from_me: false,
-            sender: None,
-            attachments: vec![],
-            reactions: vec![],
-        };
+        let msg = Message { guid: "test".into(), ..message("Hello \u{FFFC} world") };
         assert_eq!(msg.display_text(), "Hello  world");
     }
 
     #[test]
     fn test_conversation_is_group() {
-        let group = Conversation {
-            chat_id: 1,
-            display_name: None,
-            chat_identifier: "chat123".into(),
-            style: 43,
-            unread_count: 5,
-            last_message_date: Utc::now(),
-            messages: vec![],
-            participants: vec![],
-            resolved_name: None,
-        };
+        let group = Conversation { style: 43, unread_count: 5, ..conversation("chat123") };
         assert!(group.is_group());
 
         let direct = Conversation {
@@ -223,15 +678,9 @@ mod tests {
     fn test_conversation_name_priority() {
         // display_name takes priority
         let conv = Conversation {
-            chat_id: 1,
             display_name: Some("Group Chat".into()),
-            chat_identifier: "+15551234567".into(),
-            style: 45,
-            unread_count: 1,
-            last_message_date: Utc::now(),
-            messages: vec![],
-            participants: vec![],
             resolved_name: Some("John Doe".into()),
+            ..conversation("+15551234567")
         };
         assert_eq!(conv.name(), "Group Chat");
 
@@ -253,17 +702,7 @@ mod tests {
 
     #[test]
     fn test_conversation_messages_url() {
-        let direct = Conversation {
-            chat_id: 1,
-            display_name: None,
-            chat_identifier: "+15551234567".into(),
-            style: 45,
-            unread_count: 1,
-            last_message_date: Utc::now(),
-            messages: vec![],
-            participants: vec![],
-            resolved_name: None,
-        };
+        let direct = conversation("+15551234567");
         assert_eq!(direct.messages_url(), "imessage://+15551234567");
 
         let group = Conversation {
@@ -275,23 +714,49 @@ mod tests {
     }
 
     #[test]
-    fn test_message_is_image_only() {
-        let img_attachment = Attachment {
-            filename: "photo.jpg".into(),
-            mime_type: "image/jpeg".into(),
-            transfer_name: "photo.jpg".into(),
+    fn test_active_since_my_last_message() {
+        let msg = |rowid: i64, sender: Option<&str>, reactions: Vec<Reaction>| Message {
+            rowid,
+            guid: format!("g{}", rowid),
+            is_from_me: sender.is_none(),
+            sender: sender.map(String::from),
+            reactions,
+            ..message("hi")
         };
+        let liked_by = |sender: &str| Reaction { emoji: "👍".into(), is_from_me: false, sender: Some(sender.into()) };
+
+        let mut group = Conversation {
+            guid: "iMessage;+;chat1".into(),
+            style: 43,
+            messages: vec![msg(1, Some("+15550000001"), vec![])],
+            participants: vec!["+15550000001".into(), "+15550000002".into(), "+15550000003".into()],
+            ..conversation("chat1")
+        };
+        assert_eq!(group.active_since_my_last_message(), None);
+
+        group.messages.extend([
+            msg(2, None, vec![liked_by("+15550000003")]),
+            msg(3, Some("+15550000002"), vec![liked_by("+1 (555) 000-0003")]),
+            msg(4, Some("+15550000002"), vec![]),
+        ]);
+        assert_eq!(
+            group.active_since_my_last_message(),
+            Some(vec!["+15550000003".to_string(), "+15550000002".to_string()])
+        );
+
+        group.messages.push(msg(5, None, vec![]));
+        assert_eq!(group.active_since_my_last_message(), Some(vec![]));
+    }
+
+    #[test]
+    fn test_message_is_image_only() {
+        let img_attachment = Attachment::new("photo.jpg".into(), "image/jpeg".into(), "photo.jpg".into());
 
         // Image with no text
         let msg = Message {
-            rowid: 1,
             guid: "test".into(),
-            text: "\u{FFFC}".into(), // Just placeholder
-            date: Utc::now(),
-            is_from_me: false,
-            sender: None,
             attachments: vec![img_attachment.clone()],
-            reactions: vec![],
+            ..message("\u{FFFC}") // Just placeholder
         };
         assert!(msg.is_image_only());
 
@@ -313,34 +778,44 @@ mod tests {
     #[test]
     fn test_message_reaction_summary() {
         let msg = Message {
-            rowid: 1,
             guid: "test".into(),
-            text: "Hello".into(),
-            date: Utc::now(),
-            is_from_me: false,
-            sender: None,
-            attachments: vec![],
             reactions: vec![
                 Reaction { emoji: "❤️".into(), is_from_me: false, sender: None },
                 Reaction { emoji: "👍".into(), is_from_me: true, sender: None },
                 Reaction { emoji: "❤️".into(), is_from_me: true, sender: None }, // Duplicate
             ],
+            ..message("Hello")
         };
         assert_eq!(msg.reaction_summary(), "❤️👍");
+        assert_eq!(msg.reaction_counts(), vec![
+            ReactionCount { emoji: "❤️".into(), count: 2, reacted_by_me: true },
+            ReactionCount { emoji: "👍".into(), count: 1, reacted_by_me: true },
+        ]);
+        assert_eq!(msg.reaction_count_summary(), "❤️ 2 · 👍 1");
+        assert_eq!(msg.reaction_label_summary("en"), "Loved 2 · Liked 1");
+        assert_eq!(msg.reaction_label_summary("es"), "Le encantó 2 · Le gustó 1");
+    }
+
+    #[test]
+    fn test_reaction_label() {
+        let laugh = Reaction { emoji: "😂".into(), is_from_me: false, sender: None };
+        assert_eq!(laugh.label(), "Laughed at");
+        assert_eq!(laugh.label_in("de"), "Gelacht über");
+        // Unknown languages fall back to English
+        assert_eq!(laugh.label_in("xx"), "Laughed at");
+        assert_eq!(reaction_label("🙃", "en"), None);
+
+        for (_, emoji) in REACTION_EMOJI {
+            assert!(reaction_label(emoji, "fr").is_some());
+        }
     }
 
     #[test]
     fn test_conversation_empty_display_name() {
         let conv = Conversation {
-            chat_id: 1,
             display_name: Some("".into()), // Empty string
-            chat_identifier: "+15551234567".into(),
-            style: 45,
-            unread_count: 1,
-            last_message_date: Utc::now(),
-            messages: vec![],
-            participants: vec![],
             resolved_name: Some("John".into()),
+            ..conversation("+15551234567")
         };
         // Should skip empty display_name and use resolved_name
         assert_eq!(conv.name(), "John");
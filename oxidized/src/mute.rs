@@ -0,0 +1,105 @@
+//! Group chats muted until someone needs me.
+//!
+//! Large groups produce a steady stream of unread messages that rarely call
+//! for an answer. A muted group stays out of the queue until an unread
+//! message @mentions me or asks a question, then shows up as usual. Muted
+//! chats are saved by identifier as JSON.
+
+use std::collections::BTreeSet;
+
+use crate::models::Conversation;
+use crate::store::Document;
+
+/// Muted group chats by chat identifier.
+pub struct MuteList {
+    doc: Document,
+    muted: BTreeSet<String>,
+}
+
+impl MuteList {
+    /// Load the list, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let muted = doc.load().unwrap_or_default();
+        Self { doc, muted }
+    }
+
+    /// Write the list to disk.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.doc.save(&self.muted)
+    }
+
+    /// Mute or unmute a chat. Returns whether anything changed.
+    pub fn set(&mut self, chat_identifier: &str, muted: bool) -> bool {
+        if muted {
+            self.muted.insert(chat_identifier.to_string())
+        } else {
+            self.muted.remove(chat_identifier)
+        }
+    }
+
+    pub fn is_muted(&self, chat_identifier: &str) -> bool {
+        self.muted.contains(chat_identifier)
+    }
+
+    /// Whether `conv` stays out of the queue: a muted group where no unread
+    /// message mentions me or asks a question.
+    pub fn is_hidden(&self, conv: &Conversation) -> bool {
+        conv.is_group() && self.is_muted(&conv.chat_identifier) && !conv.mentions_or_asks()
+    }
+
+    /// Every muted chat identifier.
+    pub fn all(&self) -> impl Iterator<Item = &String> {
+        self.muted.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use crate::fixtures::{conversation, message};
+    use crate::models::Message;
+
+    fn group(texts: &[&str]) -> Conversation {
+        let messages = texts
+            .iter()
+            .map(|text| Message { sender: Some("+14158675309".into()), ..message(text) })
+            .collect();
+        Conversation {
+            guid: "iMessage;+;chat1".into(),
+            display_name: Some("Neighbours".into()),
+            style: 43,
+            unread_count: texts.len() as i64,
+            first_unread_date: Utc::now() - Duration::hours(1),
+            last_unread_rowid: 1,
+            messages,
+            participants: vec!["+14158675309".into()],
+            my_handles: vec!["me@example.com".into()],
+            ..conversation("chat1")
+        }
+    }
+
+    #[test]
+    fn test_muted_until_mention_or_question() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("muted.json");
+        let mut list = MuteList::load(Document::file(&path));
+        assert!(list.set("chat1", true));
+        assert!(!list.set("chat1", true));
+        list.save().unwrap();
+
+        let list = MuteList::load(Document::file(&path));
+        assert!(list.is_hidden(&group(&["lol", "the bins go out tuesday"])));
+        assert!(!list.is_hidden(&group(&["lol", "anyone have a ladder?"])));
+
+        let mut mention = group(&["@Sam look at this"]);
+        mention.messages[0].mentions = vec!["carol@example.com".into()];
+        assert!(list.is_hidden(&mention));
+        mention.messages[0].mentions = vec!["me@example.com".into()];
+        assert!(!list.is_hidden(&mention));
+
+        let mut direct = group(&["ok"]);
+        direct.style = 45;
+        assert!(!list.is_hidden(&direct));
+    }
+}
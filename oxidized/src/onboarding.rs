@@ -0,0 +1,75 @@
+//! First-run checks for permissions and Messages.app setup.
+
+use std::fs::File;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::db::Database;
+use crate::send::{osascript_output, ErrorCategory};
+
+/// Result of each first-run check.
+#[derive(Debug, Clone, Serialize)]
+pub struct OnboardingStatus {
+    /// chat.db exists and the app may read it
+    pub full_disk_access: bool,
+    /// chat.db opens and answers a query
+    pub database_openable: bool,
+    /// Identifiers the contact resolver can name
+    pub contacts_loaded: usize,
+    /// Apple events to Messages.app are allowed; None if undetermined
+    pub automation_permitted: Option<bool>,
+    /// Messages.app has an enabled account; None if it couldn't be asked
+    pub messages_signed_in: Option<bool>,
+}
+
+/// Run every check. May prompt for Automation permission the first time.
+pub fn onboarding_status(db_path: &Path, contacts_loaded: usize) -> OnboardingStatus {
+    // Without Full Disk Access, opening chat.db fails with EPERM
+    let full_disk_access = File::open(db_path).is_ok();
+
+    let database_openable = Database::open(&db_path.to_path_buf())
+        .and_then(|db| db.ping())
+        .is_ok();
+
+    let (automation_permitted, messages_signed_in) =
+        match osascript_output(r#"tell application "Messages" to count of (every account whose enabled is true)"#) {
+            Ok(output) => (Some(true), parse_account_count(&output).map(|n| n > 0)),
+            Err(e) if e.category() == ErrorCategory::Permission => (Some(false), None),
+            Err(_) => (None, None),
+        };
+
+    OnboardingStatus {
+        full_disk_access,
+        database_openable,
+        contacts_loaded,
+        automation_permitted,
+        messages_signed_in,
+    }
+}
+
+/// Parse the account count printed by osascript.
+fn parse_account_count(output: &str) -> Option<usize> {
+    output.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_account_count() {
+        assert_eq!(parse_account_count("2\n"), Some(2));
+        assert_eq!(parse_account_count("0"), Some(0));
+        assert_eq!(parse_account_count(""), None);
+    }
+
+    #[test]
+    fn test_missing_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = onboarding_status(&dir.path().join("chat.db"), 3);
+        assert!(!status.full_disk_access);
+        assert!(!status.database_openable);
+        assert_eq!(status.contacts_loaded, 3);
+    }
+}
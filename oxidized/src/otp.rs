@@ -0,0 +1,145 @@
+//! One-time verification codes.
+//!
+//! Login and verification texts need reading once, within minutes, and never
+//! a reply. Once the code has expired the chat can be treated as handled.
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+
+use crate::models::{Conversation, Message};
+
+/// How long a code stays useful after it arrives.
+pub const OTP_EXPIRY_MINUTES: i64 = 10;
+
+/// Words that appear in verification texts, in a few languages.
+fn keyword_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(?:code|passcode|verification|verify|one[- ]time|otp|2fa|pin|login|log in|sign[- ]in|authenticat\w*|código|codigo|Bestätigungscode|Code de vérification)\b",
+        )
+        .unwrap()
+    })
+}
+
+/// 4–8 digits, optionally split in two halves ("123 456", "123-456") or
+/// behind a letter prefix ("G-123456").
+fn code_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"\b(?:[A-Z]-)?(\d{3}[ -]\d{3}|\d{4,8})\b").unwrap())
+}
+
+impl Message {
+    /// The verification code in an incoming message, without separators.
+    pub fn otp_code(&self) -> Option<String> {
+        if self.is_from_me {
+            return None;
+        }
+        let text = self.display_text();
+        if !keyword_pattern().is_match(&text) {
+            return None;
+        }
+        let digits = code_pattern().captures(&text)?.get(1)?.as_str();
+        Some(digits.chars().filter(char::is_ascii_digit).collect())
+    }
+
+    /// Whether this is a one-time code message.
+    pub fn is_otp(&self) -> bool {
+        self.otp_code().is_some()
+    }
+
+    /// Whether the code in this message is still usable at `now`.
+    pub fn otp_is_fresh(&self, now: DateTime<Utc>) -> bool {
+        self.is_otp() && now - self.date < Duration::minutes(OTP_EXPIRY_MINUTES)
+    }
+}
+
+impl Conversation {
+    /// True when every unread message is a code that has expired, so there's
+    /// nothing left to read or answer.
+    pub fn otp_expired(&self, now: DateTime<Utc>) -> bool {
+        let unread: Vec<&Message> = self
+            .messages
+            .iter()
+            .filter(|m| !m.is_from_me && m.date >= self.first_unread_date)
+            .collect();
+        !unread.is_empty() && unread.iter().all(|m| m.is_otp() && !m.otp_is_fresh(now))
+    }
+
+    /// The newest code that is still usable.
+    pub fn latest_otp(&self, now: DateTime<Utc>) -> Option<(DateTime<Utc>, String)> {
+        self.messages
+            .iter()
+            .rev()
+            .filter(|m| m.otp_is_fresh(now))
+            .find_map(|m| Some((m.date, m.otp_code()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{conversation, message};
+
+    fn msg(text: &str, minutes_ago: i64, now: DateTime<Utc>) -> Message {
+        Message {
+            rowid: minutes_ago,
+            guid: format!("g{}", minutes_ago),
+            date: now - Duration::minutes(minutes_ago),
+            sender: Some("72975".into()),
+            ..message(text)
+        }
+    }
+
+    fn conv(messages: Vec<Message>, first_unread: DateTime<Utc>) -> Conversation {
+        Conversation {
+            guid: "SMS;-;72975".into(),
+            service_name: Some("SMS".into()),
+            unread_count: messages.len() as i64,
+            last_message_date: first_unread,
+            last_incoming_date: first_unread,
+            first_unread_date: first_unread,
+            messages,
+            ..conversation("72975")
+        }
+    }
+
+    #[test]
+    fn test_extracts_codes() {
+        let now = Utc::now();
+        let code = |text: &str| msg(text, 0, now).otp_code();
+
+        assert_eq!(code("Your verification code is 482913").as_deref(), Some("482913"));
+        assert_eq!(code("G-204815 is your Google verification code.").as_deref(), Some("204815"));
+        assert_eq!(code("Your Uber code: 1234. Never share this code.").as_deref(), Some("1234"));
+        assert_eq!(code("123 456 is your login code").as_deref(), Some("123456"));
+        assert_eq!(code("Tu código de verificación es 9981").as_deref(), Some("9981"));
+
+        assert_eq!(code("See you at 1930?"), None);
+        assert_eq!(code("What's the code word?"), None);
+
+        let mut mine = msg("code 1234", 0, now);
+        mine.is_from_me = true;
+        assert!(!mine.is_otp());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let now = Utc::now();
+        let old = msg("Your code is 111111", 30, now);
+        let fresh = msg("Your code is 222222", 2, now);
+
+        assert!(conv(vec![old.clone()], old.date).otp_expired(now));
+        assert!(!conv(vec![old.clone(), fresh.clone()], old.date).otp_expired(now));
+
+        // A real message alongside the code keeps the chat in the queue
+        let chat = msg("also, lunch tomorrow?", 29, now);
+        assert!(!conv(vec![old.clone(), chat], old.date).otp_expired(now));
+
+        let both = conv(vec![old.clone(), fresh.clone()], old.date);
+        assert_eq!(both.latest_otp(now).map(|(_, c)| c).as_deref(), Some("222222"));
+        assert_eq!(conv(vec![old.clone()], old.date).latest_otp(now), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Session view of outgoing replies.
+//!
+//! Committed replies and the failed queue say what's waiting, but not when it
+//! was queued, which reply is sending right now, which is waiting out a retry
+//! backoff, or what already went out. The outbox tracks those and merges them
+//! with the rest of the persisted state into one list.
+//!
+//! Replies still waiting are saved, and a send is saved as in flight before
+//! it's handed to Messages. One still in flight when the app next starts may
+//! or may not have gone out, so it's kept aside as interrupted until the
+//! user confirms sending it again.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::store::Document;
+use crate::retry::FailedSend;
+
+/// Where a reply is in its way out. Variants are in display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    InFlight,
+    /// Waiting out a retry backoff
+    Delayed,
+    /// Held by a send guard
+    NeedsConfirmation,
+    Queued,
+    Failed,
+    Sent,
+}
+
+/// One reply in the outbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub chat_guid: String,
+    /// Known once a send has been attempted
+    pub name: Option<String>,
+    pub text: String,
+    pub status: OutboxStatus,
+    /// When the reply entered its current status, if known
+    pub updated_at: Option<DateTime<Utc>>,
+    /// When a delayed reply will be tried again
+    pub retry_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+/// What's saved: replies on their way out, and ones interrupted by the app
+/// quitting mid-send, as chat GUID to text.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SavedOutbox {
+    active: HashMap<String, OutboxItem>,
+    interrupted: HashMap<String, String>,
+}
+
+/// Record of sends on their way out, and of this session's sent replies.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    /// Where to save; None keeps everything in memory
+    doc: Option<Document>,
+    active: HashMap<String, OutboxItem>,
+    interrupted: HashMap<String, String>,
+    sent: Vec<OutboxItem>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the saved outbox. Sends that were in flight or waiting out a
+    /// retry when it was saved are interrupted, not resumed.
+    pub fn load(doc: Document) -> Self {
+        let saved: SavedOutbox = doc.load().unwrap_or_default();
+        let mut interrupted = saved.interrupted;
+        let mut active = HashMap::new();
+        for (chat_guid, item) in saved.active {
+            if matches!(item.status, OutboxStatus::InFlight | OutboxStatus::Delayed) {
+                interrupted.insert(chat_guid, item.text);
+            } else {
+                active.insert(chat_guid, item);
+            }
+        }
+        Self { doc: Some(doc), active, interrupted, sent: Vec::new() }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(doc) = &self.doc else { return Ok(()) };
+        doc.save(&SavedOutbox { active: self.active.clone(), interrupted: self.interrupted.clone() })
+    }
+
+    /// Whether `text` was being sent to this chat when the app last quit,
+    /// so it may already have gone out.
+    pub fn was_interrupted(&self, chat_guid: &str, text: &str) -> bool {
+        self.interrupted.get(chat_guid).is_some_and(|t| t == text)
+    }
+
+    fn set(&mut self, chat_guid: &str, name: Option<&str>, text: &str, status: OutboxStatus, now: DateTime<Utc>) {
+        let name = name
+            .map(str::to_string)
+            .or_else(|| self.active.get(chat_guid).and_then(|i| i.name.clone()));
+        self.active.insert(chat_guid.to_string(), OutboxItem {
+            chat_guid: chat_guid.to_string(),
+            name,
+            text: text.to_string(),
+            status,
+            updated_at: Some(now),
+            retry_at: None,
+            error: None,
+        });
+    }
+
+    /// A reply was committed.
+    pub fn queue(&mut self, chat_guid: &str, text: &str, now: DateTime<Utc>) {
+        self.set(chat_guid, None, text, OutboxStatus::Queued, now);
+    }
+
+    /// A send guard held a reply back.
+    pub fn hold(&mut self, chat_guid: &str, name: &str, text: &str, now: DateTime<Utc>) {
+        self.set(chat_guid, Some(name), text, OutboxStatus::NeedsConfirmation, now);
+    }
+
+    /// A reply is being handed to Messages. Save before sending, so a crash
+    /// mid-send leaves it interrupted rather than quietly sent again.
+    pub fn start(&mut self, chat_guid: &str, name: &str, text: &str, now: DateTime<Utc>) {
+        self.interrupted.remove(chat_guid);
+        self.set(chat_guid, Some(name), text, OutboxStatus::InFlight, now);
+    }
+
+    /// A reply failed and will be tried again at `retry_at`.
+    pub fn delay(&mut self, chat_guid: &str, retry_at: DateTime<Utc>, error: Option<String>, now: DateTime<Utc>) {
+        if let Some(item) = self.active.get_mut(chat_guid) {
+            item.status = OutboxStatus::Delayed;
+            item.updated_at = Some(now);
+            item.retry_at = Some(retry_at);
+            item.error = error;
+        }
+    }
+
+    /// A send finished. Successes move to the sent list; failures are
+    /// dropped here because the failed queue reports them.
+    pub fn finish(&mut self, chat_guid: &str, success: bool, now: DateTime<Utc>) {
+        let Some(mut item) = self.active.remove(chat_guid) else { return };
+        if success {
+            item.status = OutboxStatus::Sent;
+            item.updated_at = Some(now);
+            item.retry_at = None;
+            item.error = None;
+            self.sent.push(item);
+        }
+    }
+
+    /// Everything in the outbox, in-flight first and sent last (newest first).
+    ///
+    /// Committed replies are queued unless a guard is holding them; anything
+    /// recorded as queued but no longer committed was withdrawn and is left out.
+    pub fn items(&self, committed: &HashMap<String, String>, failed: &[FailedSend]) -> Vec<OutboxItem> {
+        let mut items: Vec<OutboxItem> = self
+            .active
+            .values()
+            .filter(|i| matches!(i.status, OutboxStatus::InFlight | OutboxStatus::Delayed))
+            .cloned()
+            .collect();
+
+        let mut queued: Vec<_> = committed.iter().collect();
+        queued.sort();
+        for (chat_guid, text) in queued {
+            match self.active.get(chat_guid) {
+                // Already listed above
+                Some(item) if matches!(item.status, OutboxStatus::InFlight | OutboxStatus::Delayed) => {}
+                Some(item) if item.text == *text => items.push(item.clone()),
+                _ => items.push(OutboxItem {
+                    chat_guid: chat_guid.clone(),
+                    name: None,
+                    text: text.clone(),
+                    status: OutboxStatus::Queued,
+                    updated_at: None,
+                    retry_at: None,
+                    error: None,
+                }),
+            }
+        }
+
+        items.extend(failed.iter().map(|f| OutboxItem {
+            chat_guid: f.chat_guid.clone(),
+            name: Some(f.name.clone()),
+            text: f.text.clone(),
+            status: OutboxStatus::Failed,
+            updated_at: f.failed_at,
+            retry_at: None,
+            error: Some(f.last_error.clone()),
+        }));
+        items.extend(self.sent.iter().rev().cloned());
+
+        // Stable, so sent stays newest first
+        items.sort_by_key(|i| i.status);
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::send::ErrorCategory;
+
+    fn statuses(items: &[OutboxItem]) -> Vec<(&str, OutboxStatus)> {
+        items.iter().map(|i| (i.chat_guid.as_str(), i.status)).collect()
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let now = Utc::now();
+        let mut outbox = Outbox::new();
+        let mut committed = HashMap::from([("a".to_string(), "hi".to_string()), ("b".to_string(), "yo".to_string()), ("c".to_string(), "hey".to_string())]);
+        outbox.queue("a", "hi", now);
+        outbox.queue("b", "yo", now);
+        outbox.queue("c", "hey", now);
+
+        outbox.start("a", "John", "hi", now);
+        committed.remove("a");
+        outbox.hold("b", "Team", "yo", now);
+        assert_eq!(
+            statuses(&outbox.items(&committed, &[])),
+            [("a", OutboxStatus::InFlight), ("b", OutboxStatus::NeedsConfirmation), ("c", OutboxStatus::Queued)]
+        );
+
+        outbox.delay("a", now, Some("timed out".into()), now);
+        let items = outbox.items(&committed, &[]);
+        assert_eq!(items[0].status, OutboxStatus::Delayed);
+        assert!(items[0].retry_at.is_some());
+
+        outbox.finish("a", true, now);
+        let items = outbox.items(&committed, &[]);
+        assert_eq!(statuses(&items).last(), Some(&("a", OutboxStatus::Sent)));
+        assert_eq!(items.last().unwrap().name.as_deref(), Some("John"));
+    }
+
+    #[test]
+    fn test_withdrawn_and_failed() {
+        let now = Utc::now();
+        let mut outbox = Outbox::new();
+        outbox.queue("a", "hi", now);
+        outbox.start("b", "Jane", "hello", now);
+        outbox.finish("b", false, now);
+
+        let failed = FailedSend {
+            chat_id: 2,
+            chat_guid: "b".into(),
+            chat_identifier: "+15551234567".into(),
+            is_group: false,
+            name: "Jane".into(),
+            text: "hello".into(),
+            attempts: 1,
+            category: ErrorCategory::Timeout,
+            last_error: "timed out".into(),
+            failed_at: Some(now),
+        };
+        let items = outbox.items(&HashMap::new(), &[failed]);
+        assert_eq!(statuses(&items), [("b", OutboxStatus::Failed)]);
+        assert_eq!(items[0].error.as_deref(), Some("timed out"));
+    }
+
+    #[test]
+    fn test_save_and_interrupted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outbox.json");
+        let now = Utc::now();
+        let mut outbox = Outbox::load(Document::file(&path));
+        outbox.queue("a", "hi", now);
+        outbox.start("b", "Jane", "hello", now);
+        outbox.start("c", "John", "hey", now);
+        outbox.finish("c", true, now);
+        outbox.save().unwrap();
+
+        // Quit with "hello" out: it may have been sent
+        let reloaded = Outbox::load(Document::file(&path));
+        assert!(reloaded.was_interrupted("b", "hello"));
+        assert!(!reloaded.was_interrupted("b", "hello again"));
+        assert!(!reloaded.was_interrupted("a", "hi"));
+        let committed = HashMap::from([("a".to_string(), "hi".to_string())]);
+        assert_eq!(statuses(&reloaded.items(&committed, &[])), [("a", OutboxStatus::Queued)]);
+
+        // Still interrupted after another restart, until sent again
+        reloaded.save().unwrap();
+        let mut reloaded = Outbox::load(Document::file(&path));
+        assert!(reloaded.was_interrupted("b", "hello"));
+        reloaded.start("b", "Jane", "hello", now);
+        assert!(!reloaded.was_interrupted("b", "hello"));
+    }
+}
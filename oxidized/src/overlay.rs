@@ -0,0 +1,96 @@
+//! Local read-state overlay that never writes to chat.db.
+//!
+//! Handling a chat records the newest message ROWID seen in it. The chat stays
+//! hidden until an unread message arrives with a higher ROWID.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::models::Conversation;
+use crate::store::Document;
+
+/// Per-chat high-watermark ROWIDs, saved as JSON.
+pub struct ReadOverlay {
+    doc: Document,
+    watermarks: HashMap<String, i64>,
+}
+
+impl ReadOverlay {
+    /// Default overlay file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().read_overlay()
+    }
+
+    /// Load the overlay, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let watermarks = doc.load().unwrap_or_default();
+        Self { doc, watermarks }
+    }
+
+    /// Write the overlay to disk.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.doc.save(&self.watermarks)
+    }
+
+    /// Record that everything up to `rowid` in a chat has been handled.
+    pub fn mark(&mut self, chat_identifier: &str, rowid: i64) {
+        let mark = self.watermarks.entry(chat_identifier.to_string()).or_insert(rowid);
+        *mark = (*mark).max(rowid);
+    }
+
+    /// Whether every unread message in the conversation is below the watermark.
+    pub fn is_read(&self, conv: &Conversation) -> bool {
+        self.watermarks
+            .get(&conv.chat_identifier)
+            .is_some_and(|&mark| conv.last_unread_rowid <= mark)
+    }
+}
+
+impl Default for ReadOverlay {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::conversation;
+
+    fn conv(chat_identifier: &str, last_unread_rowid: i64) -> Conversation {
+        Conversation { last_unread_rowid, ..conversation(chat_identifier) }
+    }
+
+    #[test]
+    fn test_watermark_hides_until_newer_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut overlay = ReadOverlay::load(Document::file(&dir.path().join("read_overlay.json")));
+        assert!(!overlay.is_read(&conv("+15551234567", 100)));
+
+        overlay.mark("+15551234567", 100);
+        assert!(overlay.is_read(&conv("+15551234567", 100)));
+        assert!(!overlay.is_read(&conv("+15551234567", 101)));
+        assert!(!overlay.is_read(&conv("+15559999999", 50)));
+    }
+
+    #[test]
+    fn test_watermark_never_moves_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut overlay = ReadOverlay::load(Document::file(&dir.path().join("read_overlay.json")));
+        overlay.mark("a", 200);
+        overlay.mark("a", 150);
+        assert!(overlay.is_read(&conv("a", 200)));
+    }
+
+    #[test]
+    fn test_overlay_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("read_overlay.json");
+        let mut overlay = ReadOverlay::load(Document::file(&path));
+        overlay.mark("a", 10);
+        overlay.save().unwrap();
+
+        let reloaded = ReadOverlay::load(Document::file(&path));
+        assert!(reloaded.is_read(&conv("a", 10)));
+    }
+}
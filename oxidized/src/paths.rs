@@ -0,0 +1,181 @@
+//! Where the app keeps the files it writes.
+//!
+//! State, logs, staged attachments and exports live under one data
+//! directory; converted images live under a cache directory. Either can be
+//! moved with `AEROMESSAGE_DATA_DIR` or `AEROMESSAGE_CACHE_DIR`, e.g. into a
+//! synced folder.
+
+use std::env;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// Overrides the data directory when set.
+pub const DATA_DIR_ENV: &str = "AEROMESSAGE_DATA_DIR";
+/// Overrides the cache directory when set.
+pub const CACHE_DIR_ENV: &str = "AEROMESSAGE_CACHE_DIR";
+
+/// The app's data and cache directories and the files inside them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AppPaths {
+    pub data_dir: PathBuf,
+    pub cache_dir: PathBuf,
+}
+
+impl AppPaths {
+    pub fn new(data_dir: PathBuf, cache_dir: PathBuf) -> Self {
+        Self { data_dir, cache_dir }
+    }
+
+    /// The usual macOS locations, unless overridden by the environment.
+    pub fn from_env() -> Self {
+        Self::with_overrides(env::var_os(DATA_DIR_ENV).map(PathBuf::from), env::var_os(CACHE_DIR_ENV).map(PathBuf::from))
+    }
+
+    /// The usual locations with any non-empty override swapped in.
+    pub fn with_overrides(data_dir: Option<PathBuf>, cache_dir: Option<PathBuf>) -> Self {
+        let set = |p: &PathBuf| !p.as_os_str().is_empty();
+        Self {
+            data_dir: data_dir.filter(set).unwrap_or_else(default_data_dir),
+            cache_dir: cache_dir.filter(set).unwrap_or_else(default_cache_dir),
+        }
+    }
+
+    pub fn settings(&self) -> PathBuf {
+        self.data_dir.join("settings.json")
+    }
+
+    /// Send history as kept before the state store, copied into it the
+    /// first time the store's log is empty.
+    pub fn send_history(&self) -> PathBuf {
+        self.data_dir.join("send_history.jsonl")
+    }
+
+    pub fn failed_sends(&self) -> PathBuf {
+        self.data_dir.join("failed.json")
+    }
+
+    pub fn read_overlay(&self) -> PathBuf {
+        self.data_dir.join("read_overlay.json")
+    }
+
+    pub fn snoozed(&self) -> PathBuf {
+        self.data_dir.join("snoozed.json")
+    }
+
+    pub fn ignored(&self) -> PathBuf {
+        self.data_dir.join("ignored.json")
+    }
+
+    /// Groups hidden until a message mentions me or asks a question.
+    pub fn muted(&self) -> PathBuf {
+        self.data_dir.join("muted.json")
+    }
+
+    /// Drafts, committed replies and the later list.
+    pub fn queue_state(&self) -> PathBuf {
+        self.data_dir.join("queue.json")
+    }
+
+    /// Replies on their way out, so a crash mid-send isn't resent unasked
+    pub fn outbox(&self) -> PathBuf {
+        self.data_dir.join("outbox.json")
+    }
+
+    pub fn templates(&self) -> PathBuf {
+        self.data_dir.join("templates.json")
+    }
+
+    /// Default folder for template attachment slots.
+    pub fn template_assets(&self) -> PathBuf {
+        self.data_dir.join("template_assets")
+    }
+
+    /// Names that override contacts, one identifier and name per line.
+    pub fn people(&self) -> PathBuf {
+        self.data_dir.join("people.tsv")
+    }
+
+    pub fn bookmarks(&self) -> PathBuf {
+        self.data_dir.join("bookmarks.json")
+    }
+
+    pub fn sessions(&self) -> PathBuf {
+        self.data_dir.join("sessions.json")
+    }
+
+    /// Directory of attachments staged for replies.
+    pub fn staging(&self) -> PathBuf {
+        self.data_dir.join("staging")
+    }
+
+    /// Folder exports given a relative path are written to.
+    pub fn exports(&self) -> PathBuf {
+        self.data_dir.join("exports")
+    }
+
+    /// Point-in-time copy of chat.db read when snapshot reads are on.
+    pub fn chat_snapshot(&self) -> PathBuf {
+        self.cache_dir.join("chat-snapshot.db")
+    }
+
+    /// App state when kept in SQLite instead of JSON files.
+    pub fn state_db(&self) -> PathBuf {
+        self.data_dir.join("state.sqlite")
+    }
+
+    /// Full-text index of every message, rebuilt from chat.db if lost.
+    /// Kept with the data rather than the cache since it holds every
+    /// message's text.
+    pub fn search_index(&self) -> PathBuf {
+        self.data_dir.join("search.db")
+    }
+
+    /// State saved whole, which a [`crate::StateStore`] holds as documents
+    /// named by these files' names.
+    pub fn state_documents(&self) -> Vec<PathBuf> {
+        vec![
+            self.failed_sends(), self.read_overlay(), self.snoozed(), self.ignored(), self.muted(), self.queue_state(),
+            self.outbox(), self.templates(), self.bookmarks(), self.sessions(),
+        ]
+    }
+
+    /// State files worth checking in diagnostics.
+    pub fn state_files(&self) -> Vec<PathBuf> {
+        vec![self.settings(), self.failed_sends(), self.read_overlay(), self.snoozed(), self.ignored(), self.muted(), self.queue_state(), self.outbox(), self.bookmarks()]
+    }
+}
+
+impl Default for AppPaths {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+/// ~/Library/Application Support/Aeromessage
+fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .expect("data directory required")
+        .join("Aeromessage")
+}
+
+/// ~/Library/Caches/Aeromessage
+pub(crate) fn default_cache_dir() -> PathBuf {
+    dirs::home_dir()
+        .expect("home directory required")
+        .join("Library/Caches/Aeromessage")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let paths = AppPaths::with_overrides(Some("/tmp/aero".into()), Some(PathBuf::new()));
+        assert_eq!(paths.bookmarks(), PathBuf::from("/tmp/aero/bookmarks.json"));
+        assert_eq!(paths.staging(), PathBuf::from("/tmp/aero/staging"));
+        assert_eq!(paths.cache_dir, default_cache_dir());
+        assert_eq!(AppPaths::with_overrides(None, None).data_dir, default_data_dir());
+    }
+}
@@ -0,0 +1,154 @@
+//! Crash-safe writes for everything the app persists.
+//!
+//! Files are replaced whole via a temporary file and a rename, so a crash
+//! or force-quit leaves either the old version or the new one. Each write
+//! gets its own temporary file, so two threads saving the same file at once
+//! can't tear each other's copy. State files keep a `.bak` of the last
+//! version that parsed, and loading falls back to it when the file itself
+//! doesn't.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Backup path for a state file ("failed.json" -> "failed.json.bak").
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Replace `path` with `bytes` without keeping a backup, for files that
+/// aren't app state, like exports in a folder the user picked.
+pub(crate) fn atomic_write(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    replace(path, bytes, false)
+}
+
+/// Write beside `path` and rename over it, backing up what was there first
+/// if `backup` is set.
+fn replace(path: &Path, bytes: &[u8], backup: bool) -> io::Result<()> {
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    if backup {
+        write_renaming(parent, &backup_path(path), &fs::read(path)?)?;
+    }
+    write_renaming(parent, path, bytes)?;
+
+    // Make the rename itself durable; not possible everywhere, so best effort
+    if let Ok(dir) = fs::File::open(parent) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Write `bytes` to a new temporary file in `dir` and rename it to `path`.
+fn write_renaming(dir: &Path, path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut prefix = path.file_name().unwrap_or_default().to_owned();
+    prefix.push(".");
+    let mut file = tempfile::Builder::new().prefix(&prefix).suffix(".tmp").tempfile_in(dir)?;
+    file.write_all(bytes)?;
+    file.as_file().sync_all()?;
+    file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Load a state file, falling back to its backup if the file is unreadable.
+pub(crate) fn load_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    read_json(path).or_else(|| read_json(&backup_path(path)))
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Save a state file, moving the previous version to its backup first.
+pub(crate) fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    // Only back up a file that still parses, so a corrupt write never
+    // replaces a good backup
+    replace(path, json.as_bytes(), read_json::<serde_json::Value>(path).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(backup_path(Path::new("/a/failed.json")), PathBuf::from("/a/failed.json.bak"));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state/test.json");
+
+        save_json(&path, &vec![1, 2]).unwrap();
+        save_json(&path, &vec![3]).unwrap();
+        assert_eq!(load_json::<Vec<i32>>(&path), Some(vec![3]));
+        assert_eq!(read_json::<Vec<i32>>(&backup_path(&path)), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_load_falls_back_to_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.json");
+
+        save_json(&path, &vec![1]).unwrap();
+        save_json(&path, &vec![2]).unwrap();
+        fs::write(&path, "{corrupt").unwrap();
+        assert_eq!(load_json::<Vec<i32>>(&path), Some(vec![1]));
+
+        // A corrupt file is not backed up over the good backup
+        save_json(&path, &vec![3]).unwrap();
+        assert_eq!(read_json::<Vec<i32>>(&backup_path(&path)), Some(vec![1]));
+    }
+
+    #[test]
+    fn test_atomic_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out/snapshot.txt");
+
+        atomic_write(&path, b"one").unwrap();
+        atomic_write(&path, b"two").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"two");
+        // Nothing left beside an export but the export
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_saves() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.json");
+        let long = |n: usize| vec![n; 50_000];
+
+        std::thread::scope(|scope| {
+            for n in 0..4 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..5 {
+                        save_json(path, &long(n)).unwrap();
+                    }
+                });
+            }
+        });
+        // Whichever save landed last, it landed whole
+        let saved = read_json::<Vec<usize>>(&path).unwrap();
+        assert_eq!(saved, long(saved[0]));
+        assert_eq!(read_json::<Vec<usize>>(&backup_path(&path)).unwrap().len(), 50_000);
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load_json::<Vec<i32>>(&dir.path().join("none.json")), None);
+    }
+}
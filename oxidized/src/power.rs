@@ -0,0 +1,172 @@
+//! AC vs battery detection, so background work can ease off on battery.
+
+use std::process::Command;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+
+/// How long a `pmset` reading is reused. Every watcher asks before each
+/// poll, and spawning pmset that often would cost more than it saves.
+pub const POWER_CHECK_INTERVAL: Duration = Duration::seconds(60);
+
+/// Where the Mac is drawing power from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerSource {
+    Ac,
+    Battery,
+    /// pmset unavailable or its output unrecognized
+    Unknown,
+}
+
+/// Whether to throttle background work to save power.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerSaving {
+    /// Save power only while on battery
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Current power source and charge.
+#[derive(Debug, Clone, Serialize)]
+pub struct PowerState {
+    pub source: PowerSource,
+    /// Battery charge percentage, if there is a battery
+    pub battery_percent: Option<u8>,
+    /// Whether background work is currently being reduced
+    pub low_power: bool,
+}
+
+impl PowerState {
+    /// Parse `pmset -g batt` output, e.g.
+    /// "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=1)\t82%; discharging; ..."
+    pub fn from_pmset(output: &str, saving: PowerSaving) -> Self {
+        let source = if output.contains("'AC Power'") {
+            PowerSource::Ac
+        } else if output.contains("'Battery Power'") {
+            PowerSource::Battery
+        } else {
+            PowerSource::Unknown
+        };
+
+        let battery_percent = output
+            .split_whitespace()
+            .find_map(|word| word.strip_suffix("%;")?.parse().ok());
+
+        let low_power = match saving {
+            PowerSaving::Auto => source == PowerSource::Battery,
+            PowerSaving::Always => true,
+            PowerSaving::Never => false,
+        };
+
+        Self { source, battery_percent, low_power }
+    }
+}
+
+fn read_pmset() -> String {
+    // No pmset off macOS; the source reads as unknown
+    if cfg!(feature = "dev-sample") {
+        return String::new();
+    }
+    Command::new("pmset")
+        .args(["-g", "batt"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        .unwrap_or_default()
+}
+
+/// The power state, read from `pmset` at most once per
+/// [`POWER_CHECK_INTERVAL`].
+pub struct PowerMonitor {
+    read: Box<dyn Fn() -> String + Send + Sync>,
+    last: Mutex<Option<(DateTime<Utc>, String)>>,
+}
+
+impl Default for PowerMonitor {
+    fn default() -> Self {
+        Self::with_reader(read_pmset)
+    }
+}
+
+impl PowerMonitor {
+    /// A monitor reading `pmset -g batt`-style output from `read`.
+    pub fn with_reader(read: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        Self { read: Box::new(read), last: Mutex::new(None) }
+    }
+
+    /// The current power state, rereading it once the last reading is stale.
+    pub fn state(&self, saving: PowerSaving, clock: &dyn Clock) -> PowerState {
+        let now = clock.now();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        let fresh = last.as_ref().is_some_and(|(at, _)| now >= *at && now - *at < POWER_CHECK_INTERVAL);
+        if !fresh {
+            *last = Some((now, (self.read)()));
+        }
+        PowerState::from_pmset(last.as_ref().map_or("", |(_, output)| output), saving)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BATTERY: &str = "Now drawing from 'Battery Power'\n -InternalBattery-0 (id=4653155)\t82%; discharging; 5:12 remaining present: true\n";
+    const AC: &str = "Now drawing from 'AC Power'\n -InternalBattery-0 (id=4653155)\t100%; charged; 0:00 remaining present: true\n";
+    const DESKTOP: &str = "Now drawing from 'AC Power'\n";
+
+    #[test]
+    fn test_parse_pmset() {
+        let battery = PowerState::from_pmset(BATTERY, PowerSaving::Auto);
+        assert_eq!(battery.source, PowerSource::Battery);
+        assert_eq!(battery.battery_percent, Some(82));
+        assert!(battery.low_power);
+
+        let ac = PowerState::from_pmset(AC, PowerSaving::Auto);
+        assert_eq!(ac.source, PowerSource::Ac);
+        assert_eq!(ac.battery_percent, Some(100));
+        assert!(!ac.low_power);
+
+        let desktop = PowerState::from_pmset(DESKTOP, PowerSaving::Auto);
+        assert_eq!(desktop.battery_percent, None);
+
+        let unknown = PowerState::from_pmset("", PowerSaving::Auto);
+        assert_eq!(unknown.source, PowerSource::Unknown);
+        assert!(!unknown.low_power);
+    }
+
+    #[test]
+    fn test_monitor_caches_readings() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use crate::clock::FixedClock;
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let counter = reads.clone();
+        let monitor = PowerMonitor::with_reader(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            BATTERY.to_string()
+        });
+        let clock = FixedClock::new(Utc::now());
+        assert!(monitor.state(PowerSaving::Auto, &clock).low_power);
+        assert!(!monitor.state(PowerSaving::Never, &clock).low_power);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        clock.advance(POWER_CHECK_INTERVAL);
+        monitor.state(PowerSaving::Auto, &clock);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_overrides() {
+        assert!(!PowerState::from_pmset(BATTERY, PowerSaving::Never).low_power);
+        assert!(PowerState::from_pmset(AC, PowerSaving::Always).low_power);
+    }
+}
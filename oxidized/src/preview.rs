@@ -0,0 +1,136 @@
+//! Which image stands for a conversation in the list.
+//!
+//! The latest image sent in the chat wins, then the contact's photo for
+//! one-to-one chats, then initials drawn from the name. The choice is made
+//! while loading, and small images come along inline, so the list doesn't
+//! have to ask per row.
+
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::contacts::ContactResolver;
+use crate::models::{Attachment, Conversation};
+
+/// Largest image sent inline with its conversation; bigger ones are
+/// fetched with `get_attachment` or `get_contact_avatar`.
+pub const MAX_INLINE_PREVIEW_BYTES: u64 = 96 * 1024;
+
+/// What to draw as a conversation's thumbnail.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PreviewImageRef {
+    /// An image attachment, by its path for `get_attachment`
+    Attachment {
+        relative_path: String,
+        /// The image as a data: URL when it's small enough to send inline
+        #[serde(default)]
+        data_url: Option<String>,
+    },
+    /// The contact photo for a handle, from `get_contact_avatar`
+    Avatar {
+        handle: String,
+        /// The photo as a data: URL when it's small enough to send inline
+        #[serde(default)]
+        data_url: Option<String>,
+    },
+    /// Up to two letters, or "#" for a bare number
+    Initials { initials: String },
+}
+
+impl Conversation {
+    /// Pick the thumbnail: `latest_image`, the newest image in the whole
+    /// chat, then the contact photo in `contacts`, then initials. Images
+    /// under [`MAX_INLINE_PREVIEW_BYTES`] are read, attachments from under
+    /// `attachments_dir`, and sent inline.
+    pub fn preview_image_ref(
+        &self,
+        latest_image: Option<&Attachment>,
+        contacts: &ContactResolver,
+        attachments_dir: &Path,
+    ) -> PreviewImageRef {
+        let latest_image = latest_image.filter(|a| a.is_image()).and_then(|a| Some((a.relative_path.clone()?, &a.mime_type)));
+        if let Some((relative_path, mime_type)) = latest_image {
+            let path = attachments_dir.join(&relative_path);
+            let small = std::fs::metadata(&path).is_ok_and(|m| m.len() <= MAX_INLINE_PREVIEW_BYTES);
+            let data_url = small.then(|| std::fs::read(&path).ok()).flatten().map(|data| data_url(mime_type, &data));
+            return PreviewImageRef::Attachment { relative_path, data_url };
+        }
+
+        if !self.is_group() {
+            if let Some(avatar) = contacts.avatar_source(&self.chat_identifier) {
+                let small = avatar.size().is_some_and(|size| size <= MAX_INLINE_PREVIEW_BYTES);
+                let data_url = small.then(|| avatar.read()).flatten().map(|data| data_url("image/jpeg", &data));
+                return PreviewImageRef::Avatar { handle: self.chat_identifier.clone(), data_url };
+            }
+        }
+
+        PreviewImageRef::Initials { initials: initials(self.name()) }
+    }
+}
+
+fn data_url(mime_type: &str, data: &[u8]) -> String {
+    format!("data:{};base64,{}", mime_type, STANDARD.encode(data))
+}
+
+/// First letters of the first and last words that start with a letter.
+fn initials(name: &str) -> String {
+    let letters: Vec<char> = name
+        .split_whitespace()
+        .filter_map(|w| w.chars().next())
+        .filter(|c| c.is_alphabetic())
+        .collect();
+    match letters.as_slice() {
+        [] => "#".to_string(),
+        [only] => only.to_uppercase().collect(),
+        [first, .., last] => first.to_uppercase().chain(last.to_uppercase()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{conversation, message};
+    use crate::models::{Attachment, Message};
+
+    fn conv(identifier: &str, style: i32) -> Conversation {
+        Conversation {
+            style,
+            messages: vec![Message { guid: "m1".into(), ..message("") }],
+            ..conversation(identifier)
+        }
+    }
+
+    fn attachment(name: &str, mime: &str) -> Attachment {
+        Attachment::new(format!("~/Library/Messages/Attachments/{}", name), mime.into(), name.into())
+    }
+
+    #[test]
+    fn test_preview_fallbacks() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b.png"), b"png").unwrap();
+        std::fs::write(dir.path().join("big.jpg"), vec![0; MAX_INLINE_PREVIEW_BYTES as usize + 1]).unwrap();
+        let contacts = ContactResolver::new();
+        let one = conv("+15551234567", 45);
+
+        let png = attachment("b.png", "image/png");
+        assert_eq!(
+            one.preview_image_ref(Some(&png), &contacts, dir.path()),
+            PreviewImageRef::Attachment { relative_path: "b.png".into(), data_url: Some("data:image/png;base64,cG5n".into()) }
+        );
+        let big = attachment("big.jpg", "image/jpeg");
+        assert_eq!(
+            one.preview_image_ref(Some(&big), &contacts, dir.path()),
+            PreviewImageRef::Attachment { relative_path: "big.jpg".into(), data_url: None }
+        );
+        let pdf = attachment("c.pdf", "application/pdf");
+        assert_eq!(one.preview_image_ref(Some(&pdf), &contacts, dir.path()), PreviewImageRef::Initials { initials: "#".into() });
+
+        let mut named = conv("+15551234567", 43);
+        named.display_name = Some("book club 2024".into());
+        assert_eq!(named.preview_image_ref(None, &contacts, dir.path()), PreviewImageRef::Initials { initials: "BC".into() });
+        assert_eq!(initials("émile"), "É");
+    }
+}
@@ -0,0 +1,168 @@
+//! Conversation context formatted for LLM prompts.
+//!
+//! Token counts are estimated at four characters per token, which is close
+//! enough for English to keep prompts under a model's limit.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::contacts::format_display;
+use crate::models::{Conversation, Message};
+
+/// One message as it appears in a prompt.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptMessage {
+    pub sender: String,
+    pub date: DateTime<Utc>,
+    pub text: String,
+}
+
+/// The JSON form of a prompt context.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptContext {
+    pub name: String,
+    pub is_group: bool,
+    pub participants: Vec<String>,
+    /// Oldest messages left out to stay within the token budget
+    pub omitted: usize,
+    pub messages: Vec<PromptMessage>,
+}
+
+/// Rough token count for a piece of text.
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+impl PromptMessage {
+    fn line(&self) -> String {
+        format!("[{}] {}: {}", self.date.format("%Y-%m-%d %H:%M"), self.sender, self.text)
+    }
+}
+
+impl Conversation {
+    /// Who sent a message, by name where we have one.
+    fn prompt_sender(&self, message: &Message) -> String {
+        if message.is_from_me {
+            "Me".to_string()
+        } else if !self.is_group() {
+            self.name().to_string()
+        } else {
+            message.sender_label()
+        }
+    }
+
+    /// Message text with attachments and reactions spelled out.
+    fn prompt_text(message: &Message) -> String {
+        let mut text = message.display_text();
+        if text.is_empty() {
+            text = match (message.audio_label(), &message.link_preview) {
+                (Some(audio), _) => format!("[{}]", audio),
+                (None, Some(link)) => format!("[link: {}]", link.label()),
+                (None, None) if message.is_image_only() => "[image]".to_string(),
+                (None, None) => "[attachment]".to_string(),
+            };
+        }
+        let reactions = message.reaction_label_summary("en");
+        if !reactions.is_empty() {
+            text.push_str(&format!(" (reactions: {})", reactions));
+        }
+        text
+    }
+
+    /// The newest messages that fit in `max_tokens_estimate`, as structured data.
+    pub fn to_prompt_json(&self, max_tokens_estimate: usize) -> PromptContext {
+        let header_tokens = estimate_tokens(&self.prompt_header());
+        let mut budget = max_tokens_estimate.saturating_sub(header_tokens);
+
+        let mut messages = Vec::new();
+        for message in self.messages.iter().rev() {
+            let message = PromptMessage {
+                sender: self.prompt_sender(message),
+                date: message.date,
+                text: Self::prompt_text(message),
+            };
+            let tokens = estimate_tokens(&message.line());
+            if tokens > budget {
+                break;
+            }
+            budget -= tokens;
+            messages.push(message);
+        }
+        messages.reverse();
+
+        PromptContext {
+            name: self.name().to_string(),
+            is_group: self.is_group(),
+            participants: self.participants.iter().map(|p| format_display(p)).collect(),
+            omitted: self.messages.len() - messages.len(),
+            messages,
+        }
+    }
+
+    /// A plain-text transcript of the newest messages that fit in
+    /// `max_tokens_estimate`, with a note when older ones were cut.
+    pub fn to_prompt_context(&self, max_tokens_estimate: usize) -> String {
+        let context = self.to_prompt_json(max_tokens_estimate);
+
+        let mut out = self.prompt_header();
+        if context.omitted > 0 {
+            out.push_str(&format!("({} earlier messages omitted)\n", context.omitted));
+        }
+        for message in &context.messages {
+            out.push_str(&message.line());
+            out.push('\n');
+        }
+        out
+    }
+
+    fn prompt_header(&self) -> String {
+        if self.is_group() {
+            let participants: Vec<String> = self.participants.iter().map(|p| format_display(p)).collect();
+            format!("Group chat \"{}\" with {}\n", self.name(), participants.join(", "))
+        } else {
+            format!("Conversation with {}\n", self.name())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{conversation, message};
+
+    fn msg(text: &str, is_from_me: bool) -> Message {
+        Message {
+            guid: "test".into(),
+            date: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            is_from_me,
+            sender: (!is_from_me).then(|| "+15551234567".into()),
+            ..message(text)
+        }
+    }
+
+    fn conv(messages: Vec<Message>) -> Conversation {
+        Conversation { messages, resolved_name: Some("John".into()), ..conversation("+15551234567") }
+    }
+
+    #[test]
+    fn test_prompt_context_names_senders() {
+        let conv = conv(vec![msg("dinner tonight?", false), msg("sure!", true)]);
+        let text = conv.to_prompt_context(1000);
+        assert_eq!(
+            text,
+            "Conversation with John\n\
+             [2023-11-14 22:13] John: dinner tonight?\n\
+             [2023-11-14 22:13] Me: sure!\n"
+        );
+    }
+
+    #[test]
+    fn test_prompt_context_drops_oldest_first() {
+        let conv = conv(vec![msg(&"old ".repeat(50), false), msg("newest", false)]);
+        let context = conv.to_prompt_json(30);
+        assert_eq!(context.omitted, 1);
+        assert_eq!(context.messages.len(), 1);
+        assert_eq!(context.messages[0].text, "newest");
+        assert!(conv.to_prompt_context(30).contains("(1 earlier messages omitted)"));
+    }
+}
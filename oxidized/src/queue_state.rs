@@ -0,0 +1,73 @@
+//! Drafts, committed replies, the later list and composed batch messages,
+//! kept across restarts.
+//!
+//! The app holds these in memory while running; this is the copy on disk
+//! it starts from. Chats are keyed by chat.guid, which survives the chat.db
+//! rebuilds that renumber ROWIDs, so a restored reply can't land in another
+//! chat.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compose::ComposedMessage;
+use crate::store::Document;
+
+/// Reply work in progress, by chat GUID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueState {
+    pub drafts: HashMap<String, String>,
+    /// Replies marked ready for `send_all`
+    pub committed: HashMap<String, String>,
+    pub later: HashSet<String>,
+    /// Batch messages to handles with no chat, waiting for `send_composed`
+    pub composed: Vec<ComposedMessage>,
+}
+
+impl QueueState {
+    /// Default queue file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().queue_state()
+    }
+
+    /// Load the saved state, starting empty if nothing readable was saved.
+    pub fn load(doc: &Document) -> Self {
+        doc.load().unwrap_or_default()
+    }
+
+    pub fn save(&self, doc: &Document) -> std::io::Result<()> {
+        doc.save(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.json");
+        let doc = Document::file(&path);
+        assert_eq!(QueueState::load(&doc), QueueState::default());
+
+        let mut state = QueueState::default();
+        state.drafts.insert("iMessage;-;+15551234567".into(), "see you then".into());
+        state.committed.insert("iMessage;+;chat123".into(), "on my way".into());
+        state.later.insert("SMS;-;+15557654321".into());
+        state.composed.push(ComposedMessage {
+            recipient: "+15559876543".into(),
+            name: "(555) 987-6543".into(),
+            text: "welcome aboard".into(),
+            attachments: Vec::new(),
+        });
+        state.save(&doc).unwrap();
+        assert_eq!(QueueState::load(&doc), state);
+
+        // Files from before a field existed still load
+        std::fs::write(&path, r#"{"later": ["SMS;-;+15557654321"]}"#).unwrap();
+        assert_eq!(QueueState::load(&doc).later, HashSet::from(["SMS;-;+15557654321".to_string()]));
+    }
+}
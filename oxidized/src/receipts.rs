@@ -0,0 +1,93 @@
+//! Whether marking a chat read tells the other person.
+//!
+//! Messages has a global "Send Read Receipts" preference in
+//! com.apple.iChat.plist, which each chat can override from its details
+//! pane. The override is stored in the chat's `properties` plist in chat.db.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use plist::Value;
+
+use crate::models::Conversation;
+
+/// ~/Library/Preferences/com.apple.iChat.plist, where the global setting lives.
+pub fn messages_prefs_path() -> PathBuf {
+    dirs::home_dir()
+        .expect("home directory required")
+        .join("Library/Preferences/com.apple.iChat.plist")
+}
+
+/// The global "Send Read Receipts" setting, or None if it can't be read.
+pub fn global_read_receipts(prefs: &Path) -> Option<bool> {
+    let value = Value::from_file(prefs).ok()?;
+    value.as_dictionary()?.get("ReadReceiptsEnabled")?.as_boolean()
+}
+
+/// A chat's own read receipt setting from its `properties` blob, or None if
+/// it follows the global one.
+pub fn chat_read_receipts(properties: &[u8]) -> Option<bool> {
+    let value = Value::from_reader(Cursor::new(properties)).ok()?;
+    let dict = value.as_dictionary()?;
+    // Set once the user has touched the per-chat toggle
+    if dict.get("ReadReceiptsManuallyEnabled").and_then(Value::as_boolean) == Some(false) {
+        return None;
+    }
+    dict.get("EnableReadReceiptForChat")?.as_boolean()
+}
+
+impl Conversation {
+    /// Whether this kind of chat can send read receipts at all. SMS has no
+    /// receipts and iMessage groups never send them.
+    pub fn supports_read_receipts(&self) -> bool {
+        !self.is_group() && self.service_name.as_deref() != Some("SMS")
+    }
+
+    /// Fill in `read_receipts` from the global setting for chats without their
+    /// own.
+    pub fn apply_global_read_receipts(&mut self, global: Option<bool>) {
+        if self.read_receipts.is_none() {
+            self.read_receipts = global;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plist::Dictionary;
+
+    fn properties(entries: &[(&str, bool)]) -> Vec<u8> {
+        let mut dict = Dictionary::new();
+        for (key, value) in entries {
+            dict.insert(key.to_string(), Value::Boolean(*value));
+        }
+        let mut out = Vec::new();
+        Value::Dictionary(dict).to_writer_binary(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_chat_override() {
+        let on = properties(&[("EnableReadReceiptForChat", true), ("ReadReceiptsManuallyEnabled", true)]);
+        assert_eq!(chat_read_receipts(&on), Some(true));
+
+        let off = properties(&[("EnableReadReceiptForChat", false)]);
+        assert_eq!(chat_read_receipts(&off), Some(false));
+
+        let untouched = properties(&[("EnableReadReceiptForChat", true), ("ReadReceiptsManuallyEnabled", false)]);
+        assert_eq!(chat_read_receipts(&untouched), None);
+
+        assert_eq!(chat_read_receipts(&properties(&[])), None);
+        assert_eq!(chat_read_receipts(b"not a plist"), None);
+    }
+
+    #[test]
+    fn test_global_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("com.apple.iChat.plist");
+        std::fs::write(&path, properties(&[("ReadReceiptsEnabled", true)])).unwrap();
+        assert_eq!(global_read_receipts(&path), Some(true));
+        assert_eq!(global_read_receipts(&dir.path().join("missing.plist")), None);
+    }
+}
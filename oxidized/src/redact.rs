@@ -0,0 +1,164 @@
+//! Masking of personal details before text leaves the machine.
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::db::ExportEvent;
+use crate::models::Conversation;
+
+/// Which kinds of personal details to mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionConfig {
+    pub phone_numbers: bool,
+    pub emails: bool,
+    pub card_numbers: bool,
+    pub street_addresses: bool,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            phone_numbers: true,
+            emails: true,
+            card_numbers: true,
+            street_addresses: true,
+        }
+    }
+}
+
+/// Replaces personal details in text with placeholders like "[phone]".
+pub struct Redactor {
+    config: RedactionConfig,
+    email: Regex,
+    card: Regex,
+    phone: Regex,
+    address: Regex,
+}
+
+impl Redactor {
+    pub fn new(config: RedactionConfig) -> Self {
+        Self {
+            config,
+            email: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap(),
+            card: Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap(),
+            phone: Regex::new(r"(?:\+\d{1,3}[\s.-]?\(?\d{3}\)?|\(\d{3}\)|\b\d{3})[\s.-]?\d{3}[\s.-]?\d{4}\b").unwrap(),
+            address: Regex::new(
+                r"\b\d{1,5}\s+(?:[A-Z][A-Za-z]*\s+){1,3}(?i:street|st|avenue|ave|road|rd|boulevard|blvd|lane|ln|drive|dr|court|ct|way|place|pl)\b\.?",
+            ).unwrap(),
+        }
+    }
+
+    /// Mask everything the config enables. Cards go before phones so a card
+    /// number isn't half-matched as a phone number.
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        if self.config.emails {
+            out = self.email.replace_all(&out, "[email]").into_owned();
+        }
+        if self.config.card_numbers {
+            out = self.card
+                .replace_all(&out, |caps: &Captures| {
+                    if luhn_valid(&caps[0]) { "[card]".to_string() } else { caps[0].to_string() }
+                })
+                .into_owned();
+        }
+        if self.config.phone_numbers {
+            out = self.phone.replace_all(&out, "[phone]").into_owned();
+        }
+        if self.config.street_addresses {
+            out = self.address.replace_all(&out, "[address]").into_owned();
+        }
+        out
+    }
+
+    /// Redact message text and handles in a conversation.
+    pub fn redact_conversation(&self, conv: &mut Conversation) {
+        for message in &mut conv.messages {
+            message.text = self.redact(&message.text);
+            message.sender = message.sender.as_deref().map(|s| self.redact(s));
+            message.sender_name = message.sender_name.as_deref().map(|s| self.redact(s));
+        }
+        conv.participants = conv.participants.iter().map(|p| self.redact(p)).collect();
+        conv.chat_identifier = self.redact(&conv.chat_identifier);
+        conv.resolved_name = conv.resolved_name.as_deref().map(|n| self.redact(n));
+        conv.messages_app_draft = conv.messages_app_draft.as_deref().map(|d| self.redact(d));
+    }
+
+    /// Redact text and handles in an exported message.
+    pub fn redact_event(&self, event: &mut ExportEvent) {
+        event.text = self.redact(&event.text);
+        event.sender = event.sender.as_deref().map(|s| self.redact(s));
+        event.sender_name = event.sender_name.as_deref().map(|s| self.redact(s));
+        event.chat_identifier = self.redact(&event.chat_identifier);
+        event.chat_guid = self.redact(&event.chat_guid);
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(RedactionConfig::default())
+    }
+}
+
+/// Luhn checksum, so order numbers and the like aren't masked as cards.
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum % 10 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_phone_numbers() {
+        let r = Redactor::default();
+        assert_eq!(r.redact("call me at (555) 123-4567"), "call me at [phone]");
+        assert_eq!(r.redact("or +1 555.123.4567 tonight"), "or [phone] tonight");
+        assert_eq!(r.redact("+15551234567"), "[phone]");
+        assert_eq!(r.redact("meet at 5:30, room 204"), "meet at 5:30, room 204");
+    }
+
+    #[test]
+    fn test_masks_emails() {
+        let r = Redactor::default();
+        assert_eq!(r.redact("send it to jane.doe+work@example.co.uk"), "send it to [email]");
+    }
+
+    #[test]
+    fn test_masks_only_luhn_valid_cards() {
+        let r = Redactor::default();
+        assert_eq!(r.redact("card 4111 1111 1111 1111 exp 12/29"), "card [card] exp 12/29");
+        assert_eq!(r.redact("4111-1111-1111-1111"), "[card]");
+        // Fails the checksum, so it's probably an order number
+        assert_eq!(r.redact("order 1234567890123456"), "order 1234567890123456");
+    }
+
+    #[test]
+    fn test_masks_street_addresses() {
+        let r = Redactor::default();
+        assert_eq!(r.redact("I'm at 221 Baker Street now"), "I'm at [address] now");
+        assert_eq!(r.redact("party at 1600 Pennsylvania Ave."), "party at [address]");
+        assert_eq!(r.redact("I have 3 cats"), "I have 3 cats");
+    }
+
+    #[test]
+    fn test_config_disables_categories() {
+        let r = Redactor::new(RedactionConfig { emails: false, ..RedactionConfig::default() });
+        assert_eq!(r.redact("a@b.com (555) 123-4567"), "a@b.com [phone]");
+    }
+
+    #[test]
+    fn test_luhn() {
+        assert!(luhn_valid("4111111111111111"));
+        assert!(luhn_valid("5500 0000 0000 0004"));
+        assert!(!luhn_valid("4111111111111112"));
+    }
+}
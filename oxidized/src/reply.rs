@@ -0,0 +1,167 @@
+//! Whether a conversation is waiting on an answer from me.
+//!
+//! Unread doesn't mean unanswered: "ok" needs nothing back, and a group
+//! chat question is usually for whoever knows. A chat needs a reply when a
+//! person asked me a question one-to-one, or @mentioned me in a group.
+//! Automated senders never do.
+
+use chrono::{DateTime, Utc};
+
+use crate::contacts::{same_handle, ContactResolver, format_display};
+use crate::kind::MessageKind;
+use crate::models::{Conversation, Message};
+
+/// Openings that make a question even without the question mark.
+const QUESTION_OPENINGS: &[&str] = &[
+    "can you", "could you", "would you", "will you", "are you", "do you", "did you", "have you",
+    "what", "when", "where", "who", "why", "how", "any chance",
+];
+
+/// Whether a message asks something.
+fn is_question(message: &Message) -> bool {
+    if message.kind() != MessageKind::Text {
+        return false;
+    }
+    let text = message.display_text().to_lowercase();
+    text.contains('?')
+        || QUESTION_OPENINGS.iter().any(|opening| {
+            text.strip_prefix(opening).is_some_and(|rest| rest.starts_with(|c: char| !c.is_alphanumeric()))
+        })
+}
+
+/// "2 days ago" and the like, for reply reasons.
+fn ago(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = now - then;
+    let (n, unit) = if elapsed.num_minutes() < 1 {
+        return "just now".to_string();
+    } else if elapsed.num_hours() < 1 {
+        (elapsed.num_minutes(), "minute")
+    } else if elapsed.num_days() < 1 {
+        (elapsed.num_hours(), "hour")
+    } else if elapsed.num_weeks() < 2 {
+        (elapsed.num_days(), "day")
+    } else {
+        (elapsed.num_weeks(), "week")
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+impl Conversation {
+    /// Whether `message` @mentions me, by any of `my_handles`.
+    fn mentions_me(&self, message: &Message) -> bool {
+        message.mentions.iter().any(|handle| self.my_handles.iter().any(|mine| same_handle(mine, handle)))
+    }
+
+    /// Who sent `message`, by name where `contacts` knows it.
+    fn sender_name(&self, message: &Message, contacts: Option<&ContactResolver>) -> String {
+        if !self.is_group() {
+            return self.name().to_string();
+        }
+        let Some(handle) = message.sender.as_deref() else {
+            return "Someone".to_string();
+        };
+        message
+            .sender_name
+            .clone()
+            .or_else(|| contacts.and_then(|c| c.resolve(handle)).map(str::to_string))
+            .unwrap_or_else(|| format_display(handle))
+    }
+
+    /// Why the chat needs a reply, e.g. "Alice asked a question 2 days ago",
+    /// or None if it doesn't. Looks at the oldest unread message that counts.
+    pub fn reply_reason(&self, contacts: Option<&ContactResolver>, now: DateTime<Utc>) -> Option<String> {
+        if self.automated_kind().is_some() {
+            return None;
+        }
+        self.messages
+            .iter()
+            .filter(|m| !m.is_from_me && m.date >= self.first_unread_date)
+            .find_map(|m| {
+                let what = if self.is_group() {
+                    self.mentions_me(m).then_some("mentioned you")
+                } else {
+                    is_question(m).then_some("asked a question")
+                }?;
+                Some(format!("{} {} {}", self.sender_name(m, contacts), what, ago(m.date, now)))
+            })
+    }
+
+    /// Whether an unread message @mentions me or asks a question, whoever
+    /// it's put to.
+    pub fn mentions_or_asks(&self) -> bool {
+        self.messages
+            .iter()
+            .filter(|m| !m.is_from_me && m.date >= self.first_unread_date)
+            .any(|m| self.mentions_me(m) || is_question(m))
+    }
+
+    /// Fill in `needs_reply` and its reason.
+    pub fn update_needs_reply(&mut self, contacts: Option<&ContactResolver>, now: DateTime<Utc>) {
+        self.needs_reply_reason = self.reply_reason(contacts, now);
+        self.needs_reply = self.needs_reply_reason.is_some();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::fixtures::{conversation, message};
+
+    fn msg(text: &str, date: DateTime<Utc>, sender: Option<&str>) -> Message {
+        Message { date, is_from_me: sender.is_none(), sender: sender.map(Into::into), ..message(text) }
+    }
+
+    fn conv(identifier: &str, style: i32, messages: Vec<Message>, first_unread: DateTime<Utc>) -> Conversation {
+        Conversation {
+            style,
+            messages,
+            first_unread_date: first_unread,
+            resolved_name: Some("Alice".into()),
+            my_handles: vec!["me@example.com".into()],
+            ..conversation(identifier)
+        }
+    }
+
+    #[test]
+    fn test_reply_reason() {
+        let now = Utc::now();
+        let earlier = now - Duration::days(2);
+        let alice = Some("+15551234567");
+
+        let mut asked = conv("+15551234567", 45, vec![msg("Can you send the photos", earlier, alice), msg("ok", now, alice)], earlier);
+        asked.update_needs_reply(None, now);
+        assert!(asked.needs_reply);
+        assert_eq!(asked.needs_reply_reason.as_deref(), Some("Alice asked a question 2 days ago"));
+
+        // Answered before the unread ones arrived
+        let answered = conv("+15551234567", 45, vec![msg("coming?", earlier, alice), msg("yes", earlier, None), msg("thanks", now, alice)], now);
+        assert_eq!(answered.reply_reason(None, now), None);
+        assert_eq!(conv("72975", 45, vec![msg("Why not reply YES?", now, Some("72975"))], now).reply_reason(None, now), None);
+
+        let mut mention = msg("@Sam are you in", now, Some("+14158675309"));
+        let mut group = conv("chat123", 43, vec![msg("who's in?", now, Some("+14158675309"))], now);
+        group.participants = vec!["+14158675309".into(), "+1 (555) 000-2222".into()];
+        assert_eq!(group.reply_reason(None, now), None);
+        mention.mentions = vec!["+15550002222".into()];
+        group.messages.push(mention.clone());
+        assert_eq!(group.reply_reason(None, now), None);
+        // Someone who has since left the chat isn't me either
+        mention.mentions = vec!["+15550003333".into()];
+        group.messages.push(mention.clone());
+        assert_eq!(group.reply_reason(None, now), None);
+        mention.mentions = vec!["me@example.com".into()];
+        group.messages.push(mention);
+        assert_eq!(group.reply_reason(None, now).as_deref(), Some("(415) 867-5309 mentioned you just now"));
+    }
+
+    #[test]
+    fn test_ago() {
+        let now = Utc::now();
+        assert_eq!(ago(now - Duration::seconds(30), now), "just now");
+        assert_eq!(ago(now - Duration::minutes(1), now), "1 minute ago");
+        assert_eq!(ago(now - Duration::hours(5), now), "5 hours ago");
+        assert_eq!(ago(now - Duration::days(9), now), "9 days ago");
+        assert_eq!(ago(now - Duration::days(30), now), "4 weeks ago");
+    }
+}
@@ -0,0 +1,225 @@
+//! Persisted queue of failed sends and retry with exponential backoff.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::store::Document;
+use crate::send::{ErrorCategory, SendError};
+
+/// A send that failed and may be retried.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedSend {
+    pub chat_id: i64,
+    #[serde(default)]
+    pub chat_guid: String,
+    pub chat_identifier: String,
+    pub is_group: bool,
+    pub name: String,
+    pub text: String,
+    /// Total attempts so far, including the original send
+    pub attempts: u32,
+    pub category: ErrorCategory,
+    pub last_error: String,
+    /// When the latest attempt failed
+    #[serde(default)]
+    pub failed_at: Option<DateTime<Utc>>,
+}
+
+/// Failed sends, saved as JSON so they survive a restart.
+pub struct FailedQueue {
+    doc: Document,
+    entries: Vec<FailedSend>,
+}
+
+impl FailedQueue {
+    /// Default queue file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().failed_sends()
+    }
+
+    /// Load the queue, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let entries = doc.load().unwrap_or_default();
+        Self { doc, entries }
+    }
+
+    /// Write the queue to disk.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.save_with(&[])
+    }
+
+    /// Write the queue to disk along with `pending`, entries taken out for
+    /// a retry that hasn't happened yet.
+    pub fn save_with(&self, pending: &[FailedSend]) -> std::io::Result<()> {
+        let entries: Vec<&FailedSend> = self.entries.iter().chain(pending).collect();
+        self.doc.save(&entries)
+    }
+
+    pub fn entries(&self) -> &[FailedSend] {
+        &self.entries
+    }
+
+    /// Add a failure, replacing any earlier one for the same chat.
+    pub fn push(&mut self, failed: FailedSend) {
+        self.entries.retain(|f| f.chat_guid != failed.chat_guid);
+        self.entries.push(failed);
+    }
+
+    /// Remove and return all entries.
+    pub fn take(&mut self) -> Vec<FailedSend> {
+        std::mem::take(&mut self.entries)
+    }
+
+    /// Drop the entry for a chat, e.g. after a new reply is committed.
+    pub fn remove(&mut self, chat_guid: &str) {
+        self.entries.retain(|f| f.chat_guid != chat_guid);
+    }
+}
+
+impl Default for FailedQueue {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+/// How many times to try and how long to wait between tries.
+#[derive(Debug, Clone, Copy)]
+pub struct BackoffPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl BackoffPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+
+    /// Delay after the given failed attempt (1-based), doubling each time.
+    pub fn delay_after(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+/// Result of retrying one send.
+#[derive(Debug)]
+pub struct RetryOutcome {
+    /// Attempts made during this retry
+    pub attempts: u32,
+    pub result: Result<(), SendError>,
+}
+
+/// Call `send` until it succeeds, fails with a non-retryable error, or the
+/// policy runs out of attempts. `sleep` is injected so tests don't wait.
+pub fn send_with_backoff<F, S>(policy: &BackoffPolicy, mut send: F, mut sleep: S) -> RetryOutcome
+where
+    F: FnMut() -> Result<(), SendError>,
+    S: FnMut(Duration),
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = send();
+        let give_up = match &result {
+            Ok(()) => true,
+            Err(e) => !e.category().is_retryable() || attempts >= policy.max_attempts,
+        };
+        if give_up {
+            return RetryOutcome { attempts, result };
+        }
+        sleep(policy.delay_after(attempts));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(chat_id: i64) -> FailedSend {
+        FailedSend {
+            chat_id,
+            chat_guid: format!("iMessage;-;chat{}", chat_id),
+            chat_identifier: "+15551234567".into(),
+            is_group: false,
+            name: "John".into(),
+            text: "hi".into(),
+            attempts: 1,
+            category: ErrorCategory::Timeout,
+            last_error: "timed out".into(),
+            failed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff_delays() {
+        let policy = BackoffPolicy::new(10);
+        assert_eq!(policy.delay_after(1), Duration::from_secs(1));
+        assert_eq!(policy.delay_after(2), Duration::from_secs(2));
+        assert_eq!(policy.delay_after(3), Duration::from_secs(4));
+        assert_eq!(policy.delay_after(20), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_retries_timeouts_until_success() {
+        let policy = BackoffPolicy::new(5);
+        let mut calls = 0;
+        let mut slept = Vec::new();
+        let outcome = send_with_backoff(
+            &policy,
+            || {
+                calls += 1;
+                if calls < 3 { Err(SendError::Timeout) } else { Ok(()) }
+            },
+            |d| slept.push(d),
+        );
+        assert!(outcome.result.is_ok());
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(slept, vec![Duration::from_secs(1), Duration::from_secs(2)]);
+    }
+
+    #[test]
+    fn test_does_not_retry_permission_errors() {
+        let policy = BackoffPolicy::new(5);
+        let outcome = send_with_backoff(
+            &policy,
+            || Err(SendError::ScriptError("Not authorized to send Apple events (-1743)".into())),
+            |_| panic!("should not sleep"),
+        );
+        assert_eq!(outcome.attempts, 1);
+        assert!(outcome.result.is_err());
+    }
+
+    #[test]
+    fn test_stops_at_max_attempts() {
+        let policy = BackoffPolicy::new(3);
+        let outcome = send_with_backoff(&policy, || Err(SendError::Timeout), |_| {});
+        assert_eq!(outcome.attempts, 3);
+    }
+
+    #[test]
+    fn test_queue_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("failed.json");
+
+        let mut queue = FailedQueue::load(Document::file(&path));
+        assert!(queue.entries().is_empty());
+        queue.push(failed(1));
+        queue.push(failed(2));
+        queue.push(failed(1));
+        queue.save().unwrap();
+
+        let mut reloaded = FailedQueue::load(Document::file(&path));
+        assert_eq!(reloaded.entries().len(), 2);
+        reloaded.remove("iMessage;-;chat2");
+        assert_eq!(reloaded.take().len(), 1);
+        assert!(reloaded.entries().is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+//! A small synthetic Messages library for working away from macOS.
+//!
+//! With the `dev-sample` feature the app reads a generated chat.db and
+//! address book from the cache folder instead of `~/Library`. AppleScript,
+//! `open`, `pbcopy`, `sips` and `pmset` aren't run, so the app starts and
+//! the tests pass on Linux. The same schema backs the database test
+//! fixtures.
+
+use std::path::Path;
+#[cfg(feature = "dev-sample")]
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection};
+
+use crate::db::DbError;
+use crate::unix_to_apple_nanos;
+#[cfg(feature = "dev-sample")]
+use crate::paths::AppPaths;
+
+/// The chat.db tables and columns the queries read, empty.
+pub(crate) const CHAT_DB_SCHEMA: &str = "
+    CREATE TABLE chat (ROWID INTEGER PRIMARY KEY, guid TEXT, chat_identifier TEXT,
+        display_name TEXT, style INTEGER, service_name TEXT, is_filtered INTEGER DEFAULT 0,
+        properties BLOB, last_addressed_handle TEXT);
+    CREATE TABLE handle (ROWID INTEGER PRIMARY KEY, id TEXT, service TEXT);
+    CREATE TABLE message (ROWID INTEGER PRIMARY KEY, guid TEXT, text TEXT,
+        attributedBody BLOB, date INTEGER, is_from_me INTEGER DEFAULT 0,
+        is_read INTEGER DEFAULT 0, item_type INTEGER DEFAULT 0,
+        is_finished INTEGER DEFAULT 1, cache_has_attachments INTEGER DEFAULT 0,
+        handle_id INTEGER DEFAULT 0, service TEXT, associated_message_guid TEXT,
+        associated_message_type INTEGER DEFAULT 0, other_handle INTEGER DEFAULT 0,
+        group_action_type INTEGER DEFAULT 0, group_title TEXT,
+        is_delivered INTEGER DEFAULT 0, date_delivered INTEGER DEFAULT 0,
+        date_read INTEGER DEFAULT 0);
+    CREATE TABLE chat_message_join (chat_id INTEGER, message_id INTEGER);
+    CREATE TABLE chat_handle_join (chat_id INTEGER, handle_id INTEGER);
+    CREATE TABLE attachment (ROWID INTEGER PRIMARY KEY, filename TEXT,
+        mime_type TEXT, transfer_name TEXT);
+    CREATE TABLE message_attachment_join (message_id INTEGER, attachment_id INTEGER);";
+
+/// Names for the sample handles, as the address book would give them.
+pub const SAMPLE_CONTACTS: &[(&str, &str)] = &[
+    ("+15550100001", "Alice Smith"),
+    ("+15550100002", "Bob Jones"),
+    ("carol@example.com", "Carol White"),
+];
+
+/// Chats in the sample: identifier, display name, style (45 direct, 43 group).
+const SAMPLE_CHATS: &[(&str, Option<&str>, i64)] = &[
+    ("+15550100001", None, 45),
+    ("carol@example.com", None, 45),
+    ("chat100001", Some("Weekend trip"), 43),
+    ("72975", None, 45),
+];
+
+/// Messages in the sample: chat index, sender handle (None for me), text,
+/// hours ago, read.
+const SAMPLE_MESSAGES: &[(usize, Option<&str>, &str, i64, bool)] = &[
+    (0, None, "Are we still on for Thursday?", 30, true),
+    (0, Some("+15550100001"), "Yes! Can you bring the projector?", 26, false),
+    (0, Some("+15550100001"), "Also, what time works for you?", 2, false),
+    (1, Some("carol@example.com"), "Sent you the draft, no rush", 24 * 9, false),
+    (2, Some("+15550100002"), "Who's driving on Saturday?", 5, false),
+    (2, Some("+15550100001"), "I can take four people", 4, false),
+    (2, None, "I'll drive too", 3, true),
+    (2, Some("+15550100002"), "👍", 1, false),
+    (3, Some("72975"), "Your verification code is 482913", 0, false),
+];
+
+/// Where the sample library lives: beside the app's other caches.
+#[cfg(feature = "dev-sample")]
+pub fn sample_dir() -> PathBuf {
+    AppPaths::default().cache_dir.join("sample")
+}
+
+/// Write the sample library into `dir`, replacing one already there:
+/// `chat.db`, and an address book under `Sources` naming
+/// [`SAMPLE_CONTACTS`]. Dates count back from `now` so the chats land in
+/// different age buckets.
+pub fn write_sample_library(dir: &Path, now: DateTime<Utc>) -> Result<(), DbError> {
+    let _ = std::fs::remove_dir_all(dir);
+    let address_book = dir.join("Sources/sample");
+    std::fs::create_dir_all(&address_book)?;
+    write_address_book(&address_book.join("AddressBook-v22.abcddb"))?;
+
+    let conn = Connection::open(dir.join("chat.db"))?;
+    conn.execute_batch(CHAT_DB_SCHEMA)?;
+
+    let tx = conn.unchecked_transaction()?;
+    // Handle ROWIDs by position, added as they're first needed
+    let mut handles: Vec<&str> = Vec::new();
+    let handle_id = |handle: &'static str, handles: &mut Vec<&str>| -> Result<i64, DbError> {
+        if let Some(i) = handles.iter().position(|h| *h == handle) {
+            return Ok(i as i64 + 1);
+        }
+        handles.push(handle);
+        let service = if handle.starts_with('7') { "SMS" } else { "iMessage" };
+        tx.execute("INSERT INTO handle (ROWID, id, service) VALUES (?1, ?2, ?3)", params![handles.len() as i64, handle, service])?;
+        Ok(handles.len() as i64)
+    };
+
+    for (i, (identifier, name, style)) in SAMPLE_CHATS.iter().enumerate() {
+        let chat_id = i as i64 + 1;
+        let separator = if *style == 43 { '+' } else { '-' };
+        tx.execute(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, display_name, style, service_name, last_addressed_handle)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'iMessage', 'me@example.com')",
+            params![chat_id, format!("iMessage;{};{}", separator, identifier), identifier, name, style],
+        )?;
+        let members: Vec<&str> = if *style == 43 {
+            vec![SAMPLE_CONTACTS[0].0, SAMPLE_CONTACTS[1].0]
+        } else {
+            vec![identifier]
+        };
+        for member in members {
+            let id = handle_id(member, &mut handles)?;
+            tx.execute("INSERT INTO chat_handle_join VALUES (?1, ?2)", [chat_id, id])?;
+        }
+    }
+
+    for (i, (chat, sender, text, hours_ago, is_read)) in SAMPLE_MESSAGES.iter().enumerate() {
+        let rowid = i as i64 + 1;
+        let date = unix_to_apple_nanos((now - Duration::hours(*hours_ago)).timestamp());
+        let sender_id = sender.map(|s| handle_id(s, &mut handles)).transpose()?.unwrap_or(0);
+        tx.execute(
+            "INSERT INTO message (ROWID, guid, text, date, is_from_me, is_read, handle_id, service, is_delivered)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 'iMessage', 1)",
+            params![rowid, format!("sample-{}", rowid), text, date, sender.is_none(), *is_read || sender.is_none(), sender_id],
+        )?;
+        tx.execute("INSERT INTO chat_message_join VALUES (?1, ?2)", [*chat as i64 + 1, rowid])?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// The tables of an AddressBook database that contacts are read from.
+fn write_address_book(path: &Path) -> Result<(), DbError> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE ZABCDRECORD (Z_PK INTEGER PRIMARY KEY, ZFIRSTNAME TEXT, ZLASTNAME TEXT, ZUNIQUEID TEXT);
+         CREATE TABLE ZABCDPHONENUMBER (ZOWNER INTEGER, ZFULLNUMBER TEXT);
+         CREATE TABLE ZABCDEMAILADDRESS (ZOWNER INTEGER, ZADDRESSNORMALIZED TEXT);",
+    )?;
+    for (i, (handle, name)) in SAMPLE_CONTACTS.iter().enumerate() {
+        let owner = i as i64 + 1;
+        let (first, last) = name.split_once(' ').unwrap_or((name, ""));
+        conn.execute("INSERT INTO ZABCDRECORD VALUES (?1, ?2, ?3, NULL)", params![owner, first, last])?;
+        let table = if handle.contains('@') { "ZABCDEMAILADDRESS" } else { "ZABCDPHONENUMBER" };
+        conn.execute(&format!("INSERT INTO {} VALUES (?1, ?2)", table), params![owner, handle])?;
+    }
+    Ok(())
+}
+
+/// The sample library's folder, written on first use so a fresh checkout
+/// runs without setup.
+#[cfg(feature = "dev-sample")]
+pub fn ensure_sample_library() -> PathBuf {
+    let dir = sample_dir();
+    if !dir.join("chat.db").exists() {
+        // If this fails, opening chat.db reports it missing
+        let _ = write_sample_library(&dir, Utc::now());
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::contacts::{load_address_books, ContactResolver};
+    use crate::db::Database;
+
+    #[test]
+    fn test_sample_library() {
+        let dir = tempfile::tempdir().unwrap();
+        write_sample_library(dir.path(), Utc::now()).unwrap();
+
+        let contacts = Mutex::new(ContactResolver::new());
+        assert_eq!(load_address_books(&dir.path().join("Sources"), &contacts, |_| {}), Ok(SAMPLE_CONTACTS.len()));
+        let contacts = contacts.into_inner().unwrap();
+        assert_eq!(contacts.resolve("carol@example.com"), Some("Carol White"));
+
+        let path = dir.path().join("chat.db");
+        let db = Database::open(&path).unwrap();
+        assert!(db.missing_schema().unwrap().is_empty());
+        let convs = db.unread_conversations(Some(&contacts)).unwrap();
+        let names: Vec<_> = convs.iter().map(|c| c.name()).collect();
+        assert_eq!(convs.len(), 4);
+        assert!(names.contains(&"Weekend trip") && names.contains(&"Alice Smith"), "{:?}", names);
+
+        // Writing again replaces rather than appends
+        write_sample_library(dir.path(), Utc::now()).unwrap();
+        assert_eq!(Database::open(&path).unwrap().message_count().unwrap(), SAMPLE_MESSAGES.len() as i64);
+    }
+}
@@ -0,0 +1,430 @@
+//! Full-text search across every conversation.
+//!
+//! chat.db has no text index, and newer messages keep their text only in
+//! attributedBody, so messages are copied into an FTS5 index. The index
+//! remembers how far it got and catches up before each search, re-reading
+//! messages edited since and dropping those unsent or deleted. It's a copy
+//! of every message, so it lives in the data directory, readable only by
+//! the user, rather than the relocatable cache.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::contacts::same_handle;
+use crate::db::{Database, DbError, ExportEvent, BUSY_TIMEOUT};
+use crate::models::MessageFilter;
+
+/// Messages read from chat.db per indexing transaction.
+const INDEX_BATCH: usize = 5000;
+
+/// Bumped when the index layout changes; an older index is rebuilt.
+/// 2 stores Unix seconds rather than chat.db dates; 3 keeps its place by
+/// ROWID, so messages an index read in date order skipped are picked up.
+const INDEX_VERSION: i64 = 3;
+
+/// Words of context either side of a match in [`SearchHit::snippet`].
+const SNIPPET_TOKENS: i64 = 12;
+
+/// A message matching a search.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub chat_id: i64,
+    pub chat_identifier: String,
+    pub rowid: i64,
+    pub guid: String,
+    pub date: DateTime<Utc>,
+    pub is_from_me: bool,
+    pub sender: Option<String>,
+    /// The matching part of the text
+    pub snippet: String,
+}
+
+/// How far the index has read chat.db.
+#[derive(Debug, Clone, Copy)]
+struct Progress {
+    /// The highest ROWID indexed. Messages are read in ROWID order, which
+    /// only grows, so one arriving late with an earlier date isn't missed.
+    rowid: i64,
+    /// Latest edit or unsend applied, as a raw chat.db date
+    revised: i64,
+    /// chat.db's highest ROWID at the last update, and how many messages
+    /// were at or below it, for spotting deletions
+    census_rowid: i64,
+    census_count: i64,
+}
+
+/// The on-disk search index.
+pub struct SearchIndex {
+    conn: Connection,
+}
+
+impl SearchIndex {
+    /// Default index in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().search_index()
+    }
+
+    /// Open the index, creating it if needed. A new index file is readable
+    /// only by the user.
+    pub fn open(path: &Path) -> Result<Self, DbError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        restrict_to_owner(path)?;
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != INDEX_VERSION {
+            conn.execute_batch("DROP TABLE IF EXISTS message_fts; DROP TABLE IF EXISTS progress;")?;
+        }
+        conn.execute_batch(&format!(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS message_fts USING fts5(
+                 text, chat_id UNINDEXED, chat_identifier UNINDEXED, guid UNINDEXED,
+                 date UNINDEXED, is_from_me UNINDEXED, sender UNINDEXED, has_attachments UNINDEXED,
+                 tokenize = 'unicode61 remove_diacritics 2'
+             );
+             CREATE TABLE IF NOT EXISTS progress (
+                 id INTEGER PRIMARY KEY CHECK (id = 0), rowid INTEGER,
+                 revised INTEGER, census_rowid INTEGER, census_count INTEGER);
+             PRAGMA user_version = {};",
+            INDEX_VERSION
+        ))?;
+        Ok(Self { conn })
+    }
+
+    fn progress(&self) -> Result<Progress, DbError> {
+        let progress = self.conn
+            .query_row("SELECT rowid, revised, census_rowid, census_count FROM progress WHERE id = 0", [], |row| {
+                Ok(Progress {
+                    rowid: row.get(0)?,
+                    revised: row.get(1)?,
+                    census_rowid: row.get(2)?,
+                    census_count: row.get(3)?,
+                })
+            })
+            .optional()?;
+        Ok(progress.unwrap_or(Progress { rowid: 0, revised: 0, census_rowid: 0, census_count: 0 }))
+    }
+
+    fn save_progress(tx: &rusqlite::Transaction, progress: &Progress) -> Result<(), DbError> {
+        tx.execute(
+            "INSERT OR REPLACE INTO progress (id, rowid, revised, census_rowid, census_count)
+             VALUES (0, ?, ?, ?, ?)",
+            [
+                progress.rowid,
+                progress.revised,
+                progress.census_rowid,
+                progress.census_count,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Bring the index up to date with `db`: index new messages, re-read
+    /// edited ones, and drop those unsent or deleted. Returns how many new
+    /// messages were read.
+    pub fn update(&mut self, db: &Database) -> Result<usize, DbError> {
+        let mut progress = self.progress()?;
+        let mut indexed = 0;
+        loop {
+            let events = db.messages_after_rowid(progress.rowid, None, INDEX_BATCH)?;
+            let Some(last) = events.last() else { break };
+            progress.rowid = last.rowid;
+
+            let tx = self.conn.transaction()?;
+            insert_events(&tx, &events)?;
+            Self::save_progress(&tx, &progress)?;
+            tx.commit()?;
+            indexed += events.len();
+        }
+
+        let revised = db.revised_since(progress.revised)?;
+        let (_, census_count) = db.message_census(Some(progress.census_rowid))?;
+        let deleted = census_count < progress.census_count;
+        if revised.is_empty() && !deleted && progress.census_rowid != 0 {
+            return Ok(indexed);
+        }
+
+        let tx = self.conn.transaction()?;
+        if let Some(latest) = revised.last() {
+            progress.revised = latest.revised_at;
+            let mut delete = tx.prepare("DELETE FROM message_fts WHERE rowid = ?")?;
+            for message in &revised {
+                delete.execute([message.rowid])?;
+            }
+            // Edited text is read again; unsent messages stay out
+            let edited: Vec<i64> = revised.iter().filter(|m| !m.retracted).map(|m| m.rowid).collect();
+            insert_events(&tx, &db.messages_by_rowid(&edited)?)?;
+        }
+        if deleted {
+            tx.execute_batch("CREATE TEMP TABLE IF NOT EXISTS present (rowid INTEGER PRIMARY KEY); DELETE FROM present;")?;
+            {
+                let mut present = tx.prepare("INSERT INTO present (rowid) VALUES (?)")?;
+                for rowid in db.message_rowids()? {
+                    present.execute([rowid])?;
+                }
+            }
+            tx.execute("DELETE FROM message_fts WHERE rowid NOT IN (SELECT rowid FROM present)", [])?;
+        }
+        (progress.census_rowid, progress.census_count) = db.message_census(None)?;
+        Self::save_progress(&tx, &progress)?;
+        tx.commit()?;
+        Ok(indexed)
+    }
+
+    /// Catch up with `db`, then search it.
+    pub fn search(&mut self, db: &Database, query: &str, filter: &MessageFilter, limit: usize) -> Result<Vec<SearchHit>, DbError> {
+        self.update(db)?;
+        self.query(query, filter, limit)
+    }
+
+    /// Best matches for `query` first. Every word must appear; the last one
+    /// may be a prefix, so results update as the user types.
+    pub fn query(&self, query: &str, filter: &MessageFilter, limit: usize) -> Result<Vec<SearchHit>, DbError> {
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        let mut values: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(fts_query)];
+        let mut clauses = Vec::new();
+        if let Some(from) = filter.from_date {
+            values.push(Box::new(from.timestamp()));
+            clauses.push(format!("AND date >= ?{}", values.len()));
+        }
+        if let Some(to) = filter.to_date {
+            values.push(Box::new(to.timestamp()));
+            clauses.push(format!("AND date < ?{}", values.len()));
+        }
+        if let Some(has_attachment) = filter.has_attachment {
+            values.push(Box::new(has_attachment));
+            clauses.push(format!("AND has_attachments = ?{}", values.len()));
+        }
+        if let Some(is_from_me) = filter.is_from_me {
+            values.push(Box::new(is_from_me));
+            clauses.push(format!("AND is_from_me = ?{}", values.len()));
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT chat_id, chat_identifier, rowid, guid, date, is_from_me, sender,
+                    snippet(message_fts, 0, '', '', '…', {})
+             FROM message_fts
+             WHERE message_fts MATCH ?1 {}
+             ORDER BY rank",
+            SNIPPET_TOKENS,
+            clauses.join(" ")
+        ))?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| {
+            Ok(SearchHit {
+                chat_id: row.get(0)?,
+                chat_identifier: row.get(1)?,
+                rowid: row.get(2)?,
+                guid: row.get(3)?,
+                date: DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+                is_from_me: row.get(5)?,
+                sender: row.get(6)?,
+                snippet: row.get(7)?,
+            })
+        })?;
+
+        // Handles are compared ignoring formatting, which SQL can't do
+        let mut hits = Vec::new();
+        for hit in rows {
+            let hit = hit?;
+            let sender_matches = filter.sender.as_deref().is_none_or(|wanted| {
+                !hit.is_from_me && hit.sender.as_deref().is_some_and(|s| same_handle(s, wanted))
+            });
+            if sender_matches {
+                hits.push(hit);
+                if hits.len() == limit {
+                    break;
+                }
+            }
+        }
+        Ok(hits)
+    }
+}
+
+/// Index `events` with text, dated in Unix seconds.
+fn insert_events(tx: &rusqlite::Transaction, events: &[ExportEvent]) -> Result<(), DbError> {
+    let mut insert = tx.prepare(
+        "INSERT OR REPLACE INTO message_fts (rowid, text, chat_id, chat_identifier, guid, date,
+             is_from_me, sender, has_attachments)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )?;
+    for e in events.iter().filter(|e| !e.text.trim().is_empty()) {
+        insert.execute(rusqlite::params![
+            e.rowid, e.text, e.chat_id, e.chat_identifier, e.guid, e.date.timestamp(),
+            e.is_from_me, e.sender, e.has_attachments,
+        ])?;
+    }
+    Ok(())
+}
+
+/// Create `path` if needed and make it readable and writable by its owner
+/// alone.
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    std::fs::OpenOptions::new().create(true).append(true).mode(0o600).open(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn restrict_to_owner(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Quote each word so FTS5 syntax in the query is taken literally, and let
+/// the last word match as a prefix.
+fn fts_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect();
+    let last = words.len().checked_sub(1)?;
+    Some(
+        words
+            .iter()
+            .enumerate()
+            .map(|(i, w)| if i == last { format!("{}*", w) } else { w.clone() })
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::CHAT_DB_SCHEMA;
+
+    /// chat.db with one chat, and a message inserter taking seconds after
+    /// the Apple epoch.
+    fn fixture() -> (tempfile::TempDir, PathBuf, Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(CHAT_DB_SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, style, service_name)
+                 VALUES (1, 'iMessage;-;+15551234567', '+15551234567', 45, 'iMessage');
+             INSERT INTO handle (ROWID, id, service) VALUES (1, '+15551234567', 'iMessage');
+             ALTER TABLE message ADD COLUMN date_edited INTEGER DEFAULT 0;
+             ALTER TABLE message ADD COLUMN date_retracted INTEGER DEFAULT 0;"
+        ).unwrap();
+        (dir, path, conn)
+    }
+
+    fn insert_message(conn: &Connection, rowid: i64, text: &str, secs: i64, is_from_me: bool) {
+        conn.execute(
+            "INSERT INTO message (ROWID, guid, text, date, is_from_me, handle_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![rowid, format!("guid-{}", rowid), text, secs * 1_000_000_000, is_from_me, if is_from_me { 0 } else { 1 }],
+        ).unwrap();
+        conn.execute("INSERT INTO chat_message_join VALUES (1, ?)", [rowid]).unwrap();
+    }
+
+    fn rowids(hits: Vec<SearchHit>) -> Vec<i64> {
+        let mut rowids: Vec<_> = hits.iter().map(|h| h.rowid).collect();
+        rowids.sort();
+        rowids
+    }
+
+    #[test]
+    fn test_search_catches_up() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "Dinner at Café Lumière?", 5_000, false);
+        insert_message(&conn, 2, "sure, dinner sounds good", 5_100, true);
+        insert_message(&conn, 3, "unrelated", 5_200, false);
+        let db = Database::open(&path).unwrap();
+        let mut index = SearchIndex::open(&dir.path().join("data/search.db")).unwrap();
+
+        let hits = index.search(&db, "dinner", &MessageFilter::default(), 10).unwrap();
+        let mut rowids: Vec<_> = hits.iter().map(|h| h.rowid).collect();
+        rowids.sort();
+        assert_eq!(rowids, [1, 2]);
+        assert_eq!(index.search(&db, "cafe lum", &MessageFilter::default(), 10).unwrap()[0].rowid, 1);
+
+        let theirs = MessageFilter { sender: Some("(555) 123-4567".into()), ..Default::default() };
+        let hits = index.search(&db, "dinner", &theirs, 10).unwrap();
+        assert_eq!((hits.len(), hits[0].chat_identifier.as_str()), (1, "+15551234567"));
+
+        // Only the new message is indexed on the next search
+        insert_message(&conn, 4, "dinner moved to 8", 5_300, false);
+        assert_eq!(index.update(&db).unwrap(), 1);
+        let mine = MessageFilter { is_from_me: Some(false), ..Default::default() };
+        assert_eq!(index.search(&db, "dinner", &mine, 1).unwrap().len(), 1);
+        assert!(index.search(&db, "\"", &mine, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_indexes_late_arrivals() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "boarding now", 5_000, false);
+        let db = Database::open(&path).unwrap();
+        let mut index = SearchIndex::open(&dir.path().join("search.db")).unwrap();
+        assert_eq!(index.update(&db).unwrap(), 1);
+
+        // Synced in after the first update, dated before what was indexed
+        insert_message(&conn, 2, "boarding pass attached", 4_000, false);
+        assert_eq!(index.update(&db).unwrap(), 1);
+        assert_eq!(rowids(index.search(&db, "boarding", &MessageFilter::default(), 10).unwrap()), [1, 2]);
+    }
+
+    #[test]
+    fn test_search_follows_edits_and_deletions() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "see you at 7", 5_000, false);
+        insert_message(&conn, 2, "wrong chat, sorry", 5_100, true);
+        insert_message(&conn, 3, "see you there", 5_200, false);
+        let db = Database::open(&path).unwrap();
+        let index_path = dir.path().join("data/search.db");
+        let mut index = SearchIndex::open(&index_path).unwrap();
+        let all = MessageFilter::default();
+        assert_eq!(rowids(index.search(&db, "see", &all, 10).unwrap()), [1, 3]);
+
+        conn.execute_batch(
+            "UPDATE message SET text = 'see you at 8', date_edited = 6000000000000 WHERE ROWID = 1;
+             UPDATE message SET text = '', date_retracted = 6000000000000 WHERE ROWID = 2;
+             DELETE FROM chat_message_join WHERE message_id = 3;
+             DELETE FROM message WHERE ROWID = 3;",
+        ).unwrap();
+        assert_eq!(rowids(index.search(&db, "8", &all, 10).unwrap()), [1]);
+        assert!(index.search(&db, "7", &all, 10).unwrap().is_empty());
+        assert!(index.search(&db, "sorry", &all, 10).unwrap().is_empty());
+        assert_eq!(rowids(index.search(&db, "see", &all, 10).unwrap()), [1]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&index_path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_date_filter_with_seconds_dates() {
+        let (dir, path, conn) = fixture();
+        insert_message(&conn, 1, "lunch monday", 5_000, false);
+        insert_message(&conn, 2, "lunch friday", 9_000, false);
+        // Older macOS stored seconds, not nanoseconds
+        conn.execute("UPDATE message SET date = date / 1000000000", []).unwrap();
+        let db = Database::open(&path).unwrap();
+        let mut index = SearchIndex::open(&dir.path().join("search.db")).unwrap();
+
+        let from = DateTime::from_timestamp(crate::APPLE_EPOCH_OFFSET + 8_000, 0);
+        let recent = MessageFilter { from_date: from, ..Default::default() };
+        let hits = index.search(&db, "lunch", &recent, 10).unwrap();
+        assert_eq!(rowids(hits.clone()), [2]);
+        assert_eq!(hits[0].date.timestamp(), crate::APPLE_EPOCH_OFFSET + 9_000);
+    }
+
+    #[test]
+    fn test_fts_query_escapes() {
+        assert_eq!(fts_query("  "), None);
+        assert_eq!(fts_query("dinner at"), Some("\"dinner\" \"at\"*".into()));
+        assert_eq!(fts_query("say \"hi\" OR"), Some("\"say\" \"\"\"hi\"\"\" \"OR\"*".into()));
+    }
+}
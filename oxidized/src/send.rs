@@ -1,8 +1,20 @@
 //! Send messages via AppleScript.
+//!
+//! Replies always go to the end of the chat. Messages' AppleScript
+//! dictionary has no way to address a thread, and scripting the Reply menu
+//! item would depend on which message is selected on screen, so sending
+//! in a thread is not supported; threads are only read, as
+//! `Message::reply_to_guid`.
 
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::db::{Database, DbError};
+use crate::models::messages_url;
+
 #[derive(Error, Debug)]
 pub enum SendError {
     #[error("AppleScript failed: {0}")]
@@ -11,6 +23,352 @@ pub enum SendError {
     CommandError(#[from] std::io::Error),
     #[error("Timeout waiting for message send")]
     Timeout,
+    #[error("{0} no longer seems to receive iMessages; try sending as SMS")]
+    RecipientUnavailable(String),
+    #[error("Can't send {0}: {1}")]
+    UnsupportedAttachment(String, String),
+    #[error("Only the latest message in a chat can get a tapback")]
+    NotLatestMessage,
+    #[error(transparent)]
+    Database(#[from] DbError),
+}
+
+/// Largest file Messages.app will send.
+pub const MAX_ATTACHMENT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Broad cause of a failed send, used to decide whether to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCategory {
+    /// Automation not allowed; retrying cannot help
+    Permission,
+    /// Chat does not exist in Messages.app
+    NotFound,
+    /// Messages.app did not answer in time
+    Timeout,
+    /// An attachment Messages.app can't send; retrying cannot help
+    Unsupported,
+    Other,
+}
+
+impl ErrorCategory {
+    /// Whether trying again could plausibly succeed.
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorCategory::Timeout | ErrorCategory::Other)
+    }
+}
+
+impl SendError {
+    /// Classify this error by AppleScript error code or message.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            SendError::Timeout => ErrorCategory::Timeout,
+            SendError::RecipientUnavailable(_) => ErrorCategory::NotFound,
+            SendError::UnsupportedAttachment(..) | SendError::NotLatestMessage => ErrorCategory::Unsupported,
+            SendError::CommandError(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                ErrorCategory::Permission
+            }
+            SendError::CommandError(_) | SendError::Database(_) => ErrorCategory::Other,
+            SendError::ScriptError(msg) => {
+                // -1743: not authorized to send Apple events
+                // -25211: no Accessibility access for keystrokes
+                if msg.contains("-1743") || msg.contains("Not authorized") || msg.contains("-25211") {
+                    ErrorCategory::Permission
+                // -1728: can't get object (unknown chat id)
+                } else if msg.contains("-1728") || msg.contains("Can’t get") || msg.contains("Can't get") {
+                    ErrorCategory::NotFound
+                // -1712: AppleEvent timed out
+                } else if msg.contains("-1712") || msg.contains("timed out") {
+                    ErrorCategory::Timeout
+                } else {
+                    ErrorCategory::Other
+                }
+            }
+        }
+    }
+
+    /// Turn a missing-chat error into `RecipientUnavailable` when the
+    /// handle's most recent message went over something other than iMessage,
+    /// which usually means the number was deactivated or changed phones.
+    pub fn with_recipient_service(self, handle: &str, latest_service: Option<&str>) -> SendError {
+        let switched = latest_service.is_some_and(|s| s != "iMessage");
+        if self.category() == ErrorCategory::NotFound && switched {
+            SendError::RecipientUnavailable(handle.to_string())
+        } else {
+            self
+        }
+    }
+}
+
+/// The exact chat ID, text, and AppleScript a send would use.
+#[derive(Debug, Clone, Serialize)]
+pub struct SendPlan {
+    /// Chat ID in the form Messages.app expects (e.g. "any;-;+15551234567")
+    pub full_chat_id: String,
+    /// Handle to send to directly, starting a chat if there isn't one yet
+    pub recipient: Option<String>,
+    /// Final text as it will be sent
+    pub text: String,
+    /// Files sent after the text, in order
+    pub attachments: Vec<PathBuf>,
+    /// AppleScript passed to osascript
+    pub script: String,
+}
+
+impl SendPlan {
+    /// Build the plan for sending text to a chat without running anything.
+    pub fn new(chat_identifier: &str, text: &str, is_group: bool) -> Self {
+        // Build full chat ID format Messages.app expects
+        let full_chat_id = if is_group {
+            format!("any;+;{}", chat_identifier)
+        } else {
+            format!("any;-;{}", chat_identifier)
+        };
+
+        let mut plan = Self {
+            full_chat_id,
+            recipient: None,
+            text: text.to_string(),
+            attachments: Vec::new(),
+            script: String::new(),
+        };
+        plan.script = plan.build_script();
+        plan
+    }
+
+    /// Build the plan for sending to a handle over iMessage whether or not
+    /// there's a chat with them yet.
+    pub fn to_recipient(handle: &str, text: &str) -> Self {
+        let mut plan = Self::new(handle, text, false);
+        plan.recipient = Some(handle.to_string());
+        plan.script = plan.build_script();
+        plan
+    }
+
+    /// Also send these files, each as its own message after the text.
+    pub fn with_attachments(mut self, files: &[PathBuf]) -> Self {
+        self.attachments = files.to_vec();
+        self.script = self.build_script();
+        self
+    }
+
+    fn build_script(&self) -> String {
+        let mut sends = String::new();
+        // An attachment-only send has no text line
+        if !self.text.is_empty() || self.attachments.is_empty() {
+            sends.push_str(&format!("    send \"{}\" to targetChat\n", escape_applescript(&self.text)));
+        }
+        for file in &self.attachments {
+            sends.push_str(&format!(
+                "    send (POSIX file \"{}\") to targetChat\n",
+                escape_applescript(&file.to_string_lossy())
+            ));
+        }
+
+        let target = match &self.recipient {
+            Some(handle) => format!(
+                "    set targetService to 1st account whose service type = iMessage\n    set targetChat to participant \"{}\" of targetService\n",
+                escape_applescript(handle)
+            ),
+            None => format!("    set targetChat to chat id \"{}\"\n", escape_applescript(&self.full_chat_id)),
+        };
+        format!("tell application \"Messages\"\n{}{}end tell", target, sends)
+    }
+
+    /// Run the script via osascript.
+    pub fn execute(&self) -> Result<(), SendError> {
+        self.execute_with(&Osascript::default())
+    }
+
+    /// Run the script with a specific runner.
+    pub fn execute_with(&self, runner: &dyn ScriptRunner) -> Result<(), SendError> {
+        runner.run(&self.script).map(|_| ())
+    }
+}
+
+/// Something that can run an AppleScript and return what it printed.
+///
+/// Lets tests check the exact scripts a send produces without Messages.app.
+pub trait ScriptRunner {
+    fn run(&self, script: &str) -> Result<String, SendError>;
+}
+
+/// Runs scripts with `osascript`, giving up after a timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct Osascript {
+    pub timeout: Duration,
+}
+
+impl Default for Osascript {
+    fn default() -> Self {
+        // Messages.app can take a while to launch on the first send
+        Self { timeout: Duration::from_secs(30) }
+    }
+}
+
+impl ScriptRunner for Osascript {
+    fn run(&self, script: &str) -> Result<String, SendError> {
+        // Nothing to drive off macOS
+        if cfg!(feature = "dev-sample") {
+            return Ok(String::new());
+        }
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        run_with_timeout(command, self.timeout)
+    }
+}
+
+/// Escape quotes and backslashes for an AppleScript string literal.
+pub(crate) fn escape_applescript(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Run a command, killing it if it outlives `timeout`. A non-zero exit
+/// becomes a ScriptError carrying stderr.
+fn run_with_timeout(mut command: Command, timeout: Duration) -> Result<String, SendError> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let deadline = Instant::now() + timeout;
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(SendError::Timeout);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(SendError::ScriptError(stderr.to_string()))
+    }
+}
+
+/// Run an AppleScript, turning a non-zero exit into a ScriptError.
+fn run_osascript(script: &str) -> Result<(), SendError> {
+    osascript_output(script).map(|_| ())
+}
+
+/// Run an AppleScript and return what it printed.
+pub(crate) fn osascript_output(script: &str) -> Result<String, SendError> {
+    Osascript::default().run(script)
+}
+
+/// Script that shows a chat in Messages.app, then returns focus.
+fn mark_read_script(messages_url: &str) -> String {
+    format!(
+        r#"set previousApp to path to frontmost application as text
+open location "{}"
+delay 1.5
+tell application previousApp to activate"#,
+        escape_applescript(messages_url)
+    )
+}
+
+/// Mark a chat read by briefly opening it in Messages.app.
+///
+/// Unlike writing `is_read` to chat.db, this goes through Messages.app itself,
+/// so the read state syncs to other devices via iCloud.
+pub fn mark_read_via_messages(messages_url: &str) -> Result<(), SendError> {
+    run_osascript(&mark_read_script(messages_url))
+}
+
+/// A tapback, in the order Messages.app's ⌘T picker numbers them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tapback {
+    Love,
+    Like,
+    Dislike,
+    Laugh,
+    Emphasize,
+    Question,
+}
+
+impl Tapback {
+    /// The associated_message_type chat.db records for it.
+    pub fn code(self) -> i32 {
+        2000 + self as i32
+    }
+
+    /// Key that picks it once the tapback picker is open.
+    fn picker_key(self) -> char {
+        char::from(b'1' + self as u8)
+    }
+}
+
+/// Script that opens a chat in Messages.app and brings Messages to the
+/// front. Prints the app that was in front, for `reaction_script` to
+/// return to.
+fn open_chat_script(messages_url: &str) -> String {
+    format!(
+        r#"set previousApp to path to frontmost application as text
+open location "{}"
+delay 1.5
+tell application "Messages" to activate
+delay 0.3
+return previousApp"#,
+        escape_applescript(messages_url)
+    )
+}
+
+/// Script that gives the open chat's latest message a tapback with ⌘T,
+/// then returns focus to `previous_app`. Stops without a keystroke if
+/// Messages isn't frontmost, since it would go to another app.
+fn reaction_script(reaction: Tapback, previous_app: &str) -> String {
+    format!(
+        r#"tell application "System Events"
+    if name of first application process whose frontmost is true is not "Messages" then
+        error "Messages isn't in front; tapback not sent"
+    end if
+    tell process "Messages"
+        keystroke "t" using command down
+        delay 0.3
+        keystroke "{}"
+    end tell
+end tell
+{}"#,
+        reaction.picker_key(),
+        activate_script(previous_app)
+    )
+}
+
+/// Script that brings an app back to the front.
+fn activate_script(app: &str) -> String {
+    format!(r#"tell application "{}" to activate"#, escape_applescript(app))
+}
+
+/// Give a message a tapback, checking `db`, the live chat.db.
+///
+/// Messages.app has no scripting command for tapbacks, so this opens the
+/// chat and presses ⌘T, which needs Accessibility access. ⌘T only reaches
+/// the chat's latest message, so any other message is refused, both up
+/// front and again once the chat is open in case one arrived meanwhile.
+/// Opening the chat also marks it read, as with `mark_read_via_messages`.
+pub fn send_reaction(db: &Database, message_guid: &str, reaction: Tapback) -> Result<(), SendError> {
+    send_reaction_with(&Osascript::default(), db, message_guid, reaction)
+}
+
+/// Send a tapback using a specific script runner.
+pub fn send_reaction_with(
+    runner: &dyn ScriptRunner,
+    db: &Database,
+    message_guid: &str,
+    reaction: Tapback,
+) -> Result<(), SendError> {
+    let (chat_identifier, is_group) = db.latest_message_chat(message_guid)?.ok_or(SendError::NotLatestMessage)?;
+    let previous_app = runner.run(&open_chat_script(&messages_url(&chat_identifier, is_group)))?;
+    let previous_app = previous_app.trim();
+    if db.latest_message_chat(message_guid)?.is_none() {
+        runner.run(&activate_script(previous_app))?;
+        return Err(SendError::NotLatestMessage);
+    }
+    runner.run(&reaction_script(reaction, previous_app)).map(|_| ())
 }
 
 /// Send a message to a chat via Messages.app.
@@ -23,51 +381,335 @@ pub enum SendError {
 /// # Returns
 /// Ok(()) on success, Err on failure.
 pub fn send_message(chat_identifier: &str, text: &str, is_group: bool) -> Result<(), SendError> {
-    // Escape quotes and backslashes for AppleScript
-    let escaped = text
-        .replace('\\', "\\\\")
-        .replace('"', "\\\"");
-
-    // Build full chat ID format Messages.app expects
-    let full_chat_id = if is_group {
-        format!("any;+;{}", chat_identifier)
-    } else {
-        format!("any;-;{}", chat_identifier)
-    };
-
-    let script = format!(
-        r#"tell application "Messages"
-    set targetChat to chat id "{}"
-    send "{}" to targetChat
-end tell"#,
-        full_chat_id, escaped
-    );
+    send_message_with(&Osascript::default(), chat_identifier, text, is_group)
+}
 
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(&script)
-        .output()?;
+/// Check a file can go out as an attachment before anything is sent.
+pub fn check_attachment(path: &Path) -> Result<(), SendError> {
+    let name = path.file_name().map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy()).into_owned();
+    let unsupported = |reason: &str| Err(SendError::UnsupportedAttachment(name.clone(), reason.to_string()));
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return unsupported("the file is missing");
+    };
+    if metadata.is_dir() {
+        // Includes packages such as .app and .pages bundles
+        return unsupported("folders can't be sent; compress it first");
+    }
+    if metadata.len() == 0 {
+        return unsupported("the file is empty");
+    }
+    if metadata.len() > MAX_ATTACHMENT_BYTES {
+        return unsupported(&format!("it's over {} MB", MAX_ATTACHMENT_BYTES / (1024 * 1024)));
+    }
+    Ok(())
+}
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(SendError::ScriptError(stderr.to_string()))
+/// Send a message followed by files, e.g. staged attachments. Every file
+/// is checked first, so a bad one fails the send before the text goes out.
+pub fn send_message_with_attachments(
+    chat_identifier: &str,
+    text: &str,
+    is_group: bool,
+    attachments: &[PathBuf],
+) -> Result<(), SendError> {
+    for file in attachments {
+        check_attachment(file)?;
     }
+    SendPlan::new(chat_identifier, text, is_group)
+        .with_attachments(attachments)
+        .execute()
+}
+
+/// Send a message using a specific script runner.
+pub fn send_message_with(
+    runner: &dyn ScriptRunner,
+    chat_identifier: &str,
+    text: &str,
+    is_group: bool,
+) -> Result<(), SendError> {
+    SendPlan::new(chat_identifier, text, is_group).execute_with(runner)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn test_escape_text() {
-        // Just test the escaping logic
         let text = r#"Hello "world" \ test"#;
-        let escaped = text
-            .replace('\\', "\\\\")
-            .replace('"', "\\\"");
-        assert_eq!(escaped, r#"Hello \"world\" \\ test"#);
+        assert_eq!(escape_applescript(text), r#"Hello \"world\" \\ test"#);
+    }
+
+    #[test]
+    fn test_plan_full_chat_id() {
+        let direct = SendPlan::new("+15551234567", "hi", false);
+        assert_eq!(direct.full_chat_id, "any;-;+15551234567");
+
+        let group = SendPlan::new("chat123456", "hi", true);
+        assert_eq!(group.full_chat_id, "any;+;chat123456");
+    }
+
+    #[test]
+    fn test_plan_script() {
+        let plan = SendPlan::new("+15551234567", r#"Say "hi""#, false);
+        assert_eq!(plan.text, r#"Say "hi""#);
+        assert!(plan.script.contains(r#"set targetChat to chat id "any;-;+15551234567""#));
+        assert!(plan.script.contains(r#"send "Say \"hi\"" to targetChat"#));
+
+        let odd = SendPlan::new(r#"chat"1\x"#, "hi", true);
+        assert!(odd.script.contains(r#"set targetChat to chat id "any;+;chat\"1\\x""#));
+    }
+
+    #[test]
+    fn test_plan_to_recipient() {
+        let plan = SendPlan::to_recipient("new@example.com", "hi");
+        assert_eq!(plan.recipient.as_deref(), Some("new@example.com"));
+        assert!(plan.script.contains(r#"set targetChat to participant "new@example.com" of targetService"#));
+        assert!(!plan.script.contains("chat id"));
+        assert!(plan.script.contains(r#"send "hi" to targetChat"#));
+    }
+
+    #[test]
+    fn test_error_category() {
+        let denied = SendError::ScriptError("execution error: Not authorized to send Apple events to Messages. (-1743)".into());
+        assert_eq!(denied.category(), ErrorCategory::Permission);
+        assert!(!denied.category().is_retryable());
+
+        let missing = SendError::ScriptError("execution error: Messages got an error: Can’t get chat id \"any;-;x\". (-1728)".into());
+        assert_eq!(missing.category(), ErrorCategory::NotFound);
+
+        let slow = SendError::ScriptError("execution error: Messages got an error: AppleEvent timed out. (-1712)".into());
+        assert_eq!(slow.category(), ErrorCategory::Timeout);
+        assert!(slow.category().is_retryable());
+
+        assert_eq!(SendError::Timeout.category(), ErrorCategory::Timeout);
+        assert_eq!(SendError::ScriptError("weird".into()).category(), ErrorCategory::Other);
+    }
+
+    #[test]
+    fn test_recipient_unavailable() {
+        let missing = || SendError::ScriptError("Can’t get chat id \"any;-;+15551234567\". (-1728)".into());
+
+        let sms = missing().with_recipient_service("+15551234567", Some("SMS"));
+        assert!(matches!(sms, SendError::RecipientUnavailable(ref h) if h == "+15551234567"));
+        assert!(sms.to_string().contains("SMS"));
+        assert_eq!(sms.category(), ErrorCategory::NotFound);
+
+        // Still on iMessage, or nothing to go on: keep the original error
+        let imessage = missing().with_recipient_service("+15551234567", Some("iMessage"));
+        assert!(matches!(imessage, SendError::ScriptError(_)));
+        assert!(matches!(missing().with_recipient_service("+15551234567", None), SendError::ScriptError(_)));
+
+        // Only missing-chat errors are reclassified
+        let slow = SendError::Timeout.with_recipient_service("+15551234567", Some("SMS"));
+        assert!(matches!(slow, SendError::Timeout));
+    }
+
+    #[test]
+    fn test_check_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let photo = dir.path().join("photo.jpg");
+        std::fs::write(&photo, b"jpeg").unwrap();
+        let empty = dir.path().join("empty.txt");
+        std::fs::write(&empty, b"").unwrap();
+        let bundle = dir.path().join("Report.pages");
+        std::fs::create_dir(&bundle).unwrap();
+
+        assert!(check_attachment(&photo).is_ok());
+        for (path, reason) in [(&empty, "empty"), (&bundle, "folders"), (&dir.path().join("gone.png"), "missing")] {
+            let err = check_attachment(path).unwrap_err();
+            assert!(err.to_string().contains(reason), "{}", err);
+            assert_eq!(err.category(), ErrorCategory::Unsupported);
+            assert!(!err.category().is_retryable());
+        }
+
+        let err = send_message_with_attachments("+15551234567", "hi", false, &[photo, bundle]).unwrap_err();
+        assert_eq!(err.to_string(), "Can't send Report.pages: folders can't be sent; compress it first");
+    }
+
+    #[test]
+    fn test_mark_read_script() {
+        let script = mark_read_script("imessage://+15551234567");
+        assert!(script.contains(r#"open location "imessage://+15551234567""#));
+        assert!(script.ends_with("tell application previousApp to activate"));
+    }
+
+    /// Records scripts instead of running them.
+    struct MockRunner {
+        scripts: std::cell::RefCell<Vec<String>>,
+        result: fn() -> Result<String, SendError>,
+    }
+
+    impl MockRunner {
+        fn new(result: fn() -> Result<String, SendError>) -> Self {
+            Self { scripts: Default::default(), result }
+        }
+
+        fn last_script(&self) -> String {
+            self.scripts.borrow().last().cloned().unwrap_or_default()
+        }
+    }
+
+    impl ScriptRunner for MockRunner {
+        fn run(&self, script: &str) -> Result<String, SendError> {
+            self.scripts.borrow_mut().push(script.to_string());
+            (self.result)()
+        }
+    }
+
+    #[test]
+    fn test_reaction_script() {
+        assert_eq!(Tapback::Love.code(), 2000);
+        assert_eq!(Tapback::Question.code(), 2005);
+        let script = open_chat_script("imessage://+15551234567");
+        assert!(script.contains("open location \"imessage://+15551234567\""));
+        assert!(script.contains("tell application \"Messages\" to activate"));
+        let script = reaction_script(Tapback::Like, "Macintosh HD:Applications:Notes.app:");
+        assert!(script.contains("is not \"Messages\" then\n        error"));
+        assert!(script.contains("keystroke \"t\" using command down\n        delay 0.3\n        keystroke \"2\""));
+        assert!(script.ends_with("tell application \"Macintosh HD:Applications:Notes.app:\" to activate"));
+        assert_eq!(Tapback::Question.picker_key(), '6');
+        assert_eq!(SendError::NotLatestMessage.category(), ErrorCategory::Unsupported);
+    }
+
+    /// chat.db with one chat whose latest message is "guid-1".
+    fn reaction_fixture() -> (tempfile::TempDir, Database, rusqlite::Connection) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chat.db");
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(crate::sample::CHAT_DB_SCHEMA).unwrap();
+        conn.execute_batch(
+            "INSERT INTO chat (ROWID, guid, chat_identifier, style) VALUES (1, 'iMessage;-;+15551234567', '+15551234567', 45);
+             INSERT INTO message (ROWID, guid, text, date) VALUES (1, 'guid-1', 'lunch?', 100);
+             INSERT INTO chat_message_join VALUES (1, 1);",
+        ).unwrap();
+        (dir, Database::open(&path).unwrap(), conn)
+    }
+
+    #[test]
+    fn test_send_reaction() {
+        let (_dir, db, _conn) = reaction_fixture();
+        let runner = MockRunner::new(|| Ok("Notes\n".to_string()));
+        send_reaction_with(&runner, &db, "guid-1", Tapback::Like).unwrap();
+        let scripts = runner.scripts.borrow();
+        assert_eq!(scripts.len(), 2);
+        assert!(scripts[0].starts_with("set previousApp"));
+        assert!(scripts[1].ends_with("tell application \"Notes\" to activate"));
+
+        assert!(matches!(send_reaction_with(&runner, &db, "gone", Tapback::Like), Err(SendError::NotLatestMessage)));
+    }
+
+    /// Lets a message arrive while the chat is opening.
+    struct ArrivingRunner {
+        conn: rusqlite::Connection,
+        scripts: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl ScriptRunner for ArrivingRunner {
+        fn run(&self, script: &str) -> Result<String, SendError> {
+            if script.starts_with("set previousApp") {
+                self.conn.execute_batch(
+                    "INSERT INTO message (ROWID, guid, text, date) VALUES (2, 'guid-2', 'never mind', 200);
+                     INSERT INTO chat_message_join VALUES (1, 2);",
+                ).unwrap();
+            }
+            self.scripts.borrow_mut().push(script.to_string());
+            Ok("Notes".to_string())
+        }
+    }
+
+    #[test]
+    fn test_reaction_rechecks_latest_message() {
+        let (_dir, db, conn) = reaction_fixture();
+        let runner = ArrivingRunner { conn, scripts: Default::default() };
+        assert!(matches!(send_reaction_with(&runner, &db, "guid-1", Tapback::Like), Err(SendError::NotLatestMessage)));
+        // Focus goes back without a keystroke
+        let scripts = runner.scripts.borrow();
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[1], "tell application \"Notes\" to activate");
     }
 
-    // Note: Actual send_message tests would require mocking osascript
-    // or running in an environment with Messages.app access.
+    #[test]
+    fn test_send_exact_script() {
+        let runner = MockRunner::new(|| Ok(String::new()));
+        send_message_with(&runner, "+15551234567", "On my way", false).unwrap();
+        assert_eq!(
+            runner.last_script(),
+            "tell application \"Messages\"\n    \
+             set targetChat to chat id \"any;-;+15551234567\"\n    \
+             send \"On my way\" to targetChat\n\
+             end tell"
+        );
+    }
+
+    #[test]
+    fn test_send_attachments_after_text() {
+        let runner = MockRunner::new(|| Ok(String::new()));
+        let files = [PathBuf::from("/tmp/staging/1/photo.jpg"), PathBuf::from("/tmp/staging/1/say \"hi\".pdf")];
+        SendPlan::new("+15551234567", "Here you go", false)
+            .with_attachments(&files)
+            .execute_with(&runner)
+            .unwrap();
+        assert_eq!(
+            runner.last_script(),
+            "tell application \"Messages\"\n    \
+             set targetChat to chat id \"any;-;+15551234567\"\n    \
+             send \"Here you go\" to targetChat\n    \
+             send (POSIX file \"/tmp/staging/1/photo.jpg\") to targetChat\n    \
+             send (POSIX file \"/tmp/staging/1/say \\\"hi\\\".pdf\") to targetChat\n\
+             end tell"
+        );
+
+        let only_files = SendPlan::new("+15551234567", "", false).with_attachments(&files[..1]);
+        assert!(!only_files.script.contains("send \"\""));
+    }
+
+    #[test]
+    fn test_send_group_chat_id() {
+        let runner = MockRunner::new(|| Ok(String::new()));
+        send_message_with(&runner, "chat123456789", "hi all", true).unwrap();
+        assert!(runner.last_script().contains(r#"chat id "any;+;chat123456789""#));
+    }
+
+    #[test]
+    fn test_send_escaping() {
+        let runner = MockRunner::new(|| Ok(String::new()));
+        send_message_with(&runner, "+15551234567", "line one\nsaid \"hi\" \\ 🎉", false).unwrap();
+        // Quotes and backslashes are escaped; newlines and emoji pass through
+        // since AppleScript string literals may contain them
+        assert!(runner.last_script().contains("send \"line one\nsaid \\\"hi\\\" \\\\ 🎉\" to targetChat"));
+    }
+
+    #[test]
+    fn test_send_propagates_errors() {
+        let runner = MockRunner::new(|| Err(SendError::Timeout));
+        let err = send_message_with(&runner, "+15551234567", "hi", false).unwrap_err();
+        assert!(matches!(err, SendError::Timeout));
+        assert!(err.category().is_retryable());
+
+        let runner = MockRunner::new(|| Err(SendError::ScriptError("Not authorized (-1743)".into())));
+        let err = send_message_with(&runner, "+15551234567", "hi", false).unwrap_err();
+        assert_eq!(err.category(), ErrorCategory::Permission);
+        assert_eq!(runner.scripts.borrow().len(), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_with_timeout() {
+        let mut slow = Command::new("sleep");
+        slow.arg("5");
+        let started = Instant::now();
+        let err = run_with_timeout(slow, Duration::from_millis(100)).unwrap_err();
+        assert!(matches!(err, SendError::Timeout));
+        assert!(started.elapsed() < Duration::from_secs(4));
+
+        let mut quick = Command::new("sh");
+        quick.args(["-c", "echo ok"]);
+        assert_eq!(run_with_timeout(quick, Duration::from_secs(5)).unwrap(), "ok");
+
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "echo boom >&2; exit 1"]);
+        let err = run_with_timeout(failing, Duration::from_secs(5)).unwrap_err();
+        assert!(matches!(err, SendError::ScriptError(ref msg) if msg.contains("boom")));
+    }
 }
@@ -0,0 +1,169 @@
+//! Time-boxed triage sessions.
+//!
+//! Starting a session snapshots the queue and sets a deadline. Chats marked
+//! read or replied to count as handled; whatever is left from the snapshot
+//! when the session ends was skipped. Finished sessions are kept in a log
+//! for later stats.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::Conversation;
+use crate::store::Document;
+
+/// A session in progress.
+#[derive(Debug, Clone, Serialize)]
+pub struct TriageSession {
+    pub started_at: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    /// Chat identifiers in the queue when the session started
+    pub queue: Vec<String>,
+    handled: HashSet<String>,
+    sent: usize,
+}
+
+/// How a session went.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionReport {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub planned_minutes: i64,
+    pub queue_size: usize,
+    /// Chats from the starting queue that were read or replied to
+    pub handled: usize,
+    /// Chats from the starting queue left untouched
+    pub skipped: usize,
+    /// Replies sent, including to chats that arrived mid-session
+    pub sent: usize,
+    /// Ended after the deadline
+    pub overran: bool,
+}
+
+impl TriageSession {
+    /// Start a session over `queue` lasting `minutes`. None if the deadline
+    /// would be out of range.
+    pub fn start(queue: &[Conversation], minutes: i64, now: DateTime<Utc>) -> Option<Self> {
+        Some(Self {
+            started_at: now,
+            deadline: now.checked_add_signed(Duration::try_minutes(minutes)?)?,
+            queue: queue.iter().map(|c| c.chat_identifier.clone()).collect(),
+            handled: HashSet::new(),
+            sent: 0,
+        })
+    }
+
+    /// A chat was marked read.
+    pub fn record_handled(&mut self, chat_identifier: &str) {
+        self.handled.insert(chat_identifier.to_string());
+    }
+
+    /// A reply went out.
+    pub fn record_sent(&mut self, chat_identifier: &str) {
+        self.sent += 1;
+        self.record_handled(chat_identifier);
+    }
+
+    /// Chats from the starting queue handled so far.
+    pub fn handled_count(&self) -> usize {
+        self.queue.iter().filter(|c| self.handled.contains(*c)).count()
+    }
+
+    /// Time left, zero once the deadline has passed.
+    pub fn remaining(&self, now: DateTime<Utc>) -> Duration {
+        (self.deadline - now).max(Duration::zero())
+    }
+
+    pub fn end(&self, now: DateTime<Utc>) -> SessionReport {
+        let handled = self.handled_count();
+        SessionReport {
+            started_at: self.started_at,
+            ended_at: now,
+            planned_minutes: (self.deadline - self.started_at).num_minutes(),
+            queue_size: self.queue.len(),
+            handled,
+            skipped: self.queue.len() - handled,
+            sent: self.sent,
+            overran: now > self.deadline,
+        }
+    }
+}
+
+/// Finished session reports, saved as JSON, oldest first.
+pub struct SessionLog {
+    doc: Document,
+    reports: Vec<SessionReport>,
+}
+
+impl SessionLog {
+    /// Default session log in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().sessions()
+    }
+
+    /// Load the log, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let reports = doc.load().unwrap_or_default();
+        Self { doc, reports }
+    }
+
+    /// Add a report and write the log to disk.
+    pub fn record(&mut self, report: SessionReport) -> std::io::Result<()> {
+        self.reports.push(report);
+        self.doc.save(&self.reports)
+    }
+
+    pub fn reports(&self) -> &[SessionReport] {
+        &self.reports
+    }
+}
+
+impl Default for SessionLog {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::conversation;
+
+    fn conv(identifier: &str) -> Conversation {
+        conversation(identifier)
+    }
+
+    #[test]
+    fn test_session_report() {
+        let now = Utc::now();
+        let mut session = TriageSession::start(&[conv("a"), conv("b"), conv("c")], 15, now).unwrap();
+        session.record_sent("a");
+        session.record_handled("b");
+        session.record_handled("b");
+        // Arrived after the session started
+        session.record_sent("d");
+
+        assert_eq!(session.remaining(now + Duration::minutes(5)), Duration::minutes(10));
+        assert_eq!(session.remaining(now + Duration::minutes(20)), Duration::zero());
+
+        let report = session.end(now + Duration::minutes(10));
+        assert_eq!(report.planned_minutes, 15);
+        assert_eq!((report.queue_size, report.handled, report.skipped, report.sent), (3, 2, 1, 2));
+        assert!(!report.overran);
+        assert!(session.end(now + Duration::minutes(16)).overran);
+
+        assert!(TriageSession::start(&[], i64::MAX, now).is_none());
+        assert!(TriageSession::start(&[], 200_000_000_000, now).is_none());
+    }
+
+    #[test]
+    fn test_log_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sessions.json");
+        let report = TriageSession::start(&[conv("a")], 5, Utc::now()).unwrap().end(Utc::now());
+        SessionLog::load(Document::file(&path)).record(report.clone()).unwrap();
+        assert_eq!(SessionLog::load(Document::file(&path)).reports(), [report]);
+    }
+}
@@ -0,0 +1,156 @@
+//! User-configurable behavior.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::contact_store::ContactsBackend;
+use crate::contacts::EmailMatching;
+use crate::focus::FocusSettings;
+use crate::group_name::GroupNameStyle;
+use crate::guard::SendGuards;
+use crate::link_title::LinkTitles;
+use crate::persist::{load_json, save_json};
+use crate::power::PowerSaving;
+use crate::redact::RedactionConfig;
+use crate::stale::StaleDraftPolicy;
+use crate::store::StateStoreKind;
+
+/// How handled chats are marked read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadStrategy {
+    /// Set `is_read` directly in chat.db
+    #[default]
+    Database,
+    /// Hide handled chats locally without touching chat.db
+    LocalOnly,
+    /// Open the chat in Messages.app so read state syncs via iCloud
+    MessagesApp,
+}
+
+/// Order of the triage queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Most recent activity first
+    #[default]
+    Recent,
+    /// Whoever has waited longest for a reply first
+    OldestUnread,
+}
+
+/// CardDAV server to sync contacts from. The password is kept in the Keychain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardDavConfig {
+    /// Address book collection URL
+    pub url: String,
+    pub username: String,
+    pub sync_interval_minutes: u64,
+}
+
+/// Another Messages library to read, e.g. a family member's on a shared Mac.
+/// Needs read access to their chat.db; chats from it are view-only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibrarySource {
+    /// Tag carried on each conversation as `source_id`
+    pub id: String,
+    /// Path to the library's chat.db
+    pub path: PathBuf,
+}
+
+/// Settings that change how conversations are loaded and sent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Read Messages.app's own per-chat drafts onto each conversation.
+    pub read_messages_app_drafts: bool,
+    /// Keep full message text in the send history instead of only a hash.
+    pub log_full_text: bool,
+    /// How "Read" and successful sends mark a chat as handled.
+    pub read_strategy: ReadStrategy,
+    /// Order conversations are listed in.
+    pub sort_order: SortOrder,
+    /// How unnamed group chats are labelled.
+    pub group_name_style: GroupNameStyle,
+    /// Load message totals and reply times with each conversation.
+    pub conversation_stats: bool,
+    /// Show renames, joins and leaves among group chat messages.
+    pub group_events: bool,
+    /// Read the queue from a copy of chat.db, retaken on
+    /// `refresh_snapshot` or as messages arrive but at most every 30
+    /// seconds, so every view shows the same moment. Sends still check
+    /// chat.db itself.
+    pub read_from_snapshot: bool,
+    /// Where contact names are read from. The Contacts framework is only
+    /// tried when built with the `contacts-framework` feature.
+    pub contacts_backend: ContactsBackend,
+    /// TSV or CSV of identifier and name that wins over contacts; None for
+    /// people.tsv in the data folder.
+    pub people_file: Option<PathBuf>,
+    /// Where send history is kept; read at startup.
+    pub state_store: StateStoreKind,
+    /// How loosely email handles are matched to contacts.
+    pub email_matching: EmailMatching,
+    /// Leave short codes, no-reply addresses and SMS sender IDs out of the queue.
+    pub ignore_automated: bool,
+    /// Hide chats once every unread message is an expired one-time code.
+    pub auto_expire_otp: bool,
+    /// How long `handoff` snoozes a chat; None for the default two hours.
+    pub handoff_snooze_minutes: Option<u64>,
+    /// What happens to drafts for chats since answered in Messages.app.
+    pub stale_drafts: StaleDraftPolicy,
+    /// Personal details masked before text is exported or sent to a service.
+    pub redaction: RedactionConfig,
+    /// Write local exports without redaction; they're redacted by default.
+    pub export_unredacted: bool,
+    /// When to slow background polling and photo conversion to save power.
+    pub power_saving: PowerSaving,
+    /// Replies that need confirming before `send_all` sends them.
+    pub send_guards: SendGuards,
+    /// Quiet hours during macOS Focus modes.
+    pub focus: FocusSettings,
+    /// When a reply that's only a link gets the page title appended on commit.
+    pub link_titles: LinkTitles,
+    /// Folder template attachment slots are looked up in; None for the default.
+    pub template_assets_dir: Option<PathBuf>,
+    /// Extra Messages libraries shown alongside the user's own.
+    pub libraries: Vec<LibrarySource>,
+    /// Contact sync server; only used when built with the `carddav` feature.
+    pub carddav: Option<CardDavConfig>,
+}
+
+impl Settings {
+    /// Load saved settings, with defaults if the file is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        load_json(path).unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        save_json(path, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        assert!(!Settings::load(&path).read_from_snapshot);
+
+        let settings = Settings { read_from_snapshot: true, read_strategy: ReadStrategy::LocalOnly, ..Default::default() };
+        settings.save(&path).unwrap();
+        let loaded = Settings::load(&path);
+        assert!(loaded.read_from_snapshot);
+        assert_eq!(loaded.read_strategy, ReadStrategy::LocalOnly);
+
+        // Unknown or missing fields fall back to defaults
+        std::fs::write(&path, r#"{"log_full_text": true, "retired": 1}"#).unwrap();
+        let loaded = Settings::load(&path);
+        assert!(loaded.log_full_text);
+        assert_eq!(loaded.sort_order, SortOrder::Recent);
+    }
+}
@@ -0,0 +1,141 @@
+//! Coordinating a clean quit with sends and background threads.
+//!
+//! Quitting halfway through a batch must not lose or repeat replies. Each
+//! send holds a [`SendSlot`] while it's out; once quitting starts no new
+//! sends begin, and the app waits for the ones in flight so their outcome
+//! is recorded before state is written for the last time.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Longest to wait for sends in flight when quitting. A little over the
+/// osascript timeout, so a send that hangs has been given up on by then.
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(35);
+
+/// Whether the app is quitting, and how many sends are still out.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    stopping: AtomicBool,
+    in_flight: Mutex<usize>,
+    /// Signalled when the last send finishes and when quitting starts
+    idle: Condvar,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether quitting has started. Background loops should return.
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    /// Claim a slot for one send, held until the send's outcome is saved.
+    /// None once quitting has started, so the reply stays queued instead.
+    pub fn begin_send(&self) -> Option<SendSlot<'_>> {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if self.is_stopping() {
+            return None;
+        }
+        *in_flight += 1;
+        Some(SendSlot { shutdown: self })
+    }
+
+    /// Sleep for up to `timeout`, waking early once quitting starts, for
+    /// background loops with long pauses. Returns whether quitting started.
+    pub fn sleep(&self, timeout: Duration) -> bool {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = self
+            .idle
+            .wait_timeout_while(in_flight, timeout, |_| !self.is_stopping())
+            .unwrap_or_else(|e| e.into_inner());
+        self.is_stopping()
+    }
+
+    /// Start quitting and wait up to `grace` for sends in flight to finish.
+    /// Returns whether they all did.
+    pub fn stop(&self, grace: Duration) -> bool {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        self.stopping.store(true, Ordering::SeqCst);
+        self.idle.notify_all();
+        let (in_flight, _) = self
+            .idle
+            .wait_timeout_while(in_flight, grace, |n| *n > 0)
+            .unwrap_or_else(|e| e.into_inner());
+        *in_flight == 0
+    }
+}
+
+/// One send in flight; dropping it lets a waiting quit go ahead.
+#[derive(Debug)]
+pub struct SendSlot<'a> {
+    shutdown: &'a Shutdown,
+}
+
+impl Drop for SendSlot<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.shutdown.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        *in_flight -= 1;
+        if *in_flight == 0 {
+            self.shutdown.idle.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn test_stop_waits_for_sends() {
+        let shutdown = Arc::new(Shutdown::new());
+        assert!(shutdown.stop(Duration::ZERO));
+        assert!(shutdown.begin_send().is_none());
+
+        let shutdown = Arc::new(Shutdown::new());
+        let sender = {
+            let shutdown = shutdown.clone();
+            let (started, wait) = std::sync::mpsc::channel();
+            let handle = std::thread::spawn(move || {
+                let _slot = shutdown.begin_send().unwrap();
+                started.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+            });
+            wait.recv().unwrap();
+            handle
+        };
+        let start = Instant::now();
+        assert!(shutdown.stop(Duration::from_secs(5)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(shutdown.is_stopping());
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_stop_wakes_sleepers() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.sleep(Duration::from_millis(10)));
+
+        let shutdown = Arc::new(Shutdown::new());
+        let sleeper = {
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || shutdown.sleep(Duration::from_secs(60)))
+        };
+        let start = Instant::now();
+        std::thread::sleep(Duration::from_millis(20));
+        shutdown.stop(Duration::ZERO);
+        assert!(sleeper.join().unwrap());
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_stop_gives_up_after_grace() {
+        let shutdown = Shutdown::new();
+        let _slot = shutdown.begin_send().unwrap();
+        assert!(!shutdown.stop(Duration::from_millis(20)));
+    }
+}
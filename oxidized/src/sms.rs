@@ -0,0 +1,134 @@
+//! SMS length and segment counting for drafts.
+//!
+//! A single SMS holds 160 GSM-7 characters or 70 UCS-2 code units. Longer
+//! texts are split into parts of 153 or 67, the rest of each part going to the
+//! header that stitches them back together. One character outside GSM-7 (an
+//! emoji, a curly quote) switches the whole message to UCS-2.
+
+use serde::Serialize;
+
+/// Segments beyond which a draft is flagged as long.
+pub const WARN_SEGMENTS: usize = 3;
+
+/// GSM 03.38 basic character set.
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞ\u{1B}ÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+    ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+/// Characters sent as an escape plus a second septet.
+const GSM7_EXTENSION: &str = "\u{0C}^{}\\[~]|€";
+
+/// How an SMS is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsEncoding {
+    Gsm7,
+    Ucs2,
+}
+
+/// Length details for a draft, recomputed as it's typed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DraftAnalysis {
+    pub characters: usize,
+    /// None for iMessage, which doesn't split messages
+    pub encoding: Option<SmsEncoding>,
+    pub segments: usize,
+    /// Room left in the last segment before another is needed
+    pub remaining: usize,
+    /// More than [`WARN_SEGMENTS`] segments
+    pub warning: bool,
+}
+
+/// Count characters and, for SMS, the segments `text` will be sent as.
+pub fn analyze_draft(text: &str, service: &str) -> DraftAnalysis {
+    let characters = text.chars().count();
+    if !service.eq_ignore_ascii_case("SMS") {
+        return DraftAnalysis { characters, encoding: None, segments: 1, remaining: 0, warning: false };
+    }
+
+    let (encoding, units): (SmsEncoding, Vec<usize>) = if text.chars().all(is_gsm7) {
+        (SmsEncoding::Gsm7, text.chars().map(|c| if GSM7_EXTENSION.contains(c) { 2 } else { 1 }).collect())
+    } else {
+        (SmsEncoding::Ucs2, text.chars().map(char::len_utf16).collect())
+    };
+    let (single, multi) = match encoding {
+        SmsEncoding::Gsm7 => (160, 153),
+        SmsEncoding::Ucs2 => (70, 67),
+    };
+
+    let total: usize = units.iter().sum();
+    let (segments, remaining) = if total <= single {
+        (1, single - total)
+    } else {
+        // Escapes and surrogate pairs can't straddle two parts
+        let mut segments = 1;
+        let mut used = 0;
+        for unit in units {
+            if used + unit > multi {
+                segments += 1;
+                used = 0;
+            }
+            used += unit;
+        }
+        (segments, multi - used)
+    };
+
+    DraftAnalysis { characters, encoding: Some(encoding), segments, remaining, warning: segments > WARN_SEGMENTS }
+}
+
+fn is_gsm7(c: char) -> bool {
+    GSM7_BASIC.contains(c) || GSM7_EXTENSION.contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gsm7_segments() {
+        let short = analyze_draft("hello", "SMS");
+        assert_eq!(short.encoding, Some(SmsEncoding::Gsm7));
+        assert_eq!((short.segments, short.remaining), (1, 155));
+
+        assert_eq!(analyze_draft(&"a".repeat(160), "SMS").segments, 1);
+        let two = analyze_draft(&"a".repeat(161), "SMS");
+        assert_eq!((two.segments, two.remaining), (2, 145));
+    }
+
+    #[test]
+    fn test_extension_chars_take_two_septets() {
+        assert_eq!(analyze_draft(&"€".repeat(80), "SMS").segments, 1);
+        assert_eq!(analyze_draft(&"€".repeat(81), "SMS").segments, 2);
+        // 152 septets then an escape pair that must move to the next part
+        let text = format!("{}€{}", "a".repeat(152), "a".repeat(7));
+        let analysis = analyze_draft(&text, "SMS");
+        assert_eq!((analysis.segments, analysis.remaining), (2, 144));
+    }
+
+    #[test]
+    fn test_unicode_switches_to_ucs2() {
+        let analysis = analyze_draft("on my way 👍", "SMS");
+        assert_eq!(analysis.encoding, Some(SmsEncoding::Ucs2));
+        assert_eq!(analysis.characters, 11);
+        assert_eq!(analysis.remaining, 70 - 12);
+
+        assert_eq!(analyze_draft(&"é".repeat(160), "SMS").encoding, Some(SmsEncoding::Gsm7));
+        assert_eq!(analyze_draft(&"“".repeat(70), "SMS").segments, 1);
+        assert_eq!(analyze_draft(&"“".repeat(71), "SMS").segments, 2);
+    }
+
+    #[test]
+    fn test_warning_after_three_segments() {
+        assert!(!analyze_draft(&"a".repeat(153 * 3), "SMS").warning);
+        let long = analyze_draft(&"a".repeat(153 * 3 + 1), "SMS");
+        assert_eq!(long.segments, 4);
+        assert!(long.warning);
+    }
+
+    #[test]
+    fn test_imessage_is_never_split() {
+        let analysis = analyze_draft(&"a".repeat(1000), "iMessage");
+        assert_eq!(analysis.encoding, None);
+        assert_eq!(analysis.segments, 1);
+        assert!(!analysis.warning);
+    }
+}
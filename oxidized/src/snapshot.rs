@@ -0,0 +1,236 @@
+//! JSON snapshot of the triage queue for external tooling.
+//!
+//! Scripts can read the snapshot, generate replies, and hand them back as a
+//! JSON map of chat GUID (or chat identifier) to draft text.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Conversation, Message};
+use crate::persist::atomic_write;
+
+/// One conversation in the queue with its latest messages and reply state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotConversation {
+    pub chat_id: i64,
+    /// chat.guid; prefer this over chat_id when matching back up
+    #[serde(default)]
+    pub guid: String,
+    pub chat_identifier: String,
+    pub name: String,
+    pub is_group: bool,
+    pub unread_count: i64,
+    pub last_message_date: DateTime<Utc>,
+    pub messages: Vec<Message>,
+    #[serde(default)]
+    pub draft: Option<String>,
+    #[serde(default)]
+    pub committed: Option<String>,
+    #[serde(default)]
+    pub later: bool,
+}
+
+/// The whole triage queue at one point in time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnreadSnapshot {
+    pub exported_at: DateTime<Utc>,
+    pub conversations: Vec<SnapshotConversation>,
+}
+
+impl UnreadSnapshot {
+    /// Build a snapshot, taken at `now`, from loaded conversations and the
+    /// current reply state.
+    pub fn new(
+        conversations: &[Conversation],
+        drafts: &HashMap<String, String>,
+        committed: &HashMap<String, String>,
+        later: &HashSet<String>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let conversations = conversations
+            .iter()
+            .map(|c| SnapshotConversation {
+                chat_id: c.chat_id,
+                guid: c.guid.clone(),
+                chat_identifier: c.chat_identifier.clone(),
+                name: c.name().to_string(),
+                is_group: c.is_group(),
+                unread_count: c.unread_count,
+                last_message_date: c.last_message_date,
+                messages: c.messages.clone(),
+                draft: drafts.get(&c.guid).cloned(),
+                committed: committed.get(&c.guid).cloned(),
+                later: later.contains(&c.guid),
+            })
+            .collect();
+
+        Self { exported_at: now, conversations }
+    }
+
+    /// Write the snapshot as pretty-printed JSON.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        atomic_write(path, json.as_bytes())
+    }
+
+    /// Read a snapshot back, e.g. after a script filled in drafts.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("Cannot read snapshot: {}", e))?;
+        serde_json::from_str(&json).map_err(|e| format!("Invalid snapshot: {}", e))
+    }
+}
+
+/// A draft that wasn't imported because the chat already has different text.
+#[derive(Debug, Clone, Serialize)]
+pub struct DraftConflict {
+    pub chat_guid: String,
+    pub existing: String,
+    pub incoming: String,
+}
+
+/// What happened to each entry of an imported draft map.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DraftImportReport {
+    /// GUIDs of chats that received a new draft
+    pub imported: Vec<String>,
+    /// GUIDs of chats whose draft already matched
+    pub unchanged: Vec<String>,
+    pub conflicts: Vec<DraftConflict>,
+    /// Keys that match no conversation in the queue
+    pub unmatched: Vec<String>,
+}
+
+/// Read a JSON object mapping chat GUID or identifier to draft text.
+pub fn read_draft_map(path: &Path) -> Result<HashMap<String, String>, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Cannot read drafts: {}", e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Invalid drafts file: {}", e))
+}
+
+/// Merge imported drafts into app state. Existing drafts or committed
+/// replies are never overwritten; differing text is reported as a conflict.
+pub fn merge_drafts(
+    incoming: HashMap<String, String>,
+    conversations: &[Conversation],
+    drafts: &mut HashMap<String, String>,
+    committed: &HashMap<String, String>,
+) -> DraftImportReport {
+    let mut report = DraftImportReport::default();
+
+    let mut entries: Vec<_> = incoming.into_iter().collect();
+    entries.sort();
+
+    for (key, text) in entries {
+        // Exact GUID first, then "iMessage;-;+15551234567" -> "+15551234567"
+        let identifier = key.rsplit(';').next().unwrap_or(&key);
+        let conv = conversations
+            .iter()
+            .find(|c| c.guid == key)
+            .or_else(|| conversations.iter().find(|c| c.chat_identifier == identifier));
+        let Some(conv) = conv else {
+            report.unmatched.push(key);
+            continue;
+        };
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let existing = committed.get(&conv.guid).or_else(|| drafts.get(&conv.guid));
+        match existing {
+            Some(current) if *current == text => report.unchanged.push(conv.guid.clone()),
+            Some(current) => report.conflicts.push(DraftConflict {
+                chat_guid: conv.guid.clone(),
+                existing: current.clone(),
+                incoming: text,
+            }),
+            None => {
+                drafts.insert(conv.guid.clone(), text);
+                report.imported.push(conv.guid.clone());
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::conversation;
+
+    fn conv(chat_id: i64) -> Conversation {
+        Conversation {
+            chat_id,
+            unread_count: 2,
+            resolved_name: Some("John".into()),
+            ..conversation(&format!("+1555000000{}", chat_id))
+        }
+    }
+
+    #[test]
+    fn test_snapshot_includes_reply_state() {
+        let drafts = HashMap::from([(conv(1).guid, "draft".to_string())]);
+        let committed = HashMap::from([(conv(2).guid, "ready".to_string())]);
+        let later = HashSet::from([conv(2).guid]);
+
+        let snapshot = UnreadSnapshot::new(&[conv(1), conv(2)], &drafts, &committed, &later, Utc::now());
+        assert_eq!(snapshot.conversations[0].name, "John");
+        assert_eq!(snapshot.conversations[0].draft.as_deref(), Some("draft"));
+        assert!(!snapshot.conversations[0].later);
+        assert_eq!(snapshot.conversations[1].committed.as_deref(), Some("ready"));
+        assert!(snapshot.conversations[1].later);
+    }
+
+    #[test]
+    fn test_merge_drafts() {
+        let convs = [conv(1), conv(2), conv(3), conv(4)];
+        let mut drafts = HashMap::from([(conv(2).guid, "same".to_string()), (conv(3).guid, "mine".to_string())]);
+        let committed = HashMap::from([(conv(4).guid, "sent soon".to_string())]);
+        let incoming = HashMap::from([
+            ("iMessage;-;+15550000001".to_string(), "hello".to_string()),
+            ("+15550000002".to_string(), "same".to_string()),
+            ("+15550000003".to_string(), "theirs".to_string()),
+            ("+15550000004".to_string(), "other".to_string()),
+            ("+15559999999".to_string(), "nobody".to_string()),
+        ]);
+
+        let report = merge_drafts(incoming, &convs, &mut drafts, &committed);
+        assert_eq!(report.imported, vec![conv(1).guid]);
+        assert_eq!(report.unchanged, vec![conv(2).guid]);
+        assert_eq!(report.conflicts.len(), 2);
+        assert_eq!(report.conflicts[0].existing, "mine");
+        assert_eq!(report.unmatched, vec!["+15559999999".to_string()]);
+
+        assert_eq!(drafts.get(&conv(1).guid).map(String::as_str), Some("hello"));
+        assert_eq!(drafts.get(&conv(3).guid).map(String::as_str), Some("mine"));
+    }
+
+    #[test]
+    fn test_read_draft_map() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("drafts.json");
+        fs::write(&path, r#"{"+15550000001": "hi"}"#).unwrap();
+        assert_eq!(read_draft_map(&path).unwrap().len(), 1);
+
+        fs::write(&path, "[1, 2]").unwrap();
+        assert!(read_draft_map(&path).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.json");
+
+        let snapshot = UnreadSnapshot::new(&[conv(1)], &HashMap::new(), &HashMap::new(), &HashSet::new(), Utc::now());
+        snapshot.write(&path).unwrap();
+
+        let read = UnreadSnapshot::read(&path).unwrap();
+        assert_eq!(read.conversations.len(), 1);
+        assert_eq!(read.conversations[0].chat_id, 1);
+        assert_eq!(read.conversations[0].guid, "iMessage;-;+15550000001");
+        assert!(UnreadSnapshot::read(&dir.path().join("none.json")).is_err());
+    }
+}
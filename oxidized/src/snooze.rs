@@ -0,0 +1,131 @@
+//! Snoozed chats, hidden from the queue for a while.
+//!
+//! A snooze ends at its deadline, or earlier if a message newer than the ones
+//! seen when snoozing arrives, so a reply to a handed-off chat isn't missed.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::Conversation;
+use crate::store::Document;
+
+/// Snooze length for a hand-off when settings don't say.
+pub const DEFAULT_HANDOFF_SNOOZE_MINUTES: u64 = 120;
+
+/// One snoozed chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snooze {
+    pub until: DateTime<Utc>,
+    /// Newest unread ROWID when snoozed
+    pub rowid: i64,
+}
+
+/// Snoozes by chat GUID, saved as JSON.
+pub struct SnoozeList {
+    doc: Document,
+    snoozes: HashMap<String, Snooze>,
+}
+
+impl SnoozeList {
+    /// Default snooze file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().snoozed()
+    }
+
+    /// Load the list, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let snoozes = doc.load().unwrap_or_default();
+        Self { doc, snoozes }
+    }
+
+    /// Write the list to disk.
+    pub fn save(&self) -> std::io::Result<()> {
+        self.doc.save(&self.snoozes)
+    }
+
+    /// Hide `conv` until `until` or its next new message.
+    pub fn snooze(&mut self, conv: &Conversation, until: DateTime<Utc>) {
+        self.snoozes.insert(conv.guid.clone(), Snooze { until, rowid: conv.last_unread_rowid });
+    }
+
+    /// Cancel a snooze; returns whether there was one.
+    pub fn wake(&mut self, chat_guid: &str) -> bool {
+        self.snoozes.remove(chat_guid).is_some()
+    }
+
+    /// Whether `conv` should stay hidden at `now`.
+    pub fn is_snoozed(&self, conv: &Conversation, now: DateTime<Utc>) -> bool {
+        self.snoozes
+            .get(&conv.guid)
+            .is_some_and(|s| now < s.until && conv.last_unread_rowid <= s.rowid)
+    }
+
+    /// Drop snoozes whose deadline has passed. Returns whether any were.
+    pub fn prune(&mut self, now: DateTime<Utc>) -> bool {
+        let before = self.snoozes.len();
+        self.snoozes.retain(|_, s| now < s.until);
+        self.snoozes.len() != before
+    }
+
+    /// Every active snooze.
+    pub fn all(&self) -> &HashMap<String, Snooze> {
+        &self.snoozes
+    }
+}
+
+impl Default for SnoozeList {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::fixtures::conversation;
+
+    fn conv(chat_id: i64, last_unread_rowid: i64) -> Conversation {
+        Conversation {
+            chat_id,
+            guid: format!("iMessage;-;test{}", chat_id),
+            last_unread_rowid,
+            ..conversation("+15551234567")
+        }
+    }
+
+    #[test]
+    fn test_snooze_ends_at_deadline_or_new_message() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut list = SnoozeList::load(Document::file(&dir.path().join("snoozed.json")));
+        let now = Utc::now();
+        list.snooze(&conv(1, 100), now + Duration::hours(2));
+
+        assert!(list.is_snoozed(&conv(1, 100), now));
+        assert!(!list.is_snoozed(&conv(1, 101), now));
+        assert!(!list.is_snoozed(&conv(1, 100), now + Duration::hours(3)));
+        assert!(!list.is_snoozed(&conv(2, 100), now));
+
+        assert!(!list.prune(now));
+        assert!(list.prune(now + Duration::hours(3)));
+        assert!(list.all().is_empty());
+    }
+
+    #[test]
+    fn test_snoozes_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("snoozed.json");
+        let now = Utc::now();
+        let mut list = SnoozeList::load(Document::file(&path));
+        list.snooze(&conv(1, 10), now + Duration::minutes(30));
+        list.save().unwrap();
+
+        let mut reloaded = SnoozeList::load(Document::file(&path));
+        assert!(reloaded.is_snoozed(&conv(1, 10), now));
+        assert!(reloaded.wake("iMessage;-;test1"));
+        assert!(!reloaded.is_snoozed(&conv(1, 10), now));
+    }
+}
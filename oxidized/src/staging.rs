@@ -0,0 +1,328 @@
+//! Files staged to go out with a chat's reply.
+//!
+//! Attaching a file copies it into a per-chat staging directory, so the reply
+//! still sends if the original is moved or deleted. The index of staged files
+//! is saved as JSON so they survive a restart. Chats are keyed by GUID; their
+//! directories are named by its hash, since GUIDs hold `;` and `+`.
+//!
+//! Pasted or dropped images arrive as bytes rather than files and are written
+//! straight into staging, PNG or JPEG only and capped in size.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::persist::{atomic_write, load_json, save_json};
+use crate::send::{escape_applescript, osascript_output};
+
+/// Largest pasted image accepted.
+pub const MAX_IMAGE_BYTES: usize = 25 * 1024 * 1024;
+
+/// A copy of a file waiting to be sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagedFile {
+    /// Where the copy lives
+    pub path: PathBuf,
+    /// File name as the user picked it
+    pub original_name: String,
+    pub size: u64,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Staged attachments for every chat.
+pub struct AttachmentStaging {
+    dir: PathBuf,
+    files: HashMap<String, Vec<StagedFile>>,
+}
+
+impl AttachmentStaging {
+    /// Default staging directory in the app data directory.
+    pub fn default_dir() -> PathBuf {
+        crate::AppPaths::default().staging()
+    }
+
+    fn index_path(dir: &Path) -> PathBuf {
+        dir.join("staged.json")
+    }
+
+    /// Load the index, starting empty if it is missing or unreadable. Entries
+    /// whose copy has disappeared are dropped.
+    pub fn load(dir: PathBuf) -> Self {
+        let mut files: HashMap<String, Vec<StagedFile>> = load_json(&Self::index_path(&dir)).unwrap_or_default();
+        for staged in files.values_mut() {
+            staged.retain(|f| f.path.exists());
+        }
+        files.retain(|_, staged| !staged.is_empty());
+        Self { dir, files }
+    }
+
+    /// Write the index to disk.
+    pub fn save(&self) -> io::Result<()> {
+        save_json(&Self::index_path(&self.dir), &self.files)
+    }
+
+    /// Copy `source` into the chat's staging directory, as added at `now`. A
+    /// name already staged for the chat gets a numeric prefix rather than
+    /// replacing the first file.
+    pub fn stage(&mut self, chat_guid: &str, source: &Path, now: DateTime<Utc>) -> io::Result<StagedFile> {
+        let metadata = fs::metadata(source)?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file", source.display())));
+        }
+        let original_name = source
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "attachment".to_string());
+
+        let path = self.unique_path(chat_guid, &original_name)?;
+        fs::copy(source, &path)?;
+        Ok(self.record(chat_guid, path, original_name, metadata.len(), now))
+    }
+
+    /// Stage files for several chats, all or none: if one can't be copied,
+    /// the copies already made are removed again.
+    pub fn stage_all(&mut self, files: &[(&str, &Path)], now: DateTime<Utc>) -> io::Result<Vec<StagedFile>> {
+        let mut staged = Vec::new();
+        for &(chat_guid, source) in files {
+            match self.stage(chat_guid, source, now) {
+                Ok(file) => staged.push((chat_guid, file)),
+                Err(e) => {
+                    for (chat_guid, file) in &staged {
+                        let _ = self.remove(chat_guid, &file.path);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(staged.into_iter().map(|(_, file)| file).collect())
+    }
+
+    /// Write pasted or dropped image bytes into staging. Only PNG and JPEG
+    /// up to [`MAX_IMAGE_BYTES`] are accepted; the name says when, from `now`.
+    pub fn stage_image(&mut self, chat_guid: &str, bytes: &[u8], now: DateTime<Utc>) -> io::Result<StagedFile> {
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Image is {} MB; the limit is {} MB", bytes.len() / (1024 * 1024), MAX_IMAGE_BYTES / (1024 * 1024)),
+            ));
+        }
+        let Some(extension) = image_extension(bytes) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Only PNG and JPEG images can be pasted"));
+        };
+
+        let original_name = format!("Pasted Image {}.{}", now.format("%Y-%m-%d at %H.%M.%S"), extension);
+        let path = self.unique_path(chat_guid, &original_name)?;
+        atomic_write(&path, bytes)?;
+        Ok(self.record(chat_guid, path, original_name, bytes.len() as u64, now))
+    }
+
+    /// A path in the chat's staging directory that doesn't exist yet.
+    fn unique_path(&self, chat_guid: &str, name: &str) -> io::Result<PathBuf> {
+        let chat_dir = self.dir.join(chat_dir_name(chat_guid));
+        fs::create_dir_all(&chat_dir)?;
+        let mut path = chat_dir.join(name);
+        let mut n = 1;
+        while path.exists() {
+            path = chat_dir.join(format!("{}-{}", n, name));
+            n += 1;
+        }
+        Ok(path)
+    }
+
+    fn record(&mut self, chat_guid: &str, path: PathBuf, original_name: String, size: u64, now: DateTime<Utc>) -> StagedFile {
+        let staged = StagedFile { path, original_name, size, added_at: now };
+        self.files.entry(chat_guid.to_string()).or_default().push(staged.clone());
+        staged
+    }
+
+    /// Files staged for a chat, in the order they were added.
+    pub fn files(&self, chat_guid: &str) -> &[StagedFile] {
+        self.files.get(chat_guid).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Paths of a chat's staged files, ready for the sender.
+    pub fn paths(&self, chat_guid: &str) -> Vec<PathBuf> {
+        self.files(chat_guid).iter().map(|f| f.path.clone()).collect()
+    }
+
+    /// Everything staged, keyed by chat.
+    pub fn all(&self) -> &HashMap<String, Vec<StagedFile>> {
+        &self.files
+    }
+
+    /// Unstage one file and delete its copy.
+    pub fn remove(&mut self, chat_guid: &str, path: &Path) -> io::Result<()> {
+        if let Some(staged) = self.files.get_mut(chat_guid) {
+            staged.retain(|f| f.path != path);
+            if staged.is_empty() {
+                self.files.remove(chat_guid);
+            }
+        }
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Unstage everything for a chat, e.g. after it was sent.
+    pub fn clear(&mut self, chat_guid: &str) -> io::Result<()> {
+        self.files.remove(chat_guid);
+        match fs::remove_dir_all(self.dir.join(chat_dir_name(chat_guid))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Staging directory name for a chat: the start of its GUID's SHA-256.
+fn chat_dir_name(chat_guid: &str) -> String {
+    Sha256::digest(chat_guid.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The image on the clipboard as PNG, passed through a temporary file in
+/// `dir`. This runs osascript, so call it without the staging lock held and
+/// stage the bytes afterwards.
+pub fn read_clipboard_image(dir: &Path) -> io::Result<Vec<u8>> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    fs::create_dir_all(dir)?;
+    let temp = dir.join(format!(".clipboard-{}-{}.png", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+    let result = osascript_output(&clipboard_png_script(&temp))
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "No image on the clipboard"))
+        .and_then(|_| fs::read(&temp));
+    let _ = fs::remove_file(&temp);
+    result
+}
+
+/// File extension for PNG or JPEG data, by magic number.
+fn image_extension(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else {
+        None
+    }
+}
+
+/// AppleScript writing the clipboard's PNG data to `path`. Fails when the
+/// clipboard holds no image.
+fn clipboard_png_script(path: &Path) -> String {
+    format!(
+        r#"set imageData to the clipboard as «class PNGf»
+set outFile to open for access POSIX file "{}" with write permission
+set eof outFile to 0
+write imageData to outFile
+close access outFile"#,
+        escape_applescript(&path.to_string_lossy())
+    )
+}
+
+impl Default for AttachmentStaging {
+    fn default() -> Self {
+        Self::load(Self::default_dir())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "iMessage;-;+15551234567";
+    const B: &str = "iMessage;+;chat123";
+
+    #[test]
+    fn test_stage_copies_and_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("photo.jpg");
+        fs::write(&source, b"jpeg").unwrap();
+
+        let mut staging = AttachmentStaging::load(dir.path().join("staging"));
+        let first = staging.stage(A, &source, Utc::now()).unwrap();
+        let second = staging.stage(A, &source, Utc::now()).unwrap();
+        assert_ne!(first.path, second.path);
+        assert_eq!(second.original_name, "photo.jpg");
+        assert_eq!(first.size, 4);
+
+        // The copy survives the original going away
+        fs::remove_file(&source).unwrap();
+        staging.save().unwrap();
+        let reloaded = AttachmentStaging::load(dir.path().join("staging"));
+        assert_eq!(reloaded.paths(A), [first.path, second.path]);
+    }
+
+    #[test]
+    fn test_stage_all_or_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("card.jpg");
+        fs::write(&source, b"jpeg").unwrap();
+        let missing = dir.path().join("missing.jpg");
+
+        let mut staging = AttachmentStaging::load(dir.path().join("staging"));
+        assert!(staging.stage_all(&[(A, &source), (B, &source), (B, &missing)], Utc::now()).is_err());
+        assert!(staging.all().is_empty());
+        assert_eq!(fs::read_dir(dir.path().join("staging")).unwrap().flatten().filter(|e| e.path().is_file()).count(), 0);
+
+        let staged = staging.stage_all(&[(A, &source), (B, &source)], Utc::now()).unwrap();
+        assert_eq!(staged.len(), 2);
+        assert_eq!(staging.files(B)[0].path, staged[1].path);
+    }
+
+    #[test]
+    fn test_remove_and_clear() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("notes.txt");
+        fs::write(&source, b"hi").unwrap();
+
+        let mut staging = AttachmentStaging::load(dir.path().join("staging"));
+        let a = staging.stage(A, &source, Utc::now()).unwrap();
+        let b = staging.stage(A, &source, Utc::now()).unwrap();
+        staging.stage(B, &source, Utc::now()).unwrap();
+
+        staging.remove(A, &a.path).unwrap();
+        assert!(!a.path.exists());
+        assert_eq!(staging.files(A)[0].path, b.path);
+
+        staging.clear(A).unwrap();
+        assert!(staging.files(A).is_empty());
+        assert!(!b.path.exists());
+        assert_eq!(staging.files(B).len(), 1);
+    }
+
+    #[test]
+    fn test_stage_image_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut staging = AttachmentStaging::load(dir.path().join("staging"));
+
+        let png = b"\x89PNG\r\n\x1a\nrest".to_vec();
+        let staged = staging.stage_image(A, &png, Utc::now()).unwrap();
+        assert!(staged.original_name.starts_with("Pasted Image "));
+        assert!(staged.original_name.ends_with(".png"));
+        assert_eq!(fs::read(&staged.path).unwrap(), png);
+
+        assert!(staging.stage_image(A, &[0xFF, 0xD8, 0xFF, 0xE0], Utc::now()).unwrap().original_name.ends_with(".jpg"));
+        assert!(staging.stage_image(A, b"GIF89a", Utc::now()).is_err());
+        assert!(staging.stage_image(A, &vec![0; MAX_IMAGE_BYTES + 1], Utc::now()).is_err());
+        assert_eq!(staging.files(A).len(), 2);
+    }
+
+    #[test]
+    fn test_clipboard_script() {
+        let script = clipboard_png_script(Path::new("/tmp/a \"b\".png"));
+        assert!(script.contains("the clipboard as «class PNGf»"));
+        assert!(script.contains(r#"POSIX file "/tmp/a \"b\".png""#));
+    }
+
+    #[test]
+    fn test_rejects_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut staging = AttachmentStaging::load(dir.path().join("staging"));
+        assert!(staging.stage(A, dir.path(), Utc::now()).is_err());
+        assert!(staging.stage(A, &dir.path().join("missing"), Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Drafts left behind after a chat was handled in Messages.app.
+//!
+//! A chat that drops out of the queue because it was answered elsewhere
+//! keeps its draft or committed reply here. Picking it back up later risks
+//! replying twice, so such drafts are flagged or cleared.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Database, DbError};
+use crate::models::Conversation;
+
+/// What happens to drafts for chats handled in Messages.app.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaleDraftPolicy {
+    /// Keep them, but report them through `get_stale_drafts`
+    #[default]
+    Flag,
+    /// Delete them when the queue is next loaded
+    Clear,
+}
+
+/// A draft or committed reply for a chat that no longer needs one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StaleDraft {
+    pub chat_guid: String,
+    pub text: String,
+    /// Committed for sending rather than still being written
+    pub committed: bool,
+}
+
+/// Drafts and committed replies whose chat has nothing unread and ends with
+/// my own message. Chats still in `queue` are skipped without a lookup.
+pub fn find_stale_drafts(
+    db: &Database,
+    queue: &[Conversation],
+    drafts: &HashMap<String, String>,
+    committed: &HashMap<String, String>,
+) -> Result<Vec<StaleDraft>, DbError> {
+    let mut stale = Vec::new();
+    let entries = drafts
+        .iter()
+        .map(|(guid, text)| (guid, text, false))
+        .chain(committed.iter().map(|(guid, text)| (guid, text, true)));
+
+    for (chat_guid, text, committed) in entries {
+        if queue.iter().any(|c| c.guid == *chat_guid) || !db.handled_in_messages(chat_guid)? {
+            continue;
+        }
+        stale.push(StaleDraft { chat_guid: chat_guid.clone(), text: text.clone(), committed });
+    }
+
+    stale.sort_by(|a, b| (&a.chat_guid, a.committed).cmp(&(&b.chat_guid, b.committed)));
+    Ok(stale)
+}
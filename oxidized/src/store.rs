@@ -0,0 +1,436 @@
+//! Where app state is kept: JSON files or one SQLite database.
+//!
+//! Readable JSON files suit most people. Someone with years of send
+//! history pays for them, though, since every duplicate check reads the
+//! whole log. A [`StateStore`] holds named documents (a whole list saved at
+//! once) and append-only logs. [`JsonFileStore`] keeps each document in its
+//! own file and each log as JSON lines; [`SqliteStore`] keeps both in one
+//! database and indexes logs by key and time so those queries stay quick.
+//! The `state_store` setting picks one.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::paths::AppPaths;
+use crate::persist::{atomic_write, load_json, save_json};
+
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("State I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("State database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("State invalid: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// One record in a log, as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// What the record is about, e.g. a chat ID, for [`LogQuery::key`]
+    pub key: String,
+    pub at: DateTime<Utc>,
+    pub json: String,
+}
+
+/// Which log records to read. The default is all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogQuery {
+    /// Only records under this key
+    pub key: Option<String>,
+    /// Only records at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// At most this many of the newest matching records
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    /// Whether `entry` is under the key and time asked for; `limit` is
+    /// left to the caller.
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.key.as_ref().is_none_or(|key| *key == entry.key) && self.since.is_none_or(|since| entry.at >= since)
+    }
+}
+
+/// Saved app state, by name.
+pub trait StateStore: Send + Sync {
+    /// The JSON last written under `name`, if any.
+    fn read(&self, name: &str) -> Result<Option<String>, StoreError>;
+    /// Replace the document `name`.
+    fn write(&self, name: &str, json: &str) -> Result<(), StoreError>;
+    /// Add a record to the end of `log`.
+    fn append(&self, log: &str, entry: &LogEntry) -> Result<(), StoreError>;
+    /// Add records to the end of `log`: all of them, or none if it fails.
+    fn append_all(&self, log: &str, entries: &[LogEntry]) -> Result<(), StoreError>;
+    /// Records in `log` matching `query`, oldest first.
+    fn entries(&self, log: &str, query: &LogQuery) -> Result<Vec<LogEntry>, StoreError>;
+}
+
+/// Where app state is kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateStoreKind {
+    /// Each kind of state in its own JSON file, in [`JsonFileStore`]
+    #[default]
+    Json,
+    /// Everything in [`SqliteStore`]
+    Sqlite,
+}
+
+/// The store of `kind` in the data directory. A new SQLite store starts
+/// with the state files' contents, so switching to it keeps them; a
+/// document it already has is left alone.
+pub fn open_state_store(kind: StateStoreKind, paths: &AppPaths) -> Result<Arc<dyn StateStore>, StoreError> {
+    let files = JsonFileStore::new(paths.data_dir.clone());
+    if kind == StateStoreKind::Json {
+        return Ok(Arc::new(files));
+    }
+    let store = SqliteStore::open(&paths.state_db())?;
+    for path in paths.state_documents() {
+        let name = document_name(&path);
+        if store.read(&name)?.is_none() {
+            if let Some(json) = files.read(&name)? {
+                store.write(&name, &json)?;
+            }
+        }
+    }
+    Ok(Arc::new(store))
+}
+
+/// A state file's document name: its file name, so the JSON store keeps
+/// it in the same file.
+fn document_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// One document in a [`StateStore`], for state saved whole.
+#[derive(Clone)]
+pub struct Document {
+    store: Arc<dyn StateStore>,
+    name: String,
+}
+
+impl std::fmt::Debug for Document {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Document").field(&self.name).finish()
+    }
+}
+
+impl Document {
+    /// The document for the state file at `path` in the data directory.
+    pub fn for_file(store: &Arc<dyn StateStore>, path: &Path) -> Self {
+        Self { store: store.clone(), name: document_name(path) }
+    }
+
+    /// The JSON file at `path` on its own, whichever store is configured.
+    pub fn file(path: &Path) -> Self {
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Self::for_file(&(Arc::new(JsonFileStore::new(dir)) as Arc<dyn StateStore>), path)
+    }
+
+    /// The saved value, or None if nothing readable was saved.
+    pub fn load<T: DeserializeOwned>(&self) -> Option<T> {
+        let json = self.store.read(&self.name).ok()??;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Replace the saved value. Failures come back as I/O errors, as they
+    /// did when state was only ever files.
+    pub fn save<T: Serialize>(&self, value: &T) -> io::Result<()> {
+        let json = serde_json::to_string(value)?;
+        self.store.write(&self.name, &json).map_err(|e| match e {
+            StoreError::Io(e) => e,
+            e => io::Error::other(e),
+        })
+    }
+}
+
+/// Documents as JSON files in one folder, each with a `.bak` of its last
+/// good version, and logs as JSON lines in its `logs` folder.
+pub struct JsonFileStore {
+    dir: PathBuf,
+}
+
+/// A log record in a JSON store, one per line. The entry is inline rather
+/// than a string so the file stays readable.
+#[derive(Serialize, Deserialize)]
+struct LogLine {
+    key: String,
+    at: DateTime<Utc>,
+    entry: serde_json::Value,
+}
+
+impl JsonFileStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn log_path(&self, log: &str) -> PathBuf {
+        self.dir.join("logs").join(format!("{}.jsonl", log))
+    }
+
+    /// A record as its line, newline included.
+    fn line(entry: &LogEntry) -> Result<String, StoreError> {
+        let line = LogLine { key: entry.key.clone(), at: entry.at, entry: serde_json::from_str(&entry.json)? };
+        let mut line = serde_json::to_string(&line)?;
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+impl StateStore for JsonFileStore {
+    fn read(&self, name: &str) -> Result<Option<String>, StoreError> {
+        Ok(load_json::<serde_json::Value>(&self.dir.join(name)).map(|value| value.to_string()))
+    }
+
+    fn write(&self, name: &str, json: &str) -> Result<(), StoreError> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        Ok(save_json(&self.dir.join(name), &value)?)
+    }
+
+    fn append(&self, log: &str, entry: &LogEntry) -> Result<(), StoreError> {
+        let path = self.log_path(log);
+        let mut line = Self::line(entry)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        // A crash mid-append leaves a partial line; start a fresh one so
+        // this record isn't glued onto it and skipped too
+        if file.metadata()?.len() > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n');
+            }
+        }
+        file.write_all(line.as_bytes())?;
+        // On disk before the caller goes on, e.g. treats a send as done
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn append_all(&self, log: &str, entries: &[LogEntry]) -> Result<(), StoreError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        // The whole file is replaced in one rename, so it's all or nothing
+        let path = self.log_path(log);
+        let mut bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.last().is_some_and(|last| *last != b'\n') {
+            bytes.push(b'\n');
+        }
+        for entry in entries {
+            bytes.extend(Self::line(entry)?.into_bytes());
+        }
+        Ok(atomic_write(&path, &bytes)?)
+    }
+
+    fn entries(&self, log: &str, query: &LogQuery) -> Result<Vec<LogEntry>, StoreError> {
+        let file = match fs::File::open(self.log_path(log)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            // Unreadable lines, like one torn by a crash, are skipped
+            let Ok(line) = serde_json::from_str::<LogLine>(&line?) else { continue };
+            let entry = LogEntry { key: line.key, at: line.at, json: line.entry.to_string() };
+            if query.matches(&entry) {
+                entries.push(entry);
+            }
+        }
+        if let Some(limit) = query.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+}
+
+/// Documents and logs in one SQLite database, logs indexed by key and time.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &std::path::Path) -> Result<Self, StoreError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS documents (name TEXT PRIMARY KEY, json TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS log (
+                 id INTEGER PRIMARY KEY, log TEXT NOT NULL, key TEXT NOT NULL,
+                 at INTEGER NOT NULL, json TEXT NOT NULL);
+             CREATE INDEX IF NOT EXISTS log_by_key ON log (log, key, at);
+             CREATE INDEX IF NOT EXISTS log_by_time ON log (log, at);",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // A panic mid-query leaves nothing half-written worth refusing
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn read(&self, name: &str) -> Result<Option<String>, StoreError> {
+        Ok(self.conn().query_row("SELECT json FROM documents WHERE name = ?1", [name], |row| row.get(0)).optional()?)
+    }
+
+    fn write(&self, name: &str, json: &str) -> Result<(), StoreError> {
+        serde_json::from_str::<serde_json::Value>(json)?;
+        self.conn().execute("INSERT OR REPLACE INTO documents (name, json) VALUES (?1, ?2)", [name, json])?;
+        Ok(())
+    }
+
+    fn append(&self, log: &str, entry: &LogEntry) -> Result<(), StoreError> {
+        self.append_all(log, std::slice::from_ref(entry))
+    }
+
+    fn append_all(&self, log: &str, entries: &[LogEntry]) -> Result<(), StoreError> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO log (log, key, at, json) VALUES (?1, ?2, ?3, ?4)")?;
+            for entry in entries {
+                stmt.execute(params![log, entry.key, entry.at.timestamp_micros(), entry.json])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn entries(&self, log: &str, query: &LogQuery) -> Result<Vec<LogEntry>, StoreError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT key, at, json FROM log
+             WHERE log = ?1 AND (?2 IS NULL OR key = ?2) AND (?3 IS NULL OR at >= ?3)
+             ORDER BY at DESC, id DESC
+             LIMIT ?4",
+        )?;
+        let limit = query.limit.map_or(-1, |l| l as i64);
+        let since = query.since.map(|s| s.timestamp_micros());
+        let rows = stmt.query_map(params![log, query.key, since, limit], |row| {
+            let at: i64 = row.get(1)?;
+            Ok(LogEntry { key: row.get(0)?, at: DateTime::from_timestamp_micros(at).unwrap_or_default(), json: row.get(2)? })
+        })?;
+        let mut entries = rows.collect::<Result<Vec<_>, _>>()?;
+        entries.reverse();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn entry(key: &str, at: DateTime<Utc>, n: i32) -> LogEntry {
+        LogEntry { key: key.into(), at, json: n.to_string() }
+    }
+
+    fn check_store(store: &dyn StateStore) {
+        assert_eq!(store.read("muted").unwrap(), None);
+        store.write("muted", r#"["chat1"]"#).unwrap();
+        store.write("muted", r#"["chat2"]"#).unwrap();
+        assert_eq!(store.read("muted").unwrap().as_deref(), Some(r#"["chat2"]"#));
+        assert!(store.write("muted", "{torn").is_err());
+
+        let now = Utc::now();
+        assert!(store.entries("history", &LogQuery::default()).unwrap().is_empty());
+        store.append("history", &entry("1", now - Duration::days(2), 1)).unwrap();
+        store.append("history", &entry("2", now - Duration::hours(1), 2)).unwrap();
+        store.append("history", &entry("1", now, 3)).unwrap();
+        store.append("other", &entry("1", now, 4)).unwrap();
+
+        let json = |query: LogQuery| -> Vec<String> {
+            store.entries("history", &query).unwrap().into_iter().map(|e| e.json).collect()
+        };
+        assert_eq!(json(LogQuery::default()), ["1", "2", "3"]);
+        assert_eq!(json(LogQuery { key: Some("1".into()), ..Default::default() }), ["1", "3"]);
+        assert_eq!(json(LogQuery { since: Some(now - Duration::days(1)), ..Default::default() }), ["2", "3"]);
+        assert_eq!(json(LogQuery { limit: Some(2), ..Default::default() }), ["2", "3"]);
+    }
+
+    #[test]
+    fn test_sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.sqlite");
+        check_store(&SqliteStore::open(&path).unwrap());
+
+        // Reopening keeps what was written
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.entries("history", &LogQuery::default()).unwrap().len(), 3);
+
+        // A batch that fails partway adds nothing
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER no_bad BEFORE INSERT ON log WHEN NEW.json = 'bad'
+             BEGIN SELECT RAISE(ABORT, 'bad'); END;"
+        ).unwrap();
+        let now = Utc::now();
+        assert!(store.append_all("history", &[entry("1", now, 5), LogEntry { json: "bad".into(), ..entry("1", now, 0) }]).is_err());
+        assert_eq!(store.entries("history", &LogQuery::default()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_json_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        check_store(&JsonFileStore::new(dir.path().to_path_buf()));
+
+        // Reopening keeps what was written
+        let store = JsonFileStore::new(dir.path().to_path_buf());
+        assert_eq!(store.entries("history", &LogQuery::default()).unwrap().len(), 3);
+
+        // A torn last line from a crash mid-append is skipped, and the
+        // next record starts its own line
+        let log = dir.path().join("logs/history.jsonl");
+        OpenOptions::new().append(true).open(&log).unwrap().write_all(b"{\"key\": \"1\", \"a").unwrap();
+        store.append("history", &entry("2", Utc::now(), 5)).unwrap();
+        store.append_all("history", &[entry("2", Utc::now(), 6)]).unwrap();
+        let json: Vec<_> = store.entries("history", &LogQuery::default()).unwrap().into_iter().map(|e| e.json).collect();
+        assert_eq!(json, ["1", "2", "3", "5", "6"]);
+
+        // A document that stops parsing falls back to its backup
+        fs::write(dir.path().join("muted"), "{torn").unwrap();
+        assert_eq!(store.read("muted").unwrap().as_deref(), Some(r#"["chat1"]"#));
+    }
+
+    #[test]
+    fn test_sqlite_store_starts_from_state_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = AppPaths::new(dir.path().to_path_buf(), dir.path().join("cache"));
+        let files = open_state_store(StateStoreKind::Json, &paths).unwrap();
+        Document::for_file(&files, &paths.muted()).save(&["chat1"]).unwrap();
+        assert_eq!(Document::file(&paths.muted()).load::<Vec<String>>(), Some(vec!["chat1".to_string()]));
+
+        let sqlite = open_state_store(StateStoreKind::Sqlite, &paths).unwrap();
+        let muted = Document::for_file(&sqlite, &paths.muted());
+        assert_eq!(muted.load::<Vec<String>>(), Some(vec!["chat1".to_string()]));
+        assert_eq!(Document::for_file(&sqlite, &paths.snoozed()).load::<Vec<String>>(), None);
+
+        // Saved in the database from then on, and not copied over again
+        muted.save(&["chat2"]).unwrap();
+        let reopened = open_state_store(StateStoreKind::Sqlite, &paths).unwrap();
+        assert_eq!(Document::for_file(&reopened, &paths.muted()).load::<Vec<String>>(), Some(vec!["chat2".to_string()]));
+        assert_eq!(Document::file(&paths.muted()).load::<Vec<String>>(), Some(vec!["chat1".to_string()]));
+    }
+}
@@ -0,0 +1,170 @@
+//! Compact conversation summaries.
+//!
+//! Anything that shows a conversation outside the main list (menus,
+//! notifications, digests, webhooks) should build a [`SummaryCard`] so they
+//! all agree on the name, preview, and flags.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::{AgeBucket, Conversation, Message};
+
+/// Longest preview line, in characters, before it is cut with an ellipsis.
+pub const SUMMARY_TEXT_CHARS: usize = 80;
+
+/// Messages shown on a card.
+const SUMMARY_MESSAGES: usize = 2;
+
+/// One message on a summary card.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SummaryLine {
+    pub sender: String,
+    pub text: String,
+}
+
+/// A conversation reduced to what fits in a notification or menu item.
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryCard {
+    pub chat_id: i64,
+    pub name: String,
+    pub is_group: bool,
+    pub unread_count: i64,
+    pub age_bucket: AgeBucket,
+    pub waiting_since: DateTime<Utc>,
+    /// The newest one or two messages, oldest first
+    pub last_messages: Vec<SummaryLine>,
+    /// An unread message asks a question
+    pub has_question: bool,
+    /// An unread message carries an attachment
+    pub has_attachment: bool,
+    /// Every unread message is an acknowledgment like "ok" or "👍"
+    pub acknowledgments_only: bool,
+}
+
+/// Cut text to `max` characters, ending with an ellipsis when shortened.
+pub(crate) fn trim_preview(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= max {
+        return text;
+    }
+    let cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    format!("{}…", cut.trim_end())
+}
+
+impl Conversation {
+    fn summary_sender(&self, message: &Message) -> String {
+        if message.is_from_me {
+            "Me".to_string()
+        } else if !self.is_group() {
+            self.name().to_string()
+        } else {
+            message.sender_label()
+        }
+    }
+
+    /// Build the summary card for this conversation.
+    pub fn summary_card(&self) -> SummaryCard {
+        let last_messages = self
+            .messages
+            .iter()
+            .rev()
+            .take(SUMMARY_MESSAGES)
+            .rev()
+            .map(|m| {
+                let text = m.display_text();
+                let text = if !text.is_empty() {
+                    text
+                } else if let Some(audio) = m.audio_label() {
+                    audio
+                } else if let Some(link) = &m.link_preview {
+                    link.label()
+                } else if m.is_image_only() {
+                    "[image]".to_string()
+                } else {
+                    "[attachment]".to_string()
+                };
+                SummaryLine { sender: self.summary_sender(m), text: trim_preview(&text, SUMMARY_TEXT_CHARS) }
+            })
+            .collect();
+
+        let unread: Vec<&Message> = self
+            .messages
+            .iter()
+            .filter(|m| !m.is_from_me && m.date >= self.first_unread_date)
+            .collect();
+
+        SummaryCard {
+            chat_id: self.chat_id,
+            name: self.name().to_string(),
+            is_group: self.is_group(),
+            unread_count: self.unread_count,
+            age_bucket: self.age_bucket,
+            waiting_since: self.first_unread_date,
+            last_messages,
+            has_question: unread.iter().any(|m| m.text.contains('?')),
+            has_attachment: unread.iter().any(|m| !m.attachments.is_empty()),
+            acknowledgments_only: self.only_acknowledgments(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{conversation, message};
+    use crate::models::Attachment;
+
+    fn msg(text: &str, secs: i64, is_from_me: bool) -> Message {
+        Message {
+            rowid: secs,
+            guid: format!("g{}", secs),
+            date: DateTime::from_timestamp(secs, 0).unwrap(),
+            is_from_me,
+            sender: (!is_from_me).then(|| "+15551234567".into()),
+            ..message(text)
+        }
+    }
+
+    fn conv(messages: Vec<Message>, first_unread: i64) -> Conversation {
+        Conversation {
+            chat_id: 7,
+            unread_count: 2,
+            first_unread_date: DateTime::from_timestamp(first_unread, 0).unwrap(),
+            messages,
+            resolved_name: Some("John".into()),
+            ..conversation("+15551234567")
+        }
+    }
+
+    #[test]
+    fn test_summary_card() {
+        let mut photo = msg("\u{FFFC}", 300, false);
+        photo.attachments.push(Attachment::new("~/Library/Messages/Attachments/a.jpg".into(), "image/jpeg".into(), "a.jpg".into()));
+        let card = conv(
+            vec![msg("are we still on?", 100, false), msg("yes", 150, true), msg("running late", 200, false), photo],
+            200,
+        )
+        .summary_card();
+
+        assert_eq!(card.name, "John");
+        assert_eq!(card.last_messages, [
+            SummaryLine { sender: "John".into(), text: "running late".into() },
+            SummaryLine { sender: "John".into(), text: "[image]".into() },
+        ]);
+        // The question was answered before the unread ones arrived
+        assert!(!card.has_question);
+        assert!(card.has_attachment);
+        assert!(!card.acknowledgments_only);
+
+        let acks = conv(vec![msg("can you bring chairs?", 100, true), msg("ok", 200, false), msg("👍", 210, false)], 200);
+        assert!(acks.summary_card().acknowledgments_only);
+    }
+
+    #[test]
+    fn test_trim_preview() {
+        assert_eq!(trim_preview("short", 10), "short");
+        assert_eq!(trim_preview("line one\n\nline   two", 80), "line one line two");
+        assert_eq!(trim_preview("abcdefghijkl", 6), "abcde…");
+        assert_eq!(trim_preview("abc def ghi", 5), "abc…");
+    }
+}
@@ -0,0 +1,224 @@
+//! Reusable reply templates.
+//!
+//! A template is text with `{name}` and `{first_name}` placeholders plus
+//! optional attachment slots, file names looked up in an assets folder when
+//! the template is applied. Applying one template to many chats gives a
+//! mail-merge style send, e.g. the same invitation and flyer to everyone.
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::models::Conversation;
+use crate::store::Document;
+
+#[derive(Error, Debug)]
+pub enum TemplateError {
+    #[error("Template not found: {0}")]
+    NotFound(String),
+    #[error("Attachment {0} is not in the assets folder")]
+    MissingAttachment(String),
+    #[error("Attachment {0} must be a file name inside the assets folder")]
+    InvalidAttachment(String),
+    #[error("Templates I/O failed: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// A saved reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Template {
+    pub name: String,
+    pub text: String,
+    /// Files to send with it, relative to the assets folder
+    #[serde(default)]
+    pub attachments: Vec<String>,
+}
+
+/// A template filled in for one chat.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AppliedTemplate {
+    pub text: String,
+    pub attachments: Vec<PathBuf>,
+}
+
+impl Template {
+    /// Fill in the placeholders for `conv` and find each attachment slot in
+    /// `assets_dir`. Fails if any slot can't be found, so a send never goes
+    /// out missing its file.
+    pub fn apply(&self, conv: &Conversation, assets_dir: &Path) -> Result<AppliedTemplate, TemplateError> {
+        self.apply_to(conv.name(), conv.is_group(), assets_dir)
+    }
+
+    /// [`Template::apply`] for a recipient known only by name, e.g. someone
+    /// there's no chat with yet. Groups use the whole name for `{first_name}`.
+    pub fn apply_to(&self, name: &str, is_group: bool, assets_dir: &Path) -> Result<AppliedTemplate, TemplateError> {
+        let first_name = if is_group { name } else { name.split_whitespace().next().unwrap_or(name) };
+        let text = self.text.replace("{first_name}", first_name).replace("{name}", name);
+
+        let attachments = self
+            .attachments
+            .iter()
+            .map(|slot| resolve_asset(assets_dir, slot))
+            .collect::<Result<_, _>>()?;
+
+        Ok(AppliedTemplate { text, attachments })
+    }
+}
+
+/// Path of an attachment slot, which must stay inside `assets_dir`.
+fn resolve_asset(assets_dir: &Path, slot: &str) -> Result<PathBuf, TemplateError> {
+    let relative = Path::new(slot);
+    if slot.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(TemplateError::InvalidAttachment(slot.to_string()));
+    }
+    let path = assets_dir.join(relative);
+    if !path.is_file() {
+        return Err(TemplateError::MissingAttachment(slot.to_string()));
+    }
+    // A symlink in the folder could still point outside it
+    let inside = match (path.canonicalize(), assets_dir.canonicalize()) {
+        (Ok(path), Ok(dir)) => path.starts_with(dir),
+        _ => false,
+    };
+    if !inside {
+        return Err(TemplateError::InvalidAttachment(slot.to_string()));
+    }
+    Ok(path)
+}
+
+/// Saved templates, kept as JSON in the order they were created.
+pub struct TemplateStore {
+    doc: Document,
+    templates: Vec<Template>,
+}
+
+impl TemplateStore {
+    /// Default templates file in the app data directory.
+    pub fn default_path() -> PathBuf {
+        crate::AppPaths::default().templates()
+    }
+
+    /// Default assets folder for attachment slots.
+    pub fn default_assets_dir() -> PathBuf {
+        crate::AppPaths::default().template_assets()
+    }
+
+    /// Load templates, starting empty if nothing readable was saved.
+    pub fn load(doc: Document) -> Self {
+        let templates = doc.load().unwrap_or_default();
+        Self { doc, templates }
+    }
+
+    /// Write templates to disk.
+    pub fn save(&self) -> io::Result<()> {
+        self.doc.save(&self.templates)
+    }
+
+    pub fn all(&self) -> &[Template] {
+        &self.templates
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Template, TemplateError> {
+        self.templates
+            .iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| TemplateError::NotFound(name.to_string()))
+    }
+
+    /// Add a template, replacing any with the same name.
+    pub fn upsert(&mut self, template: Template) {
+        match self.templates.iter_mut().find(|t| t.name == template.name) {
+            Some(existing) => *existing = template,
+            None => self.templates.push(template),
+        }
+    }
+
+    /// Delete a template; returns whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.templates.len();
+        self.templates.retain(|t| t.name != name);
+        self.templates.len() != before
+    }
+}
+
+impl Default for TemplateStore {
+    fn default() -> Self {
+        Self::load(Document::file(&Self::default_path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::conversation;
+
+    fn conv(name: &str, style: i32) -> Conversation {
+        Conversation { style, resolved_name: Some(name.into()), ..conversation("+15551234567") }
+    }
+
+    fn invitation() -> Template {
+        Template {
+            name: "Party".into(),
+            text: "Hi {first_name}! You're invited, flyer attached.".into(),
+            attachments: vec!["flyer.pdf".into()],
+        }
+    }
+
+    #[test]
+    fn test_apply_fills_text_and_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("flyer.pdf"), b"%PDF").unwrap();
+
+        let applied = invitation().apply(&conv("Jane Doe", 45), dir.path()).unwrap();
+        assert_eq!(applied.text, "Hi Jane! You're invited, flyer attached.");
+        assert_eq!(applied.attachments, [dir.path().join("flyer.pdf")]);
+
+        let group = invitation().apply(&conv("Book Club", 43), dir.path()).unwrap();
+        assert!(group.text.starts_with("Hi Book Club!"));
+    }
+
+    #[test]
+    fn test_attachment_slots_must_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            invitation().apply(&conv("Jane", 45), dir.path()),
+            Err(TemplateError::MissingAttachment(_))
+        ));
+
+        for slot in ["../secret.txt", "/etc/passwd", "./card.jpg", ""] {
+            assert!(matches!(resolve_asset(dir.path(), slot), Err(TemplateError::InvalidAttachment(_))));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_cannot_leave_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        let assets = dir.path().join("assets");
+        std::fs::create_dir(&assets).unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "hunter2").unwrap();
+        std::fs::write(assets.join("card.jpg"), "jpeg").unwrap();
+        std::os::unix::fs::symlink(dir.path().join("secret.txt"), assets.join("card-link.jpg")).unwrap();
+
+        assert!(resolve_asset(&assets, "card.jpg").is_ok());
+        assert!(matches!(resolve_asset(&assets, "card-link.jpg"), Err(TemplateError::InvalidAttachment(_))));
+    }
+
+    #[test]
+    fn test_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("templates.json");
+        let mut store = TemplateStore::load(Document::file(&path));
+        store.upsert(invitation());
+        store.upsert(Template { text: "Updated".into(), ..invitation() });
+        store.save().unwrap();
+
+        let mut reloaded = TemplateStore::load(Document::file(&path));
+        assert_eq!(reloaded.all().len(), 1);
+        assert_eq!(reloaded.get("Party").unwrap().text, "Updated");
+        assert!(reloaded.remove("Party"));
+        assert!(matches!(reloaded.get("Party"), Err(TemplateError::NotFound(_))));
+    }
+}
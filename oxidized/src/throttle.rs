@@ -0,0 +1,100 @@
+//! Adaptive interval for background polling.
+//!
+//! Polls slow down while nothing happens and snap back to the fastest rate
+//! after activity, so an idle app barely wakes the CPU.
+
+use std::time::Duration;
+
+use crate::clock::Clock;
+
+/// Poll interval that doubles on each idle poll, up to a ceiling.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    /// Multiplier applied on top, e.g. while on battery
+    slowdown: u32,
+}
+
+impl Throttle {
+    /// Start at `min`, backing off no further than `max`.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        let max = max.max(min);
+        Self { min, max, current: min, slowdown: 1 }
+    }
+
+    /// How long to wait before the next poll.
+    pub fn interval(&self) -> Duration {
+        self.current * self.slowdown
+    }
+
+    /// Stretch every interval by `factor` until set back to 1.
+    pub fn set_slowdown(&mut self, factor: u32) {
+        self.slowdown = factor.max(1);
+    }
+
+    /// Record the result of a poll: activity tightens the interval back to
+    /// the minimum, an idle poll doubles it.
+    pub fn record(&mut self, active: bool) {
+        self.current = if active {
+            self.min
+        } else {
+            (self.current * 2).min(self.max)
+        };
+    }
+
+    /// Sleep on `clock` for the current interval.
+    pub fn wait(&self, clock: &dyn Clock) {
+        clock.sleep(self.interval());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backs_off_when_idle() {
+        let mut throttle = Throttle::new(Duration::from_secs(5), Duration::from_secs(60));
+        assert_eq!(throttle.interval(), Duration::from_secs(5));
+
+        throttle.record(false);
+        assert_eq!(throttle.interval(), Duration::from_secs(10));
+        for _ in 0..10 {
+            throttle.record(false);
+        }
+        assert_eq!(throttle.interval(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_tightens_after_activity() {
+        let mut throttle = Throttle::new(Duration::from_secs(5), Duration::from_secs(60));
+        throttle.record(false);
+        throttle.record(false);
+        throttle.record(true);
+        assert_eq!(throttle.interval(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_slowdown() {
+        let mut throttle = Throttle::new(Duration::from_secs(5), Duration::from_secs(60));
+        throttle.set_slowdown(4);
+        assert_eq!(throttle.interval(), Duration::from_secs(20));
+        throttle.set_slowdown(0);
+        assert_eq!(throttle.interval(), Duration::from_secs(5));
+
+        let clock = crate::clock::FixedClock::new(chrono::DateTime::UNIX_EPOCH);
+        throttle.set_slowdown(2);
+        throttle.wait(&clock);
+        assert_eq!(clock.now().timestamp(), 10);
+    }
+
+    #[test]
+    fn test_max_below_min() {
+        let throttle = Throttle::new(Duration::from_secs(5), Duration::from_secs(1));
+        let mut backed_off = throttle.clone();
+        backed_off.record(false);
+        assert_eq!(backed_off.interval(), throttle.interval());
+    }
+}
@@ -0,0 +1,110 @@
+//! Polling change detection for files and directories.
+//!
+//! Polling keeps this dependency-free and works the same for SQLite files,
+//! whose WAL writes file-system events report unreliably.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Summary of watched files; any write changes at least one field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fingerprint {
+    files: usize,
+    total_size: u64,
+    latest_modified: Option<SystemTime>,
+}
+
+/// Detects changes under a set of paths between polls.
+pub struct ChangeWatcher {
+    paths: Vec<PathBuf>,
+    max_depth: usize,
+    last: Option<Fingerprint>,
+}
+
+impl ChangeWatcher {
+    /// Watch files, or directories up to `max_depth` levels deep.
+    pub fn new(paths: Vec<PathBuf>, max_depth: usize) -> Self {
+        let mut watcher = Self { paths, max_depth, last: None };
+        watcher.last = Some(watcher.fingerprint());
+        watcher
+    }
+
+    /// Whether anything changed since the last poll (or since creation).
+    pub fn poll(&mut self) -> bool {
+        let current = self.fingerprint();
+        let changed = self.last.as_ref() != Some(&current);
+        self.last = Some(current);
+        changed
+    }
+
+    fn fingerprint(&self) -> Fingerprint {
+        let mut print = Fingerprint { files: 0, total_size: 0, latest_modified: None };
+        for path in &self.paths {
+            add_to_fingerprint(path, self.max_depth, &mut print);
+        }
+        print
+    }
+}
+
+fn add_to_fingerprint(path: &Path, depth: usize, print: &mut Fingerprint) {
+    let Ok(meta) = fs::metadata(path) else { return };
+
+    if meta.is_dir() {
+        if depth == 0 {
+            return;
+        }
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.flatten() {
+                add_to_fingerprint(&entry.path(), depth - 1, print);
+            }
+        }
+        return;
+    }
+
+    print.files += 1;
+    print.total_size += meta.len();
+    if let Ok(modified) = meta.modified() {
+        print.latest_modified = print.latest_modified.max(Some(modified));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_new_and_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+
+        let mut watcher = ChangeWatcher::new(vec![dir.path().to_path_buf()], 2);
+        assert!(!watcher.poll());
+
+        fs::write(source.join("AddressBook-v22.abcddb"), b"one").unwrap();
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        fs::write(source.join("AddressBook-v22.abcddb"), b"three").unwrap();
+        assert!(watcher.poll());
+    }
+
+    #[test]
+    fn test_respects_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = dir.path().join("a/b");
+        fs::create_dir_all(&deep).unwrap();
+
+        let mut watcher = ChangeWatcher::new(vec![dir.path().to_path_buf()], 2);
+        fs::write(deep.join("ignored"), b"x").unwrap();
+        assert!(!watcher.poll());
+    }
+
+    #[test]
+    fn test_missing_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut watcher = ChangeWatcher::new(vec![dir.path().join("none")], 1);
+        assert!(!watcher.poll());
+    }
+}
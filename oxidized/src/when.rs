@@ -0,0 +1,283 @@
+//! Typed times like "tomorrow 9am", "next monday" or "in 2h".
+//!
+//! Everything is read in the viewer's time zone and against a given `now`,
+//! so results don't depend on the clock. The locale decides weekday names
+//! and whether "3/4" is the 4th of March or the 3rd of April.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use thiserror::Error;
+
+use crate::days::weekday_names;
+
+/// Hour used when only a day is given.
+pub const DEFAULT_HOUR: u32 = 9;
+
+/// Hour "tonight" means.
+const TONIGHT_HOUR: u32 = 20;
+
+/// Regions writing numeric dates month first; everywhere else is day first.
+const MONTH_FIRST_REGIONS: &[&str] = &["US", "PH", "FM", "MH", "PW"];
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WhenError {
+    #[error("Couldn't read \"{0}\" as a time")]
+    Unrecognized(String),
+    #[error("\"{0}\" is already past")]
+    InPast(String),
+}
+
+/// How a locale writes dates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateLocale {
+    /// Language for weekday names ("en", "fr", ...)
+    pub lang: String,
+    /// "3/4" is March 4th rather than April 3rd
+    pub month_first: bool,
+}
+
+impl DateLocale {
+    /// From a BCP 47 tag such as "en-US" or "fr_FR".
+    pub fn from_tag(tag: &str) -> Self {
+        let mut parts = tag.split(['-', '_']);
+        let lang = parts.next().unwrap_or("en").to_lowercase();
+        let region = parts.find(|p| p.len() == 2).map(str::to_uppercase);
+        let month_first = match region {
+            Some(region) => MONTH_FIRST_REGIONS.contains(&region.as_str()),
+            None => lang == "en",
+        };
+        Self { lang, month_first }
+    }
+}
+
+impl Default for DateLocale {
+    fn default() -> Self {
+        Self::from_tag("en-US")
+    }
+}
+
+/// The moment `input` names, after `now`.
+///
+/// Understands "now", "in 2h" / "in 30 minutes", a day ("today",
+/// "tomorrow", "tonight", a weekday, "next monday", "next week", "3/4",
+/// "2024-03-04"), a time ("9am", "9:30pm", "21:00", "noon"), or a day and
+/// a time in either order. A day alone means [`DEFAULT_HOUR`]; a time alone
+/// means its next occurrence. Weekdays always mean a day after today.
+pub fn parse_when<Tz: TimeZone>(input: &str, now: DateTime<Tz>, locale: &DateLocale) -> Result<DateTime<Utc>, WhenError> {
+    let unrecognized = || WhenError::Unrecognized(input.trim().to_string());
+    let text = input.trim().to_lowercase();
+    let words: Vec<&str> = text.split_whitespace().filter(|w| *w != "at" && *w != "on").collect();
+
+    if words == ["now"] {
+        return Ok(now.with_timezone(&Utc));
+    }
+    if let Some(rest) = words.strip_prefix(&["in"]) {
+        let offset = parse_offset(&rest.join(" ")).ok_or_else(unrecognized)?;
+        let when = now.checked_add_signed(offset).ok_or_else(unrecognized)?;
+        return Ok(when.with_timezone(&Utc));
+    }
+
+    let today = now.date_naive();
+    let mut day = None;
+    let mut time = None;
+    let mut rest = words.as_slice();
+    while !rest.is_empty() {
+        if let Some((d, used)) = parse_day(rest, today, locale) {
+            if day.replace(d).is_some() {
+                return Err(unrecognized());
+            }
+            rest = &rest[used..];
+        } else if let Some(t) = parse_time(rest[0]) {
+            if time.replace(t).is_some() {
+                return Err(unrecognized());
+            }
+            rest = &rest[1..];
+        } else {
+            return Err(unrecognized());
+        }
+    }
+
+    let tonight = words.contains(&"tonight");
+    let (date, time) = match (day, time) {
+        (None, None) => return Err(unrecognized()),
+        (Some(date), time) => {
+            let hour = if tonight { TONIGHT_HOUR } else { DEFAULT_HOUR };
+            (date, time.unwrap_or_else(|| NaiveTime::from_hms_opt(hour, 0, 0).unwrap()))
+        }
+        (None, Some(time)) => {
+            let date = if time > now.time() { today } else { today.succ_opt().ok_or_else(unrecognized)? };
+            (date, time)
+        }
+    };
+
+    let at = local_time(&now.timezone(), date.and_time(time)).ok_or_else(unrecognized)?;
+    if at <= now.with_timezone(&Utc) {
+        return Err(WhenError::InPast(input.trim().to_string()));
+    }
+    Ok(at)
+}
+
+/// A wall-clock time in `tz`. Times skipped by a daylight saving change
+/// move forward an hour.
+fn local_time<Tz: TimeZone>(tz: &Tz, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(naive + Duration::hours(1))).earliest())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// "2h", "2 hours", "90 min", "1 week". None if it's too long to represent.
+fn parse_offset(text: &str) -> Option<Duration> {
+    let text = text.replace(' ', "");
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = text.split_at(split);
+    let n: i64 = n.parse().ok()?;
+    match unit {
+        "m" | "min" | "mins" | "minute" | "minutes" => Duration::try_minutes(n),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Duration::try_hours(n),
+        "d" | "day" | "days" => Duration::try_days(n),
+        "w" | "wk" | "week" | "weeks" => Duration::try_weeks(n),
+        _ => None,
+    }
+}
+
+/// A day at the start of `words`, and how many words it took.
+fn parse_day(words: &[&str], today: NaiveDate, locale: &DateLocale) -> Option<(NaiveDate, usize)> {
+    let weekday = |word: &str| {
+        let word = word.trim_end_matches(',');
+        [weekday_names(&locale.lang), weekday_names("en")]
+            .iter()
+            .find_map(|names| {
+                names.iter().position(|n| {
+                    let n = n.to_lowercase();
+                    n == word || n.chars().take(3).eq(word.chars())
+                })
+            })
+    };
+    // The next such weekday after today
+    let upcoming = |index: usize| {
+        let ahead = (index as i64 - today.weekday().num_days_from_monday() as i64 - 1).rem_euclid(7) + 1;
+        today + Duration::days(ahead)
+    };
+
+    match words {
+        ["today" | "tonight", ..] => Some((today, 1)),
+        ["tomorrow", ..] => Some((today.succ_opt()?, 1)),
+        ["next", "week", ..] => Some((upcoming(0), 2)),
+        ["next", word, ..] => weekday(word).map(|i| (upcoming(i), 2)),
+        [word, ..] => weekday(word)
+            .map(|i| (upcoming(i), 1))
+            .or_else(|| parse_date(word, today, locale).map(|d| (d, 1))),
+        [] => None,
+    }
+}
+
+/// "2024-03-04", or "3/4" read in the locale's order, taking the next such
+/// day when no year is given.
+fn parse_date(word: &str, today: NaiveDate, locale: &DateLocale) -> Option<NaiveDate> {
+    if let Ok(date) = NaiveDate::parse_from_str(word, "%Y-%m-%d") {
+        return Some(date);
+    }
+    let parts: Vec<u32> = word.split(['/', '.']).filter(|p| !p.is_empty()).map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let (first, second, year) = match parts.as_slice() {
+        [a, b] => (*a, *b, None),
+        [a, b, y] => (*a, *b, Some(if *y < 100 { 2000 + *y as i32 } else { *y as i32 })),
+        _ => return None,
+    };
+    let (month, day) = if locale.month_first { (first, second) } else { (second, first) };
+    match year {
+        Some(year) => NaiveDate::from_ymd_opt(year, month, day),
+        None => {
+            let this_year = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+            if this_year >= today { Some(this_year) } else { NaiveDate::from_ymd_opt(today.year() + 1, month, day) }
+        }
+    }
+}
+
+/// "9am", "9:30pm", "21:00", "noon", "midnight". A bare number isn't a time.
+fn parse_time(word: &str) -> Option<NaiveTime> {
+    match word {
+        "noon" | "midday" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let (clock, meridiem) = if let Some(clock) = word.strip_suffix("am") {
+        (clock, Some(false))
+    } else if let Some(clock) = word.strip_suffix("pm") {
+        (clock, Some(true))
+    } else {
+        (word, None)
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        None if meridiem.is_some() => (clock.parse::<u32>().ok()?, 0),
+        _ => return None,
+    };
+    let hour = match meridiem {
+        Some(pm) if (1..=12).contains(&hour) => hour % 12 + if pm { 12 } else { 0 },
+        Some(_) => return None,
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    /// Friday 15 March 2024, 14:20 in UTC-4
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-03-15T14:20:00-04:00").unwrap()
+    }
+
+    fn at(input: &str) -> String {
+        let when = parse_when(input, now(), &DateLocale::default()).unwrap();
+        when.with_timezone(&now().timezone()).format("%a %Y-%m-%d %H:%M").to_string()
+    }
+
+    #[test]
+    fn test_relative_and_named_days() {
+        assert_eq!(at("in 2h"), "Fri 2024-03-15 16:20");
+        assert_eq!(at("in 30 minutes"), "Fri 2024-03-15 14:50");
+        assert_eq!(at("In 1 week"), "Fri 2024-03-22 14:20");
+        assert_eq!(at("tomorrow 9am"), "Sat 2024-03-16 09:00");
+        assert_eq!(at("tomorrow"), "Sat 2024-03-16 09:00");
+        assert_eq!(at("tonight"), "Fri 2024-03-15 20:00");
+        assert_eq!(at("5:30pm"), "Fri 2024-03-15 17:30");
+        assert_eq!(at("9am"), "Sat 2024-03-16 09:00");
+        assert_eq!(at("at noon on monday"), "Mon 2024-03-18 12:00");
+        assert_eq!(at("next monday"), "Mon 2024-03-18 09:00");
+        assert_eq!(at("friday 21:00"), "Fri 2024-03-22 21:00");
+        assert_eq!(at("next week"), "Mon 2024-03-18 09:00");
+    }
+
+    #[test]
+    fn test_locale_dates() {
+        assert_eq!(at("4/1 8am"), "Mon 2024-04-01 08:00");
+        assert_eq!(at("3/1"), "Sat 2025-03-01 09:00");
+        assert_eq!(at("2024-12-25 12pm"), "Wed 2024-12-25 12:00");
+
+        let fr = DateLocale::from_tag("fr-FR");
+        assert!(!fr.month_first);
+        let when = parse_when("4/1 lundi", now(), &fr);
+        assert_eq!(when, Err(WhenError::Unrecognized("4/1 lundi".into())));
+        let when = parse_when("lundi 8:00", now(), &fr).unwrap();
+        assert_eq!(when, DateTime::parse_from_rfc3339("2024-03-18T12:00:00Z").unwrap());
+        let when = parse_when("4/1", now(), &fr).unwrap();
+        assert_eq!(when, DateTime::parse_from_rfc3339("2025-01-04T13:00:00Z").unwrap());
+        assert!(DateLocale::from_tag("en_GB").lang == "en" && !DateLocale::from_tag("en_GB").month_first);
+    }
+
+    #[test]
+    fn test_rejects() {
+        let locale = DateLocale::default();
+        for input in [
+            "", "soon", "in 2 fortnights", "tomorrow tomorrow", "9", "13pm", "2/30",
+            // Past what a Duration or a date can hold
+            "in 99999999999999 weeks", "in 9999999999 days",
+        ] {
+            assert_eq!(parse_when(input, now(), &locale), Err(WhenError::Unrecognized(input.into())), "{}", input);
+        }
+        assert_eq!(parse_when("today 9am", now(), &locale), Err(WhenError::InPast("today 9am".into())));
+    }
+}
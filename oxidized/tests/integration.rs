@@ -40,7 +40,7 @@ fn test_fetch_unread_conversations() {
         }
     };
 
-    match db.unread_conversations() {
+    match db.unread_conversations(None) {
         Ok(convs) => {
             println!("Found {} unread conversations", convs.len());
             for conv in convs.iter().take(3) {
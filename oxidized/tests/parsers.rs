@@ -0,0 +1,77 @@
+//! Property tests for parsers that read untrusted bytes from chat.db and
+//! Messages.app. Fuzz targets for the same functions live in `fuzz/`.
+
+use aeromessage::{decode_attributed_string, parse_attributed_body, parse_draft_plist};
+use proptest::prelude::*;
+
+/// Encode text the way typedstream stores an NSString: marker, five bytes of
+/// class info, then a 1-byte length or 0x81 and a 2-byte little-endian length.
+fn attributed_body(prefix: &[u8], text: &str) -> Vec<u8> {
+    let mut blob = prefix.to_vec();
+    blob.extend_from_slice(b"NSString");
+    blob.extend_from_slice(&[1, 148, 132, 1, 43]);
+    let len = text.len();
+    if len < 0x80 {
+        blob.push(len as u8);
+    } else {
+        blob.push(0x81);
+        blob.extend_from_slice(&(len as u16).to_le_bytes());
+    }
+    blob.extend_from_slice(text.as_bytes());
+    blob
+}
+
+proptest! {
+    #[test]
+    fn attributed_body_never_panics(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let _ = parse_attributed_body(&data);
+    }
+
+    #[test]
+    fn typedstream_never_panics(body in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let mut data = b"\x04\x0bstreamtyped\x81\xe8\x03\x84\x01@".to_vec();
+        data.extend_from_slice(&body);
+        let _ = decode_attributed_string(&data);
+    }
+
+    #[test]
+    fn attributed_body_with_marker_never_panics(
+        before in proptest::collection::vec(any::<u8>(), 0..32),
+        after in proptest::collection::vec(any::<u8>(), 0..300),
+    ) {
+        let mut data = before;
+        data.extend_from_slice(b"NSString");
+        data.extend_from_slice(&after);
+        let _ = parse_attributed_body(&data);
+    }
+
+    #[test]
+    fn attributed_body_roundtrip(
+        // No "NSString" in the prefix, so the marker we add is the first one
+        prefix in proptest::collection::vec(0u8..0x40, 0..32),
+        text in ".{0,400}",
+    ) {
+        prop_assume!(text.len() <= u16::MAX as usize);
+        let blob = attributed_body(&prefix, &text);
+        prop_assert_eq!(parse_attributed_body(&blob), Some(text));
+    }
+
+    #[test]
+    fn attributed_body_truncated_is_none(text in ".{1,200}", cut in 1usize..200) {
+        let blob = attributed_body(b"", &text);
+        let cut = cut.min(text.len());
+        prop_assert_eq!(parse_attributed_body(&blob[..blob.len() - cut]), None);
+    }
+
+    #[test]
+    fn draft_plist_never_panics(data in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let _ = parse_draft_plist(&data);
+    }
+
+    #[test]
+    fn draft_plist_binary_header_never_panics(rest in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let mut data = b"bplist00".to_vec();
+        data.extend_from_slice(&rest);
+        let _ = parse_draft_plist(&data);
+    }
+}